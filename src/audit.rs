@@ -0,0 +1,127 @@
+//! Opt-in audit log.
+//!
+//! When `CCPROF_LOG_FILE` names a file, mutating commands append timestamped
+//! lines to it: the command line, each filesystem change (backups, removals,
+//! symlinks, copies) and the outcome, as a trail for working out what a switch
+//! did after the fact. Only paths are logged, never file contents, since
+//! settings can hold API keys.
+
+use anyhow::{Context, Result};
+use chrono::{SecondsFormat, Utc};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Environment variable naming the audit log file
+pub const LOG_FILE_ENV: &str = "CCPROF_LOG_FILE";
+
+/// The log of this process, once opened
+static LOG: OnceLock<AuditLog> = OnceLock::new();
+
+/// An append-only log file
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    /// Open `path` for appending, creating it and its directory if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// The file being written
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append one timestamped line
+    pub fn write(&self, message: &str) -> std::io::Result<()> {
+        let line = format!(
+            "{} [{}] {}\n",
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            std::process::id(),
+            message.replace('\n', " ")
+        );
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+    }
+}
+
+/// Start logging to the file named by `CCPROF_LOG_FILE`, if it is set
+///
+/// An error means the log can't be written; the command should warn once
+/// and carry on without it.
+pub fn init_from_env() -> Result<()> {
+    let Some(path) = std::env::var_os(LOG_FILE_ENV).filter(|p| !p.is_empty()) else {
+        return Ok(());
+    };
+    let log = AuditLog::open(Path::new(&path)).with_context(|| {
+        format!(
+            "{} is set but not writable; continuing without an audit log",
+            LOG_FILE_ENV
+        )
+    })?;
+    let _ = LOG.set(log);
+    Ok(())
+}
+
+/// Record an event in the audit log, if one is open
+///
+/// Write errors are ignored: a broken log must not fail the operation.
+pub fn record(message: impl AsRef<str>) {
+    if let Some(log) = LOG.get() {
+        let _ = log.write(message.as_ref());
+    }
+}
+
+/// Record the outcome of the command
+pub fn record_outcome<T>(result: &Result<T>) {
+    match result {
+        Ok(_) => record("outcome: ok"),
+        Err(e) => record(format!("outcome: failed: {:#}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_log_appends_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("logs/ccprof.log");
+
+        let log = AuditLog::open(&path).unwrap();
+        log.write("command: ccprof use work").unwrap();
+        let log = AuditLog::open(&path).unwrap();
+        log.write("outcome: ok").unwrap();
+
+        let content = fs::read_to_string(log.path()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(&format!(
+            "[{}] command: ccprof use work",
+            std::process::id()
+        )));
+        assert!(lines[1].ends_with("outcome: ok"));
+
+        // A directory can't be opened as a log
+        assert!(AuditLog::open(temp_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+//! Export archives of profiles.
+//!
+//! `ccprof export` writes profiles to a `.tar.gz` for another machine, made
+//! with the system `tar`. The archive holds a [`MANIFEST_FILE`] naming what it
+//! contains, `profiles/<name>/` for each profile, `shared/<name>/` for the
+//! shared items they reference and, if asked for, a `state.json` keeping only
+//! preferences (strategy, link style, when profiles were last used); what is
+//! linked or copied into this machine's `~/.claude` is left out. `ccprof
+//! import` unpacks one and adds everything in it.
+
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::components::{Component, ProfileMetadata};
+use crate::fs_utils::{CopyReport, copy_dir_recursive, move_path, remove_path, replace_dir_atomic};
+use crate::paths::Paths;
+use crate::profiles::{
+    archive_destination, ensure_profile_exists, profile_exists, validate_profile_name,
+};
+use crate::state::{LockedState, State};
+
+/// File at the top of an archive describing its content
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// What an archive contains
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// ccprof version that wrote the archive
+    pub tool_version: String,
+    pub exported_at: DateTime<Utc>,
+    pub profiles: Vec<String>,
+    /// Shared items referenced by the profiles
+    #[serde(default)]
+    pub shared: Vec<String>,
+    /// Whether state.json is included
+    #[serde(default)]
+    pub state: bool,
+}
+
+/// What to do with a profile in the archive that already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// Import nothing if any profile exists
+    #[default]
+    Fail,
+    /// Keep the existing profile
+    Skip,
+    /// Replace the existing profile, keeping a copy in the archive directory
+    Overwrite,
+    /// Import it as `<name>-imported-<n>`, with the first free `n`
+    Rename,
+}
+
+/// What happened to one profile in the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportOutcome {
+    Imported,
+    Skipped,
+    /// Replaced the existing profile, which was copied here first
+    Replaced(PathBuf),
+    /// Imported under this name, since its own was taken
+    Renamed(String),
+}
+
+/// What [`import_bundle`] did
+#[derive(Debug)]
+pub struct ImportReport {
+    pub manifest: BundleManifest,
+    /// Profiles in archive order
+    pub profiles: Vec<(String, ImportOutcome)>,
+    /// Shared items added; those that already existed are kept
+    pub shared: Vec<String>,
+    /// Name here of the profile that was active on the exporting machine, if
+    /// state was included and the profile imported
+    pub exported_profile: Option<String>,
+    /// What copying profiles out of the archive reported
+    pub copies: CopyReport,
+}
+
+/// The profiles `names` extend, directly or through another, not already in it
+fn bases_of(paths: &Paths, names: &[String]) -> Result<Vec<String>> {
+    let mut seen: BTreeSet<String> = names.iter().cloned().collect();
+    let mut bases = Vec::new();
+    for name in names {
+        let mut current = ProfileMetadata::read(&paths.profile_dir(name))?.extends;
+        while let Some(base) = current {
+            if !seen.insert(base.clone()) || !profile_exists(paths, &base) {
+                break;
+            }
+            current = ProfileMetadata::read(&paths.profile_dir(&base))?.extends;
+            bases.push(base);
+        }
+    }
+    Ok(bases)
+}
+
+/// Write `names`, the profiles they extend and the shared items they
+/// reference to the archive `output`, returning its manifest with what copying
+/// them reported
+pub fn export_profiles(
+    paths: &Paths,
+    names: &[String],
+    with_state: bool,
+    output: &Path,
+) -> Result<(BundleManifest, CopyReport)> {
+    if names.is_empty() {
+        bail!("No profiles to export\nHint: Create one with 'ccprof add <name>'.");
+    }
+    for name in names {
+        ensure_profile_exists(paths, name)?;
+    }
+    if output.exists() {
+        bail!(
+            "{} already exists\nHint: Pass --file to write the archive elsewhere.",
+            output.display()
+        );
+    }
+    let mut profiles = names.to_vec();
+    profiles.extend(bases_of(paths, names)?);
+
+    let mut shared = BTreeSet::new();
+    for name in &profiles {
+        let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+        shared.extend(
+            Component::all()
+                .into_iter()
+                .filter_map(|c| metadata.shared_source(c).map(str::to_string))
+                .filter(|item| paths.shared_item(item).is_dir()),
+        );
+    }
+    let manifest = BundleManifest {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        exported_at: Utc::now(),
+        profiles,
+        shared: shared.into_iter().collect(),
+        state: with_state,
+    };
+
+    let output = std::path::absolute(output)
+        .with_context(|| format!("Failed to resolve {}", output.display()))?;
+    let mut report = CopyReport::default();
+    with_scratch_dir(paths, "export", |stage| {
+        for name in &manifest.profiles {
+            let to = stage.join("profiles").join(name);
+            report.extend(copy_dir_recursive(&paths.profile_dir(name), &to)?);
+            remove_path(&to.join(crate::editor::EDITING_MARKER))?;
+        }
+        for item in &manifest.shared {
+            report.extend(copy_dir_recursive(
+                &paths.shared_item(item),
+                &stage.join("shared").join(item),
+            )?);
+        }
+        if with_state {
+            let mut state = State::read(&paths.state_file).unwrap_or_default();
+            state.updated_at = None;
+            state.copies.clear();
+            state.active_components.clear();
+            state
+                .last_used
+                .retain(|name, _| manifest.profiles.contains(name));
+            state.write(&stage.join("state.json"))?;
+        }
+        fs::write(
+            stage.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(&manifest)?,
+        )
+        .context("Failed to write the archive manifest")?;
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        run_tar(&[
+            OsStr::new("-czf"),
+            output.as_os_str(),
+            OsStr::new("-C"),
+            stage.as_os_str(),
+            OsStr::new("."),
+        ])
+    })?;
+    Ok((manifest, report))
+}
+
+/// An unpacked archive, removed from the staging directory when dropped
+pub struct Bundle {
+    dir: PathBuf,
+    pub manifest: BundleManifest,
+}
+
+impl Bundle {
+    /// Unpack `archive` and check its manifest
+    pub fn open(paths: &Paths, archive: &Path) -> Result<Self> {
+        if !archive.is_file() {
+            bail!("{} does not exist", archive.display());
+        }
+        paths.ensure_dirs()?;
+        let dir = scratch_dir(paths, "import")?;
+        match read_unpacked(archive, &dir) {
+            Ok(manifest) => Ok(Self { dir, manifest }),
+            Err(e) => {
+                let _ = remove_path(&dir);
+                Err(e)
+            }
+        }
+    }
+
+    /// Profiles in the archive that already exist here
+    pub fn conflicts(&self, paths: &Paths) -> Vec<String> {
+        self.manifest
+            .profiles
+            .iter()
+            .filter(|name| profile_exists(paths, name))
+            .cloned()
+            .collect()
+    }
+
+    /// Add the profiles, shared items and preferences
+    ///
+    /// With [`ConflictPolicy::Fail`] nothing is imported if any of the
+    /// profiles already exists. Profiles extending a renamed one are pointed
+    /// at its new name. `on_wait` is called if another process holds the
+    /// state lock.
+    pub fn import(
+        &self,
+        paths: &Paths,
+        policy: ConflictPolicy,
+        on_wait: &mut dyn FnMut(),
+    ) -> Result<ImportReport> {
+        let existing = self.conflicts(paths);
+        if policy == ConflictPolicy::Fail && !existing.is_empty() {
+            let (noun, verb, them) = if existing.len() == 1 {
+                ("A profile", "exists", "it")
+            } else {
+                ("Profiles", "exist", "them")
+            };
+            bail!(
+                "{} already {}: {}\nHint: Pass --skip to keep {}, --overwrite to replace {} or --rename-on-conflict to import {} under new names.",
+                noun,
+                verb,
+                existing.join(", "),
+                them,
+                them,
+                them
+            );
+        }
+
+        // Every name is settled first, so extends can follow renames
+        let mut taken: HashSet<String> = self.manifest.profiles.iter().cloned().collect();
+        let mut imported_as: HashMap<&str, String> = HashMap::new();
+        for name in &self.manifest.profiles {
+            let local = if !existing.contains(name) || policy == ConflictPolicy::Overwrite {
+                name.clone()
+            } else if policy == ConflictPolicy::Rename {
+                let renamed = free_name(paths, name, &taken)?;
+                taken.insert(renamed.clone());
+                renamed
+            } else {
+                continue;
+            };
+            imported_as.insert(name, local);
+        }
+
+        let mut profiles = Vec::new();
+        let mut copies = CopyReport::default();
+        for name in &self.manifest.profiles {
+            let Some(local) = imported_as.get(name.as_str()) else {
+                profiles.push((name.clone(), ImportOutcome::Skipped));
+                continue;
+            };
+            let staged = self.dir.join("profiles").join(name);
+            let target = paths.profile_dir(local);
+            let outcome = if existing.contains(local) {
+                let backup = archive_destination(paths, local);
+                fs::create_dir_all(&paths.archive_dir).with_context(|| {
+                    format!("Failed to create directory {}", paths.archive_dir.display())
+                })?;
+                copies.extend(copy_dir_recursive(&target, &backup)?);
+                copies.extend(replace_dir_atomic(&staged, &target)?);
+                ImportOutcome::Replaced(backup)
+            } else {
+                move_path(&staged, &target)?;
+                if local == name {
+                    ImportOutcome::Imported
+                } else {
+                    ImportOutcome::Renamed(local.clone())
+                }
+            };
+            // Renders of a replaced profile are refreshed from its new content
+            let mut metadata = ProfileMetadata::read(&target)?;
+            metadata.updated_at = Utc::now();
+            if let Some(base) = metadata
+                .extends
+                .as_deref()
+                .and_then(|base| imported_as.get(base))
+            {
+                metadata.extends = Some(base.clone());
+            }
+            metadata.write(&target)?;
+            profiles.push((name.clone(), outcome));
+        }
+
+        let mut shared = Vec::new();
+        for item in &self.manifest.shared {
+            let staged = self.dir.join("shared").join(item);
+            let target = paths.shared_item(item);
+            if staged.is_dir() && !target.exists() {
+                fs::create_dir_all(&paths.shared_dir).with_context(|| {
+                    format!("Failed to create directory {}", paths.shared_dir.display())
+                })?;
+                move_path(&staged, &target)?;
+                shared.push(item.clone());
+            }
+        }
+
+        let mut exported_profile = None;
+        let state_path = self.dir.join("state.json");
+        if self.manifest.state && state_path.exists() {
+            let exported = State::read(&state_path)?;
+            LockedState::lock_or_wait(&paths.state_file, on_wait)?.update(|s| {
+                s.strategy = s.strategy.or(exported.strategy);
+                s.link_style = s.link_style.or(exported.link_style);
+                for (name, local) in &imported_as {
+                    if let Some(used) = exported.last_used.get(*name) {
+                        s.last_used.insert(local.clone(), *used);
+                    }
+                }
+            })?;
+            exported_profile = exported
+                .default_profile
+                .and_then(|name| imported_as.get(name.as_str()).cloned());
+        }
+
+        Ok(ImportReport {
+            manifest: self.manifest.clone(),
+            profiles,
+            shared,
+            exported_profile,
+            copies,
+        })
+    }
+}
+
+impl Drop for Bundle {
+    fn drop(&mut self) {
+        let _ = remove_path(&self.dir);
+    }
+}
+
+/// Open `archive` and import it in one go
+pub fn import_bundle(
+    paths: &Paths,
+    archive: &Path,
+    policy: ConflictPolicy,
+) -> Result<ImportReport> {
+    Bundle::open(paths, archive)?.import(paths, policy, &mut || {})
+}
+
+/// Unpack `archive` into `dir` and read its manifest
+fn read_unpacked(archive: &Path, dir: &Path) -> Result<BundleManifest> {
+    run_tar(&[
+        OsStr::new("-xzf"),
+        archive.as_os_str(),
+        OsStr::new("-C"),
+        dir.as_os_str(),
+    ])?;
+    let manifest: BundleManifest = fs::read_to_string(dir.join(MANIFEST_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .with_context(|| {
+            format!(
+                "{} is not a ccprof export (no readable {})\nHint: Create one with 'ccprof export'.",
+                archive.display(),
+                MANIFEST_FILE
+            )
+        })?;
+    for name in manifest.profiles.iter().chain(&manifest.shared) {
+        validate_profile_name(name)?;
+    }
+    for name in &manifest.profiles {
+        if !dir.join("profiles").join(name).is_dir() {
+            bail!(
+                "{} is incomplete: profile '{}' is missing",
+                archive.display(),
+                name
+            );
+        }
+    }
+    Ok(manifest)
+}
+
+/// `<name>-imported-<n>` with the first `n` free here and in the archive
+fn free_name(paths: &Paths, name: &str, taken: &HashSet<String>) -> Result<String> {
+    let mut n = 1;
+    loop {
+        let candidate = format!("{}-imported-{}", name, n);
+        if !taken.contains(&candidate) && !profile_exists(paths, &candidate) {
+            validate_profile_name(&candidate)?;
+            return Ok(candidate);
+        }
+        n += 1;
+    }
+}
+
+/// A fresh directory under the staging directory
+fn scratch_dir(paths: &Paths, purpose: &str) -> Result<PathBuf> {
+    let dir = paths.staging_dir.join(format!(
+        "{}-{}-{}",
+        purpose,
+        Utc::now().format("%Y%m%d_%H%M%S%.f"),
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create staging directory {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Run `f` with a [`scratch_dir`], removed after
+fn with_scratch_dir<T>(
+    paths: &Paths,
+    purpose: &str,
+    f: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    let dir = scratch_dir(paths, purpose)?;
+    let result = f(&dir);
+    let _ = remove_path(&dir);
+    result
+}
+
+fn run_tar(args: &[&OsStr]) -> Result<()> {
+    let output = Command::new("tar")
+        .args(args)
+        .output()
+        .context("Failed to run 'tar'\nHint: Export and import need tar on PATH.")?;
+    if !output.status.success() {
+        bail!(
+            "tar failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_export_all_and_import_with_conflict_policies() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        for name in ["work", "personal"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+        LockedState::lock(&paths.state_file)
+            .unwrap()
+            .update(|s| {
+                s.default_profile = Some("work".to_string());
+                s.last_used.insert("work".to_string(), Utc::now());
+            })
+            .unwrap();
+
+        let archive = temp_dir.path().join("out/all.tar.gz");
+        let names = crate::profiles::list_profiles(&paths).unwrap();
+        let (manifest, _) = export_profiles(&paths, &names, true, &archive).unwrap();
+        assert_eq!(manifest.profiles, vec!["personal", "work"]);
+        assert!(archive.is_file());
+        assert!(export_profiles(&paths, &names, true, &archive).is_err());
+
+        // A second machine with a 'work' of its own
+        let other = TempDir::new().unwrap();
+        let target = setup_test_paths(&other);
+        target.ensure_dirs().unwrap();
+        fs::create_dir_all(target.profile_dir("work")).unwrap();
+        fs::write(target.profile_settings("work"), r#"{"model": "mine"}"#).unwrap();
+
+        let err = import_bundle(&target, &archive, ConflictPolicy::Fail).unwrap_err();
+        assert!(err.to_string().contains("A profile already exists: work"));
+        assert!(!profile_exists(&target, "personal"));
+
+        let report = import_bundle(&target, &archive, ConflictPolicy::Skip).unwrap();
+        assert_eq!(
+            report.profiles,
+            vec![
+                ("personal".to_string(), ImportOutcome::Imported),
+                ("work".to_string(), ImportOutcome::Skipped)
+            ]
+        );
+        // The exported active profile was skipped, so there is nothing to suggest
+        assert_eq!(report.exported_profile, None);
+        assert_eq!(
+            fs::read_to_string(target.profile_settings("work")).unwrap(),
+            r#"{"model": "mine"}"#
+        );
+        // Importing never selects a profile on this machine
+        let state = State::read(&target.state_file).unwrap();
+        assert_eq!(state.default_profile, None);
+        assert!(!state.last_used.contains_key("work"));
+
+        let report = import_bundle(&target, &archive, ConflictPolicy::Overwrite).unwrap();
+        assert_eq!(report.exported_profile.as_deref(), Some("work"));
+        let ImportOutcome::Replaced(backup) = &report.profiles[1].1 else {
+            panic!("expected a replacement: {:?}", report.profiles);
+        };
+        assert_eq!(
+            fs::read_to_string(backup.join("settings.json")).unwrap(),
+            r#"{"model": "mine"}"#
+        );
+        assert_eq!(
+            fs::read_to_string(target.profile_settings("work")).unwrap(),
+            r#"{"model": "opus"}"#
+        );
+        assert!(
+            State::read(&target.state_file)
+                .unwrap()
+                .last_used
+                .contains_key("work")
+        );
+        assert_eq!(fs::read_dir(&target.staging_dir).unwrap().count(), 0);
+
+        let junk = temp_dir.path().join("junk.tar.gz");
+        fs::write(&junk, "not an archive").unwrap();
+        assert!(import_bundle(&target, &junk, ConflictPolicy::Skip).is_err());
+    }
+
+    #[test]
+    fn test_rename_on_conflict_keeps_extends_pointing_at_renamed_base() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        for name in ["base", "child"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+        let child_dir = paths.profile_dir("child");
+        let mut metadata = ProfileMetadata::read(&child_dir).unwrap();
+        metadata.extends = Some("base".to_string());
+        metadata.write(&child_dir).unwrap();
+
+        let archive = temp_dir.path().join("child.tar.gz");
+        let (manifest, _) =
+            export_profiles(&paths, &["child".to_string()], false, &archive).unwrap();
+        assert_eq!(manifest.profiles, vec!["child", "base"]);
+
+        let other = TempDir::new().unwrap();
+        let target = setup_test_paths(&other);
+        target.ensure_dirs().unwrap();
+        for name in ["base", "base-imported-1"] {
+            fs::create_dir_all(target.profile_dir(name)).unwrap();
+        }
+        let report = import_bundle(&target, &archive, ConflictPolicy::Rename).unwrap();
+        assert_eq!(
+            report.profiles,
+            vec![
+                ("child".to_string(), ImportOutcome::Imported),
+                (
+                    "base".to_string(),
+                    ImportOutcome::Renamed("base-imported-2".to_string())
+                )
+            ]
+        );
+        let metadata = ProfileMetadata::read(&target.profile_dir("child")).unwrap();
+        assert_eq!(metadata.extends.as_deref(), Some("base-imported-2"));
+        assert!(
+            target
+                .profile_dir("base-imported-2")
+                .join("metadata.json")
+                .exists()
+        );
+    }
+}
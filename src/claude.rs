@@ -0,0 +1,79 @@
+//! The installed Claude Code binary.
+//!
+//! Settings schemas change between Claude Code releases, so profiles record the
+//! version they were created or synced with. Finding the version is best
+//! effort: a missing, slow or failing `claude` binary just yields `None`.
+
+use std::env;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Environment variable naming the Claude Code binary (default: `claude`)
+pub const CLAUDE_BIN_ENV: &str = "CCPROF_CLAUDE_BIN";
+
+/// How long `claude --version` may take before it's given up on
+const VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Version of the installed Claude Code, if it can be determined
+pub fn installed_version() -> Option<String> {
+    let bin = env::var(CLAUDE_BIN_ENV)
+        .ok()
+        .filter(|b| !b.is_empty())
+        .unwrap_or_else(|| "claude".to_string());
+
+    let mut child = Command::new(bin)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let deadline = Instant::now() + VERSION_TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(20)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    if !status.success() {
+        return None;
+    }
+
+    let mut output = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    parse_version(&output)
+}
+
+/// Extract the version from `claude --version` output, e.g. `1.0.3 (Claude Code)`
+fn parse_version(output: &str) -> Option<String> {
+    let line = output.lines().map(str::trim).find(|l| !l.is_empty())?;
+    let version = line
+        .split_whitespace()
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(line);
+    Some(version.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(
+            parse_version("1.0.3 (Claude Code)\n").as_deref(),
+            Some("1.0.3")
+        );
+        assert_eq!(parse_version("\nclaude 2.1.0\n").as_deref(), Some("2.1.0"));
+        assert_eq!(parse_version("dev build").as_deref(), Some("dev build"));
+        assert_eq!(parse_version("  \n"), None);
+    }
+}
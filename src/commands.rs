@@ -12,84 +12,118 @@
 
 use anstyle::AnsiColor;
 use anyhow::{Context, Result, bail};
-use inquire::MultiSelect;
+use indicatif::ProgressBar;
+use serde_json::json;
 use std::collections::HashSet;
-use std::path::Path;
-use std::process::Command;
+use std::io::{IsTerminal, Read};
+use std::path::{Path, PathBuf};
 
 use crate::components::Component;
+use crate::config::{CONFIG_TEMPLATE, Config};
 use crate::doctor::run_doctor;
-use crate::paths::Paths;
+use crate::editor::{EditingMarker, SafeEdit};
+use crate::error::Error;
+use crate::fs_utils::{CopyOptions, CopyReport, DirStats, SymlinkMode};
+use crate::interaction::{ComponentChoice, Interaction};
+use crate::merge::{Decision, MergeOptions, Side, apply_merge, plan_merge};
+use crate::paths::{CLAUDE_CONFIG_DIR_ENV, Layout, Paths};
+use crate::plan::Change;
 use crate::profiles::{
-    create_profile_with_components,
-    list_profiles,
-    profile_exists,
-    update_profile_components,
+    ComponentInspection, ProfileInspection, ProfileSort,
+    create_profile_with_components_with_progress, inspect_profile, list_profile_entries,
+    list_profiles, profile_exists, resolve_name, track_file, update_profile_components,
 };
+use crate::redact::redact_str;
+use crate::render::EnvShell;
 use crate::state::State;
-use crate::switch::{SettingsStatus, switch_to_profile};
+use crate::switch::{
+    BackupOptions, ComponentHealth, ComponentStatus, LinkStyle, LiveDrift, LiveSource,
+    SettingsStatus, SwitchEvent, SwitchOptions, SwitchStrategy, adopt_live_drift,
+    component_for_backup, current_status, decode_backup_origin, detect_live_drift, prompt_status,
+    switch_to_profile_with_progress, sync_copies,
+};
 use crate::ui::Ui;
 
 /// List all available profiles
-pub fn list(paths: &Paths, ui: &Ui) -> Result<()> {
-    let profiles = list_profiles(paths)?;
+///
+/// With `porcelain`, each profile is one line of tab-separated fields: name,
+/// `active` or `inactive`, and its components joined by commas. The format is
+/// stable; new fields are only ever appended as further columns.
+pub fn list(paths: &Paths, ui: &Ui, sort: ProfileSort, porcelain: bool) -> Result<()> {
+    let entries = list_profile_entries(paths, sort)?;
+    if porcelain {
+        for entry in &entries {
+            let components: Vec<&str> = entry
+                .components
+                .iter()
+                .flatten()
+                .map(|c| c.short_name())
+                .collect();
+            ui.println(format!(
+                "{}\t{}\t{}",
+                entry.name,
+                if entry.active { "active" } else { "inactive" },
+                components.join(",")
+            ));
+        }
+        return Ok(());
+    }
 
-    if profiles.is_empty() {
+    if entries.is_empty() {
         ui.warn("No profiles found.");
         ui.newline();
-        ui.println("Create one with:");
-        ui.println(format!("  {} add <name> --from-current", ui.bold("ccprof")));
+        ui.note("Create one with:");
+        ui.note(format!("  {} add <name> --from-current", ui.bold("ccprof")));
         return Ok(())
     }
 
-    // Get current default profile for marking
-    let state = State::read(&paths.state_file).unwrap_or_default();
-    let current = state.default_profile.as_deref();
-
     // Build table
     let mut table = ui.simple_table();
     table.set_header(vec![
         ui.header_cell(""),
         ui.header_cell("Profile"),
         ui.header_cell("Components"),
+        ui.header_cell("Last used"),
         ui.header_cell("Status"),
     ]);
 
-    for name in &profiles {
-        let is_active = Some(name.as_str()) == current;
-        let icon = if is_active { ui.icon_ok() } else { " " };
-        let status_cell = if is_active {
+    for entry in &entries {
+        let icon = if entry.active { ui.icon_ok() } else { " " };
+        let status_cell = if entry.active {
             ui.colored_cell("active", AnsiColor::Green)
         } else {
             ui.cell("-")
         };
 
-        // Read profile metadata to show components
-        let profile_dir = paths.profile_dir(name);
-        let components_display = match crate::components::ProfileMetadata::read(&profile_dir) {
-            Ok(metadata) => {
-                let mut comp_codes: Vec<&str> = metadata
-                    .managed_components
+        let components_display = match &entry.components {
+            Some(components) => {
+                let display = components
                     .iter()
                     .map(|c| c.short_name())
-                    .collect();
-                comp_codes.sort();
-                let display = comp_codes.join(",");
+                    .collect::<Vec<_>>()
+                    .join(",");
 
-                // Show migration indicator if migrated
-                if metadata.migration.is_some() {
+                // Show legacy/migration indicator
+                if entry.legacy {
+                    format!("{} (legacy)", display)
+                } else if entry.migrated {
                     format!("{} (migrated)", display)
                 } else {
                     display
                 }
             }
-            Err(_) => String::from("?"),
+            None => String::from("?"),
         };
 
         table.add_row(vec![
             ui.cell(icon),
-            ui.cell(name),
+            ui.cell(&entry.name),
             ui.cell(components_display),
+            ui.cell(
+                entry
+                    .last_used
+                    .map_or_else(|| "never".to_string(), format_time_ago),
+            ),
             status_cell,
         ]);
     }
@@ -97,13 +131,122 @@ pub fn list(paths: &Paths, ui: &Ui) -> Result<()> {
     ui.section("Profiles");
     ui.println(table.to_string());
 
+    if entries.iter().any(|entry| entry.legacy) {
+        if paths.ensure_writable().is_ok() {
+            ui.info("Legacy profiles have no metadata.json; run 'ccprof migrate' to upgrade them.");
+        } else {
+            ui.note(format!(
+                "Legacy profiles have no metadata.json and can't be migrated: {} is not writable.",
+                paths.profiles_dir.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Move ccprof's data from ~/.claude-profiles to the XDG directories
+pub fn migrate_paths(ui: &Ui) -> Result<()> {
+    let from = Paths::from_env_with_layout(Layout::Home)?;
+    let to = Paths::from_env_with_layout(Layout::Xdg)?;
+    let report = crate::migrate_paths::migrate_paths(&from, &to)?;
+
+    ui.ok(format!(
+        "Moved {} to {}",
+        from.base_dir.display(),
+        to.base_dir.display()
+    ));
+    if let Some(config_file) = &report.config_file {
+        ui.ok(format!("Moved config.toml to {}", config_file.display()));
+    }
+    if !report.relinked.is_empty() {
+        ui.ok(format!(
+            "Re-pointed {} symlink(s) to the new location",
+            report.relinked.len()
+        ));
+    }
+    ui.note(format!(
+        "Left a symlink at {}; ccprof uses the XDG layout from now on.",
+        from.base_dir.display()
+    ));
+    Ok(())
+}
+
+/// Write metadata.json for legacy profiles
+pub fn migrate(paths: &Paths, ui: &Ui) -> Result<()> {
+    let migrated = crate::profiles::migrate_legacy_profiles(paths)?;
+    if migrated.is_empty() {
+        ui.ok("No legacy profiles to migrate");
+        return Ok(());
+    }
+
+    for name in &migrated {
+        ui.println(format!("  {} {}", ui.icon_ok(), name));
+    }
+    ui.ok(format!("Migrated {} legacy profile(s)", migrated.len()));
+    Ok(())
+}
+
+/// Regenerate a profile's metadata.json from the files in it
+pub fn repair_metadata(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    let Some(repair) = crate::profiles::repair_metadata(paths, name)? else {
+        ui.ok(format!(
+            "{}'s metadata.json is fine; nothing to repair",
+            name
+        ));
+        return Ok(());
+    };
+
+    let components: Vec<&str> = repair.components.iter().map(|c| c.short_name()).collect();
+    ui.println(format!(
+        "  {} components: {}",
+        ui.icon_info(),
+        if components.is_empty() {
+            "none".to_string()
+        } else {
+            components.join(", ")
+        }
+    ));
+    ui.println(format!(
+        "  {} created_at: {} ({})",
+        ui.icon_info(),
+        repair.created_at.format("%Y-%m-%d %H:%M:%S"),
+        if repair.created_at_kept {
+            "kept from the old file"
+        } else {
+            "from the directory's modification time"
+        }
+    ));
+    if !repair.salvaged.is_empty() {
+        ui.println(format!(
+            "  {} kept from the old file: {}",
+            ui.icon_info(),
+            repair.salvaged.join(", ")
+        ));
+    }
+    ui.ok(format!("Regenerated metadata.json for '{}'", name));
     Ok(())
 }
 
 /// Show the current/active profile and settings status
-pub fn current(paths: &Paths, ui: &Ui) -> Result<()> {
-    // Read state
-    let state = State::read(&paths.state_file).unwrap_or_default();
+///
+/// With `porcelain`, one line of tab-separated fields: the selected profile
+/// and its status (see [`porcelain_status`]), or nothing when no profile is
+/// selected. The format is stable; new fields are only appended.
+pub fn current(paths: &Paths, ui: &Ui, json: bool, porcelain: bool) -> Result<()> {
+    let status = current_status(paths);
+    if porcelain {
+        if let Some(profile) = &status.selected {
+            ui.println(format!("{}\t{}", profile, porcelain_status(&status)));
+        }
+        return Ok(());
+    }
+    if json {
+        ui.println(serde_json::to_string_pretty(&status)?);
+        return Ok(());
+    }
 
     ui.section("Current Profile");
     ui.newline();
@@ -112,10 +255,19 @@ pub fn current(paths: &Paths, ui: &Ui) -> Result<()> {
     let mut table = ui.simple_table();
 
     // Show default profile from state
-    match &state.default_profile {
+    match &status.selected {
+        Some(profile) if status.selected_missing => {
+            table.add_row(vec![
+                ui.cell("Selected profile:"),
+                ui.colored_cell(
+                    format!("{} (deleted; run 'ccprof doctor --fix')", profile),
+                    AnsiColor::Red,
+                ),
+            ]);
+        }
         Some(profile) => {
             table.add_row(vec![ui.cell("Selected profile:"), ui.header_cell(profile)]); // bold
-            if let Some(updated) = &state.updated_at {
+            if let Some(updated) = &status.last_switched {
                 table.add_row(vec![ui.cell("Last switched:"), ui.cell(updated.to_string())]);
             }
         }
@@ -125,10 +277,23 @@ pub fn current(paths: &Paths, ui: &Ui) -> Result<()> {
     }
 
     // Inspect the actual settings file
-    let status = SettingsStatus::detect(&paths.claude_settings);
-    let status_cell = match &status {
+    let status_cell = match &status.settings {
         SettingsStatus::Missing => ui.colored_cell("missing", AnsiColor::Yellow),
         SettingsStatus::RegularFile => ui.cell("regular file"),
+        SettingsStatus::ManagedCopy {
+            profile,
+            drifted: false,
+        } => ui.cell(format!("copy of profile '{}'", profile)),
+        SettingsStatus::ManagedCopy {
+            profile,
+            drifted: true,
+        } => ui.colored_cell(
+            format!(
+                "copy of profile '{}' (modified, run 'ccprof sync')",
+                profile
+            ),
+            AnsiColor::Yellow,
+        ),
         SettingsStatus::Symlink { target } => ui.cell(format!("symlink → {}", target.display())),
         SettingsStatus::BrokenSymlink { target } => ui.colored_cell(
             format!("broken symlink → {}", target.display()),
@@ -136,39 +301,445 @@ pub fn current(paths: &Paths, ui: &Ui) -> Result<()> {
         ),
     };
     table.add_row(vec![ui.cell("Settings file:"), status_cell]);
+    table.add_row(vec![
+        ui.cell("Switch strategy:"),
+        ui.cell(status.strategy.to_string()),
+    ]);
+    if let Some(profile) = crate::render::shell_profile(paths) {
+        table.add_row(vec![
+            ui.cell("This shell:"),
+            ui.cell(format!(
+                "profile '{}' via {} (ccprof env)",
+                profile, CLAUDE_CONFIG_DIR_ENV
+            )),
+        ]);
+    }
 
-    if let SettingsStatus::Symlink { ref target } = status {
-        if status.is_profile_symlink(paths) {
-            if let Some(profile_name) = target
-                .strip_prefix(&paths.profiles_dir)
-                .ok()
-                .and_then(|p| p.components().next())
-                .and_then(|c| c.as_os_str().to_str()) {
-                table.add_row(vec![
-                    ui.cell("Linked profile:"),
-                    ui.colored_cell(profile_name, AnsiColor::Green),
-                ]);
+    if let SettingsStatus::Symlink { .. } = status.settings {
+        match &status.linked_profile {
+            Some(profile_name) => table.add_row(vec![
+                ui.cell("Linked profile:"),
+                ui.colored_cell(profile_name, AnsiColor::Green),
+            ]),
+            None => table.add_row(vec![
+                ui.cell(""),
+                ui.colored_cell("(symlink outside profiles dir)", AnsiColor::Yellow),
+            ]),
+        };
+    }
+    let recorded = status
+        .components
+        .iter()
+        .find(|owner| owner.component == Component::Settings)
+        .and_then(|owner| owner.recorded.as_deref());
+    if !status.in_sync {
+        table.add_row(vec![
+            ui.cell("State:"),
+            ui.colored_cell(
+                format!(
+                    "state/symlink mismatch: state records {}",
+                    recorded.map_or("no profile".to_string(), |p| format!("'{}'", p))
+                ),
+                AnsiColor::Yellow,
+            ),
+        ]);
+    }
+
+    ui.println(table.to_string());
+    if let (false, Some(linked)) = (status.in_sync, &status.linked_profile) {
+        match recorded {
+            Some(recorded) => ui.warn(format!(
+                "settings.json links into '{}', but state records '{}'; run 'ccprof use {}' to relink it or 'ccprof use {}' to switch.",
+                linked, recorded, recorded, linked
+            )),
+            None => ui.warn(format!(
+                "settings.json links into '{}', but state records no profile; run 'ccprof use {}' to record it.",
+                linked, linked
+            )),
+        }
+    }
+    ui.newline();
+
+    // Each component of the selected profile against what switching set up
+    if let Some(profile) = status
+        .selected
+        .as_ref()
+        .filter(|_| !status.profile_components.is_empty())
+    {
+        let mut health_table = ui.simple_table();
+        health_table.set_header(vec![ui.header_cell("Component"), ui.header_cell("Status")]);
+        let mut broken = false;
+        for entry in &status.profile_components {
+            let cell = match &entry.health {
+                ComponentHealth::Live => ui.colored_cell("ok", AnsiColor::Green),
+                health @ (ComponentHealth::Missing | ComponentHealth::Broken { .. }) => {
+                    ui.colored_cell(health.label(), AnsiColor::Red)
+                }
+                health => ui.colored_cell(health.label(), AnsiColor::Yellow),
+            };
+            broken |= !entry.health.is_live();
+            health_table.add_row(vec![ui.cell(entry.component.display_name()), cell]);
+        }
+        ui.section(format!("Profile '{}'", profile));
+        ui.println(health_table.to_string());
+        if broken {
+            ui.warn(format!(
+                "Profile '{}' is not fully live; run 'ccprof use {}' to relink it.",
+                profile, profile
+            ));
+        }
+        ui.newline();
+    }
+
+    // Per-component view: what each live component is linked to vs. what state says
+    let mut comp_table = ui.simple_table();
+    comp_table.set_header(vec![
+        ui.header_cell("Component"),
+        ui.header_cell("Linked to"),
+        ui.header_cell("State"),
+    ]);
+    let mut mismatched = false;
+    for owner in &status.components {
+        let consistent = owner.is_consistent();
+        mismatched |= !consistent;
+        comp_table.add_row(vec![
+            ui.cell(owner.component.display_name()),
+            if consistent {
+                ui.cell(owner.live.label())
+            } else {
+                ui.colored_cell(owner.live.label(), AnsiColor::Yellow)
+            },
+            ui.cell(
+                owner
+                    .recorded
+                    .as_ref()
+                    .map_or("-".to_string(), |p| format!("profile '{}'", p)),
+            ),
+        ]);
+    }
+    ui.section("Components");
+    ui.println(comp_table.to_string());
+    if mismatched {
+        ui.warn("Some components don't match state; run 'ccprof doctor' for details.");
+    }
+    Ok(())
+}
+
+/// Snippet for starship.toml printed by `ccprof prompt --print-config`
+const STARSHIP_CONFIG: &str = r#"# Show the selected ccprof profile; "!" means ~/.claude/settings.json is out of sync
+[custom.ccprof]
+command = "ccprof prompt --starship"
+when = true
+shell = ["sh"]
+format = "[$symbol$output]($style) "
+symbol = "ccprof:"
+style = "bold purple"
+description = "The selected Claude Code profile"
+"#;
+
+/// Status word of `current --porcelain`: `deleted` (the profile is gone),
+/// `out-of-sync` (settings lead into another profile), `degraded` (a component
+/// isn't live) or `ok`
+fn porcelain_status(status: &crate::switch::CurrentStatus) -> &'static str {
+    if status.selected_missing {
+        "deleted"
+    } else if !status.in_sync {
+        "out-of-sync"
+    } else if status
+        .profile_components
+        .iter()
+        .any(|c| !c.health.is_live())
+    {
+        "degraded"
+    } else {
+        "ok"
+    }
+}
+
+/// Print the selected profile for a shell prompt
+///
+/// Prints nothing when no profile is selected, so the prompt segment hides.
+/// With `starship`, the output is a single token: the name, with `!` appended
+/// when the live settings don't belong to it.
+pub fn prompt(paths: &Paths, ui: &Ui, starship: bool, print_config: bool) -> Result<()> {
+    if print_config {
+        ui.println(STARSHIP_CONFIG.trim_end());
+        return Ok(());
+    }
+    let Some(status) = prompt_status(paths) else {
+        return Ok(());
+    };
+    match (status.in_sync, starship) {
+        (true, _) => ui.println(&status.profile),
+        (false, true) => ui.println(format!("{}!", status.profile)),
+        (false, false) => ui.println(format!("{} (out of sync)", status.profile)),
+    }
+    Ok(())
+}
+
+/// Print shell lines pointing `CLAUDE_CONFIG_DIR` at a rendered profile
+///
+/// Only the export goes to stdout, for `eval "$(ccprof env work)"`.
+pub fn env(paths: &Paths, profile: &str, shell: Option<EnvShell>, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let (dir, rendered) = crate::render::render_profile(paths, profile)?;
+    if rendered {
+        ui.info(format!(
+            "Rendered profile '{}' in {}",
+            profile,
+            dir.display()
+        ));
+    }
+    let shell = shell.unwrap_or_else(EnvShell::detect);
+    ui.println(shell.export(CLAUDE_CONFIG_DIR_ENV, &dir.to_string_lossy()));
+    // The profile's own exports, from its env.sh
+    for (name, value) in profile_env_vars(paths, profile, ui)? {
+        ui.println(shell.export(&name, &value));
+    }
+    Ok(())
+}
+
+/// Variables of profile `name`'s env.sh, warning about lines that were skipped
+fn profile_env_vars(paths: &Paths, name: &str, ui: &Ui) -> Result<Vec<(String, String)>> {
+    let Some(env) = crate::env_file::read_profile_env(paths, name)? else {
+        return Ok(Vec::new());
+    };
+    for warning in &env.warnings {
+        ui.warn(format!("env.sh {}", warning));
+    }
+    Ok(env.vars)
+}
+
+/// One row of the `components` matrix
+#[derive(Debug, serde::Serialize)]
+struct ComponentRow {
+    component: &'static str,
+    path: std::path::PathBuf,
+    exists: bool,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<std::path::PathBuf>,
+    /// Profile name -> how it manages the component ("full", "shared", "partial")
+    managed_by: std::collections::BTreeMap<String, &'static str>,
+}
+
+/// Show which components exist in ~/.claude and which profiles track them
+pub fn components(paths: &Paths, ui: &Ui, json: bool) -> Result<()> {
+    let profiles = list_profiles(paths)?;
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let metadata: Vec<_> = profiles
+        .iter()
+        .map(|name| crate::components::ProfileMetadata::read(&paths.profile_dir(name)).ok())
+        .collect();
+
+    let rows: Vec<ComponentRow> = Component::all()
+        .into_iter()
+        .map(|component| {
+            let path = component.source_path(paths);
+            let status = ComponentStatus::detect_with_state(&path, component, &state);
+            let managed_by = profiles
+                .iter()
+                .zip(&metadata)
+                .filter_map(|(name, meta)| {
+                    let meta = meta.as_ref()?;
+                    if !meta.managed_components.contains(&component) {
+                        return None;
+                    }
+                    let how = if meta.shared_source(component).is_some() {
+                        "shared"
+                    } else if meta.tracked_files_for(component).is_some() {
+                        "partial"
+                    } else {
+                        "full"
+                    };
+                    Some((name.clone(), how))
+                })
+                .collect();
+
+            ComponentRow {
+                component: component.short_name(),
+                exists: path.exists(),
+                status: status.label(),
+                target: status.target().map(Path::to_path_buf),
+                path,
+                managed_by,
             }
+        })
+        .collect();
+
+    if json {
+        ui.println(serde_json::to_string_pretty(&serde_json::json!({
+            "profiles": profiles,
+            "components": rows,
+        }))?);
+        return Ok(());
+    }
+
+    let mut table = ui.table();
+    let mut header = vec![
+        ui.header_cell("Component"),
+        ui.header_cell("Exists"),
+        ui.header_cell("Live status"),
+    ];
+    header.extend(profiles.iter().map(|name| ui.header_cell(name)));
+    table.set_header(header);
+
+    for row in &rows {
+        let exists = if row.exists {
+            ui.colored_cell(ui.icon_ok(), AnsiColor::Green)
         } else {
-            table.add_row(vec![ui.cell(""), ui.colored_cell("(symlink outside profiles dir)", AnsiColor::Yellow)]);
-        }
+            ui.colored_cell(ui.icon_err(), AnsiColor::Red)
+        };
+        let status = match (row.status, &row.target) {
+            ("broken symlink", _) => ui.colored_cell(row.status, AnsiColor::Red),
+            (_, Some(target)) => ui.cell(format!("{} → {}", row.status, target.display())),
+            _ => ui.cell(row.status),
+        };
+
+        let mut cells = vec![ui.cell(row.component), exists, status];
+        cells.extend(profiles.iter().map(|name| match row.managed_by.get(name) {
+            Some(&"full") => ui.colored_cell(ui.icon_ok(), AnsiColor::Green),
+            Some(how) => ui.colored_cell(*how, AnsiColor::Cyan),
+            None => ui.cell("-"),
+        }));
+        table.add_row(cells);
     }
 
+    ui.section("Components");
     ui.println(table.to_string());
     Ok(())
 }
 
-/// Show detailed information about a profile
-pub fn inspect(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
-    if !profile_exists(paths, name) {
+/// Print the content digest of a profile, or of one of its components
+///
+/// The profile digest covers its directory without ccprof's own files
+/// (metadata, notes, generated settings), so it only changes with content.
+pub fn hash(paths: &Paths, name: &str, component: Option<&str>, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    let profile_dir = paths.profile_dir(name);
+
+    let Some(component) = component else {
+        let digest = crate::fs_utils::hash_dir_excluding(
+            &profile_dir,
+            &crate::components::PROFILE_OWN_FILES,
+        )?;
+        ui.println(format!("{}  {}", digest, name));
+        return Ok(());
+    };
+
+    let component: Component = component.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid component name: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
+            component
+        )
+    })?;
+    let metadata = crate::components::ProfileMetadata::read(&profile_dir)?;
+    let path = metadata.component_path(paths, name, component);
+    if !path.exists() {
         bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
+            "Profile '{}' has no {}.\nHint: Use 'ccprof inspect {}' to see its components.",
+            name,
+            component.display_name(),
             name
         );
     }
+    let digest = crate::fs_utils::hash_path(&path)?;
+    ui.println(format!("{}  {}/{}", digest, name, component.short_name()));
+    Ok(())
+}
 
-    let profile_dir = paths.profile_dir(name);
-    let metadata = crate::components::ProfileMetadata::read(&profile_dir)?;
+/// Show an overview of profiles, backups, the active profile and switches
+pub fn stats(paths: &Paths, ui: &Ui, json: bool) -> Result<()> {
+    let stats = crate::stats::collect_stats(paths)?;
+    if json {
+        ui.println(serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    let when = |time: Option<chrono::DateTime<chrono::Utc>>| match time {
+        Some(time) => format!(
+            "{} ({})",
+            time.format("%Y-%m-%d %H:%M:%S"),
+            format_time_ago(time)
+        ),
+        None => "-".to_string(),
+    };
+
+    ui.section("Stats");
+    ui.newline();
+    let mut table = ui.simple_table();
+    table.add_row(vec![
+        ui.cell("Profiles:"),
+        ui.cell(stats.profiles.to_string()),
+    ]);
+    table.add_row(vec![
+        ui.cell("Disk used:"),
+        ui.cell(format_bytes(stats.profiles_size)),
+    ]);
+    let active = match &stats.active_profile {
+        Some(name) => match stats.active_since {
+            Some(since) => format!("{}, since {}", name, when(Some(since))),
+            None => name.clone(),
+        },
+        None => "none".to_string(),
+    };
+    table.add_row(vec![ui.cell("Active:"), ui.cell(active)]);
+    table.add_row(vec![
+        ui.cell("Switches:"),
+        ui.cell(stats.switches.to_string()),
+    ]);
+    table.add_row(vec![
+        ui.cell("Oldest backup:"),
+        ui.cell(when(stats.oldest_backup)),
+    ]);
+    table.add_row(vec![
+        ui.cell("Newest backup:"),
+        ui.cell(when(stats.newest_backup)),
+    ]);
+    ui.println(table.to_string());
+    ui.newline();
+
+    let mut backups = ui.table();
+    backups.set_header(vec![
+        ui.header_cell("Component"),
+        ui.header_cell("Backups"),
+        ui.header_cell("Size"),
+    ]);
+    for entry in &stats.backups {
+        backups.add_row(vec![
+            ui.cell(entry.component),
+            ui.number_cell(entry.count.to_string()),
+            ui.number_cell(format_bytes(entry.size)),
+        ]);
+    }
+    ui.println(backups.to_string());
+    Ok(())
+}
+
+/// Show detailed information about a profile
+///
+/// Symlinks inside directory components are counted as `symlinks` says.
+pub fn inspect(
+    paths: &Paths,
+    name: &str,
+    ui: &Ui,
+    json: bool,
+    compare_active: bool,
+    symlinks: SymlinkMode,
+) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    let inspection = inspect_profile(paths, name, INSPECT_LARGEST_FILES, symlinks)?;
+    if json {
+        return inspect_json(&inspection, ui);
+    }
+    let ProfileInspection { metadata, .. } = &inspection;
+    for component in &inspection.components {
+        for warning in component.contents.iter().flat_map(|s| &s.warnings) {
+            ui.warn(warning);
+        }
+    }
 
     ui.section(format!("Profile: {}", name));
     ui.newline();
@@ -185,6 +756,34 @@ pub fn inspect(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
 
     table.add_row(vec![ui.cell("Version:"), ui.cell(&metadata.version)]);
 
+    if let Some(claude_version) = &metadata.claude_version {
+        table.add_row(vec![ui.cell("Claude Code:"), ui.cell(claude_version)]);
+    }
+
+    let last_used = match inspection.last_used {
+        Some(time) => format!(
+            "{} ({})",
+            format_time_ago(time),
+            time.format("%Y-%m-%d %H:%M:%S")
+        ),
+        None => "never".to_string(),
+    };
+    table.add_row(vec![ui.cell("Last used:"), ui.cell(last_used)]);
+
+    if metadata.extends.is_some() {
+        let extends = match &inspection.extends_error {
+            None => ui.cell(inspection.extends.join(" → ")),
+            Some(e) => ui.colored_cell(e.lines().next().unwrap_or_default(), AnsiColor::Red),
+        };
+        table.add_row(vec![ui.cell("Extends:"), extends]);
+    }
+    if !inspection.extended_by.is_empty() {
+        table.add_row(vec![
+            ui.cell("Extended by:"),
+            ui.cell(inspection.extended_by.join(", ")),
+        ]);
+    }
+
     if let Some(migration) = &metadata.migration {
         table.add_row(vec![
             ui.cell("Migration:"),
@@ -207,109 +806,421 @@ pub fn inspect(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
         ui.header_cell("Component"),
         ui.header_cell("Path"),
         ui.header_cell("Size"),
+        ui.header_cell("Contents"),
     ]);
 
-    for component in &metadata.managed_components {
-        let path = component.profile_path(paths, name);
-
-        if path.exists() {
-            let size_str = calculate_size(&path)?;
+    let rows: Vec<_> = inspection
+        .components
+        .iter()
+        .map(|c| {
+            let label = match &c.shared {
+                Some(shared) => format!("{} (shared: {})", c.component.display_name(), shared),
+                None => c.component.display_name().to_string(),
+            };
+            (label, c, c.contents.as_ref().map(describe_contents))
+        })
+        .collect();
+    // Component, size and contents columns plus cell padding
+    let label_width = rows
+        .iter()
+        .map(|(l, _, _)| l.chars().count())
+        .max()
+        .unwrap_or(0);
+    let contents_width = rows
+        .iter()
+        .filter_map(|(_, _, contents)| contents.as_ref())
+        .flat_map(|c| c.lines())
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let reserved = label_width + INSPECT_SIZE_WIDTH + contents_width + 9;
+
+    for (component_label, component, contents) in &rows {
+        let path_cell = ui.cell(ui.display_path(&component.path, reserved));
+        if let Some(bytes) = component.bytes {
             comp_table.add_row(vec![
-                ui.cell(component.display_name()),
-                ui.cell(format!("{}", path.display())),
-                ui.cell(size_str),
+                ui.cell(component_label),
+                path_cell,
+                ui.number_cell(format_bytes(bytes)),
+                ui.cell(contents.as_deref().unwrap_or("")),
             ]);
         } else {
             comp_table.add_row(vec![
-                ui.cell(component.display_name()),
-                ui.cell(format!("{}", path.display())),
+                ui.cell(component_label),
+                path_cell,
                 ui.colored_cell("missing", AnsiColor::Red),
+                ui.cell(""),
             ]);
         }
     }
 
     ui.println(comp_table.to_string());
 
+    // Show individually tracked files for composed components
+    let mut composed: Vec<_> = metadata
+        .tracked_files
+        .iter()
+        .filter(|(_, files)| !files.is_empty())
+        .collect();
+    if !composed.is_empty() {
+        composed.sort_by_key(|(c, _)| c.short_name());
+        ui.newline();
+        ui.section("Tracked Files");
+        ui.newline();
+        for (component, files) in composed {
+            ui.println(format!(
+                "  {} {} {}",
+                component.display_name(),
+                ui.dim("(rest from shared base):"),
+                files.join(", ")
+            ));
+        }
+    }
+
+    if let Some((lines, more)) = inspection
+        .notes
+        .as_deref()
+        .map(|notes| notes_preview(notes, INSPECT_NOTES_LINES))
+    {
+        ui.newline();
+        ui.section("Notes");
+        ui.newline();
+        for line in lines {
+            ui.println(format!("  {}", ui.redactor.redact_line(&line)));
+        }
+        if more > 0 {
+            ui.println(format!(
+                "  {}",
+                ui.dim(format!(
+                    "… {} more line(s), see 'ccprof edit {} --notes'",
+                    more, name
+                ))
+            ));
+        }
+    }
+
+    if compare_active {
+        ui.newline();
+        print_active_comparison(paths, name, ui)?;
+    }
+
     Ok(())
 }
 
-/// Calculate human-readable size of a file or directory
-fn calculate_size(path: &Path) -> Result<String> {
-    use std::fs;
+/// What switching from one profile to another does to a component
+#[derive(Debug, PartialEq, Eq)]
+enum ComponentChange {
+    Identical,
+    /// Number of settings keys or files that differ
+    Differs(usize),
+    /// Only the new profile manages it
+    Added,
+    /// Only the old profile manages it
+    Dropped,
+}
 
-    let size = if path.is_file() {
-        fs::metadata(path)
-            .with_context(|| format!("Failed to read metadata for {}", path.display()))? 
-            .len()
-    } else if path.is_dir() {
-        crate::fs_utils::dir_size(path)
-            .with_context(|| format!("Failed to calculate size for {}", path.display()))? 
-    } else {
-        0
+/// How each component managed by `from` or `to` changes when switching
+/// between them, summarized
+///
+/// Settings are compared as resolved (with inherited keys) and directories
+/// file by file. A component whose content is missing counts as unmanaged.
+fn compare_profiles(
+    paths: &Paths,
+    from: &str,
+    to: &str,
+) -> Result<Vec<(Component, ComponentChange)>> {
+    let from_meta = crate::components::ProfileMetadata::read(&paths.profile_dir(from))?;
+    let to_meta = crate::components::ProfileMetadata::read(&paths.profile_dir(to))?;
+    let content = |metadata: &crate::components::ProfileMetadata, name: &str, c: Component| {
+        let path = metadata.component_path(paths, name, c);
+        (metadata.managed_components.contains(&c) && path.exists()).then_some(path)
     };
 
-    Ok(format_bytes(size))
+    let mut changes = Vec::new();
+    for component in Component::all() {
+        let change = match (
+            content(&from_meta, from, component),
+            content(&to_meta, to, component),
+        ) {
+            (None, None) => continue,
+            (None, Some(_)) => ComponentChange::Added,
+            (Some(_), None) => ComponentChange::Dropped,
+            (Some(_), Some(_)) if component == Component::Settings => {
+                let mut differences = Vec::new();
+                compare_json_values(
+                    &crate::inheritance::resolve_settings(paths, from)?,
+                    &crate::inheritance::resolve_settings(paths, to)?,
+                    "",
+                    &mut differences,
+                );
+                match differences.len() {
+                    0 => ComponentChange::Identical,
+                    n => ComponentChange::Differs(n),
+                }
+            }
+            (Some(from_path), Some(to_path)) if component.is_file() => {
+                if crate::fs_utils::same_content(&from_path, &to_path) {
+                    ComponentChange::Identical
+                } else {
+                    ComponentChange::Differs(1)
+                }
+            }
+            (Some(from_path), Some(to_path)) => {
+                let comparison = crate::fs_utils::compare_dirs(&from_path, &to_path)?;
+                match comparison.only_in_left.len()
+                    + comparison.only_in_right.len()
+                    + comparison.changed.len()
+                {
+                    0 => ComponentChange::Identical,
+                    n => ComponentChange::Differs(n),
+                }
+            }
+        };
+        changes.push((component, change));
+    }
+    Ok(changes)
 }
 
-/// Format bytes as human-readable string
-fn format_bytes(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
+/// `inspect --compare-active`: one line per component that switching changes
+fn print_active_comparison(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let Some(active) = state
+        .default_profile
+        .filter(|active| profile_exists(paths, active))
+    else {
+        ui.info("No active profile to compare with");
+        return Ok(());
+    };
+    if active == name {
+        ui.info(format!("'{}' is the active profile", name));
+        return Ok(());
+    }
 
-    if bytes >= GB {
-        format!("{:.2} GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.2} MB", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
+    ui.section(format!("Compared with active profile '{}'", active));
+    ui.newline();
+    for (component, change) in compare_profiles(paths, &active, name)? {
+        let unit = if component == Component::Settings {
+            "key"
+        } else {
+            "file"
+        };
+        let summary = match change {
+            ComponentChange::Identical => ui.dim("identical"),
+            ComponentChange::Differs(n) => ui.colored(
+                if n == 1 {
+                    format!("1 {} differs", unit)
+                } else {
+                    format!("{} {}s differ", n, unit)
+                },
+                AnsiColor::Yellow,
+            ),
+            ComponentChange::Added => ui.colored("will be added", AnsiColor::Green),
+            ComponentChange::Dropped => ui.colored("will stop being managed", AnsiColor::Red),
+        };
+        ui.println(format!("  {}: {}", component.display_name(), summary));
+    }
+    Ok(())
+}
+
+/// `inspect --json`: the profile's metadata and what its components hold
+fn inspect_json(inspection: &ProfileInspection, ui: &Ui) -> Result<()> {
+    let metadata = &inspection.metadata;
+    let components: Vec<_> = inspection
+        .components
+        .iter()
+        .map(
+            |ComponentInspection {
+                 component,
+                 path,
+                 shared,
+                 bytes,
+                 contents,
+             }| {
+                let mut entry = serde_json::json!({
+                    "component": component.short_name(),
+                    "path": path,
+                    "shared": shared,
+                    "exists": bytes.is_some(),
+                });
+                if let Some(bytes) = bytes {
+                    entry["bytes"] = (*bytes).into();
+                }
+                if let Some(stats) = contents {
+                    entry["files"] = stats.files.into();
+                    entry["dirs"] = stats.dirs.into();
+                    entry["largest"] = stats
+                        .largest
+                        .iter()
+                        .map(|(file, bytes)| serde_json::json!({"path": file, "bytes": bytes}))
+                        .collect();
+                    if !stats.warnings.is_empty() {
+                        entry["warnings"] = stats.warnings.clone().into();
+                    }
+                }
+                entry
+            },
+        )
+        .collect();
+
+    ui.println(serde_json::to_string_pretty(&serde_json::json!({
+        "name": inspection.name,
+        "created_at": metadata.created_at,
+        "updated_at": metadata.updated_at,
+        "version": metadata.version,
+        "claude_version": metadata.claude_version,
+        "extends": metadata.extends,
+        "last_used": inspection.last_used,
+        "components": components,
+        "tracked_files": metadata.tracked_files,
+    }))?);
+    Ok(())
+}
+
+/// "37 files, 2 dirs" and the largest file, for the Contents column
+fn describe_contents(stats: &DirStats) -> String {
+    let mut text = format!(
+        "{} file{}, {} dir{}",
+        stats.files,
+        if stats.files == 1 { "" } else { "s" },
+        stats.dirs,
+        if stats.dirs == 1 { "" } else { "s" }
+    );
+    if let Some((file, bytes)) = stats.largest.first() {
+        text.push_str(&format!(
+            "\nlargest: {} ({})",
+            file.display(),
+            format_bytes(*bytes)
+        ));
+    }
+    text
+}
+
+/// Largest files `inspect` lists for a directory component
+const INSPECT_LARGEST_FILES: usize = 3;
+
+/// Lines of NOTES.md shown by `inspect`
+const INSPECT_NOTES_LINES: usize = 5;
+
+/// Widest size `inspect` shows, e.g. "1023.99 KB" or "missing"
+const INSPECT_SIZE_WIDTH: usize = 10;
+
+/// First `max` lines of the notes and how many more there are
+fn notes_preview(notes: &str, max: usize) -> (Vec<String>, usize) {
+    let lines: Vec<String> = notes.lines().map(str::to_string).collect();
+    let more = lines.len().saturating_sub(max);
+    (lines.into_iter().take(max).collect(), more)
+}
+
+/// Calculate human-readable size of a file or directory
+fn calculate_size(path: &Path) -> Result<String> {
+    use std::fs;
+
+    let size = if path.is_file() {
+        fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata for {}", path.display()))? 
+            .len()
+    } else if path.is_dir() {
+        crate::fs_utils::dir_size(path)
+            .with_context(|| format!("Failed to calculate size for {}", path.display()))? 
+    } else {
+        0
+    };
+
+    Ok(format_bytes(size))
+}
+
+/// Format bytes as human-readable string
+/// Format a past timestamp as e.g. "3 months ago"
+fn format_time_ago(time: chrono::DateTime<chrono::Utc>) -> String {
+    let elapsed = chrono::Utc::now().signed_duration_since(time);
+    let (count, unit) = if elapsed.num_days() >= 365 {
+        (elapsed.num_days() / 365, "year")
+    } else if elapsed.num_days() >= 30 {
+        (elapsed.num_days() / 30, "month")
+    } else if elapsed.num_days() >= 1 {
+        (elapsed.num_days(), "day")
+    } else if elapsed.num_hours() >= 1 {
+        (elapsed.num_hours(), "hour")
+    } else if elapsed.num_minutes() >= 1 {
+        (elapsed.num_minutes(), "minute")
+    } else {
+        return "just now".to_string();
+    };
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const KB: u64 = 1024;
+    const MB: u64 = KB * 1024;
+    const GB: u64 = MB * 1024;
+
+    if bytes >= GB {
+        format!("{:.2} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{:.2} MB", bytes as f64 / MB as f64)
+    } else if bytes >= KB {
         format!("{:.2} KB", bytes as f64 / KB as f64)
     } else {
         format!("{} B", bytes)
     }
 }
 
-/// Interactive component selection for profile creation
-pub fn select_components(paths: &Paths) -> Result<HashSet<Component>> {
-    let all_components = Component::all();
+/// Directory copies and backups with at least this many files get a progress bar
+const BACKUP_PROGRESS_MIN_FILES: u64 = 50;
 
-    // Build display options with availability indicators
-    let options: Vec<String> = all_components
+/// Progress bar for copying `sources`, hidden when there are only a few files
+fn copy_progress(ui: &Ui, message: &str, sources: &[PathBuf]) -> ProgressBar {
+    let files: u64 = sources
+        .iter()
+        .map(|p| crate::fs_utils::count_files(p))
+        .sum();
+    if files < BACKUP_PROGRESS_MIN_FILES {
+        return ProgressBar::hidden();
+    }
+    let bytes = sources
         .iter()
+        .map(|p| {
+            if p.is_dir() {
+                crate::fs_utils::dir_size_lenient(p).0
+            } else {
+                p.metadata().map(|m| m.len()).unwrap_or(0)
+            }
+        })
+        .sum();
+    ui.copy_progress(message, files, bytes)
+}
+
+/// Interactive component selection for profile creation
+pub fn select_components(
+    paths: &Paths,
+    interaction: &dyn Interaction,
+) -> Result<HashSet<Component>> {
+    // Build display options with availability indicators; components that
+    // exist are selected by default
+    let choices: Vec<ComponentChoice> = Component::all()
+        .into_iter()
         .map(|c| {
-            let path = c.source_path(paths);
-            let exists = path.exists();
+            let exists = c.source_path(paths).exists();
             let indicator = if exists { "✓" } else { "✗" };
             let availability = if exists { "" } else { " (not found)" };
-            format!("{} {}{}", indicator, c.display_name(), availability)
+            ComponentChoice {
+                component: c,
+                label: format!("{} {}{}", indicator, c.display_name(), availability),
+                selected: exists,
+            }
         })
         .collect();
 
-    // Default: select all components that exist
-    let defaults: Vec<usize> = all_components
-        .iter()
-        .enumerate()
-        .filter(|(_, c)| c.source_path(paths).exists())
-        .map(|(i, _)| i)
-        .collect();
-
-    let selected_indices = MultiSelect::new(
+    let selected = interaction.select_components(
         "Which components should this profile manage?",
-        options.clone(),
-    )
-    .with_default(&defaults)
-    .with_help_message("Space to select, Enter to confirm")
-    .prompt()
-    .context("Component selection cancelled")?;
-
-    let selected: HashSet<Component> = selected_indices
-        .into_iter()
-        .filter_map(|selected_str| {
-            options
-                .iter()
-                .position(|opt| *opt == selected_str)
-                .map(|idx| all_components[idx])
-        })
-        .collect();
+        "Space to select, Enter to confirm",
+        &choices,
+    )?;
 
     if selected.is_empty() {
         bail!(
@@ -320,132 +1231,829 @@ pub fn select_components(paths: &Paths) -> Result<HashSet<Component>> {
     Ok(selected)
 }
 
-/// Add a new profile from current settings
-pub fn add(paths: &Paths, name: &str, ui: &Ui, components_arg: Option<Vec<String>>) -> Result<()> {
-    paths.ensure_dirs()?;
+/// Where `add` takes a new profile's content from
+#[derive(Debug, Clone)]
+pub enum ProfileSource<'a> {
+    /// The live ~/.claude, with these components (prompt if `None`)
+    Current(Option<Vec<String>>),
+    /// A saved template
+    Template(&'a str),
+    /// settings.json read from stdin, plus these components from ~/.claude
+    Stdin(Option<Vec<String>>),
+    /// Nothing: empty settings, only useful when extending another profile
+    Empty,
+}
 
-    if profile_exists(paths, name) {
+/// Read the settings.json piped in for `add --from-stdin`
+///
+/// A terminal or empty input is refused, rather than waiting for input or
+/// creating empty settings.
+fn read_stdin_settings() -> Result<String> {
+    let mut stdin = std::io::stdin();
+    if stdin.is_terminal() {
         bail!(
-            "Profile '{}' already exists.\nHint: Use 'ccprof edit {}' to modify it, or choose a different name.",
-            name,
-            name
+            "--from-stdin reads settings.json from a pipe, but stdin is a terminal.\nHint: Pipe it in, e.g. 'generate-settings | ccprof add <name> --from-stdin'."
         );
     }
+    let mut settings = String::new();
+    stdin
+        .read_to_string(&mut settings)
+        .context("Failed to read settings from stdin")?;
+    if settings.trim().is_empty() {
+        bail!(
+            "Nothing was piped in.\nHint: --from-stdin expects the content of settings.json on stdin."
+        );
+    }
+    let value = crate::jsonc::from_str(&settings).map_err(|source| Error::InvalidJson {
+        path: PathBuf::from("<stdin>"),
+        source,
+    })?;
+    if !value.is_object() {
+        bail!("The settings on stdin must be a JSON object");
+    }
+    Ok(settings)
+}
+
+/// Warn about what a copy skipped or changed
+fn warn_copies(report: &CopyReport, ui: &Ui) {
+    for warning in report.skipped.iter().chain(&report.warnings) {
+        ui.warn(warning);
+    }
+}
+
+/// Warn about what the copies into a new profile left out or copied as links
+fn warn_profile_copies(report: &CopyReport, ui: &Ui) {
+    warn_copies(report, ui);
+    if !report.skipped.is_empty() {
+        ui.note("  The profile was created without them; use --strict to stop instead.");
+    }
+}
+
+/// Add a new profile from current settings, stdin, a template, or on top of another profile
+///
+/// Components taken from ~/.claude are copied as `copy` says.
+///
+/// ```no_run
+/// use std::rc::Rc;
+/// use ccprof::commands::{self, ProfileSource};
+/// use ccprof::fs_utils::CopyOptions;
+/// use ccprof::interaction::{Answer, Headless};
+/// use ccprof::{paths::Paths, ui::Ui};
+///
+/// let headless = Rc::new(Headless::new([Answer::Default]));
+/// let ui = Ui::default().with_interaction(headless.clone());
+/// let source = ProfileSource::Current(None);
+/// commands::add(&Paths::new()?, "work", &ui, source, None, CopyOptions::default())?;
+/// for (level, message) in headless.messages() {
+///     println!("{:?}: {}", level, message);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn add(
+    paths: &Paths,
+    name: &str,
+    ui: &Ui,
+    source: ProfileSource,
+    extends: Option<&str>,
+    copy: CopyOptions,
+) -> Result<()> {
+    paths.ensure_dirs()?;
+
+    if profile_exists(paths, name) {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        }
+        .into());
+    }
+    let resolved_base = extends
+        .map(|base| resolve_name(paths, base, ui.exact_names))
+        .transpose()?;
+    let extends = resolved_base.as_deref();
 
     // Determine which components to include
-    let components = if let Some(comp_names) = components_arg {
-        // Non-interactive mode: parse component names
-        let mut selected = HashSet::new();
-        for comp_name in comp_names {
-            match comp_name.parse::<Component>() {
-                Ok(c) => {
-                    selected.insert(c);
-                }
-                Err(_) => {
-                    bail!(
-                        "Invalid component name: '{}'\nHint: Valid components are settings, agents, hooks, commands",
-                        comp_name
-                    );
-                }
+    let components = match &source {
+        ProfileSource::Template(template) => {
+            let (components, report) =
+                crate::templates::create_profile_from_template(paths, name, template)?;
+            warn_copies(&report, ui);
+            components
+        }
+        ProfileSource::Empty => {
+            if extends.is_none() {
+                bail!("A profile without --from-current or --template must use --extends");
             }
+            crate::profiles::create_empty_profile(paths, name)?
         }
-        selected
-    } else {
-        // Interactive mode: use multi-select UI
-        select_components(paths)?
-    };
+        ProfileSource::Stdin(components_arg) => {
+            let settings = read_stdin_settings()?;
+            let components = components_arg
+                .iter()
+                .flatten()
+                .map(|c| parse_component(c))
+                .collect::<Result<HashSet<_>>>()?;
+            let sources: Vec<_> = components.iter().map(|c| c.source_path(paths)).collect();
+            let progress = copy_progress(ui, "Copying components", &sources);
+            let (components, report) = crate::profiles::create_profile_from_settings(
+                paths,
+                name,
+                &settings,
+                components,
+                copy,
+                &mut |bytes| progress.inc(bytes),
+            )?;
+            progress.finish_and_clear();
+            warn_profile_copies(&report, ui);
+            warn_settings_issues(&paths.profile_settings(name), ui);
+            components
+        }
+        ProfileSource::Current(components_arg) => {
+            let components = if let Some(comp_names) = components_arg {
+                // Non-interactive mode: parse component names
+                let mut selected = HashSet::new();
+                for comp_name in comp_names {
+                    match comp_name.parse::<Component>() {
+                        Ok(c) => {
+                            selected.insert(c);
+                        }
+                        Err(_) => {
+                            bail!(
+                                "Invalid component name: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
+                                comp_name
+                            );
+                        }
+                    }
+                }
+                selected
+            } else {
+                // Interactive mode: use multi-select UI
+                select_components(paths, ui)?
+            };
 
-    // Create profile with selected components
-    create_profile_with_components(paths, name, components.clone())?;
+            // Create profile with selected components
+            let sources: Vec<_> = components.iter().map(|c| c.source_path(paths)).collect();
+            let progress = copy_progress(ui, "Copying components", &sources);
+            let report = create_profile_with_components_with_progress(
+                paths,
+                name,
+                components.clone(),
+                copy,
+                &mut |bytes| progress.inc(bytes),
+            )?;
+            progress.finish_and_clear();
+            warn_profile_copies(&report, ui);
+            components
+        }
+    };
 
-    ui.ok(format!("Created profile '{}'", name));
+    if let Some(base) = extends {
+        crate::inheritance::set_extends(paths, name, Some(base))?;
+    }
+    crate::profiles::record_claude_version(paths, name)?;
+
+    match (&source, extends) {
+        (ProfileSource::Template(template), _) => ui.ok(format!(
+            "Created profile '{}' from template '{}'",
+            name, template
+        )),
+        (_, Some(base)) => ui.ok(format!("Created profile '{}' extending '{}'", name, base)),
+        _ => ui.ok(format!("Created profile '{}'", name)),
+    }
+    let mut created: Vec<_> = components.iter().map(|c| c.short_name()).collect();
+    created.sort();
+    ui.event("created", json!({"profile": name, "components": created}));
     ui.newline();
-    ui.println("Included components:");
+    ui.note("Included components:");
     for component in &components {
-        ui.println(format!("  {} {}", ui.icon_ok(), component.display_name()));
+        ui.note(format!("  {} {}", ui.icon_ok(), component.display_name()));
     }
     ui.newline();
-    ui.println("To activate it:");
-    ui.println(format!("  ccprof use {}", name));
+    ui.note("To activate it:");
+    ui.note(format!("  ccprof use {}", name));
 
     Ok(())
 }
 
-/// Switch to a profile
-pub fn use_profile(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
+/// Pin `profile` to the project around the current directory with a `.ccprof` file
+pub fn local_set(paths: &Paths, profile: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let cwd = std::env::current_dir().context("Failed to get the current directory")?;
+    let path = crate::local::write_marker(&crate::local::marker_dir(&cwd), profile)?;
+    ui.ok(format!("Wrote {} (profile '{}')", path.display(), profile));
+    ui.note("Run 'ccprof local check' from a shell cd hook to be reminded when it isn't active.");
+    Ok(())
+}
+
+/// Compare the nearest `.ccprof` with the selected profile
+///
+/// Prints nothing when there is no marker or it matches. Otherwise prints a
+/// reminder, or with `auto` switches, unless that would replace live edits.
+/// A marker naming a missing profile only warns. An automatic switch backs
+/// up what it replaces as `backup` says.
+pub fn local_check(paths: &Paths, auto: bool, backup: &BackupOptions, ui: &Ui) -> Result<()> {
+    let cwd = std::env::current_dir().context("Failed to get the current directory")?;
+    let Some(marker) = crate::local::find_marker(&cwd) else {
+        return Ok(());
+    };
+    if !profile_exists(paths, &marker.profile) {
+        ui.warn(format!(
+            "{} names profile '{}', which does not exist; not switching",
+            marker.path.display(),
+            marker.profile
+        ));
+        return Ok(());
+    }
+    let selected = State::read(&paths.state_file)
+        .unwrap_or_default()
+        .default_profile;
+    if selected.as_deref() == Some(marker.profile.as_str()) {
+        return Ok(());
+    }
+
+    if !auto {
+        ui.warn(format!(
+            "This project uses profile '{}' ({}), but {} is active",
+            marker.profile,
+            marker.path.display(),
+            selected.map_or("no profile".to_string(), |p| format!("'{}'", p))
+        ));
+        ui.note(format!(
+            "  Run 'ccprof use {}', or 'ccprof local check --auto' to switch automatically.",
+            marker.profile
+        ));
+        return Ok(());
+    }
+
+    let options = SwitchOptions {
+        backup: backup.clone(),
+        ..SwitchOptions::default()
+    };
+    let drift = detect_live_drift(paths, &marker.profile, &options)?;
+    if !drift.is_empty() {
+        let components: Vec<_> = drift.iter().map(|d| d.component.short_name()).collect();
+        bail!(
+            "Not switching to '{}' automatically: ~/.claude has edits the profile doesn't have ({}).\nHint: Run 'ccprof use {}' to review them, or 'ccprof sync' to keep them first.",
+            marker.profile,
+            components.join(", "),
+            marker.profile
+        );
+    }
+    use_profile(
+        paths,
+        &marker.profile,
+        ui,
+        &options,
+        DriftAction::Proceed,
+        false,
+    )
+}
+
+/// Print the effective config.toml, defaults filled in
+pub fn config_file_show(paths: &Paths, config: &Config, ui: &Ui) -> Result<()> {
+    if paths.config_file.exists() {
+        ui.note(format!(
+            "From {} and the defaults",
+            paths.config_file.display()
+        ));
+    } else {
+        ui.note(format!(
+            "{} doesn't exist; these are the defaults",
+            paths.config_file.display()
+        ));
+    }
+    ui.println(config.to_toml().trim_end());
+    Ok(())
+}
+
+/// Open config.toml in the editor, starting it from [`CONFIG_TEMPLATE`]
+pub fn config_file_edit(paths: &Paths, config: &Config, ui: &Ui) -> Result<()> {
+    let path = &paths.config_file;
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        std::fs::write(path, CONFIG_TEMPLATE)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+    }
+    crate::editor::current_editor(config)?.open(std::slice::from_ref(path))?;
+    ui.ok(format!("Opened {} in editor", path.display()));
+    Ok(())
+}
+
+/// Run `command` with `profile` active, then restore the previous layout
+///
+/// Returns the command's exit code for ccprof to exit with.
+pub fn exec(paths: &Paths, profile: &str, command: &[String], force: bool, ui: &Ui) -> Result<i32> {
     paths.ensure_dirs()?;
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let (temporary, _) = crate::exec::TemporarySwitch::begin(paths, profile, force)?;
+    ui.info(format!(
+        "Running '{}' with profile '{}'",
+        command.join(" "),
+        profile
+    ));
+
+    let env = profile_env_vars(paths, profile, ui)?;
+    let code = crate::exec::run_command(command, &env);
+    let previous = temporary.previous_profile().map(str::to_string);
+    temporary.restore()?;
+    let code = code?;
+    match previous {
+        Some(previous) => ui.info(format!("Restored profile '{}'", previous)),
+        None => ui.info("Restored ~/.claude"),
+    }
+    Ok(code)
+}
+
+/// What `use` does with live edits that the target profile doesn't have
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DriftAction {
+    /// Show the differences and ask before replacing them
+    #[default]
+    Prompt,
+    /// Copy the live content into the profile first (`--adopt-changes`)
+    Adopt,
+    /// Back up and replace without asking (`--yes`)
+    Proceed,
+}
+
+/// Last component of `path`, or the whole path if it has none
+fn file_name_of(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Switch to a profile
+pub fn use_profile(
+    paths: &Paths,
+    name: &str,
+    ui: &Ui,
+    options: &SwitchOptions,
+    on_drift: DriftAction,
+    dry_run: bool,
+) -> Result<()> {
+    if !dry_run {
+        paths.ensure_dirs()?;
+    }
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    warn_if_being_edited(paths, name, ui);
+
+    let drift = detect_live_drift(paths, name, options)?;
+    if !drift.is_empty() {
+        ui.warn(format!(
+            "~/.claude has changes that profile '{}' doesn't have:",
+            name
+        ));
+        for d in &drift {
+            print_drift_summary(d, ui);
+        }
+        ui.newline();
+    }
+
+    if dry_run {
+        let mut changes = Vec::new();
+        if on_drift == DriftAction::Adopt {
+            changes.extend(drift.iter().map(|d| Change::Copy {
+                from: d.live_path.clone(),
+                to: d.adopt_path.clone(),
+            }));
+        }
+        changes.extend(crate::switch::plan_switch_changes(paths, name, options)?);
+        print_plan(&format!("Switching to profile '{}'", name), &changes, ui);
+        return Ok(());
+    }
+
+    if !drift.is_empty() {
+        match on_drift {
+            DriftAction::Adopt => {
+                for d in &drift {
+                    let report = adopt_live_drift(d)?;
+                    warn_copies(&report, ui);
+                    ui.ok(format!(
+                        "Copied live {} into profile '{}'",
+                        d.component.display_name(),
+                        name
+                    ));
+                }
+            }
+            DriftAction::Proceed => {}
+            DriftAction::Prompt => {
+                let confirm = ui.confirm(
+                    &format!("Replace them with profile '{}'? (a backup is kept)", name),
+                    Some("Use --adopt-changes to keep the edits in the profile"),
+                    false,
+                )?;
+
+                if !confirm {
+                    ui.info("Switch cancelled");
+                    return Ok(());
+                }
+            }
+        }
+    }
 
     // Start spinner for the switch operation
     let spinner = ui.spinner(format!("Switching to profile '{}'...", name));
 
-    match switch_to_profile(paths, name) {
-        Ok(()) => {
-            ui.spinner_finish_ok(&spinner, format!("Active profile: {}", name));
+    let status = format!("Switching to profile '{}'...", name);
+    let mut explained_fallback = false;
+    let mut on_event = |event: SwitchEvent| match event {
+        SwitchEvent::BackupStarted { component, files } => {
+            ui.event(
+                "backup_started",
+                json!({"component": component.short_name(), "files": files}),
+            );
+            if files >= BACKUP_PROGRESS_MIN_FILES {
+                ui.spinner_progress(
+                    &spinner,
+                    files,
+                    format!("Backing up {}", component.display_name()),
+                );
+            }
+        }
+        SwitchEvent::FileBackedUp { .. } => spinner.inc(1),
+        SwitchEvent::BackedUp { component, backup } => {
+            ui.event(
+                "backup",
+                json!({"component": component.short_name(), "path": backup}),
+            );
+            ui.spinner_restore(&spinner, status.clone());
+            ui.spinner_println(
+                &spinner,
+                format!(
+                    "  Backed up {} → {}",
+                    file_name_of(&component.source_path(paths)),
+                    ui.dim(file_name_of(&backup))
+                ),
+            );
+        }
+        SwitchEvent::BackupUnchanged { component, backup } => {
+            ui.event(
+                "backup_unchanged",
+                json!({"component": component.short_name(), "path": backup}),
+            );
+            ui.spinner_println(
+                &spinner,
+                format!(
+                    "  {} unchanged since {}, not backed up again",
+                    file_name_of(&component.source_path(paths)),
+                    ui.dim(file_name_of(&backup))
+                ),
+            );
+        }
+        SwitchEvent::BackupIncomplete { component, skipped } => {
+            ui.event(
+                "backup_incomplete",
+                json!({"component": component.short_name(), "skipped": skipped}),
+            );
+            ui.spinner_println(
+                &spinner,
+                format!(
+                    "{} The backup of {} is incomplete: {} unreadable entr{} left out (use --strict to stop instead)",
+                    ui.icon_warn(),
+                    component.display_name(),
+                    skipped.len(),
+                    if skipped.len() == 1 { "y" } else { "ies" }
+                ),
+            );
+            for warning in &skipped {
+                ui.spinner_println(&spinner, format!("    {}", warning));
+            }
+        }
+        SwitchEvent::CopyWarning { component, warning } => {
+            ui.event(
+                "copy_warning",
+                json!({"component": component.short_name(), "warning": warning}),
+            );
+            ui.spinner_println(&spinner, format!("{} {}", ui.icon_warn(), warning));
+        }
+        SwitchEvent::SymlinkFallback { component } => {
+            ui.event(
+                "symlink_fallback",
+                json!({"component": component.short_name()}),
+            );
+            if !explained_fallback {
+                explained_fallback = true;
+                ui.spinner_println(
+                    &spinner,
+                    format!(
+                        "{} Windows denied creating a symlink; enable Developer Mode (Settings → System → For developers) to allow it.\n  Copying components instead (use --no-fallback to fail instead).",
+                        ui.icon_warn()
+                    ),
+                );
+            }
+            ui.spinner_println(
+                &spinner,
+                format!("  Copying {} instead of linking", component.display_name()),
+            );
+        }
+        SwitchEvent::Applied { component, action } => {
+            ui.event(
+                "applied",
+                json!({
+                    "component": component.short_name(),
+                    "action": action.label().to_lowercase(),
+                }),
+            );
+            spinner.set_message(format!("{} {}", action.label(), component.display_name()));
+        }
+        SwitchEvent::WaitingForLock => {
+            ui.event("waiting_for_lock", json!({"path": paths.state_file}));
+            ui.spinner_restore(&spinner, LOCK_WAIT_MESSAGE);
+        }
+    };
+
+    match switch_to_profile_with_progress(paths, name, options, &mut on_event) {
+        Ok(report) => {
+            let finished = match report.previous_profile.as_deref() {
+                Some(previous) if previous != name => {
+                    format!("Switched to profile '{}' (was '{}')", name, previous)
+                }
+                _ => format!("Switched to profile '{}'", name),
+            };
+            ui.spinner_finish_ok(&spinner, finished);
+            ui.event(
+                "switched",
+                json!({"profile": name, "previous": report.previous_profile}),
+            );
+            for outcome in &report.components {
+                ui.note(format!(
+                    "  {} {} {} → {}",
+                    ui.icon_ok(),
+                    outcome.component.display_name(),
+                    outcome.action.label().to_lowercase(),
+                    ui.dim(outcome.target.display().to_string())
+                ));
+            }
+            if !report.backups.is_empty() {
+                ui.info(format!(
+                    "{} backup(s) saved in {}",
+                    report.backups.len(),
+                    paths.backups_dir.display()
+                ));
+            }
+            for component in &report.skipped {
+                ui.warn(format!(
+                    "Skipped {}: missing from profile '{}', ~/.claude left untouched",
+                    component.display_name(),
+                    name
+                ));
+            }
+            if report.pruned {
+                ui.info(format!(
+                    "Removed skipped components from the metadata of '{}'",
+                    name
+                ));
+            }
             Ok(())
         }
         Err(e) => {
             ui.spinner_finish_err(&spinner, format!("Failed to switch: {}", e));
-            Err(e)
+            Err(e.into())
         }
     }
 }
 
-/// Edit a profile's settings.json
-pub fn edit(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
-    if !profile_exists(paths, name) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            name
-        );
+/// Print a short summary of how live content differs from a profile's copy
+fn print_drift_summary(drift: &LiveDrift, ui: &Ui) {
+    const MAX_LISTED: usize = 5;
+    let list = |items: &[String]| {
+        let mut listed = items
+            .iter()
+            .take(MAX_LISTED)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", ");
+        if items.len() > MAX_LISTED {
+            listed.push_str(&format!(", and {} more", items.len() - MAX_LISTED));
+        }
+        listed
+    };
+
+    ui.note(format!(
+        "  {} {}",
+        ui.icon_warn(),
+        drift.live_path.display()
+    ));
+
+    if drift.live_path.is_dir() && drift.profile_path.is_dir() {
+        match crate::fs_utils::compare_dirs(&drift.live_path, &drift.profile_path) {
+            Ok(cmp) => {
+                if !cmp.only_in_left.is_empty() {
+                    ui.note(format!(
+                        "      only in ~/.claude: {}",
+                        list(&cmp.only_in_left)
+                    ));
+                }
+                if !cmp.only_in_right.is_empty() {
+                    ui.note(format!(
+                        "      only in profile: {}",
+                        list(&cmp.only_in_right)
+                    ));
+                }
+                if !cmp.changed.is_empty() {
+                    ui.note(format!("      changed: {}", list(&cmp.changed)));
+                }
+            }
+            Err(_) => ui.note("      content differs"),
+        }
+        return;
     }
 
-    let settings_path = paths.profile_settings(name);
+    let parse = |path: &Path| -> Option<serde_json::Value> {
+        crate::jsonc::from_str(&std::fs::read_to_string(path).ok()?).ok()
+    };
+    match (parse(&drift.live_path), parse(&drift.profile_path)) {
+        (Some(live), Some(profile)) => {
+            let mut differences = Vec::new();
+            compare_json_values(&live, &profile, "", &mut differences);
+            let keys: Vec<String> = differences.into_iter().map(|(key, _, _)| key).collect();
+            ui.note(format!(
+                "      {} key(s) differ: {}",
+                keys.len(),
+                list(&keys)
+            ));
+        }
+        _ => ui.note("      content differs"),
+    }
+}
 
-    // Try $EDITOR first
-    if let Ok(editor) = std::env::var("EDITOR") {
-        let status = Command::new(&editor)
-            .arg(&settings_path)
-            .status()
-            .with_context(|| format!("Failed to run editor: {}", editor))?;
+/// Show or set the default switch strategy and link style
+pub fn strategy(
+    paths: &Paths,
+    ui: &Ui,
+    strategy: Option<SwitchStrategy>,
+    link_style: Option<LinkStyle>,
+) -> Result<()> {
+    if strategy.is_none() && link_style.is_none() {
+        let state = State::read(&paths.state_file).unwrap_or_default();
+        ui.println(format!(
+            "Switch strategy: {}",
+            ui.bold(state.strategy.unwrap_or_default().to_string())
+        ));
+        ui.println(format!(
+            "Link style:      {}",
+            ui.bold(state.link_style.unwrap_or_default().to_string())
+        ));
+        return Ok(());
+    }
 
-        if !status.success() {
-            bail!("Editor exited with non-zero status");
+    paths.ensure_dirs()?;
+    let mut locked = with_lock_spinner(ui, |on_wait| {
+        crate::state::LockedState::lock_or_wait(&paths.state_file, on_wait)
+    })?;
+    locked.update(|s| {
+        if strategy.is_some() {
+            s.strategy = strategy;
         }
-    } else {
-        // Fallback to macOS 'open -t' (opens in default text editor)
-        let status = Command::new("open")
-            .arg("-t")
-            .arg(&settings_path)
-            .status()
-            .context("Failed to run 'open -t'")?;
+        if link_style.is_some() {
+            s.link_style = link_style;
+        }
+    })?;
 
-        if !status.success() {
-            bail!("'open -t' exited with non-zero status");
+    if let Some(strategy) = strategy {
+        ui.ok(format!("Default switch strategy set to '{}'", strategy));
+    }
+    if let Some(style) = link_style {
+        ui.ok(format!("Default link style set to '{}'", style));
+    }
+    ui.note("It applies from the next 'ccprof use'.");
+    Ok(())
+}
+
+/// Write edits made to copy-mode components back into their profiles
+pub fn sync(paths: &Paths, ui: &Ui) -> Result<()> {
+    let (synced, report) = with_lock_spinner(ui, |on_wait| sync_copies(paths, on_wait))?;
+    warn_copies(&report, ui);
+
+    if synced.is_empty() {
+        ui.ok("Nothing to sync: no copied component has been modified.");
+        return Ok(());
+    }
+
+    let mut profiles = HashSet::new();
+    for (component, profile) in synced {
+        ui.ok(format!(
+            "Synced {} into profile '{}'",
+            component.display_name(),
+            profile
+        ));
+        profiles.insert(profile);
+    }
+    for profile in profiles {
+        crate::profiles::record_claude_version(paths, &profile)?;
+    }
+    Ok(())
+}
+
+/// Edit a profile's settings.json
+pub fn edit(paths: &Paths, config: &Config, name: &str, ui: &Ui, safe: bool) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+
+    edit_settings_file(paths, config, name, &paths.profile_settings(name), safe, ui)
+}
+
+/// Open a profile's settings file in the editor
+///
+/// With `safe`, and always for the settings ~/.claude links to, the editor
+/// gets a copy that replaces the file only once it parses.
+fn edit_settings_file(
+    paths: &Paths,
+    config: &Config,
+    name: &str,
+    path: &Path,
+    safe: bool,
+    ui: &Ui,
+) -> Result<()> {
+    let live = current_status(paths).linked_profile.as_deref() == Some(name);
+    if !safe && !live {
+        open_in_editor(paths, config, name, path)?;
+        ui.ok(format!("Opened {} in editor", path.display()));
+        warn_settings_issues(path, ui);
+        return Ok(());
+    }
+
+    let editor = crate::editor::current_editor(config)?;
+    let validate = |text: &str| -> Result<()> {
+        crate::jsonc::parse(text)?;
+        Ok(())
+    };
+    let marker = EditingMarker::create(&paths.profile_dir(name))?;
+    let edit = crate::editor::edit_safely(&editor, path, &paths.staging_dir, &validate, ui)?;
+    drop(marker);
+    match edit {
+        SafeEdit::Saved { before, after } => {
+            print_text_diff(&before, &after, "before", "after", ui);
+            ui.ok(format!("Saved {}", path.display()));
+        }
+        SafeEdit::Unchanged => ui.info(format!("{} is unchanged", path.display())),
+        SafeEdit::Discarded => {
+            ui.warn(format!(
+                "Discarded the edit; {} is unchanged",
+                path.display()
+            ));
+            return Ok(());
+        }
+    }
+    warn_settings_issues(path, ui);
+    Ok(())
+}
+
+/// Warn about what the edit left wrong in a settings file, without failing
+fn warn_settings_issues(path: &Path, ui: &Ui) {
+    match crate::settings_schema::check_settings_file(path) {
+        Ok(issues) => {
+            for issue in issues {
+                ui.warn(format!("settings.json: {}", issue));
+            }
         }
+        Err(e) => ui.warn(format!("{:#}", e)),
     }
+}
+
+/// Open a profile's NOTES.md in the editor, creating it if needed
+pub fn edit_notes(paths: &Paths, config: &Config, name: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
 
-    ui.ok(format!("Opened {} in editor", settings_path.display()));
+    let notes = paths.profile_notes(name);
+    if !notes.exists() {
+        std::fs::write(&notes, format!("# {}\n\n", name))
+            .with_context(|| format!("Failed to create {}", notes.display()))?;
+    }
+
+    open_in_editor(paths, config, name, &notes)?;
+    ui.ok(format!("Opened {} in editor", notes.display()));
     Ok(())
 }
 
 /// Edit a specific component of a profile
-pub fn edit_component(paths: &Paths, name: &str, component: &str, ui: &Ui) -> Result<()> {
-    if !profile_exists(paths, name) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            name
-        );
-    }
+pub fn edit_component(
+    paths: &Paths,
+    config: &Config,
+    name: &str,
+    component: &str,
+    ui: &Ui,
+    safe: bool,
+) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
 
     // Parse component
     let comp: Component = component.parse().map_err(|_| {
         anyhow::anyhow!(
-            "Invalid component: '{}'\nHint: Valid components are settings, agents, hooks, commands",
+            "Invalid component: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
             component
         )
     })?;
 
-    let component_path = comp.profile_path(paths, name);
+    let metadata = crate::components::ProfileMetadata::read(&paths.profile_dir(name))?;
+    let component_path = metadata.component_path(paths, name, comp);
 
     if !component_path.exists() {
         bail!(
@@ -455,20 +2063,23 @@ pub fn edit_component(paths: &Paths, name: &str, component: &str, ui: &Ui) -> Re
         );
     }
 
+    if comp == Component::Settings {
+        return edit_settings_file(paths, config, name, &component_path, safe, ui);
+    }
+    if safe {
+        bail!("--safe only applies to settings.json");
+    }
+
     // Open in editor
-    open_in_editor(&component_path)?;
+    open_in_editor(paths, config, name, &component_path)?;
     ui.ok(format!("Opened {} in editor", component_path.display()));
     Ok(())
 }
 
 /// Edit all managed components of a profile
-pub fn edit_all_components(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
-    if !profile_exists(paths, name) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            name
-        );
-    }
+pub fn edit_all_components(paths: &Paths, config: &Config, name: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
 
     let profile_dir = paths.profile_dir(name);
     let metadata = crate::components::ProfileMetadata::read(&profile_dir)?;
@@ -483,8 +2094,9 @@ pub fn edit_all_components(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
 
     // Collect paths to open
     let mut paths_to_open: Vec<std::path::PathBuf> = Vec::new();
+    let settings = metadata.component_path(paths, name, Component::Settings);
     for comp in &metadata.managed_components {
-        let path = comp.profile_path(paths, name);
+        let path = metadata.component_path(paths, name, *comp);
         if path.exists() {
             paths_to_open.push(path);
         }
@@ -495,61 +2107,94 @@ pub fn edit_all_components(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
     }
 
     // Open all in editor
-    open_multiple_in_editor(&paths_to_open)?;
+    open_multiple_in_editor(paths, config, name, &paths_to_open)?;
     ui.ok(format!("Opened {} component(s) in editor", paths_to_open.len()));
+    if paths_to_open.contains(&settings) {
+        warn_settings_issues(&settings, ui);
+    }
     Ok(())
 }
 
-/// Open a file in the user's editor
-fn open_in_editor(path: &std::path::Path) -> Result<()> {
-    if let Ok(editor) = std::env::var("EDITOR") {
-        let status = Command::new(&editor)
-            .arg(path)
-            .status()
-            .with_context(|| format!("Failed to run editor: {}", editor))?;
+/// Open a file of profile `name` in the user's editor
+fn open_in_editor(paths: &Paths, config: &Config, name: &str, path: &Path) -> Result<()> {
+    open_multiple_in_editor(paths, config, name, &[path.to_path_buf()])
+}
 
-        if !status.success() {
-            bail!("Editor exited with non-zero status");
-        }
-    } else {
-        // Fallback to macOS 'open -t'
-        let status = Command::new("open")
-            .arg("-t")
-            .arg(path)
-            .status()
-            .context("Failed to run 'open -t'")?;
+/// Open multiple files of profile `name` in the user's editor
+fn open_multiple_in_editor(
+    paths: &Paths,
+    config: &Config,
+    name: &str,
+    files: &[std::path::PathBuf],
+) -> Result<()> {
+    let editor = crate::editor::current_editor(config)?;
+    let _marker = EditingMarker::create(&paths.profile_dir(name))?;
+    editor.open(files)
+}
 
-        if !status.success() {
-            bail!("'open -t' exited with non-zero status");
-        }
+/// Warn when another ccprof has an editor open on profile `name`
+fn warn_if_being_edited(paths: &Paths, name: &str, ui: &Ui) {
+    if let Some(holder) = crate::editor::editing_elsewhere(&paths.profile_dir(name)) {
+        ui.warn(format!(
+            "Profile '{}' is open in an editor (ccprof pid {}, since {}); unsaved edits aren't included.",
+            name,
+            holder.pid,
+            holder.started_at.with_timezone(&chrono::Local).format("%H:%M")
+        ));
     }
-    Ok(())
 }
 
-/// Open multiple files in the user's editor
-fn open_multiple_in_editor(paths: &[std::path::PathBuf]) -> Result<()> {
-    if let Ok(editor) = std::env::var("EDITOR") {
-        let status = Command::new(&editor)
-            .args(paths)
-            .status()
-            .with_context(|| format!("Failed to run editor: {}", editor))?;
+/// Shown while a lock is held by another ccprof process
+pub const LOCK_WAIT_MESSAGE: &str = "Waiting for another ccprof process…";
+
+/// Run `f`, with a spinner while the lock it takes is held elsewhere
+///
+/// `f` passes the callback on as the `on_wait` of the lock.
+pub fn with_lock_spinner<T>(ui: &Ui, f: impl FnOnce(&mut dyn FnMut()) -> T) -> T {
+    let mut spinner = None;
+    let result = f(&mut || {
+        spinner.get_or_insert_with(|| ui.spinner(LOCK_WAIT_MESSAGE));
+    });
+    if let Some(pb) = spinner {
+        pb.finish_and_clear();
+    }
+    result
+}
 
-        if !status.success() {
-            bail!("Editor exited with non-zero status");
+/// Print what a `--dry-run` would change under the heading `title`
+fn print_plan(title: &str, changes: &[Change], ui: &Ui) {
+    ui.section(format!("{} (dry run)", title));
+    for change in changes {
+        ui.event("planned", json!(change));
+        if !ui.is_ndjson() {
+            ui.println(format!("  {}", change));
         }
-    } else {
-        // Fallback to macOS 'open -t' with multiple files
-        let status = Command::new("open")
-            .arg("-t")
-            .args(paths)
-            .status()
-            .context("Failed to run 'open -t'")?;
+    }
+    ui.note("Nothing was changed.");
+}
 
-        if !status.success() {
-            bail!("'open -t' exited with non-zero status");
-        }
+/// Refuse to remove or rename profile `name` while another ccprof has an
+/// editor open on it, unless `force`
+fn ensure_not_being_edited(paths: &Paths, name: &str, force: bool, ui: &Ui) -> Result<()> {
+    let Some(holder) = crate::editor::editing_elsewhere(&paths.profile_dir(name)) else {
+        return Ok(());
+    };
+    if force {
+        ui.warn(format!(
+            "Profile '{}' is open in an editor (ccprof pid {}); saving there will fail or recreate it.",
+            name, holder.pid
+        ));
+        return Ok(());
     }
-    Ok(())
+    bail!(
+        "Profile '{}' is open in an editor (ccprof pid {}, started {}).\nHint: Close the editor first, or pass --force.",
+        name,
+        holder.pid,
+        holder
+            .started_at
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M")
+    );
 }
 
 /// Edit a profile's tracked components
@@ -558,13 +2203,11 @@ pub fn edit_components(
     name: &str,
     ui: &Ui,
     components_arg: Option<Vec<String>>,
+    dry_run: bool,
 ) -> Result<()> {
-    if !profile_exists(paths, name) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            name
-        );
-    }
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    warn_if_being_edited(paths, name, ui);
 
     let profile_dir = paths.profile_dir(name);
     let metadata = crate::components::ProfileMetadata::read(&profile_dir)?;
@@ -573,7 +2216,7 @@ pub fn edit_components(
     let new_components = if let Some(comp_names) = components_arg {
         // Interactive mode: use multi-select UI with current selection as default
         if comp_names.is_empty() {
-            edit_select_components(paths, &metadata.managed_components)?
+            edit_select_components(paths, &metadata.managed_components, ui)?
         } else {
             // Non-interactive mode: parse component names
             let mut selected = HashSet::new();
@@ -584,7 +2227,7 @@ pub fn edit_components(
                     }
                     Err(_) => {
                         bail!(
-                            "Invalid component name: '{}'\nValid components: settings, agents, hooks, commands",
+                            "Invalid component name: '{}'\nValid components: settings, agents, hooks, commands, env",
                             comp_name
                         );
                     }
@@ -594,18 +2237,74 @@ pub fn edit_components(
         }
     } else {
         bail!(
-            "No components specified.\nHint: Use --components settings,agents,hooks,commands or run interactively."
+            "No components specified.\nHint: Use --components settings,agents,hooks,commands,env or run interactively."
         );
     };
 
+    if dry_run {
+        let changes = crate::profiles::plan_profile_components(paths, name, &new_components)?;
+        print_plan(
+            &format!("Updating components for profile '{}'", name),
+            &changes,
+            ui,
+        );
+        return Ok(());
+    }
+
     // Update the profile components
-    update_profile_components(paths, name, new_components.clone())?;
+    let report = update_profile_components(paths, name, new_components.clone())?;
+    warn_copies(&report, ui);
 
     ui.ok(format!("Updated components for profile '{}'", name));
     ui.newline();
-    ui.println("Now tracking:");
+    ui.note("Now tracking:");
     for component in &new_components {
-        ui.println(format!("  {} {}", ui.icon_ok(), component.display_name()));
+        ui.note(format!("  {} {}", ui.icon_ok(), component.display_name()));
+    }
+
+    Ok(())
+}
+
+/// Track an individual file inside a directory component (e.g. `commands/deploy.md`)
+pub fn track(paths: &Paths, name: &str, spec: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+
+    let (component_name, file) = spec.split_once('/').with_context(|| {
+        format!(
+            "Invalid path '{}'.\nHint: Use <component>/<file>, e.g. commands/deploy.md",
+            spec
+        )
+    })?;
+
+    let component: Component = component_name.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid component: '{}'\nHint: Valid directory components are agents, hooks, commands",
+            component_name
+        )
+    })?;
+
+    let file = file.trim_end_matches('/');
+    let report = track_file(paths, name, component, file)?;
+    warn_copies(&report, ui);
+
+    ui.ok(format!(
+        "Profile '{}' now tracks {}/{}",
+        name,
+        component.short_name(),
+        file
+    ));
+    ui.note(format!(
+        "  Other {} entries come from the shared base: {}",
+        component.short_name(),
+        paths.shared_base(&component).display()
+    ));
+
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if state.default_profile.as_deref() == Some(name) {
+        ui.newline();
+        ui.note("Re-apply the active profile to compose the directory:");
+        ui.note(format!("  ccprof use {}", name));
     }
 
     Ok(())
@@ -615,60 +2314,36 @@ pub fn edit_components(
 fn edit_select_components(
     paths: &Paths,
     current_components: &HashSet<Component>,
+    interaction: &dyn Interaction,
 ) -> Result<HashSet<Component>> {
-    let all_components = Component::all();
-
-    // Build display options with availability indicators
-    let options: Vec<String> = all_components
-        .iter()
+    // Build display options with availability indicators; currently tracked
+    // components are selected by default
+    let choices: Vec<ComponentChoice> = Component::all()
+        .into_iter()
         .map(|c| {
-            let path = c.source_path(paths);
-            let exists = path.exists();
+            let exists = c.source_path(paths).exists();
             let indicator = if exists { "✓" } else { "✗" };
             let availability = if exists { "" } else { " (not found)" };
-            let tracked = if current_components.contains(c) {
-                " [tracked]"
-            } else {
-                ""
-            };
-            format!(
-                "{}{} {}{}",
-                indicator,
-                c.display_name(),
-                availability,
-                tracked
-            )
+            let tracked = current_components.contains(&c);
+            ComponentChoice {
+                component: c,
+                label: format!(
+                    "{}{} {}{}",
+                    indicator,
+                    c.display_name(),
+                    availability,
+                    if tracked { " [tracked]" } else { "" }
+                ),
+                selected: tracked,
+            }
         })
         .collect();
 
-    // Default: select all currently tracked components
-    let defaults: Vec<usize> = all_components
-        .iter()
-        .enumerate()
-        .filter(|(_, c)| current_components.contains(c))
-        .map(|(i, _)| i)
-        .collect();
-
-    let selected_indices = MultiSelect::new(
+    let selected = interaction.select_components(
         "Which components should this profile manage?",
-        options.clone(),
-    )
-    .with_default(&defaults)
-    .with_help_message(
         "Space to select, Enter to confirm. Currently tracked components are pre-selected.",
-    )
-    .prompt()
-    .context("Component selection cancelled")?;
-
-    let selected: HashSet<Component> = selected_indices
-        .into_iter()
-        .filter_map(|selected_str| {
-            options
-                .iter()
-                .position(|opt| *opt == selected_str)
-                .map(|idx| all_components[idx])
-        })
-        .collect();
+        &choices,
+    )?;
 
     if selected.is_empty() {
         bail!(
@@ -680,47 +2355,60 @@ fn edit_select_components(
 }
 
 /// Run diagnostics
-pub fn doctor(paths: &Paths, ui: &Ui) -> Result<()> {
-    run_doctor(paths, ui);
+pub fn doctor(
+    paths: &Paths,
+    config: &Config,
+    ui: &Ui,
+    fix: bool,
+    quiet: bool,
+    project_dir: Option<&Path>,
+) -> Result<()> {
+    let project_dir = match project_dir {
+        Some(dir) => std::path::absolute(dir)
+            .with_context(|| format!("Invalid project directory: {}", dir.display()))?,
+        None => std::env::current_dir().context("Failed to get the current directory")?,
+    };
+    if !run_doctor(paths, config, ui, fix, quiet, &project_dir) {
+        if fix {
+            bail!(
+                "Doctor found problems it could not fix.\nHint: Fix the issues listed above by hand."
+            );
+        }
+        bail!(
+            "Doctor found problems.\nHint: Run 'ccprof doctor --fix' to repair what can be repaired automatically."
+        );
+    }
     Ok(())
 }
 
-/// List all backups
-pub fn backup_list(paths: &Paths, ui: &Ui) -> Result<()> {
-    if !paths.backups_dir.exists() {
-        ui.warn("No backups found.");
-        ui.newline();
-        ui.println("Backups are created automatically when switching profiles.");
-        return Ok(())
-    }
+/// List all backups, optionally only those of one component
+pub fn backup_list(paths: &Paths, ui: &Ui, component: Option<&str>, json: bool) -> Result<()> {
+    let component: Option<Component> = component
+        .map(|name| {
+            name.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "Invalid component name: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
+                    name
+                )
+            })
+        })
+        .transpose()?;
 
-    let entries: Vec<_> = std::fs::read_dir(&paths.backups_dir)?
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_name().to_str().is_some_and(|n| n.ends_with(".bak")))
-        .collect();
+    let backups = crate::switch::list_backups(paths, component);
 
-    if entries.is_empty() {
-        ui.warn("No backups found.");
-        return Ok(())
+    if json {
+        ui.println(serde_json::to_string_pretty(&backups)?);
+        return Ok(());
+    }
+    if backups.is_empty() {
+        match component {
+            Some(c) => ui.warn(format!("No backups of {} found.", c.display_name())),
+            None => ui.warn("No backups found."),
+        }
+        ui.newline();
+        ui.note("Backups are created automatically when switching profiles.");
+        return Ok(());
     }
-
-    // Parse and sort backups by timestamp
-    let mut backups: Vec<_> = entries
-        .iter()
-        .filter_map(|e| {
-            let name = e.file_name().to_str()?.to_string();
-            let metadata = e.metadata().ok()?;
-            let modified = metadata.modified().ok()?;
-            let size = if metadata.is_file() {
-                metadata.len()
-            } else {
-                crate::fs_utils::dir_size(&e.path()).unwrap_or(0)
-            };
-            Some((name, modified, size, e.path()))
-        })
-        .collect();
-
-    backups.sort_by(|a, b| b.1.cmp(&a.1)); // Most recent first
 
     ui.section("Backups");
     ui.newline();
@@ -730,32 +2418,24 @@ pub fn backup_list(paths: &Paths, ui: &Ui) -> Result<()> {
         ui.header_cell("ID"),
         ui.header_cell("Component"),
         ui.header_cell("Date"),
+        ui.header_cell("Age"),
         ui.header_cell("Size"),
+        ui.header_cell("Profile"),
+        ui.header_cell("Reason"),
     ]);
 
-    for (name, modified, size, _path) in &backups {
-        // Parse component from name (e.g., "settings.json.20240115_103045.bak")
-        let component = if name.starts_with("settings.json.") {
-            "Settings"
-        } else if name.starts_with("agents.") {
-            "Agents"
-        } else if name.starts_with("hooks.") {
-            "Hooks"
-        } else if name.starts_with("commands.") {
-            "Commands"
-        } else {
-            "Unknown"
-        };
-
-        // Format date
-        let datetime: chrono::DateTime<chrono::Utc> = (*modified).into();
-        let date_str = datetime.format("%Y-%m-%d %H:%M:%S").to_string();
-
+    for backup in &backups {
+        let component = component_for_backup(&backup.id)
+            .map(|c| c.display_name())
+            .unwrap_or("Unknown");
         table.add_row(vec![
-            ui.cell(name),
+            ui.cell(&backup.id),
             ui.cell(component),
-            ui.cell(date_str),
-            ui.cell(format_bytes(*size)),
+            ui.cell(backup.timestamp.format("%Y-%m-%d %H:%M:%S").to_string()),
+            ui.cell(format_time_ago(backup.timestamp)),
+            ui.number_cell(format_bytes(backup.size)),
+            ui.cell(backup.profile.as_deref().unwrap_or("-")),
+            ui.cell(backup.reason.as_deref().unwrap_or("-")),
         ]);
     }
 
@@ -767,7 +2447,16 @@ pub fn backup_list(paths: &Paths, ui: &Ui) -> Result<()> {
 }
 
 /// Restore a backup
-pub fn backup_restore(paths: &Paths, id: &str, ui: &Ui) -> Result<()> {
+///
+/// Symlinks inside a directory backup are copied as `symlinks` says.
+pub fn backup_restore(
+    paths: &Paths,
+    id: &str,
+    to: Option<&Path>,
+    dry_run: bool,
+    symlinks: SymlinkMode,
+    ui: &Ui,
+) -> Result<()> {
     let backup_path = paths.backups_dir.join(id);
 
     if !backup_path.exists() {
@@ -777,100 +2466,114 @@ pub fn backup_restore(paths: &Paths, id: &str, ui: &Ui) -> Result<()> {
         );
     }
 
-    // Determine component from backup name
-    let component = if id.starts_with("settings.json.") {
-        Component::Settings
-    } else if id.starts_with("agents.") {
-        Component::Agents
-    } else if id.starts_with("hooks.") {
-        Component::Hooks
-    } else if id.starts_with("commands.") {
-        Component::Commands
-    } else {
-        bail!(
-            "Cannot determine component type from backup name: {}\nHint: Backup names should start with 'settings.json.', 'agents.', etc.",
-            id
-        );
+    let target = match to {
+        // Into an existing directory, like cp, rather than replacing it
+        Some(to) if to.is_dir() => std::path::absolute(to.join(id))?,
+        Some(to) => std::path::absolute(to)
+            .with_context(|| format!("Failed to resolve {}", to.display()))?,
+        None => {
+            // Determine component from backup name
+            let Some(component) = component_for_backup(id) else {
+                bail!(
+                    "Cannot determine component type from backup name: {}\nHint: Backup names should start with 'settings.', 'agents.', etc.",
+                    id
+                );
+            };
+            component.source_path(paths)
+        }
     };
 
-    // Confirm restore
-    let target = component.source_path(paths);
-    let confirm = inquire::Confirm::new(&format!("Restore '{}' to {}?", id, target.display()))
-        .with_default(false)
-        .with_help_message("This will overwrite the current file/directory")
-        .prompt()
-        .context("Confirmation cancelled")?;
-
-    if !confirm {
-        ui.warn("Restore cancelled.");
-        return Ok(())
+    if dry_run {
+        let changes = [Change::Copy {
+            from: backup_path,
+            to: target,
+        }];
+        print_plan(&format!("Restoring '{}'", id), &changes, ui);
+        return Ok(());
     }
 
-    // Remove current target if it exists
-    if target.exists() || std::fs::read_link(&target).is_ok() {
-        if target.is_dir() && !target.is_symlink() {
-            std::fs::remove_dir_all(&target)
-                .with_context(|| format!("Failed to remove {}", target.display()))?;
-        } else {
-            std::fs::remove_file(&target)
-                .with_context(|| format!("Failed to remove {}", target.display()))?;
+    // Writing elsewhere leaves ~/.claude and the profiles alone, so it needn't ask
+    let protected = [&paths.claude_dir, &paths.base_dir]
+        .iter()
+        .any(|dir| crate::paths::within(&target, dir).is_some());
+    if to.is_none() || protected {
+        let confirm = ui.confirm(
+            &format!("Restore '{}' to {}?", id, target.display()),
+            Some("This will overwrite the current file/directory"),
+            false,
+        )?;
+
+        if !confirm {
+            ui.warn("Restore cancelled.");
+            return Ok(());
         }
     }
+    if to.is_some()
+        && let Some(parent) = target.parent()
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
 
-    // Copy backup to target
+    // Copy backup over the target, which is never left missing for a directory
+    crate::audit::record(format!(
+        "restore {} -> {}",
+        backup_path.display(),
+        target.display()
+    ));
     if backup_path.is_dir() {
-        crate::fs_utils::copy_dir_recursive(&backup_path, &target)?;
+        let progress = copy_progress(ui, "Restoring backup", std::slice::from_ref(&backup_path));
+        let restored = crate::fs_utils::replace_dir_atomic_with_progress(
+            &backup_path,
+            &target,
+            symlinks,
+            &mut |bytes| progress.inc(bytes),
+        );
+        progress.finish_and_clear();
+        warn_copies(&restored?, ui);
     } else {
+        crate::fs_utils::remove_path(&target)?;
         std::fs::copy(&backup_path, &target)
             .with_context(|| format!("Failed to copy backup to {}", target.display()))?;
     }
 
     ui.ok(format!("Restored '{}' to {}", id, target.display()));
+    ui.event("restored", json!({"backup": id, "path": target}));
+    if to.is_none()
+        && let Some(origin) = decode_backup_origin(id)
+    {
+        ui.info(format!(
+            "This was the content of a symlink to {}; recreate the link to restore that setup",
+            origin.display()
+        ));
+    }
     Ok(())
 }
 
 /// Clean old backups
-pub fn backup_clean(paths: &Paths, keep: usize, ui: &Ui) -> Result<()> {
+pub fn backup_clean(paths: &Paths, keep: usize, dry_run: bool, ui: &Ui) -> Result<()> {
     if !paths.backups_dir.exists() {
         ui.warn("No backups directory found.");
         return Ok(())
     }
 
-    let mut removed = 0;
-
-    // Clean each component type separately
-    for prefix in ["settings.json.", "agents.", "hooks.", "commands."] {
-        let mut backups: Vec<_> = std::fs::read_dir(&paths.backups_dir)?
-            .filter_map(|e| e.ok())
-            .filter(|e| {
-                e.file_name()
-                    .to_str()
-                    .is_some_and(|n| n.starts_with(prefix) && n.ends_with(".bak"))
-            })
-            .filter_map(|e| {
-                let modified = e.metadata().ok()?.modified().ok()?;
-                Some((e.path(), modified))
-            })
+    let old = backups_to_clean(paths, keep)?;
+    if dry_run {
+        let changes: Vec<Change> = old
+            .into_iter()
+            .map(|path| Change::Remove { path })
             .collect();
-
-        if backups.len() <= keep {
-            continue;
-        }
-
-        // Sort by date (oldest first)
-        backups.sort_by_key(|(_, time)| *time);
-
-        // Remove oldest backups
-        let to_remove = backups.len() - keep;
-        for (path, _) in backups.iter().take(to_remove) {
-            if path.is_dir() {
-                std::fs::remove_dir_all(path)?;
-            } else {
-                std::fs::remove_file(path)?;
-            }
-            removed += 1;
-        }
+        print_plan(
+            &format!("Cleaning backups, keeping {} per component", keep),
+            &changes,
+            ui,
+        );
+        return Ok(());
+    }
+    for path in &old {
+        crate::switch::remove_backup(path)?;
     }
+    let removed = old.len();
 
     if removed > 0 {
         ui.ok(format!(
@@ -885,14 +2588,589 @@ pub fn backup_clean(paths: &Paths, keep: usize, ui: &Ui) -> Result<()> {
     Ok(())
 }
 
-/// Remove a profile
-pub fn remove(paths: &Paths, name: &str, ui: &Ui, force: bool) -> Result<()> {
-    if !profile_exists(paths, name) {
+/// Backups beyond the `keep` most recent of each component, oldest first
+///
+/// The same rotation a switch applies after each backup.
+fn backups_to_clean(paths: &Paths, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut old = Vec::new();
+    for component in Component::all() {
+        old.extend(crate::switch::backups_beyond(
+            &paths.backups_dir,
+            component.short_name(),
+            keep,
+        )?);
+    }
+    Ok(old)
+}
+
+/// List templates and the components they provide
+pub fn template_list(paths: &Paths, ui: &Ui) -> Result<()> {
+    let templates = crate::templates::list_templates(paths)?;
+
+    if templates.is_empty() {
+        ui.warn("No templates found.");
+        ui.newline();
+        ui.note("Save a profile as a template with:");
+        ui.note(format!(
+            "  {} template save <profile> <template>",
+            ui.bold("ccprof")
+        ));
+        return Ok(());
+    }
+
+    ui.section("Templates");
+    ui.newline();
+
+    let mut table = ui.simple_table();
+    table.set_header(vec![
+        ui.header_cell("Name"),
+        ui.header_cell("Size"),
+        ui.header_cell("Components"),
+    ]);
+
+    for template in &templates {
+        let names: Vec<&str> = Component::all()
+            .into_iter()
+            .filter(|c| template.metadata.managed_components.contains(c))
+            .map(|c| c.short_name())
+            .collect();
+
+        table.add_row(vec![
+            ui.cell(&template.name),
+            ui.number_cell(calculate_size(&template.path)?),
+            ui.cell(names.join(", ")),
+        ]);
+    }
+
+    ui.println(table.to_string());
+    Ok(())
+}
+
+/// Save a profile as a template
+pub fn template_save(paths: &Paths, profile: &str, name: &str, ui: &Ui) -> Result<()> {
+    paths.ensure_dirs()?;
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let (template, report) = crate::templates::save_template(paths, profile, name)?;
+    warn_copies(&report, ui);
+
+    ui.ok(format!(
+        "Saved profile '{}' as template '{}' at {}",
+        profile,
+        name,
+        template.display()
+    ));
+    ui.newline();
+    ui.note("Create a profile from it with:");
+    ui.note(format!("  ccprof add <name> --template {}", name));
+    Ok(())
+}
+
+/// Write one profile, or all of them, to an archive
+pub fn export(
+    paths: &Paths,
+    name: Option<&str>,
+    with_state: bool,
+    file: Option<&Path>,
+    ui: &Ui,
+) -> Result<()> {
+    // The archive is put together in the staging directory
+    paths.ensure_writable()?;
+    let (names, default_file) = match name {
+        Some(name) => {
+            let resolved = resolve_name(paths, name, ui.exact_names)?;
+            let file = format!(
+                "ccprof-{}-{}.tar.gz",
+                resolved,
+                chrono::Local::now().format("%Y%m%d")
+            );
+            (vec![resolved], file)
+        }
+        None => (
+            list_profiles(paths)?,
+            format!(
+                "ccprof-export-{}.tar.gz",
+                chrono::Local::now().format("%Y%m%d")
+            ),
+        ),
+    };
+    let file = file.map_or_else(|| PathBuf::from(default_file), Path::to_path_buf);
+    let (manifest, report) = crate::bundle::export_profiles(paths, &names, with_state, &file)?;
+    warn_copies(&report, ui);
+
+    ui.ok(format!(
+        "Exported {} profile(s) to {}",
+        manifest.profiles.len(),
+        file.display()
+    ));
+    let bases: Vec<&str> = manifest
+        .profiles
+        .iter()
+        .filter(|p| !names.contains(p))
+        .map(String::as_str)
+        .collect();
+    if !bases.is_empty() {
+        ui.info(format!(
+            "Included {} (extended by the exported profile)",
+            bases.join(", ")
+        ));
+    }
+    if !manifest.shared.is_empty() {
+        ui.info(format!("Included shared: {}", manifest.shared.join(", ")));
+    }
+    ui.newline();
+    ui.note("Import it on another machine with:");
+    ui.note(format!("  ccprof import {}", file.display()));
+    Ok(())
+}
+
+/// Add the profiles in an archive written by `export`
+pub fn import(
+    paths: &Paths,
+    archive: &Path,
+    policy: crate::bundle::ConflictPolicy,
+    ui: &Ui,
+) -> Result<()> {
+    use crate::bundle::{Bundle, ConflictPolicy, ImportOutcome};
+
+    let bundle = Bundle::open(paths, archive)?;
+    let conflicts = bundle.conflicts(paths);
+    if policy == ConflictPolicy::Overwrite && !conflicts.is_empty() {
+        let confirm = ui.confirm(
+            &format!("Replace existing profile(s) {}?", conflicts.join(", ")),
+            Some(&format!(
+                "Each is copied to {} first",
+                paths.archive_dir.display()
+            )),
+            false,
+        )?;
+        if !confirm {
+            ui.warn("Import cancelled.");
+            return Ok(());
+        }
+    }
+    let report = with_lock_spinner(ui, |on_wait| bundle.import(paths, policy, on_wait))?;
+    warn_copies(&report.copies, ui);
+
+    let mut table = ui.simple_table();
+    table.set_header(vec![ui.header_cell("Profile"), ui.header_cell("Result")]);
+    for (name, outcome) in &report.profiles {
+        let (profile, result) = match outcome {
+            ImportOutcome::Imported => {
+                (name.clone(), ui.colored_cell("imported", AnsiColor::Green))
+            }
+            ImportOutcome::Replaced(_) => {
+                (name.clone(), ui.colored_cell("replaced", AnsiColor::Yellow))
+            }
+            ImportOutcome::Renamed(local) => (
+                format!("{} → {}", name, local),
+                ui.colored_cell("renamed", AnsiColor::Cyan),
+            ),
+            ImportOutcome::Skipped => (name.clone(), ui.cell("skipped (exists)")),
+        };
+        table.add_row(vec![ui.cell(profile), result]);
+    }
+    ui.println(table.to_string());
+
+    let imported = report
+        .profiles
+        .iter()
+        .filter(|(_, outcome)| *outcome != ImportOutcome::Skipped)
+        .count();
+    ui.ok(format!(
+        "Imported {} of {} profile(s) from {} (exported {} by ccprof {})",
+        imported,
+        report.profiles.len(),
+        archive.display(),
+        report.manifest.exported_at.format("%Y-%m-%d"),
+        report.manifest.tool_version
+    ));
+    for (name, outcome) in &report.profiles {
+        if let ImportOutcome::Replaced(backup) = outcome {
+            ui.info(format!(
+                "The previous '{}' is kept in {}",
+                name,
+                backup.display()
+            ));
+        }
+    }
+    if !report.shared.is_empty() {
+        ui.info(format!("Added shared: {}", report.shared.join(", ")));
+    }
+    if let Some(profile) = report.exported_profile {
+        ui.newline();
+        ui.note(format!(
+            "'{}' was active on the exporting machine. Switch to it with:",
+            profile
+        ));
+        ui.note(format!("  ccprof use {}", profile));
+    }
+    Ok(())
+}
+
+/// Create a profile from one in a git repository
+pub fn clone(
+    paths: &Paths,
+    url: &str,
+    name: Option<&str>,
+    path: Option<&str>,
+    ui: &Ui,
+) -> Result<()> {
+    paths.ensure_dirs()?;
+    let name = name.map_or_else(|| crate::upstream::default_name(url, path), str::to_string);
+    let spinner = ui.spinner(format!("Cloning {}", url));
+    let (components, report) = match crate::upstream::clone_profile(paths, url, &name, path) {
+        Ok(cloned) => {
+            spinner.finish_and_clear();
+            cloned
+        }
+        Err(e) => {
+            spinner.finish_and_clear();
+            return Err(e);
+        }
+    };
+    warn_copies(&report, ui);
+    crate::profiles::record_claude_version(paths, &name)?;
+
+    ui.ok(format!("Created profile '{}' from {}", name, url));
+    ui.newline();
+    ui.note("Included components:");
+    for component in Component::all()
+        .into_iter()
+        .filter(|c| components.contains(c))
+    {
+        ui.note(format!("  {} {}", ui.icon_ok(), component.display_name()));
+    }
+    ui.newline();
+    ui.note("To activate it, or update it from the repository later:");
+    ui.note(format!("  ccprof use {}", name));
+    ui.note(format!("  ccprof pull {}", name));
+    Ok(())
+}
+
+/// Update a cloned profile from its repository, after showing the changes
+pub fn pull(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    let metadata = crate::components::ProfileMetadata::read(&paths.profile_dir(name))?;
+    let Some(upstream) = metadata.upstream else {
         bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
+            "Profile '{}' has no upstream repository\nHint: Only profiles created with 'ccprof clone' can be pulled.",
             name
         );
+    };
+
+    let spinner = ui.spinner(format!("Fetching {}", upstream.url));
+    let fetched = crate::upstream::Checkout::fetch(paths, &upstream.url, upstream.path.as_deref());
+    spinner.finish_and_clear();
+    let checkout = fetched?;
+    let changed = checkout.changed_components(paths, name)?;
+    if changed.is_empty() {
+        ui.ok(format!(
+            "Profile '{}' is up to date with {}",
+            name, upstream.url
+        ));
+        return Ok(());
+    }
+
+    for component in &changed {
+        ui.section(component.display_name());
+        let local = component.profile_path(paths, name);
+        let incoming = checkout.component_path(*component);
+        if !local.exists() {
+            ui.println("  + new from upstream");
+            ui.newline();
+        } else if component.is_file() {
+            let file = local.file_name().unwrap_or_default().to_string_lossy();
+            print_file_diff(
+                &local,
+                &incoming,
+                &format!("{}/{}", name, file),
+                &format!("upstream/{}", file),
+                ui,
+            );
+        } else {
+            diff_directories(&local, &incoming, name, "upstream", true, ui)?;
+        }
+    }
+
+    let confirm = ui.confirm(
+        &format!("Apply these changes to '{}'?", name),
+        Some("Components not in the repository are kept"),
+        false,
+    )?;
+    if !confirm {
+        ui.warn("Pull cancelled.");
+        return Ok(());
+    }
+    let report = crate::upstream::apply_pull(paths, name, &checkout, &changed)?;
+    warn_copies(&report, ui);
+    let names: Vec<&str> = changed.iter().map(|c| c.short_name()).collect();
+    ui.ok(format!("Updated {} of '{}'", names.join(", "), name));
+    Ok(())
+}
+
+/// List shared items and the profiles referencing them
+pub fn shared_list(paths: &Paths, ui: &Ui) -> Result<()> {
+    let items = crate::shared::list_shared(paths)?;
+
+    if items.is_empty() {
+        ui.warn("No shared items found.");
+        ui.newline();
+        ui.note("Create one with:");
+        ui.note(format!(
+            "  {} shared create <name> --component agents",
+            ui.bold("ccprof")
+        ));
+        return Ok(());
+    }
+
+    ui.section("Shared Items");
+    ui.newline();
+
+    let mut table = ui.simple_table();
+    table.set_header(vec![
+        ui.header_cell("Name"),
+        ui.header_cell("Size"),
+        ui.header_cell("Referenced by"),
+    ]);
+
+    for item in &items {
+        let references = if item.references.is_empty() {
+            ui.colored_cell("(unused)", AnsiColor::Yellow)
+        } else {
+            let users: Vec<String> = item
+                .references
+                .iter()
+                .map(|(profile, component)| format!("{} ({})", profile, component.short_name()))
+                .collect();
+            ui.cell(users.join(", "))
+        };
+
+        table.add_row(vec![
+            ui.cell(&item.name),
+            ui.number_cell(calculate_size(&item.path)?),
+            references,
+        ]);
+    }
+
+    ui.println(table.to_string());
+    Ok(())
+}
+
+/// Create a shared item
+pub fn shared_create(
+    paths: &Paths,
+    name: &str,
+    component: &str,
+    from: Option<&str>,
+    ui: &Ui,
+) -> Result<()> {
+    paths.ensure_dirs()?;
+    let comp = parse_component(component)?;
+
+    let resolved = from
+        .map(|profile| resolve_name(paths, profile, ui.exact_names))
+        .transpose()?;
+    let from = resolved.as_deref();
+
+    let (item, report) = crate::shared::create_shared(paths, name, comp, from)?;
+    warn_copies(&report, ui);
+
+    ui.ok(format!(
+        "Created shared item '{}' at {}",
+        name,
+        item.display()
+    ));
+    ui.newline();
+    ui.note("Reference it from a profile with:");
+    ui.note(format!(
+        "  ccprof shared link <profile> {} --component {}",
+        name,
+        comp.short_name()
+    ));
+    Ok(())
+}
+
+/// Make a profile reference a shared item
+pub fn shared_link(
+    paths: &Paths,
+    profile: &str,
+    name: &str,
+    component: &str,
+    ui: &Ui,
+) -> Result<()> {
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let comp = parse_component(component)?;
+
+    crate::shared::link_shared(paths, profile, comp, name)?;
+
+    ui.ok(format!(
+        "Profile '{}' now uses shared item '{}' for {}",
+        profile,
+        name,
+        comp.short_name()
+    ));
+    Ok(())
+}
+
+/// Stop a profile referencing a shared item
+pub fn shared_unlink(paths: &Paths, profile: &str, component: &str, ui: &Ui) -> Result<()> {
+    let resolved = resolve_name(paths, profile, ui.exact_names)?;
+    let profile = resolved.as_str();
+    let comp = parse_component(component)?;
+
+    let report = crate::shared::unlink_shared(paths, profile, comp)?;
+    warn_copies(&report, ui);
+
+    ui.ok(format!(
+        "Profile '{}' now uses its own copy of {}",
+        profile,
+        comp.short_name()
+    ));
+    Ok(())
+}
+
+/// Remove a shared item that no profile references
+pub fn shared_remove(paths: &Paths, name: &str, ui: &Ui) -> Result<()> {
+    crate::shared::remove_shared(paths, name)?;
+    ui.ok(format!("Removed shared item '{}'", name));
+    Ok(())
+}
+
+/// Parse a component name with a helpful error
+fn parse_component(component: &str) -> Result<Component> {
+    component.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid component: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
+            component
+        )
+    })
+}
+
+/// Report profiles with identical content; with `interactive`, offer to
+/// remove or archive the duplicates
+///
+/// Profiles referenced by state (selected, linked or copied) are never touched.
+pub fn dedupe(paths: &Paths, ui: &Ui, interactive: bool) -> Result<()> {
+    let groups = crate::dedupe::duplicate_groups(paths)?;
+    if groups.is_empty() {
+        ui.ok("No duplicate profiles found");
+        return Ok(());
+    }
+
+    let state = State::read(&paths.state_file).unwrap_or_default();
+
+    ui.section("Duplicate Profiles");
+    for (i, group) in groups.iter().enumerate() {
+        ui.newline();
+        ui.println(ui.bold(format!("Group {} (identical content):", i + 1)));
+        let width = group.iter().map(|n| n.len()).max().unwrap_or(0);
+        for name in group {
+            ui.println(format!(
+                "  {:width$}  {}",
+                name,
+                describe_references(&state, name).join(", "),
+                width = width
+            ));
+        }
+    }
+    ui.newline();
+
+    if !interactive {
+        ui.info(format!(
+            "{} group(s) of identical profiles. Run 'ccprof dedupe --interactive' to remove or archive duplicates.",
+            groups.len()
+        ));
+        return Ok(());
+    }
+
+    for group in &groups {
+        let (protected, mut candidates): (Vec<&String>, Vec<&String>) =
+            group.iter().partition(|name| is_referenced(&state, name));
+
+        // Keep at least one profile of each group
+        if protected.is_empty() {
+            let mut by_use = candidates.clone();
+            by_use.sort_by_key(|name| std::cmp::Reverse(state.last_used.get(*name)));
+            let options: Vec<String> = by_use.iter().map(|name| name.to_string()).collect();
+            let keep = by_use[ui.select(
+                &format!("Which of {} should be kept?", group.join(", ")),
+                &options,
+            )?];
+            candidates.retain(|name| *name != keep);
+        } else {
+            let kept: Vec<&str> = protected.iter().map(|n| n.as_str()).collect();
+            ui.info(format!("Keeping {} (in use)", kept.join(", ")));
+        }
+
+        for name in candidates {
+            let action = ui.select(
+                &format!("Duplicate profile '{}':", name),
+                &[
+                    "Keep".to_string(),
+                    "Archive".to_string(),
+                    "Remove".to_string(),
+                ],
+            )?;
+            match action {
+                1 => {
+                    let archived = with_lock_spinner(ui, |on_wait| {
+                        crate::profiles::archive_profile(paths, name, on_wait)
+                    })?;
+                    ui.ok(format!("Archived '{}' to {}", name, archived.display()));
+                }
+                2 => {
+                    with_lock_spinner(ui, |on_wait| {
+                        crate::profiles::remove_profile(paths, name, on_wait)
+                    })?;
+                    ui.ok(format!("Removed profile '{}'", name));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether state points at a profile (selected, linked or copied)
+fn is_referenced(state: &State, name: &str) -> bool {
+    state.default_profile.as_deref() == Some(name)
+        || state.active_components.values().any(|p| p == name)
+        || state.copies.values().any(|c| c.profile == name)
+}
+
+/// How state and history refer to a profile, for `dedupe`
+fn describe_references(state: &State, name: &str) -> Vec<String> {
+    let mut notes = Vec::new();
+    if state.default_profile.as_deref() == Some(name) {
+        notes.push("selected".to_string());
+    }
+    let linked: Vec<&str> = Component::all()
+        .into_iter()
+        .filter(|c| state.active_components.get(c).is_some_and(|p| p == name))
+        .map(|c| c.short_name())
+        .collect();
+    if !linked.is_empty() {
+        notes.push(format!("active for {}", linked.join(", ")));
     }
+    if state.copies.values().any(|c| c.profile == name) {
+        notes.push("copied into ~/.claude".to_string());
+    }
+    notes.push(match state.last_used.get(name) {
+        Some(time) => format!("last used {}", format_time_ago(*time)),
+        None => "never used".to_string(),
+    });
+    notes
+}
+
+/// Remove a profile
+pub fn remove(paths: &Paths, name: &str, ui: &Ui, force: bool, dry_run: bool) -> Result<()> {
+    let resolved = resolve_name(paths, name, ui.exact_names)?;
+    let name = resolved.as_str();
+    ensure_not_being_edited(paths, name, force, ui)?;
 
     // Check if this is the active profile
     let state = State::read(&paths.state_file).unwrap_or_default();
@@ -905,13 +3183,33 @@ pub fn remove(paths: &Paths, name: &str, ui: &Ui, force: bool) -> Result<()> {
         );
     }
 
+    // Components can still link here after a partial switch
+    let linked: Vec<_> = Component::all()
+        .into_iter()
+        .filter(|c| LiveSource::detect(paths, *c, &state).profile() == Some(name))
+        .map(|c| c.display_name())
+        .collect();
+    if !linked.is_empty() {
+        bail!(
+            "Cannot remove '{}' because ~/.claude still uses it for: {}.\nHint: Switch to another profile first with 'ccprof use <other-profile>'.",
+            name,
+            linked.join(", ")
+        );
+    }
+
+    if dry_run {
+        let changes = crate::profiles::plan_remove_profile(paths, name)?;
+        print_plan(&format!("Removing profile '{}'", name), &changes, ui);
+        return Ok(());
+    }
+
     // Confirm unless --force
     if !force {
-        let confirm = inquire::Confirm::new(&format!("Are you sure you want to remove profile '{}'?", name))
-            .with_default(false)
-            .with_help_message("This will permanently delete the profile and all its settings")
-            .prompt()
-            .context("Confirmation cancelled")?;
+        let confirm = ui.confirm(
+            &format!("Are you sure you want to remove profile '{}'?", name),
+            Some("This will permanently delete the profile and all its settings"),
+            false,
+        )?;
 
         if !confirm {
             ui.warn("Removal cancelled.");
@@ -920,39 +3218,80 @@ pub fn remove(paths: &Paths, name: &str, ui: &Ui, force: bool) -> Result<()> {
     }
 
     // Remove the profile
-    crate::profiles::remove_profile(paths, name)?;
-
-    ui.ok(format!("Removed profile '{}'", name));
-    Ok(())
-}
+    with_lock_spinner(ui, |on_wait| {
+        crate::profiles::remove_profile(paths, name, on_wait)
+    })?;
 
-/// Compare two profiles
-pub fn diff(paths: &Paths, profile1: &str, profile2: &str, component: &str, ui: &Ui) -> Result<()> {
-    // Validate both profiles exist
-    if !profile_exists(paths, profile1) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            profile1
-        );
-    }
-    if !profile_exists(paths, profile2) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            profile2
-        );
-    }
+    ui.ok(format!("Removed profile '{}'", name));
+    Ok(())
+}
+
+/// Flags of `ccprof diff`
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Compare settings after merging the profiles they extend
+    pub resolved: bool,
+    /// Print a unified diff of each changed file in a directory component
+    pub show_content: bool,
+    /// Print nothing, only return whether they differ
+    pub quiet: bool,
+    /// Settings keys left out of the comparison, see [`is_ignored_key`]
+    pub ignore: Vec<String>,
+}
+
+/// Compare a component of two profiles, returning whether it differs
+pub fn diff(
+    paths: &Paths,
+    profile1: &str,
+    profile2: &str,
+    component: &str,
+    options: &DiffOptions,
+    ui: &Ui,
+) -> Result<bool> {
+    let &DiffOptions {
+        resolved,
+        show_content,
+        quiet,
+        ..
+    } = options;
+    let (resolved1, resolved2) = (
+        resolve_name(paths, profile1, ui.exact_names)?,
+        resolve_name(paths, profile2, ui.exact_names)?,
+    );
+    let (profile1, profile2) = (resolved1.as_str(), resolved2.as_str());
 
     // Parse component
     let comp: Component = component.parse().map_err(|_| {
         anyhow::anyhow!(
-            "Invalid component: '{}'\nHint: Valid components are settings, agents, hooks, commands",
+            "Invalid component: '{}'\nHint: Valid components are settings, agents, hooks, commands, env",
             component
         )
     })?;
 
+    if show_content && comp.is_file() {
+        bail!("--show-content only applies to directory components");
+    }
+    if resolved {
+        if comp != Component::Settings {
+            bail!("--resolved only applies to settings");
+        }
+        let json1 = crate::inheritance::resolve_settings(paths, profile1)?;
+        let json2 = crate::inheritance::resolve_settings(paths, profile2)?;
+        if !quiet {
+            ui.section(format!(
+                "Comparing resolved settings between '{}' and '{}'",
+                profile1, profile2
+            ));
+            ui.newline();
+        }
+        return diff_json_values(&json1, &json2, profile1, profile2, options, ui);
+    }
+
     // Get paths to the component in each profile
-    let path1 = comp.profile_path(paths, profile1);
-    let path2 = comp.profile_path(paths, profile2);
+    let metadata1 = crate::components::ProfileMetadata::read(&paths.profile_dir(profile1))?;
+    let metadata2 = crate::components::ProfileMetadata::read(&paths.profile_dir(profile2))?;
+    let path1 = metadata1.component_path(paths, profile1, comp);
+    let path2 = metadata2.component_path(paths, profile2, comp);
 
     // Check if component exists in both profiles
     if !path1.exists() {
@@ -970,17 +3309,139 @@ pub fn diff(paths: &Paths, profile1: &str, profile2: &str, component: &str, ui:
         );
     }
 
-    ui.section(format!("Comparing {} between '{}' and '{}'", comp.display_name(), profile1, profile2));
-    ui.newline();
+    if !quiet {
+        ui.section(format!(
+            "Comparing {} between '{}' and '{}'",
+            comp.display_name(),
+            profile1,
+            profile2
+        ));
+        ui.newline();
+    }
 
-    if comp.is_file() {
+    if comp == Component::Settings {
         // Compare JSON files
-        diff_json_files(&path1, &path2, profile1, profile2, ui)?;
+        diff_json_files(&path1, &path2, profile1, profile2, options, ui)
+    } else if comp.is_file() {
+        let differs = !crate::fs_utils::same_content(&path1, &path2);
+        if differs && !quiet {
+            let file = comp.file_name();
+            print_file_diff(
+                &path1,
+                &path2,
+                &format!("{}/{}", profile1, file),
+                &format!("{}/{}", profile2, file),
+                ui,
+            );
+        } else if !quiet {
+            ui.ok("Files are identical");
+        }
+        Ok(differs)
+    } else if quiet {
+        Ok(!crate::fs_utils::compare_dirs(&path1, &path2)?.is_identical())
     } else {
         // Compare directories
-        diff_directories(&path1, &path2, profile1, profile2, ui)?;
+        diff_directories(&path1, &path2, profile1, profile2, show_content, ui)
+    }
+}
+
+/// Merge `overlay` over `base` into a new profile `into`, or only show how with `dry_run`
+pub fn merge(
+    paths: &Paths,
+    base: &str,
+    overlay: &str,
+    into: &str,
+    options: &MergeOptions,
+    dry_run: bool,
+    ui: &Ui,
+) -> Result<()> {
+    crate::profiles::validate_profile_name(into)?;
+    if profile_exists(paths, into) {
+        return Err(Error::ProfileExists {
+            name: into.to_string(),
+        }
+        .into());
+    }
+    let (resolved_base, resolved_overlay) = (
+        resolve_name(paths, base, ui.exact_names)?,
+        resolve_name(paths, overlay, ui.exact_names)?,
+    );
+    let (base, overlay) = (resolved_base.as_str(), resolved_overlay.as_str());
+    let plan = plan_merge(paths, base, overlay, options)?;
+
+    if dry_run {
+        ui.section(format!(
+            "Merging '{}' over '{}' into '{}' (dry run)",
+            overlay, base, into
+        ));
+        ui.newline();
+        if plan.settings.is_none() {
+            ui.info("Neither profile manages settings");
+        } else if plan.decisions.is_empty() {
+            ui.ok("Settings are identical");
+        } else {
+            let mut table = ui.table();
+            table.set_header(vec![
+                ui.header_cell("Key"),
+                ui.header_cell(base),
+                ui.header_cell(overlay),
+                ui.header_cell("Result"),
+            ]);
+            for decision in &plan.decisions {
+                let secret = ui.redactor.is_secret_path(&decision.key);
+                let result = match decision.decision {
+                    Decision::FromBase => format!("from '{}'", base),
+                    Decision::FromOverlay => format!("from '{}'", overlay),
+                    Decision::OverlayWins => format!("'{}' wins", overlay),
+                    Decision::Union => "union".to_string(),
+                };
+                table.add_row(vec![
+                    ui.cell(&decision.key),
+                    ui.cell(format_json_value(&decision.base, secret)),
+                    ui.cell(format_json_value(&decision.overlay, secret)),
+                    ui.cell(result),
+                ]);
+            }
+            ui.println(table.to_string());
+            ui.newline();
+        }
+        for decision in &plan.components {
+            let from = match decision.from {
+                Side::Base => base,
+                Side::Overlay => overlay,
+            };
+            ui.println(format!(
+                "  {} from '{}'{}",
+                decision.component.display_name(),
+                from,
+                if decision.conflict {
+                    " (both have it, --prefer chose)"
+                } else {
+                    ""
+                }
+            ));
+        }
+        ui.newline();
+        ui.note(format!(
+            "Nothing was written; run without --dry-run to create '{}'.",
+            into
+        ));
+        return Ok(());
     }
 
+    let report = apply_merge(paths, &plan, into)?;
+    warn_copies(&report, ui);
+    crate::profiles::record_claude_version(paths, into)?;
+    ui.ok(format!(
+        "Created profile '{}' from '{}' and '{}' ({} settings key(s) merged)",
+        into,
+        base,
+        overlay,
+        plan.decisions.len()
+    ));
+    ui.newline();
+    ui.note("To activate it:");
+    ui.note(format!("  ccprof use {}", into));
     Ok(())
 }
 
@@ -990,30 +3451,61 @@ fn diff_json_files(
     path2: &std::path::Path,
     name1: &str,
     name2: &str,
+    options: &DiffOptions,
     ui: &Ui,
-) -> Result<()> {
+) -> Result<bool> {
     let content1 = std::fs::read_to_string(path1)
         .with_context(|| format!("Failed to read {}", path1.display()))?;
     let content2 = std::fs::read_to_string(path2)
         .with_context(|| format!("Failed to read {}", path2.display()))?;
 
-    let json1: serde_json::Value = serde_json::from_str(&content1)
+    let json1 = crate::jsonc::parse(&content1)
         .with_context(|| format!("Failed to parse JSON from {}", path1.display()))?;
-    let json2: serde_json::Value = serde_json::from_str(&content2)
+    let json2 = crate::jsonc::parse(&content2)
         .with_context(|| format!("Failed to parse JSON from {}", path2.display()))?;
-
-    if json1 == json2 {
-        ui.ok("Files are identical");
-        return Ok(())
+    for (path, doc) in [(path1, &json1), (path2, &json2)] {
+        if doc.lenient {
+            ui.warn(format!(
+                "{} has comments or trailing commas; strict JSON is safer",
+                path.display()
+            ));
+        }
     }
 
+    diff_json_values(&json1.value, &json2.value, name1, name2, options, ui)
+}
+
+/// Display the differences between two JSON documents outside the ignored
+/// keys (nothing with `quiet`), returning whether there are any
+fn diff_json_values(
+    json1: &serde_json::Value,
+    json2: &serde_json::Value,
+    name1: &str,
+    name2: &str,
+    options: &DiffOptions,
+    ui: &Ui,
+) -> Result<bool> {
     // Find differences
     let mut differences = Vec::new();
-    compare_json_values(&json1, &json2, "", &mut differences);
+    if json1 != json2 {
+        compare_json_values(json1, json2, "", &mut differences);
+    }
+    let found = differences.len();
+    differences.retain(|(key, _, _)| !is_ignored_key(&options.ignore, key));
+    let ignored = found - differences.len();
 
+    if options.quiet {
+        return Ok(!differences.is_empty());
+    }
     if differences.is_empty() {
-        ui.ok("Files are identical");
-        return Ok(())
+        match ignored {
+            0 => ui.ok("Files are identical"),
+            n => ui.ok(format!(
+                "No differences outside ignored keys ({} ignored)",
+                n
+            )),
+        }
+        return Ok(false);
     }
 
     // Display differences
@@ -1021,14 +3513,52 @@ fn diff_json_files(
     table.set_header(vec![ui.header_cell("Key"), ui.header_cell(name1), ui.header_cell(name2)]);
 
     for (key, val1, val2) in &differences {
-        table.add_row(vec![ui.cell(key), ui.cell(format_json_value(val1)), ui.cell(format_json_value(val2))]);
+        let secret = ui.redactor.is_secret_path(key);
+        let [left, right] = diff_value_cells(ui, val1, val2, secret);
+        table.add_row(vec![ui.cell(key), left, right]);
     }
 
     ui.println(table.to_string());
     ui.newline();
-    ui.info(format!("{} difference(s) found", differences.len()));
+    match ignored {
+        0 => ui.info(format!("{} difference(s) found", differences.len())),
+        n => ui.info(format!(
+            "{} difference(s) found, {} more in ignored keys",
+            differences.len(),
+            n
+        )),
+    }
 
-    Ok(())
+    Ok(true)
+}
+
+/// Whether a dotted settings key is, or lies under, one matched by a pattern
+///
+/// Patterns are dotted keys too, where `*` stands for any characters within
+/// one key: `env.*` ignores every variable, `feedback*` every top-level key
+/// starting with "feedback".
+fn is_ignored_key(patterns: &[String], key: &str) -> bool {
+    let key: Vec<&str> = key.split('.').collect();
+    patterns.iter().any(|pattern| {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        pattern.len() <= key.len()
+            && pattern
+                .iter()
+                .zip(&key)
+                .all(|(pattern, key)| wildcard_match(pattern, key))
+    })
+}
+
+/// Match `text` against `pattern`, where `*` matches any characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((head, tail)) => text.strip_prefix(head).is_some_and(|rest| {
+            (0..=rest.len())
+                .filter(|&i| rest.is_char_boundary(i))
+                .any(|i| wildcard_match(tail, &rest[i..]))
+        }),
+    }
 }
 
 /// Recursively compare JSON values and collect differences
@@ -1084,158 +3614,255 @@ fn compare_json_values(
                 }
             }
             
-            /// Format a JSON value for display (truncate if too long)
-fn format_json_value(val: &Option<serde_json::Value>) -> String {
+/// Longest JSON value shown in a diff or merge table
+const JSON_VALUE_WIDTH: usize = 50;
+
+/// Format a JSON value for display (truncate if too long, hide if `secret`)
+fn format_json_value(val: &Option<serde_json::Value>, secret: bool) -> String {
     match val {
         None => "(missing)".to_string(),
         Some(v) => {
             let s = match v {
+                serde_json::Value::String(s) if secret => format!("\"{}\"", redact_str(s)),
+                serde_json::Value::Number(n) if secret => redact_str(&n.to_string()),
                 serde_json::Value::String(s) => format!("\"{}\"", s),
                 serde_json::Value::Array(arr) => format!("[{} items]", arr.len()),
                 serde_json::Value::Object(obj) => format!("{{...}} ({} keys)", obj.len()),
                 other => other.to_string(),
             };
-            if s.len() > 50 {
-                format!("{}...", &s[..47])
-            } else {
-                s
-            }
+            crate::ui::truncate(&s, JSON_VALUE_WIDTH)
         }
     }
 }
 
+/// Cells for a value that differs: red for the first profile, green for the
+/// second and "(missing)" dimmed
+///
+/// When both are strings, the start they share is dimmed so the change stands
+/// out. Without colors the cells are plain text.
+fn diff_value_cells(
+    ui: &Ui,
+    val1: &Option<serde_json::Value>,
+    val2: &Option<serde_json::Value>,
+    secret: bool,
+) -> [comfy_table::Cell; 2] {
+    use serde_json::Value;
+
+    let shared = match (val1, val2) {
+        (Some(Value::String(a)), Some(Value::String(b))) if !secret => {
+            a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+        }
+        _ => 0,
+    };
+    [(val1, AnsiColor::Red), (val2, AnsiColor::Green)].map(|(val, color)| {
+        let text = format_json_value(val, secret);
+        if val.is_none() {
+            return ui.cell(ui.dim(text));
+        }
+        if shared == 0 {
+            return ui.colored_cell(text, color);
+        }
+        // One more for the opening quote
+        let split = text
+            .char_indices()
+            .nth(shared + 1)
+            .map_or(text.len(), |(i, _)| i);
+        let (common, changed) = text.split_at(split);
+        ui.cell(format!("{}{}", ui.dim(common), ui.colored(changed, color)))
+    })
+}
+
 /// Compare two directories and list differences
 fn diff_directories(
     path1: &std::path::Path,
     path2: &std::path::Path,
     name1: &str,
     name2: &str,
+    show_content: bool,
     ui: &Ui,
-) -> Result<()> {
-    use std::collections::HashSet;
-
-    let files1: HashSet<String> = std::fs::read_dir(path1)?
-        .filter_map(|e| e.ok())
-        .filter_map(|e| e.file_name().to_str().map(String::from))
-        .collect();
-
-    let files2: HashSet<String> = std::fs::read_dir(path2)?
-        .filter_map(|e| e.ok())
-        .filter_map(|e| e.file_name().to_str().map(String::from))
-        .collect();
+) -> Result<bool> {
+    let comparison = crate::fs_utils::compare_dirs(path1, path2)?;
+    let only_in_1 = &comparison.only_in_left;
+    let only_in_2 = &comparison.only_in_right;
+    let content_diffs = &comparison.changed;
 
-    let only_in_1: Vec<_> = files1.difference(&files2).collect();
-    let only_in_2: Vec<_> = files2.difference(&files1).collect();
-    let in_both: Vec<_> = files1.intersection(&files2).collect();
-
-    let mut has_diff = false;
+    let has_diff = !comparison.is_identical();
 
     if !only_in_1.is_empty() {
-        has_diff = true;
         ui.println(format!("Only in '{}':", name1));
-        for f in &only_in_1 {
+        for f in only_in_1 {
             ui.println(format!("  - {}", f));
         }
         ui.newline();
     }
 
     if !only_in_2.is_empty() {
-        has_diff = true;
         ui.println(format!("Only in '{}':", name2));
-        for f in &only_in_2 {
+        for f in only_in_2 {
             ui.println(format!("  + {}", f));
         }
         ui.newline();
     }
 
-    // Check content differences for files in both
-    let mut content_diffs = Vec::new();
-    for file in &in_both {
-        let p1 = path1.join(file);
-        let p2 = path2.join(file);
-
-        if p1.is_file() && p2.is_file() {
-            let c1 = std::fs::read(&p1).unwrap_or_default();
-            let c2 = std::fs::read(&p2).unwrap_or_default();
-            if c1 != c2 {
-                content_diffs.push(file.as_str());
-            }
-        }
-    }
-
     if !content_diffs.is_empty() {
-        has_diff = true;
         ui.println("Files with different content:");
-        for f in &content_diffs {
+        for f in content_diffs {
             ui.println(format!("  ~ {}", f));
         }
         ui.newline();
     }
 
+    if show_content {
+        for f in content_diffs {
+            print_file_diff(
+                &path1.join(f),
+                &path2.join(f),
+                &format!("{}/{}", name1, f),
+                &format!("{}/{}", name2, f),
+                ui,
+            );
+        }
+    }
+
     if !has_diff {
         ui.ok("Directories are identical");
     } else {
         ui.info(format!("{} only in {}, {} only in {}, {} different", only_in_1.len(), name1, only_in_2.len(), name2, content_diffs.len()));
     }
 
-    Ok(())
+    Ok(has_diff)
 }
 
-/// Rename a profile
-pub fn rename(paths: &Paths, old_name: &str, new_name: &str, ui: &Ui) -> Result<()> {
-    if !profile_exists(paths, old_name) {
-        bail!(
-            "Profile '{}' does not exist.\nHint: Use 'ccprof list' to see available profiles.",
-            old_name
-        );
+/// Print a unified diff of two text files, with secret-looking values hidden
+fn print_file_diff(left: &Path, right: &Path, left_name: &str, right_name: &str, ui: &Ui) {
+    // Binary files, or a file where the other side has a directory, have no line diff
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .filter(|text| !text.contains('\0'))
+    };
+    let (Some(old), Some(new)) = (read(left), read(right)) else {
+        ui.println(format!(
+            "Binary files {} and {} differ",
+            left_name, right_name
+        ));
+        ui.newline();
+        return;
+    };
+    print_text_diff(&old, &new, left_name, right_name, ui);
+}
+
+/// Print a colored unified diff of two texts, with secrets redacted
+fn print_text_diff(old: &str, new: &str, left_name: &str, right_name: &str, ui: &Ui) {
+    let diff = similar::TextDiff::from_lines(old, new);
+    let unified = diff
+        .unified_diff()
+        .context_radius(3)
+        .header(left_name, right_name)
+        .to_string();
+    for line in unified.lines() {
+        let (marker, rest) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+        let redacted = format!("{}{}", marker, ui.redactor.redact_line(rest));
+        ui.println(match marker {
+            _ if line.starts_with("---") || line.starts_with("+++") => ui.bold(line),
+            "@" => ui.colored(line, AnsiColor::Cyan),
+            "-" => ui.colored(redacted, AnsiColor::Red),
+            "+" => ui.colored(redacted, AnsiColor::Green),
+            _ => redacted,
+        });
     }
+    ui.newline();
+}
+
+/// Rename a profile
+pub fn rename(
+    paths: &Paths,
+    old_name: &str,
+    new_name: &str,
+    force: bool,
+    dry_run: bool,
+    ui: &Ui,
+) -> Result<()> {
+    let resolved = resolve_name(paths, old_name, ui.exact_names)?;
+    let old_name = resolved.as_str();
+    ensure_not_being_edited(paths, old_name, force, ui)?;
 
     if profile_exists(paths, new_name) {
-        bail!(
-            "Profile '{}' already exists.\nHint: Choose a different name or remove the existing profile first.",
-            new_name
-        );
+        return Err(Error::ProfileExists {
+            name: new_name.to_string(),
+        }
+        .into());
     }
 
     // Validate new name
     crate::profiles::validate_profile_name(new_name)?;
 
-    // Check if this is the active profile
+    // Live components that link into the profile, whether or not it is selected
     let state = State::read(&paths.state_file).unwrap_or_default();
-    let is_active = state.default_profile.as_deref() == Some(old_name);
+    let linked: Vec<Component> = Component::all()
+        .into_iter()
+        .filter(|c| {
+            !state.copies.contains_key(c)
+                && LiveSource::detect(paths, *c, &state).profile() == Some(old_name)
+        })
+        .collect();
 
-    // Rename the profile directory
-    crate::profiles::rename_profile(paths, old_name, new_name)?;
+    if dry_run {
+        let mut changes = crate::profiles::plan_rename_profile(paths, old_name, new_name)?;
+        let metadata = crate::components::ProfileMetadata::read(&paths.profile_dir(old_name))?;
+        changes.extend(linked.iter().map(|c| Change::Link {
+            link: c.source_path(paths),
+            target: metadata.link_path(paths, new_name, *c),
+        }));
+        print_plan(
+            &format!("Renaming profile '{}' to '{}'", old_name, new_name),
+            &changes,
+            ui,
+        );
+        return Ok(());
+    }
 
-    // Update state if it was the active profile
-    if is_active {
-        use crate::state::LockedState;
-        let mut locked = LockedState::lock(&paths.state_file)?;
-        locked.update(|s| {
-            s.default_profile = Some(new_name.to_string());
-        })?;
-
-        // Update symlinks to point to new location
-        let profile_dir = paths.profile_dir(new_name);
-        let metadata = crate::components::ProfileMetadata::read(&profile_dir)?;
-
-        for component in &metadata.managed_components {
-            let source = component.source_path(paths);
-            let target = component.profile_path(paths, new_name);
-
-            // Only update if it's already a symlink pointing to our profiles
-            if let Ok(current_target) = std::fs::read_link(&source)
-                && (paths.is_in_profiles_dir(&current_target)
-                    || paths.is_in_profiles_dir(
-                        &source.parent().unwrap_or(&source).join(&current_target),
-                    )) {
-                crate::switch::create_component_symlink(&source, &target, component, &paths.backups_dir)?;
-            }
+    // Rename the profile directory and every state reference to it
+    with_lock_spinner(ui, |on_wait| {
+        crate::profiles::rename_profile(paths, old_name, new_name, on_wait)
+    })?;
+
+    // Point those components at the new location
+    let metadata = crate::components::ProfileMetadata::read(&paths.profile_dir(new_name))?;
+    for component in &linked {
+        let source = component.source_path(paths);
+
+        // Composed directories hold per-file links into the old location
+        if metadata.tracked_files_for(*component).is_some() && !component.is_file() {
+            let style = state.link_style.unwrap_or_default();
+            crate::switch::link_component(paths, new_name, &metadata, component, style)?;
+            continue;
         }
 
-        ui.ok(format!("Renamed profile '{}' to '{}' (symlinks updated)", old_name, new_name));
-    } else {
+        // Keep the link style the user already has
+        let style = match std::fs::read_link(&source) {
+            Ok(current_target) if current_target.is_relative() => LinkStyle::Relative,
+            _ => LinkStyle::Absolute,
+        };
+        crate::switch::create_component_symlink(
+            &source,
+            &metadata.link_path(paths, new_name, *component),
+            component,
+            paths,
+            style,
+        )?;
+    }
+
+    if linked.is_empty() {
         ui.ok(format!("Renamed profile '{}' to '{}'", old_name, new_name));
+    } else {
+        let names: Vec<&str> = linked.iter().map(|c| c.short_name()).collect();
+        ui.ok(format!(
+            "Renamed profile '{}' to '{}' (relinked {})",
+            old_name,
+            new_name,
+            names.join(", ")
+        ));
     }
 
     Ok(())
@@ -1259,7 +3886,12 @@ mod tests {
         let paths = setup_test_paths(&temp_dir);
         let ui = test_ui();
         // Should not error, just show "no profiles"
-        assert!(list(&paths, &ui).is_ok());
+        assert!(list(&paths, &ui, ProfileSort::LastUsed, false).is_ok());
+        assert!(
+            list_profile_entries(&paths, ProfileSort::Name)
+                .unwrap()
+                .is_empty()
+        );
     }
 
     #[test]
@@ -1274,12 +3906,437 @@ mod tests {
         fs::write(&paths.claude_settings, r#"{"test": true}"#).unwrap();
 
         // Add profile with explicit components (non-interactive)
-        add(&paths, "work", &ui, Some(vec!["settings".to_string()])).unwrap();
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
 
         // Verify it exists
         assert!(profile_exists(&paths, "work"));
     }
 
+    #[test]
+    fn test_headless_add_and_remove() {
+        use crate::interaction::{Answer, Headless, Level};
+        use std::rc::Rc;
+
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        let headless = Rc::new(Headless::new([
+            Answer::Components(vec![Component::Agents]),
+            Answer::Confirm(false),
+            Answer::Default,
+            Answer::Confirm(true),
+        ]));
+        let ui = test_ui().with_interaction(headless.clone());
+
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(None),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        let metadata =
+            crate::components::ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        assert_eq!(
+            metadata.managed_components,
+            HashSet::from([Component::Agents])
+        );
+
+        // Declined, then the default (no)
+        remove(&paths, "work", &ui, false, false).unwrap();
+        remove(&paths, "work", &ui, false, false).unwrap();
+        assert!(profile_exists(&paths, "work"));
+        remove(&paths, "work", &ui, false, false).unwrap();
+        assert!(!profile_exists(&paths, "work"));
+
+        let messages = headless.messages();
+        assert!(messages.contains(&(Level::Ok, "Removed profile 'work'".to_string())));
+    }
+
+    #[test]
+    fn test_inspect_json_counts_directory_contents() {
+        use crate::interaction::{Headless, Level};
+        use std::rc::Rc;
+
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        fs::create_dir_all(paths.claude_dir.join("agents/review")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(paths.claude_dir.join("agents/reviewer.md"), "x".repeat(400)).unwrap();
+        fs::write(paths.claude_dir.join("agents/review/style.md"), "x").unwrap();
+        let headless = Rc::new(Headless::new([]));
+        let ui = test_ui().with_interaction(headless.clone());
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string(), "agents".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+
+        inspect(&paths, "work", &ui, true, false, SymlinkMode::Preserve).unwrap();
+        let messages = headless.messages();
+        let (_, output) = messages.iter().find(|(l, _)| *l == Level::Output).unwrap();
+        let json: serde_json::Value = serde_json::from_str(output).unwrap();
+        let components = json["components"].as_array().unwrap();
+        assert_eq!(components[0]["component"], "settings");
+        assert_eq!(components[0]["bytes"], 2);
+        let agents = &components[1];
+        assert_eq!(
+            (agents["files"].as_u64(), agents["dirs"].as_u64()),
+            (Some(2), Some(1))
+        );
+        assert_eq!(agents["bytes"], 401);
+        assert_eq!(agents["largest"][0]["path"], "reviewer.md");
+    }
+
+    #[test]
+    fn test_settings_diff_table_without_colors() {
+        use crate::interaction::{Headless, Level};
+        use std::rc::Rc;
+
+        let headless = Rc::new(Headless::new([]));
+        let ui = test_ui().with_interaction(headless.clone());
+        let work = serde_json::json!({"model": "claude-opus-4", "verbose": true, "env": {"A": 1}});
+        let home = serde_json::json!({"model": "claude-sonnet-4", "env": {"A": 2}});
+        let options = DiffOptions::default();
+        assert!(diff_json_values(&work, &home, "work", "home", &options, &ui).unwrap());
+        let messages = headless.messages();
+        let (_, table) = messages.iter().find(|(l, _)| *l == Level::Output).unwrap();
+        assert_eq!(
+            table,
+            "\
+| Key     | work            | home              |
+|---------|-----------------|-------------------|
+| env.A   | 1               | 2                 |
+| model   | \"claude-opus-4\" | \"claude-sonnet-4\" |
+| verbose | true            | (missing)         |"
+        );
+    }
+
+    #[test]
+    fn test_ignored_keys_are_counted_not_shown() {
+        use crate::interaction::{Headless, Level};
+        use std::rc::Rc;
+
+        let patterns = ["env.*".to_string(), "feedback*".to_string()];
+        assert!(is_ignored_key(&patterns, "env.A"));
+        assert!(is_ignored_key(&patterns, "env.A.B"));
+        assert!(is_ignored_key(&patterns, "feedbackSurveyState.last"));
+        assert!(!is_ignored_key(&patterns, "env"));
+        assert!(!is_ignored_key(&patterns, "myfeedback"));
+        assert!(is_ignored_key(
+            &["permissions".to_string()],
+            "permissions.allow"
+        ));
+
+        let headless = Rc::new(Headless::new([]));
+        let ui = test_ui().with_interaction(headless.clone());
+        let work = serde_json::json!({"model": "opus", "feedbackSurveyState": 1, "env": {"A": 1}});
+        let home =
+            serde_json::json!({"model": "sonnet", "feedbackSurveyState": 2, "env": {"A": 2}});
+        let mut options = DiffOptions {
+            ignore: patterns.to_vec(),
+            ..Default::default()
+        };
+        assert!(diff_json_values(&work, &home, "work", "home", &options, &ui).unwrap());
+        let messages = headless.messages();
+        let (_, table) = messages.iter().find(|(l, _)| *l == Level::Output).unwrap();
+        assert!(!table.contains("env.A") && table.contains("model"));
+        let (_, summary) = messages.iter().find(|(l, _)| *l == Level::Info).unwrap();
+        assert_eq!(summary, "1 difference(s) found, 2 more in ignored keys");
+
+        options.ignore.push("model".to_string());
+        options.quiet = true;
+        assert!(!diff_json_values(&work, &home, "work", "home", &options, &ui).unwrap());
+    }
+
+    #[test]
+    fn test_diff_cells_dim_the_shared_start() {
+        let ui = Ui::new(ColorMode::Always, false);
+        let value = |s: &str| Some(serde_json::Value::String(s.to_string()));
+        let [left, right] =
+            diff_value_cells(&ui, &value("claude-opus"), &value("claude-sonnet"), false);
+        assert_eq!(
+            left.content(),
+            format!(
+                "{}{}",
+                ui.dim("\"claude-"),
+                ui.colored("opus\"", AnsiColor::Red)
+            )
+        );
+        assert_eq!(
+            right.content(),
+            format!(
+                "{}{}",
+                ui.dim("\"claude-"),
+                ui.colored("sonnet\"", AnsiColor::Green)
+            )
+        );
+
+        // Secrets give nothing away, not even where they differ
+        let [left, _] = diff_value_cells(&ui, &value("sk-ant-1234"), &value("sk-ant-5678"), true);
+        assert!(!left.content().contains(&ui.dim("\"sk-")));
+        let [_, right] = diff_value_cells(&ui, &value("x"), &None, false);
+        assert_eq!(right.content(), ui.dim("(missing)"));
+
+        let plain = test_ui();
+        let [left, right] = diff_value_cells(&plain, &value("claude-opus"), &None, false);
+        assert_eq!(
+            (left.content(), right.content()),
+            ("\"claude-opus\"".to_string(), "(missing)".to_string())
+        );
+    }
+
+    #[test]
+    fn test_compare_profiles_summarizes_each_component() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::create_dir_all(paths.claude_dir.join("hooks")).unwrap();
+        fs::write(
+            &paths.claude_settings,
+            r#"{"model": "opus", "theme": "dark"}"#,
+        )
+        .unwrap();
+        fs::write(paths.claude_dir.join("agents/review.md"), "x").unwrap();
+        let create = |name: &str, components: &[Component]| {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                components.iter().copied().collect(),
+            )
+            .unwrap();
+        };
+        create(
+            "work",
+            &[Component::Settings, Component::Agents, Component::Hooks],
+        );
+        fs::write(
+            &paths.claude_settings,
+            r#"{"model": "sonnet", "verbose": true}"#,
+        )
+        .unwrap();
+        fs::create_dir_all(paths.claude_dir.join("commands")).unwrap();
+        fs::write(paths.claude_dir.join("commands/deploy.md"), "x").unwrap();
+        create(
+            "personal",
+            &[Component::Settings, Component::Agents, Component::Commands],
+        );
+
+        assert_eq!(
+            compare_profiles(&paths, "work", "personal").unwrap(),
+            vec![
+                (Component::Settings, ComponentChange::Differs(3)),
+                (Component::Agents, ComponentChange::Identical),
+                (Component::Hooks, ComponentChange::Dropped),
+                (Component::Commands, ComponentChange::Added),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_refuses_profile_still_linked() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = test_ui();
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string(), "agents".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        add(
+            &paths,
+            "personal",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        crate::switch::switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        crate::switch::switch_to_profile(&paths, "personal", &SwitchOptions::default()).unwrap();
+
+        // Settings moved to 'personal', agents still link into 'work'
+        let state = State::read(&paths.state_file).unwrap();
+        assert_eq!(state.active_components[&Component::Settings], "personal");
+        assert_eq!(state.active_components[&Component::Agents], "work");
+        assert!(
+            current_status(&paths)
+                .components
+                .iter()
+                .all(|o| o.is_consistent())
+        );
+
+        let err = remove(&paths, "work", &ui, true, false)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Agents"), "{err}");
+        assert!(profile_exists(&paths, "work"));
+    }
+
+    #[test]
+    fn test_profile_without_settings_is_a_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = test_ui();
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(paths.claude_dir.join("agents/reviewer.md"), "review").unwrap();
+
+        let agents_only = || ProfileSource::Current(Some(vec!["agents".to_string()]));
+        add(
+            &paths,
+            "agents",
+            &ui,
+            agents_only(),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        assert!(!paths.profile_settings("agents").exists());
+        assert!(profile_exists(&paths, "agents"));
+        assert_eq!(list_profiles(&paths).unwrap(), vec!["agents"]);
+        inspect(&paths, "agents", &ui, false, false, SymlinkMode::Preserve).unwrap();
+
+        crate::switch::switch_to_profile(&paths, "agents", &SwitchOptions::default()).unwrap();
+        assert_eq!(
+            fs::read_link(paths.claude_dir.join("agents")).unwrap(),
+            paths.profile_dir("agents").join("agents")
+        );
+
+        // Switch away so the profile can be removed
+        add(
+            &paths,
+            "other",
+            &ui,
+            agents_only(),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        crate::switch::switch_to_profile(&paths, "other", &SwitchOptions::default()).unwrap();
+        remove(&paths, "agents", &ui, true, false).unwrap();
+        assert!(!profile_exists(&paths, "agents"));
+        assert_eq!(list_profiles(&paths).unwrap(), vec!["other"]);
+    }
+
+    #[test]
+    fn test_notes_preview_and_switch_ignores_notes() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = test_ui();
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+
+        let notes = paths.profile_notes("work");
+        let inspection = inspect_profile(&paths, "work", 0, SymlinkMode::Preserve).unwrap();
+        assert!(inspection.notes.is_none());
+        fs::write(&notes, "# work\nClient: Acme\nKey rotated 2026-01\n").unwrap();
+        let inspection = inspect_profile(&paths, "work", 0, SymlinkMode::Preserve).unwrap();
+        let (lines, more) = notes_preview(inspection.notes.as_deref().unwrap(), 2);
+        assert_eq!(lines, vec!["# work", "Client: Acme"]);
+        assert_eq!(more, 1);
+        inspect(&paths, "work", &ui, false, false, SymlinkMode::Preserve).unwrap();
+
+        crate::switch::switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(
+            !paths
+                .claude_dir
+                .join(crate::components::NOTES_FILE)
+                .exists()
+        );
+    }
+
+    #[test]
+    fn test_rename_leaves_nothing_dangling() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = test_ui();
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string(), "agents".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        add(
+            &paths,
+            "personal",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        crate::switch::switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        crate::switch::switch_to_profile(&paths, "personal", &SwitchOptions::default()).unwrap();
+
+        // 'work' is not selected but still provides agents; 'personal' is selected
+        rename(&paths, "work", "job", false, false, &ui).unwrap();
+        rename(&paths, "personal", "home", false, false, &ui).unwrap();
+
+        let state = State::read(&paths.state_file).unwrap();
+        assert_eq!(state.default_profile.as_deref(), Some("home"));
+        assert_eq!(state.active_components[&Component::Settings], "home");
+        assert_eq!(state.active_components[&Component::Agents], "job");
+        assert!(state.last_used.contains_key("job") && state.last_used.contains_key("home"));
+        assert!(!state.last_used.contains_key("work") && !state.last_used.contains_key("personal"));
+
+        assert_eq!(
+            fs::read_link(&paths.claude_settings).unwrap(),
+            paths.profile_settings("home")
+        );
+        assert_eq!(
+            fs::read_link(paths.claude_dir.join("agents")).unwrap(),
+            paths.profile_dir("job").join("agents")
+        );
+        assert!(
+            current_status(&paths)
+                .components
+                .iter()
+                .all(|o| o.is_consistent())
+        );
+    }
+
     #[test]
     fn test_add_duplicate() {
         let temp_dir = TempDir::new().unwrap();
@@ -1291,8 +4348,26 @@ mod tests {
         fs::write(&paths.claude_settings, "{}").unwrap();
 
         // Add profile with explicit components (non-interactive)
-        add(&paths, "work", &ui, Some(vec!["settings".to_string()])).unwrap();
-        assert!(add(&paths, "work", &ui, Some(vec!["settings".to_string()])).is_err());
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+        assert!(
+            add(
+                &paths,
+                "work",
+                &ui,
+                ProfileSource::Current(Some(vec!["settings".to_string()])),
+                None,
+                CopyOptions::default(),
+            )
+            .is_err()
+        );
     }
 
     #[test]
@@ -1302,7 +4377,40 @@ mod tests {
         let ui = test_ui();
         paths.ensure_dirs().unwrap();
 
-        assert!(use_profile(&paths, "nonexistent", &ui).is_err());
+        assert!(
+            use_profile(
+                &paths,
+                "nonexistent",
+                &ui,
+                &SwitchOptions::default(),
+                DriftAction::Prompt,
+                false
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_components_matrix() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = test_ui();
+        paths.ensure_dirs().unwrap();
+
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        add(
+            &paths,
+            "work",
+            &ui,
+            ProfileSource::Current(Some(vec!["settings".to_string()])),
+            None,
+            CopyOptions::default(),
+        )
+        .unwrap();
+
+        assert!(components(&paths, &ui, false).is_ok());
+        assert!(components(&paths, &ui, true).is_ok());
     }
 
     #[test]
@@ -1311,6 +4419,13 @@ mod tests {
         let paths = setup_test_paths(&temp_dir);
         let ui = test_ui();
         // Should not error
-        assert!(current(&paths, &ui).is_ok());
+        assert!(current(&paths, &ui, false, false).is_ok());
+        let status = current_status(&paths);
+        assert_eq!(status.selected, None);
+        assert!(matches!(status.settings, SettingsStatus::Missing));
+        assert!(status.components.is_empty());
+        let json = serde_json::to_value(&status).unwrap();
+        assert_eq!(json["settings"]["kind"], "missing");
+        assert_eq!(json["in_sync"], true);
     }
 }
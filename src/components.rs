@@ -1,7 +1,8 @@
 //! Component definitions and metadata.
 //!
 //! This module defines the `Component` enum which represents the different parts of
-//! the Claude configuration that `ccprof` can manage (Settings, Agents, Hooks, Commands).
+//! the Claude configuration that `ccprof` can manage (Settings, Agents, Hooks, Commands),
+//! plus an Env file of shell exports that lives outside `~/.claude`.
 //!
 //! It also handles `ProfileMetadata` serialization/deserialization, which tracks
 //! the components managed by each profile.
@@ -9,7 +10,7 @@
 use anyhow::{Context, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
@@ -28,12 +29,20 @@ pub enum Component {
     Hooks,
     /// ~/.claude/commands/
     Commands,
+    /// ~/.claude-profiles/active.env: shell exports, which Claude Code doesn't read
+    Env,
 }
 
 impl Component {
     /// Get all available components
     pub fn all() -> Vec<Self> {
-        vec![Self::Settings, Self::Agents, Self::Hooks, Self::Commands]
+        vec![
+            Self::Settings,
+            Self::Agents,
+            Self::Hooks,
+            Self::Commands,
+            Self::Env,
+        ]
     }
 
     /// Get user-friendly display name
@@ -43,6 +52,7 @@ impl Component {
             Self::Agents => "Agents",
             Self::Hooks => "Hooks",
             Self::Commands => "Commands",
+            Self::Env => "Env",
         }
     }
 
@@ -53,33 +63,46 @@ impl Component {
             Self::Agents => "agents",
             Self::Hooks => "hooks",
             Self::Commands => "commands",
+            Self::Env => "env",
         }
     }
 
-    /// Get source path in ~/.claude/
+    /// Name of the component's file or directory in a profile
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Settings => "settings.json",
+            Self::Agents => "agents",
+            Self::Hooks => "hooks",
+            Self::Commands => "commands",
+            Self::Env => ENV_FILE,
+        }
+    }
+
+    /// Get source path in ~/.claude/ (for Env, ~/.claude-profiles/active.env)
     pub fn source_path(&self, paths: &Paths) -> PathBuf {
         match self {
             Self::Settings => paths.claude_settings.clone(),
             Self::Agents => paths.claude_dir.join("agents"),
             Self::Hooks => paths.claude_dir.join("hooks"),
             Self::Commands => paths.claude_dir.join("commands"),
+            Self::Env => paths.active_env_file.clone(),
         }
     }
 
     /// Get storage path in ~/.claude-profiles/profiles/<name>/
     pub fn profile_path(&self, paths: &Paths, profile_name: &str) -> PathBuf {
-        let profile_dir = paths.profile_dir(profile_name);
-        match self {
-            Self::Settings => profile_dir.join("settings.json"),
-            Self::Agents => profile_dir.join("agents"),
-            Self::Hooks => profile_dir.join("hooks"),
-            Self::Commands => profile_dir.join("commands"),
-        }
+        paths.profile_dir(profile_name).join(self.file_name())
     }
 
     /// Is this component a single file? (vs a directory)
     pub fn is_file(&self) -> bool {
-        matches!(self, Self::Settings)
+        matches!(self, Self::Settings | Self::Env)
+    }
+
+    /// Does the component live in ~/.claude? Env is linked into the profiles
+    /// directory instead, and left out of rendered config directories.
+    pub fn in_claude_dir(&self) -> bool {
+        !matches!(self, Self::Env)
     }
 }
 
@@ -92,11 +115,35 @@ impl FromStr for Component {
             "agents" => Ok(Self::Agents),
             "hooks" => Ok(Self::Hooks),
             "commands" => Ok(Self::Commands),
+            "env" | "env.sh" => Ok(Self::Env),
             _ => Err(()),
         }
     }
 }
 
+/// File of the Env component in a profile directory
+pub const ENV_FILE: &str = "env.sh";
+
+/// Prefix of a component source referencing a shared item
+pub const SHARED_SOURCE_PREFIX: &str = "shared:";
+
+/// Free-form notes kept in a profile directory
+///
+/// Like metadata.json this belongs to ccprof: it is not a component and is
+/// never linked or copied into ~/.claude.
+pub const NOTES_FILE: &str = "NOTES.md";
+
+/// Generated settings of a profile that extends another, merged at switch time
+pub const RESOLVED_SETTINGS_FILE: &str = "settings.resolved.json";
+
+/// Files in a profile directory that belong to ccprof rather than a component
+pub const PROFILE_OWN_FILES: [&str; 4] = [
+    "metadata.json",
+    NOTES_FILE,
+    RESOLVED_SETTINGS_FILE,
+    crate::editor::EDITING_MARKER,
+];
+
 /// Metadata stored in profile's metadata.json
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProfileMetadata {
@@ -104,6 +151,27 @@ pub struct ProfileMetadata {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub managed_components: HashSet<Component>,
+    /// Per-component include lists for directory components.
+    ///
+    /// When a directory component has an entry here, switching composes the live
+    /// directory instead of symlinking it wholesale: listed files link into the
+    /// profile, everything else links into the shared base.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tracked_files: HashMap<Component, Vec<String>>,
+    /// Where a component's content lives when it isn't the profile's own copy.
+    ///
+    /// Currently only `shared:<name>`, referencing `~/.claude-profiles/shared/<name>/`.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub sources: HashMap<Component, String>,
+    /// Profile whose settings this profile's settings.json is merged over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// `claude --version` when the profile was created or last synced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claude_version: Option<String>,
+    /// Repository the profile was cloned from, for `ccprof pull`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<Upstream>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub migration: Option<MigrationInfo>,
 }
@@ -114,18 +182,45 @@ pub struct MigrationInfo {
     pub migration_date: DateTime<Utc>,
 }
 
+/// Where a profile cloned with `ccprof clone` came from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Upstream {
+    /// Git URL of the repository
+    pub url: String,
+    /// Directory in the repository holding the profile, if not its root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Commit the profile was last copied from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
 impl ProfileMetadata {
+    /// Create metadata for a new profile managing the given components
+    pub fn new(managed_components: HashSet<Component>) -> Self {
+        let now = Utc::now();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: now,
+            updated_at: now,
+            managed_components,
+            tracked_files: HashMap::new(),
+            sources: HashMap::new(),
+            extends: None,
+            claude_version: None,
+            upstream: None,
+            migration: None,
+        }
+    }
+
     pub fn read(profile_dir: &Path) -> Result<Self> {
         let path = profile_dir.join("metadata.json");
         if !path.exists() {
-            // Fallback for legacy profiles: assume only settings.json is managed
-            return Ok(Self {
-                version: "0.1.0".to_string(),
-                created_at: Utc::now(), // We don't know real creation time
-                updated_at: Utc::now(),
-                managed_components: HashSet::from([Component::Settings]),
-                migration: None,
-            });
+            // Fallback for legacy profiles: assume only settings.json is managed.
+            // We don't know the real creation time, so timestamps are "now".
+            let mut metadata = Self::new(HashSet::from([Component::Settings]));
+            metadata.version = "0.1.0".to_string();
+            return Ok(metadata);
         }
 
         let content = fs::read_to_string(&path)
@@ -134,10 +229,79 @@ impl ProfileMetadata {
             .with_context(|| format!("Failed to parse metadata from {}", path.display()))
     }
 
+    /// Whether a profile predates metadata.json (only settings.json is managed)
+    pub fn is_legacy(profile_dir: &Path) -> bool {
+        profile_dir.is_dir() && !profile_dir.join("metadata.json").exists()
+    }
+
+    /// Write metadata.json for a legacy profile, recording the migration
+    ///
+    /// Returns whether anything was migrated.
+    pub fn migrate_legacy(profile_dir: &Path) -> Result<bool> {
+        if !Self::is_legacy(profile_dir) {
+            return Ok(false);
+        }
+        let mut metadata = Self::read(profile_dir)?;
+        metadata.migration = Some(MigrationInfo {
+            original_version: std::mem::replace(
+                &mut metadata.version,
+                env!("CARGO_PKG_VERSION").to_string(),
+            ),
+            migration_date: Utc::now(),
+        });
+        metadata.write(profile_dir)?;
+        Ok(true)
+    }
+
+    /// Files tracked individually for a component, if it is composed
+    pub fn tracked_files_for(&self, component: Component) -> Option<&[String]> {
+        self.tracked_files
+            .get(&component)
+            .map(Vec::as_slice)
+            .filter(|files| !files.is_empty())
+    }
+
+    /// Name of the shared item a component references, if any
+    pub fn shared_source(&self, component: Component) -> Option<&str> {
+        self.sources
+            .get(&component)
+            .and_then(|source| source.strip_prefix(SHARED_SOURCE_PREFIX))
+    }
+
+    /// Resolve where a component's content lives for this profile
+    ///
+    /// Follows shared references; otherwise this is the profile's own copy.
+    pub fn component_path(
+        &self,
+        paths: &Paths,
+        profile_name: &str,
+        component: Component,
+    ) -> PathBuf {
+        match self.shared_source(component) {
+            Some(shared) => paths.shared_item(shared),
+            None => component.profile_path(paths, profile_name),
+        }
+    }
+
+    /// Resolve what the live component links to for this profile
+    ///
+    /// Same as [`Self::component_path`], except that the settings of a profile
+    /// extending another are the generated [`RESOLVED_SETTINGS_FILE`].
+    pub fn link_path(&self, paths: &Paths, profile_name: &str, component: Component) -> PathBuf {
+        if component == Component::Settings && self.extends.is_some() {
+            return paths.profile_dir(profile_name).join(RESOLVED_SETTINGS_FILE);
+        }
+        self.component_path(paths, profile_name, component)
+    }
+
     pub fn write(&self, profile_dir: &Path) -> Result<()> {
-        let path = profile_dir.join("metadata.json");
+        self.write_to(&profile_dir.join("metadata.json"))
+    }
+
+    /// Write the metadata to `path` rather than into a profile directory
+    pub fn write_to(&self, path: &Path) -> Result<()> {
         let content = serde_json::to_string_pretty(self)?;
-        fs::write(&path, content)
+        fs::write(path, content)
             .with_context(|| format!("Failed to write metadata to {}", path.display()))?;
         Ok(())
     }
@@ -0,0 +1,410 @@
+//! User preferences in `~/.claude-profiles/config.toml`.
+//!
+//! ```toml
+//! pager = "less -R"   # or false to never page
+//! editor = "code --wait"  # overrides VISUAL and EDITOR
+//! redact = ["key", "token", "secret", "password", "authorization", "cookie"]
+//! yes = true          # like passing --yes to every command
+//!
+//! [diff]
+//! ignore = ["feedbackSurveyState"]
+//!
+//! [backups]
+//! keep_per_component = 20  # rotated after each backup a switch takes
+//!
+//! [theme]
+//! icons = "ascii"     # auto, unicode or ascii
+//! accent = "magenta"  # color of INFO labels, spinners and progress bars
+//! table = "ascii"     # auto, unicode, ascii or markdown
+//! ```
+//!
+//! A bad file, value or unknown key never stops a command: it is reported as a
+//! warning and the built-in default is used instead. `ccprof config-file show`
+//! prints the result, defaults included.
+
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::redact::Redactor;
+use crate::switch::DEFAULT_BACKUPS_KEEP;
+use crate::ui::{Theme, accent_name, parse_accent};
+
+/// Top-level keys of config.toml
+const KNOWN_KEYS: [&str; 7] = [
+    "pager", "editor", "redact", "yes", "diff", "backups", "theme",
+];
+
+/// What `ccprof config-file edit` starts a new config.toml with
+pub const CONFIG_TEMPLATE: &str = "\
+# ccprof preferences; 'ccprof config-file show' prints them with the defaults
+
+# pager = \"less -R\"       # or false to never page
+# editor = \"code --wait\"  # overrides VISUAL and EDITOR
+# redact = [\"key\", \"token\", \"secret\", \"password\", \"authorization\", \"cookie\"]
+# yes = true              # like passing --yes to every command
+
+# [diff]
+# ignore = [\"feedbackSurveyState\"]
+
+# [backups]
+# keep_per_component = 20  # also the default of 'ccprof backup clean --keep'
+
+# [theme]
+# icons = \"ascii\"         # auto, unicode or ascii
+# accent = \"magenta\"      # cyan, blue, green, magenta, yellow, red or white
+# table = \"ascii\"         # auto, unicode, ascii or markdown
+";
+
+/// Preferences read from config.toml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub theme: Theme,
+    /// False when `pager = false`
+    pub paging: bool,
+    /// Pager command, overriding `PAGER`
+    pub pager: Option<String>,
+    /// Editor command, overriding `VISUAL` and `EDITOR`
+    pub editor: Option<String>,
+    /// Key patterns whose values are hidden in output
+    pub redact: Redactor,
+    /// Settings keys `diff` leaves out (`[diff] ignore`)
+    pub diff_ignore: Vec<String>,
+    /// Answer yes to confirmations, as with `--yes`
+    pub assume_yes: bool,
+    /// Backups kept per component (`[backups] keep_per_component`), at least 1
+    pub backups_keep: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            theme: Theme::default(),
+            paging: true,
+            pager: None,
+            editor: None,
+            redact: Redactor::default(),
+            diff_ignore: Vec::new(),
+            assume_yes: false,
+            backups_keep: DEFAULT_BACKUPS_KEEP,
+        }
+    }
+}
+
+impl Config {
+    /// The config as config.toml would spell it, every key included
+    pub fn to_toml(&self) -> String {
+        let strings = |items: &[String]| {
+            toml::Value::Array(items.iter().cloned().map(toml::Value::String).collect())
+        };
+        let mut table = toml::Table::new();
+        table.insert(
+            "pager".to_string(),
+            match (&self.pager, self.paging) {
+                (_, false) => toml::Value::Boolean(false),
+                (Some(command), true) => toml::Value::String(command.clone()),
+                (None, true) => toml::Value::Boolean(true),
+            },
+        );
+        if let Some(editor) = &self.editor {
+            table.insert("editor".to_string(), toml::Value::String(editor.clone()));
+        }
+        table.insert("redact".to_string(), strings(self.redact.patterns()));
+        table.insert("yes".to_string(), toml::Value::Boolean(self.assume_yes));
+
+        let mut diff = toml::Table::new();
+        diff.insert("ignore".to_string(), strings(&self.diff_ignore));
+        table.insert("diff".to_string(), toml::Value::Table(diff));
+
+        let mut backups = toml::Table::new();
+        backups.insert(
+            "keep_per_component".to_string(),
+            toml::Value::Integer(self.backups_keep.try_into().unwrap_or(i64::MAX)),
+        );
+        table.insert("backups".to_string(), toml::Value::Table(backups));
+
+        let mut theme = toml::Table::new();
+        for (key, value) in [
+            ("icons", self.theme.icons.to_string()),
+            ("accent", accent_name(self.theme.accent).to_string()),
+            ("table", self.theme.table.to_string()),
+        ] {
+            theme.insert(key.to_string(), toml::Value::String(value));
+        }
+        table.insert("theme".to_string(), toml::Value::Table(theme));
+        table.to_string()
+    }
+}
+
+/// Read the config from `path`, with a warning for each problem found
+///
+/// A missing file is not a problem; it means the defaults.
+pub fn load(path: &Path) -> (Config, Vec<String>) {
+    let mut config = Config::default();
+    let mut warnings = Vec::new();
+
+    let Ok(content) = fs::read_to_string(path) else {
+        return (config, warnings);
+    };
+    let table: toml::Table = match toml::from_str(&content) {
+        Ok(table) => table,
+        Err(e) => {
+            warnings.push(format!("Ignoring {}: {}", path.display(), e.message()));
+            return (config, warnings);
+        }
+    };
+
+    for key in table.keys() {
+        if !KNOWN_KEYS.contains(&key.as_str()) {
+            warnings.push(format!(
+                "Ignoring {} in {}: unknown key (expected {})",
+                key,
+                path.display(),
+                KNOWN_KEYS.join(", ")
+            ));
+        }
+    }
+
+    match table.get("pager") {
+        None => {}
+        Some(toml::Value::Boolean(paging)) => config.paging = *paging,
+        Some(toml::Value::String(command)) => config.pager = Some(command.clone()),
+        Some(_) => warnings.push(format!(
+            "Ignoring pager in {}: expected a command or false",
+            path.display()
+        )),
+    }
+
+    match table.get("editor") {
+        None => {}
+        Some(toml::Value::String(command)) => config.editor = Some(command.clone()),
+        Some(_) => warnings.push(format!(
+            "Ignoring editor in {}: expected a command",
+            path.display()
+        )),
+    }
+
+    match table.get("yes") {
+        None => {}
+        Some(toml::Value::Boolean(yes)) => config.assume_yes = *yes,
+        Some(_) => warnings.push(format!(
+            "Ignoring yes in {}: expected true or false",
+            path.display()
+        )),
+    }
+
+    match table.get("redact").map(toml::Value::as_array) {
+        None => {}
+        Some(Some(patterns)) if patterns.iter().all(toml::Value::is_str) => {
+            config.redact = Redactor::new(patterns.iter().filter_map(toml::Value::as_str));
+        }
+        Some(_) => warnings.push(format!(
+            "Ignoring redact in {}: expected a list of key patterns",
+            path.display()
+        )),
+    }
+
+    match table.get("diff").map(toml::Value::as_table) {
+        None => {}
+        Some(Some(section)) => config.diff_ignore = read_diff(section, path, &mut warnings),
+        Some(None) => warnings.push(format!(
+            "Ignoring 'diff' in {}: not a table",
+            path.display()
+        )),
+    }
+
+    match table.get("backups").map(toml::Value::as_table) {
+        None => {}
+        Some(Some(section)) => {
+            if let Some(keep) = read_backups(section, path, &mut warnings) {
+                config.backups_keep = keep;
+            }
+        }
+        Some(None) => warnings.push(format!(
+            "Ignoring 'backups' in {}: not a table",
+            path.display()
+        )),
+    }
+
+    match table.get("theme").map(toml::Value::as_table) {
+        None => {}
+        Some(Some(section)) => config.theme = read_theme(section, path, &mut warnings),
+        Some(None) => warnings.push(format!(
+            "Ignoring 'theme' in {}: not a table",
+            path.display()
+        )),
+    }
+
+    (config, warnings)
+}
+
+/// The `ignore` list of the `[diff]` table
+fn read_diff(section: &toml::Table, path: &Path, warnings: &mut Vec<String>) -> Vec<String> {
+    let mut ignore = Vec::new();
+    for (key, value) in section {
+        match (key.as_str(), value.as_array()) {
+            ("ignore", Some(keys)) if keys.iter().all(toml::Value::is_str) => {
+                ignore = keys
+                    .iter()
+                    .filter_map(toml::Value::as_str)
+                    .map(str::to_string)
+                    .collect();
+            }
+            ("ignore", _) => warnings.push(format!(
+                "Ignoring diff.ignore in {}: expected a list of settings keys",
+                path.display()
+            )),
+            _ => warnings.push(format!(
+                "Ignoring diff.{} in {}: unknown key (expected ignore)",
+                key,
+                path.display()
+            )),
+        }
+    }
+    ignore
+}
+
+/// The `keep_per_component` of the `[backups]` table, if it is valid
+fn read_backups(section: &toml::Table, path: &Path, warnings: &mut Vec<String>) -> Option<usize> {
+    let mut keep = None;
+    for (key, value) in section {
+        match (key.as_str(), value.as_integer()) {
+            ("keep_per_component", Some(n)) if n >= 1 => keep = usize::try_from(n).ok(),
+            ("keep_per_component", _) => warnings.push(format!(
+                "Ignoring backups.keep_per_component in {}: expected a whole number of at least 1",
+                path.display()
+            )),
+            _ => warnings.push(format!(
+                "Ignoring backups.{} in {}: unknown key (expected keep_per_component)",
+                key,
+                path.display()
+            )),
+        }
+    }
+    keep
+}
+
+/// The `[theme]` table, keeping the default for each invalid value
+fn read_theme(section: &toml::Table, path: &Path, warnings: &mut Vec<String>) -> Theme {
+    let mut theme = Theme::default();
+    for (key, value) in section {
+        let Some(value) = value.as_str() else {
+            warnings.push(format!(
+                "Ignoring theme.{} in {}: expected a string",
+                key,
+                path.display()
+            ));
+            continue;
+        };
+        let result = match key.as_str() {
+            "icons" => FromStr::from_str(value).map(|icons| theme.icons = icons),
+            "accent" => parse_accent(value).map(|accent| theme.accent = accent),
+            "table" => FromStr::from_str(value).map(|table| theme.table = table),
+            _ => Err("unknown key (expected icons, accent or table)".to_string()),
+        };
+        if let Err(e) = result {
+            warnings.push(format!(
+                "Ignoring theme.{} in {}: {}",
+                key,
+                path.display(),
+                e
+            ));
+        }
+    }
+    theme
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{IconStyle, TableStyle};
+    use anstyle::AnsiColor;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_invalid_values_fall_back_on_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        assert_eq!(load(&path), (Config::default(), vec![]));
+
+        fs::write(
+            &path,
+            "pager = false\n[theme]\nicons = \"ascii\"\naccent = \"teal\"\ntable = \"markdown\"\nsize = 3\n",
+        )
+        .unwrap();
+        let (config, warnings) = load(&path);
+        assert!(!config.paging);
+        assert_eq!(
+            config.theme,
+            Theme {
+                icons: IconStyle::Ascii,
+                accent: AnsiColor::Cyan,
+                table: TableStyle::Markdown,
+            }
+        );
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("theme.accent"));
+        assert!(warnings[1].contains("theme.size"));
+
+        fs::write(&path, "editor = \"code --wait\"\n").unwrap();
+        assert_eq!(load(&path).0.editor.as_deref(), Some("code --wait"));
+        fs::write(&path, "editor = 1\n").unwrap();
+        assert_eq!(load(&path).1.len(), 1);
+
+        fs::write(&path, "redact = [\"cookie\"]\n").unwrap();
+        let (config, warnings) = load(&path);
+        assert_eq!(config.redact, Redactor::new(["cookie"]));
+        assert!(warnings.is_empty());
+        fs::write(&path, "redact = \"cookie\"\n").unwrap();
+        let (config, warnings) = load(&path);
+        assert_eq!(config.redact, Redactor::default());
+        assert_eq!(warnings.len(), 1);
+
+        fs::write(
+            &path,
+            "[diff]\nignore = [\"feedbackSurveyState\", \"env.*\"]\n",
+        )
+        .unwrap();
+        let (config, warnings) = load(&path);
+        assert_eq!(config.diff_ignore, ["feedbackSurveyState", "env.*"]);
+        assert!(warnings.is_empty());
+        fs::write(&path, "[diff]\nignore = \"env\"\nkeys = []\n").unwrap();
+        let (config, warnings) = load(&path);
+        assert!(config.diff_ignore.is_empty());
+        assert_eq!(warnings.len(), 2);
+
+        fs::write(&path, "[backups]\nkeep_per_component = 0\n").unwrap();
+        let (config, warnings) = load(&path);
+        assert_eq!(config.backups_keep, DEFAULT_BACKUPS_KEEP);
+        assert_eq!(warnings.len(), 1);
+
+        fs::write(&path, "[theme\n").unwrap();
+        let (config, warnings) = load(&path);
+        assert_eq!(config, Config::default());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_unknown_keys_warn_and_to_toml_round_trips() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "yes = true\npagr = false\neditor = \"vim\"\n\
+             [backups]\nkeep_per_component = 3\n[theme]\naccent = \"magenta\"\n",
+        )
+        .unwrap();
+        let (config, warnings) = load(&path);
+        assert!(config.assume_yes);
+        assert!(config.paging);
+        assert_eq!(config.backups_keep, 3);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("Ignoring pagr"));
+
+        // `config-file show` output is itself a valid config.toml
+        for config in [Config::default(), config] {
+            fs::write(&path, config.to_toml()).unwrap();
+            assert_eq!(load(&path), (config, vec![]));
+        }
+    }
+}
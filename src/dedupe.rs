@@ -0,0 +1,111 @@
+//! Duplicate profile detection.
+//!
+//! Profiles are compared by a digest of their managed content: each managed
+//! component is hashed with [`hash_path`], together with what changes how the
+//! content is applied (shared references, tracked files, `extends`). Notes and
+//! timestamps in metadata.json are ignored.
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+use crate::components::{Component, ProfileMetadata};
+use crate::fs_utils::hash_path;
+use crate::paths::Paths;
+use crate::profiles::list_profiles;
+
+/// Digest of a profile's managed content
+pub fn profile_content_hash(paths: &Paths, name: &str) -> Result<String> {
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    let mut hasher = Sha256::new();
+
+    for component in Component::all() {
+        if !metadata.managed_components.contains(&component) {
+            continue;
+        }
+        let path = metadata.component_path(paths, name, component);
+        let digest = if path.exists() {
+            hash_path(&path)?
+        } else {
+            "missing".to_string()
+        };
+        hasher.update(format!("{}={}\n", component.short_name(), digest));
+
+        if let Some(shared) = metadata.shared_source(component) {
+            hasher.update(format!("{}.shared={}\n", component.short_name(), shared));
+        }
+        if let Some(files) = metadata.tracked_files_for(component) {
+            let mut files = files.to_vec();
+            files.sort();
+            hasher.update(format!(
+                "{}.tracked={}\n",
+                component.short_name(),
+                files.join(",")
+            ));
+        }
+    }
+    if let Some(base) = &metadata.extends {
+        hasher.update(format!("extends={}\n", base));
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Groups of two or more profiles with identical content, each sorted by name
+pub fn duplicate_groups(paths: &Paths) -> Result<Vec<Vec<String>>> {
+    let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+    for name in list_profiles(paths)? {
+        by_hash
+            .entry(profile_content_hash(paths, &name)?)
+            .or_default()
+            .push(name);
+    }
+
+    let mut groups: Vec<Vec<String>> = by_hash
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    for group in &mut groups {
+        group.sort();
+    }
+    groups.sort();
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::create_profile_with_components;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_identical_profiles_are_grouped() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        fs::write(paths.claude_dir.join("agents/a.md"), "a").unwrap();
+
+        let both = HashSet::from([Component::Settings, Component::Agents]);
+        for name in ["copy", "work"] {
+            create_profile_with_components(&paths, name, both.clone()).unwrap();
+        }
+        // Same settings, but without agents
+        create_profile_with_components(&paths, "lean", HashSet::from([Component::Settings]))
+            .unwrap();
+        // Notes don't count as content
+        fs::write(paths.profile_notes("copy"), "client X").unwrap();
+
+        assert_eq!(
+            duplicate_groups(&paths).unwrap(),
+            vec![vec!["copy".to_string(), "work".to_string()]]
+        );
+
+        fs::write(paths.profile_dir("copy").join("agents/a.md"), "changed").unwrap();
+        assert!(duplicate_groups(&paths).unwrap().is_empty());
+    }
+}
@@ -5,27 +5,60 @@
 //! - Existence of required directories.
 //! - Validity of symbolic links.
 //! - Correctness of profile metadata and JSON files.
+//! - Stray files in the backups directory.
+//! - Temp files and lock records left behind by crashed processes.
+//! - Unknown or mistyped keys in profile settings.
+//! - Profiles recorded against a different Claude Code version.
+//! - Stale or orphaned config directories rendered by `ccprof env`.
 //! - Permissions.
 //!
 //! It reports issues to the user with a pass/fail/warn status.
 
 use anstyle::AnsiColor;
+use std::cell::{Cell, RefCell};
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use crate::components::ProfileMetadata;
-use crate::paths::Paths;
-use crate::profiles::list_profiles;
-use crate::state::State;
-use crate::switch::SettingsStatus;
+use crate::components::{Component, ProfileMetadata};
+use crate::config::Config;
+use crate::fs_utils::{LinkChain, MAX_LINK_HOPS, follow_link_chain};
+use crate::journal::{Recovery, SwitchJournal, recover};
+use crate::paths::{Layout, Paths};
+use crate::profiles::{list_profiles, profile_exists};
+use crate::render::{RenderStatus, list_rendered};
+use crate::settings_schema::check_settings_file;
+use crate::state::{LockedState, OperationLock, STATE_SCHEMA_VERSION, State};
+use crate::switch::{
+    BACKUP_QUARANTINE_DIR, BackupEntryKind, ComponentStatus, ComposedSource, LiveSource,
+    SettingsStatus, classify_backup, component_ownership, inspect_composed_dir,
+};
 use crate::ui::Ui;
 
 /// Run the doctor diagnostics
-pub fn run_doctor(paths: &Paths, ui: &Ui) {
+///
+/// With `fix`, problems that can be repaired automatically (an interrupted
+/// switch, legacy profiles) are repaired instead of only reported. Project
+/// `.claude` directories are searched from `project_dir` upwards. Returns
+/// whether every check passed; warnings don't count as failures. With
+/// `quiet`, only the closing summary is printed. `config` is the loaded
+/// config.toml, whose editor and backup settings are reported.
+pub fn run_doctor(
+    paths: &Paths,
+    config: &Config,
+    ui: &Ui,
+    fix: bool,
+    quiet: bool,
+    project_dir: &Path,
+) -> bool {
+    let ui = &Report::new(ui, quiet);
     ui.section("ccprof Doctor");
     ui.newline();
+    let mut healthy = true;
 
     // 1. Check directories
-    check_step(ui, "Directories", || {
+    healthy &= check_step(ui, "Directories", || {
         let mut ok = true;
         if paths.base_dir.exists() {
             ui.println(format!(
@@ -41,6 +74,21 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
             ));
             ok = false;
         }
+        if let Err(crate::error::Error::NotWritable { path, reason }) = paths.ensure_writable() {
+            ui.println(format!(
+                "  {} {} is not writable ({}); only read-only commands work",
+                ui.icon_warn(),
+                path.display(),
+                reason
+            ));
+        }
+        if paths.layout == Layout::Xdg {
+            ui.println(format!(
+                "  {} XDG layout; config file: {}",
+                ui.icon_info(),
+                paths.config_file.display()
+            ));
+        }
 
         if paths.claude_dir.exists() {
             ui.println(format!(
@@ -60,13 +108,30 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
     });
 
     // 2. Check State
-    check_step(ui, "State File", || {
+    healthy &= check_step(ui, "State File", || {
+        let version_on_disk = State::schema_version_on_disk(&paths.state_file)
+            .ok()
+            .flatten();
         match State::read(&paths.state_file) {
             Ok(state) => {
                 ui.println(format!(
                     "  {} State file readable",
                     ui.icon_ok()
                 ));
+                match version_on_disk {
+                    Some(version) if version < STATE_SCHEMA_VERSION => ui.println(format!(
+                        "  {} Schema version {} upgraded to {}",
+                        ui.icon_ok(),
+                        version,
+                        state.schema_version
+                    )),
+                    Some(version) => ui.println(format!(
+                        "  {} Schema version {} (current)",
+                        ui.icon_ok(),
+                        version
+                    )),
+                    None => {}
+                }
                 if let Some(profile) = &state.default_profile {
                     ui.println(format!(
                         "  {} Active profile in state: {}",
@@ -80,11 +145,32 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
                             ui.icon_ok()
                         ));
                     } else {
-                         ui.println(format!(
-                            "  {} Active profile directory MISSING",
-                            ui.icon_err()
+                        ui.println(format!(
+                            "  {} state.default_profile '{}' does not exist on disk",
+                            ui.icon_err(),
+                            profile
                         ));
-                        return false;
+                        if !fix {
+                            ui.println(format!(
+                                "      {} Run 'ccprof doctor --fix' to clear it, or point it at the profile settings.json links into",
+                                ui.icon_info()
+                            ));
+                            return false;
+                        }
+                        match repair_default_profile(paths) {
+                            Ok(Some(linked)) => ui.println(format!(
+                                "  {} Default profile set to '{}' (settings.json links into it)",
+                                ui.icon_ok(),
+                                linked
+                            )),
+                            Ok(None) => {
+                                ui.println(format!("  {} Default profile cleared", ui.icon_ok()))
+                            }
+                            Err(e) => {
+                                ui.println(format!("  {} {:#}", ui.icon_err(), e));
+                                return false;
+                            }
+                        }
                     }
                 } else {
                     ui.println(format!("  {} No active profile set", ui.icon_info()));
@@ -103,10 +189,16 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
         }
     });
 
-    // 3. Check Settings Link
-    check_step(ui, "Settings Symlink", || {
-        let status = SettingsStatus::detect(&paths.claude_settings);
-        match status {
+    // 3. Check for a switch interrupted mid-way
+    healthy &= check_step(ui, "Interrupted Switch", || {
+        check_interrupted_switch(paths, ui, fix)
+    });
+
+    // 4. Check Settings Link
+    healthy &= check_step(ui, "Settings Symlink", || {
+        let state = State::read(&paths.state_file).unwrap_or_default();
+        let status = SettingsStatus::detect_with_state(&paths.claude_settings, &state);
+        match &status {
             SettingsStatus::Missing => {
                 ui.println(format!("  {} ~/.claude/settings.json is missing", ui.icon_warn()));
                 // Not fatal
@@ -117,8 +209,32 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
                 true
             }
             SettingsStatus::Symlink { target } => {
-                ui.println(format!("  {} Symlink points to: {}", ui.icon_ok(), target.display()));
-                if paths.is_in_profiles_dir(&target) {
+                let style = match std::fs::read_link(&paths.claude_settings) {
+                    Ok(raw) if raw.is_relative() => "relative",
+                    _ => "absolute",
+                };
+                ui.println(format!(
+                    "  {} Symlink points to: {} ({})",
+                    ui.icon_ok(),
+                    target.display(),
+                    style
+                ));
+                let chain = follow_link_chain(&paths.claude_settings);
+                if chain.hops().len() > 1 {
+                    ui.println(format!(
+                        "  {} Chain of symlinks: {}",
+                        ui.icon_info(),
+                        chain.display(&paths.claude_settings)
+                    ));
+                }
+                if let LinkChain::TooLong(_) = chain {
+                    ui.println(format!(
+                        "  {} Gave up after {} symlinks",
+                        ui.icon_warn(),
+                        MAX_LINK_HOPS
+                    ));
+                }
+                if status.is_profile_symlink(paths) {
                     ui.println(format!("  {} Target is within ccprof profiles", ui.icon_ok()));
                 } else {
                      ui.println(format!("  {} Target is EXTERNAL (not managed by ccprof?)", ui.icon_warn()));
@@ -126,14 +242,54 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
                 true
             }
             SettingsStatus::BrokenSymlink { target } => {
-                 ui.println(format!("  {} BROKEN symlink pointing to: {}", ui.icon_err(), target.display()));
-                 false
+                match follow_link_chain(&paths.claude_settings) {
+                    chain @ LinkChain::Loop(_) => ui.println(format!(
+                        "  {} Symlink loop: {}",
+                        ui.icon_err(),
+                        chain.display(&paths.claude_settings)
+                    )),
+                    _ => ui.println(format!(
+                        "  {} BROKEN symlink pointing to: {}",
+                        ui.icon_err(),
+                        target.display()
+                    )),
+                }
+                false
+            }
+            SettingsStatus::ManagedCopy { profile, drifted } => {
+                ui.println(format!(
+                    "  {} ~/.claude/settings.json is a copy of profile '{}' (copy strategy)",
+                    ui.icon_ok(),
+                    profile
+                ));
+                if *drifted {
+                    ui.println(format!(
+                        "  {} Modified since the switch; run 'ccprof sync' to keep the changes",
+                        ui.icon_warn()
+                    ));
+                }
+                true
             }
         }
     });
 
-    // 4. Check Profiles
-    check_step(ui, "Profiles", || {
+    // 5. Check composed directory components of the active profile
+    healthy &= check_step(ui, "Composed Components", || {
+        check_composed_components(paths, ui)
+    });
+
+    // 6. Check components activated with the copy strategy
+    healthy &= check_step(ui, "Copied Components", || {
+        check_copied_components(paths, ui)
+    });
+
+    // 7. Check which profile each live component actually uses
+    healthy &= check_step(ui, "Component Profiles", || {
+        check_component_profiles(paths, ui)
+    });
+
+    // 8. Check Profiles
+    healthy &= check_step(ui, "Profiles", || {
         let profiles = match list_profiles(paths) {
             Ok(p) => p,
             Err(e) => {
@@ -156,36 +312,138 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
 
             match metadata_res {
                 Ok(metadata) => {
+                    let legacy = ProfileMetadata::is_legacy(&dir);
+                    if legacy {
+                        if fix {
+                            match ProfileMetadata::migrate_legacy(&dir) {
+                                Ok(_) => ui.println(format!(
+                                    "    {} {} migrated from legacy (metadata.json written)",
+                                    ui.icon_ok(),
+                                    name
+                                )),
+                                Err(e) => {
+                                    ui.println(format!(
+                                        "    {} {} could not be migrated: {:#}",
+                                        ui.icon_err(),
+                                        name,
+                                        e
+                                    ));
+                                    all_valid = false;
+                                }
+                            }
+                        } else {
+                            ui.println(format!(
+                                "    {} {} is a legacy profile (no metadata.json)",
+                                ui.icon_warn(),
+                                name
+                            ));
+                            ui.println(format!(
+                                "      {} Run 'ccprof migrate' or 'ccprof doctor --fix' to upgrade it",
+                                ui.icon_info()
+                            ));
+                        }
+                    }
+
                     // Check managed components
                     let mut missing_components = Vec::new();
                     for component in &metadata.managed_components {
-                        let path = component.profile_path(paths, &name);
+                        let path = metadata.component_path(paths, &name, *component);
                         if !path.exists() {
                             missing_components.push(component.display_name());
                         }
                     }
 
-                    if missing_components.is_empty() {
-                         ui.println(format!("    {} {}", ui.icon_ok(), name));
+                    let settings = metadata.component_path(paths, &name, Component::Settings);
+                    let invalid_settings =
+                        (metadata.managed_components.contains(&Component::Settings)
+                            && settings.is_file())
+                        .then(|| crate::profiles::validate_json_file(&settings).err())
+                        .flatten();
+                    let lenient_settings = invalid_settings.is_none()
+                        && metadata.managed_components.contains(&Component::Settings)
+                        && is_lenient_json(&settings);
+
+                    if let Some(e) = invalid_settings {
+                        ui.println(format!(
+                            "    {} {} (settings.json does not parse)",
+                            ui.icon_err(),
+                            name
+                        ));
+                        ui.println(format!(
+                            "      {} {}",
+                            ui.icon_info(),
+                            crate::ui::truncate(
+                                &anyhow::Error::from(e).root_cause().to_string(),
+                                JSON_ERROR_WIDTH
+                            )
+                        ));
+                        all_valid = false;
+                    } else if missing_components.is_empty() {
+                        // Legacy profiles already got their own line
+                        if !legacy {
+                            ui.println(format!("    {} {}", ui.icon_ok(), name));
+                        }
                     } else {
-                         ui.println(format!("    {} {} (missing components: {})", ui.icon_warn(), name, missing_components.join(", ")));
-                         // Not strictly fatal, but warning
+                        ui.println(format!(
+                            "    {} {} (missing components: {})",
+                            ui.icon_warn(),
+                            name,
+                            missing_components.join(", ")
+                        ));
+                        // Not strictly fatal, but warning
+                        ui.println(format!(
+                            "      {} 'ccprof use {}' fails until restored (or use --skip-missing)",
+                            ui.icon_info(),
+                            name
+                        ));
+                    }
+                    if lenient_settings {
+                        ui.println(format!(
+                            "      {} {}: settings.json has comments or trailing commas; strict JSON is safer",
+                            ui.icon_warn(),
+                            name
+                        ));
                     }
                 },
-                Err(_) => {
-                    // Try to read settings.json directly to see if it's a legacy profile
-                    let settings_path = dir.join("settings.json");
-                    if settings_path.exists() {
-                        match crate::profiles::validate_json_file(&settings_path) {
-                            Ok(_) => ui.println(format!("    {} {} (legacy/no metadata)", ui.icon_warn(), name)),
+                Err(e) => {
+                    if fix {
+                        match crate::profiles::repair_metadata(paths, &name) {
+                            Ok(repair) => ui.println(format!(
+                                "    {} {} metadata.json regenerated (components: {})",
+                                ui.icon_ok(),
+                                name,
+                                repair
+                                    .map(|r| r
+                                        .components
+                                        .iter()
+                                        .map(|c| c.short_name())
+                                        .collect::<Vec<_>>()
+                                        .join(", "))
+                                    .unwrap_or_default()
+                            )),
                             Err(e) => {
-                                ui.println(format!("    {} {} (invalid settings.json: {})", ui.icon_err(), name, e));
+                                ui.println(format!(
+                                    "    {} {} metadata.json could not be regenerated: {:#}",
+                                    ui.icon_err(),
+                                    name,
+                                    e
+                                ));
                                 all_valid = false;
                             }
                         }
                     } else {
-                         ui.println(format!("    {} {} (empty/corrupt)", ui.icon_err(), name));
-                         all_valid = false;
+                        ui.println(format!(
+                            "    {} {} (metadata.json is unreadable: {:#})",
+                            ui.icon_err(),
+                            name,
+                            e
+                        ));
+                        ui.println(format!(
+                            "      {} Run 'ccprof repair-metadata {}' or 'ccprof doctor --fix' to regenerate it",
+                            ui.icon_info(),
+                            name
+                        ));
+                        all_valid = false;
                     }
                 }
             }
@@ -193,26 +451,1120 @@ pub fn run_doctor(paths: &Paths, ui: &Ui) {
         all_valid
     });
 
-    // 5. Environment
-    check_step(ui, "Environment", || {
-        match env::var("EDITOR") {
-            Ok(e) => ui.println(format!("  {} EDITOR set to: {}", ui.icon_ok(), e)),
-            Err(_) => ui.println(format!("  {} EDITOR not set (using system default)", ui.icon_info())),
+    // 9. Check profile settings for unknown keys and wrong types
+    healthy &= check_step(ui, "Settings Keys", || check_settings_keys(paths, ui));
+
+    // 10. Check the backups directory for entries that aren't backups
+    healthy &= check_step(ui, "Backups", || {
+        check_backups(paths, ui, fix, config.backups_keep)
+    });
+
+    // 11. Check for temp files and lock records left by crashed processes
+    healthy &= check_step(ui, "Leftover Files", || {
+        check_leftover_files(paths, ui, fix)
+    });
+
+    // 12. Project-level settings that override the active profile
+    healthy &= check_step(ui, "Project Overrides", || {
+        check_project_overrides(paths, ui, project_dir)
+    });
+
+    // 13. Claude Code version the profiles were authored against
+    healthy &= check_step(ui, "Claude Code Version", || {
+        check_claude_versions(paths, ui)
+    });
+
+    // 14. Config directories rendered by `ccprof env`
+    healthy &= check_step(ui, "Rendered Configs", || check_rendered(paths, ui));
+
+    // 15. Environment
+    healthy &= check_step(ui, "Environment", || {
+        match crate::editor::current_editor(config) {
+            Ok(editor) if editor.source == crate::editor::EditorSource::Fallback => {
+                ui.println(format!(
+                    "  {} VISUAL and EDITOR not set (using {})",
+                    ui.icon_info(),
+                    editor.command_line()
+                ))
+            }
+            Ok(editor) => ui.println(format!(
+                "  {} Editor: {} (from {})",
+                ui.icon_ok(),
+                editor.command_line(),
+                editor.source
+            )),
+            Err(e) => {
+                ui.println(format!("  {} {:#}", ui.icon_warn(), e));
+            }
+        }
+        if cfg!(windows) {
+            check_windows_symlinks(paths, ui);
         }
         true
     });
+
+    ui.print_summary();
+
+    healthy
+}
+
+/// Replace a default profile that no longer exists on disk
+///
+/// Points it at the profile the live settings come from if that one exists,
+/// otherwise clears it. Returns the new default profile.
+pub fn repair_default_profile(paths: &Paths) -> anyhow::Result<Option<String>> {
+    let mut locked = LockedState::lock(&paths.state_file)?;
+    let linked = LiveSource::detect(paths, Component::Settings, &locked.state)
+        .profile()
+        .filter(|p| profile_exists(paths, p))
+        .map(str::to_string);
+    locked.update(|state| {
+        if state
+            .default_profile
+            .as_deref()
+            .is_some_and(|p| !profile_exists(paths, p))
+        {
+            state.default_profile = linked;
+        }
+    })?;
+    Ok(locked.state.default_profile.clone())
+}
+
+/// Categorize the backups directory; with `fix`, quarantine what isn't a backup
+fn check_backups(paths: &Paths, ui: &Report, fix: bool, keep: usize) -> bool {
+    let Ok(entries) = fs::read_dir(&paths.backups_dir) else {
+        ui.println(format!("  {} No backups directory", ui.icon_info()));
+        return true;
+    };
+
+    let mut valid = 0;
+    let mut total_size = 0;
+    let mut empty = Vec::new();
+    let mut unknown = Vec::new();
+    let mut warnings = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if entry.file_name() == BACKUP_QUARANTINE_DIR {
+            continue;
+        }
+        total_size += if path.is_dir() {
+            let (size, skipped) = crate::fs_utils::dir_size_lenient(&path);
+            warnings.extend(skipped);
+            size
+        } else {
+            entry.metadata().map(|m| m.len()).unwrap_or(0)
+        };
+        match classify_backup(&path) {
+            BackupEntryKind::Valid(_) => valid += 1,
+            BackupEntryKind::Manifest => {}
+            BackupEntryKind::Empty => empty.push(path),
+            kind => unknown.push((path, kind)),
+        }
+    }
+
+    ui.println(format!(
+        "  {} {} valid backup(s), {} in total",
+        ui.icon_ok(),
+        valid,
+        crate::commands::format_bytes(total_size)
+    ));
+    ui.println(format!(
+        "  {} Keeping {} backup(s) per component (backups.keep_per_component)",
+        ui.icon_info(),
+        keep
+    ));
+    for path in &empty {
+        ui.println(format!(
+            "  {} Empty backup: {}",
+            ui.icon_warn(),
+            path.display()
+        ));
+    }
+    // Symlink loops and unreadable or too-deep directories met while sizing
+    for warning in &warnings {
+        ui.println(format!("  {} {}", ui.icon_warn(), warning));
+    }
+    if unknown.is_empty() {
+        return true;
+    }
+
+    let quarantine = paths.backups_dir.join(BACKUP_QUARANTINE_DIR);
+    let mut ok = true;
+    for (path, kind) in unknown {
+        let reason = match kind {
+            BackupEntryKind::UnparsableTimestamp => "unparsable timestamp",
+            _ => "unknown prefix",
+        };
+        if !fix {
+            ui.println(format!(
+                "  {} Not a backup ({}): {}",
+                ui.icon_warn(),
+                reason,
+                path.display()
+            ));
+            continue;
+        }
+        let mut target = quarantine.join(entry_name(&path));
+        if target.exists() {
+            // Never overwrite something quarantined earlier
+            target = quarantine.join(format!(
+                "{}.{}",
+                entry_name(&path),
+                chrono::Utc::now().format("%Y%m%d_%H%M%S")
+            ));
+        }
+        let moved = fs::create_dir_all(&quarantine).and_then(|_| fs::rename(&path, &target));
+        match moved {
+            Ok(()) => ui.println(format!(
+                "  {} Moved {} ({}) to {}",
+                ui.icon_ok(),
+                entry_name(&path),
+                reason,
+                quarantine.display()
+            )),
+            Err(e) => {
+                ui.println(format!(
+                    "  {} Could not move {}: {}",
+                    ui.icon_err(),
+                    path.display(),
+                    e
+                ));
+                ok = false;
+            }
+        }
+    }
+    if !fix {
+        ui.println(format!(
+            "      {} Run 'ccprof doctor --fix' to move them to {}",
+            ui.icon_info(),
+            quarantine.display()
+        ));
+    }
+    ok
+}
+
+/// Report (and with `fix`, remove) temp files and stale lock files
+fn check_leftover_files(paths: &Paths, ui: &Report, fix: bool) -> bool {
+    let mut leftovers: Vec<(PathBuf, String)> = leftover_temp_files(paths)
+        .into_iter()
+        .map(|path| (path, "unfinished write".to_string()))
+        .collect();
+    for path in files_with_suffix(&paths.base_dir, ".lock") {
+        if let Some(holder) = OperationLock::holder(&path)
+            && holder.pid != std::process::id()
+            && holder.is_stale()
+        {
+            leftovers.push((path, format!("stale lock of pid {}", holder.pid)));
+        }
+    }
+    for name in list_profiles(paths).unwrap_or_default() {
+        let profile_dir = paths.profile_dir(&name);
+        let path = profile_dir.join(crate::editor::EDITING_MARKER);
+        if !path.exists() {
+            continue;
+        }
+        match crate::editor::read_editing_marker(&profile_dir) {
+            Some(holder) if !holder.is_dead() => {}
+            Some(holder) => leftovers.push((
+                path,
+                format!("edit marker of pid {}, no longer running", holder.pid),
+            )),
+            None => leftovers.push((path, "unreadable edit marker".to_string())),
+        }
+    }
+
+    if leftovers.is_empty() {
+        ui.println(format!("  {} No leftover files", ui.icon_ok()));
+        return true;
+    }
+
+    let mut ok = true;
+    for (path, reason) in leftovers {
+        if !fix {
+            ui.println(format!(
+                "  {} {} ({})",
+                ui.icon_warn(),
+                path.display(),
+                reason
+            ));
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => ui.println(format!(
+                "  {} Removed {} ({})",
+                ui.icon_ok(),
+                path.display(),
+                reason
+            )),
+            Err(e) => {
+                ui.println(format!(
+                    "  {} Could not remove {}: {}",
+                    ui.icon_err(),
+                    path.display(),
+                    e
+                ));
+                ok = false;
+            }
+        }
+    }
+
+    if !fix {
+        ui.println(format!(
+            "      {} Run 'ccprof doctor --fix' to clean them up",
+            ui.icon_info()
+        ));
+    }
+    ok
+}
+
+/// `*.tmp` files in the base directory and at the top of each profile
+fn leftover_temp_files(paths: &Paths) -> Vec<PathBuf> {
+    let mut files = files_with_suffix(&paths.base_dir, ".tmp");
+    for name in list_profiles(paths).unwrap_or_default() {
+        files.extend(files_with_suffix(&paths.profile_dir(&name), ".tmp"));
+    }
+    files
+}
+
+/// Regular files directly in `dir` whose name ends with `suffix`, sorted
+fn files_with_suffix(dir: &Path, suffix: &str) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && entry_name(p).ends_with(suffix))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Longest JSON parse error shown for a profile
+const JSON_ERROR_WIDTH: usize = 80;
+
+fn entry_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Project settings files Claude Code reads on top of the user settings
+const PROJECT_SETTINGS_FILES: [&str; 2] = ["settings.json", "settings.local.json"];
+
+/// A project-level settings file found above a directory
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProjectOverride {
+    pub path: PathBuf,
+    /// Directories up from the start (0 = the start directory itself)
+    pub depth: usize,
+    /// Top-level keys also set by the active settings, sorted; `None` if the
+    /// file doesn't parse
+    pub overridden_keys: Option<Vec<String>>,
+}
+
+/// Find `.claude/settings*.json` from `start` up to the git root (or filesystem root)
+///
+/// `~/.claude` itself holds the user settings ccprof manages and is skipped.
+pub fn find_project_overrides(paths: &Paths, start: &Path) -> Vec<ProjectOverride> {
+    let active_keys = top_level_keys(&paths.claude_settings).unwrap_or_default();
+
+    let mut found = Vec::new();
+    for (depth, dir) in start.ancestors().enumerate() {
+        let claude_dir = dir.join(".claude");
+        if claude_dir != paths.claude_dir {
+            for file in PROJECT_SETTINGS_FILES {
+                let path = claude_dir.join(file);
+                if !path.is_file() {
+                    continue;
+                }
+                let overridden_keys = top_level_keys(&path).map(|keys| {
+                    keys.into_iter()
+                        .filter(|k| active_keys.contains(k))
+                        .collect()
+                });
+                found.push(ProjectOverride {
+                    path,
+                    depth,
+                    overridden_keys,
+                });
+            }
+        }
+        if dir.join(".git").exists() {
+            break;
+        }
+    }
+    found
+}
+
+/// Whether a JSON file only parses with its comments or trailing commas removed
+fn is_lenient_json(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| crate::jsonc::parse(&content).ok())
+        .is_some_and(|doc| doc.lenient)
+}
+
+/// Sorted top-level keys of a JSON object file
+fn top_level_keys(path: &Path) -> Option<Vec<String>> {
+    let content = fs::read_to_string(path).ok()?;
+    let value = crate::jsonc::from_str(&content).ok()?;
+    let mut keys: Vec<_> = value.as_object()?.keys().cloned().collect();
+    keys.sort();
+    Some(keys)
+}
+
+/// Report project `.claude` settings that take precedence over the active profile
+fn check_project_overrides(paths: &Paths, ui: &Report, project_dir: &Path) -> bool {
+    let overrides = find_project_overrides(paths, project_dir);
+    if overrides.is_empty() {
+        ui.println(format!(
+            "  {} No project settings above {}",
+            ui.icon_ok(),
+            project_dir.display()
+        ));
+        return true;
+    }
+
+    for found in overrides {
+        let depth = match found.depth {
+            0 => "here".to_string(),
+            1 => "1 level up".to_string(),
+            n => format!("{} levels up", n),
+        };
+        match found.overridden_keys {
+            Some(keys) if keys.is_empty() => ui.println(format!(
+                "  {} {} ({}): no keys shared with the active settings",
+                ui.icon_info(),
+                found.path.display(),
+                depth
+            )),
+            Some(keys) => ui.println(format!(
+                "  {} {} ({}) overrides: {}",
+                ui.icon_warn(),
+                found.path.display(),
+                depth,
+                keys.join(", ")
+            )),
+            None => ui.println(format!(
+                "  {} {} ({}) is not a JSON object",
+                ui.icon_warn(),
+                found.path.display(),
+                depth
+            )),
+        }
+    }
+    // Project settings are legitimate; they only explain surprising behavior
+    true
+}
+
+/// Warn about settings keys Claude Code doesn't know, or of the wrong type
+fn check_settings_keys(paths: &Paths, ui: &Report) -> bool {
+    let mut found = false;
+    for name in list_profiles(paths).unwrap_or_default() {
+        let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(&name)) else {
+            continue;
+        };
+        if !metadata.managed_components.contains(&Component::Settings) {
+            continue;
+        }
+        let settings = metadata.component_path(paths, &name, Component::Settings);
+        // Unreadable or invalid files are reported by the Profiles check
+        for issue in check_settings_file(&settings).unwrap_or_default() {
+            ui.println(format!("  {} {}: {}", ui.icon_warn(), name, issue));
+            found = true;
+        }
+    }
+    if found {
+        ui.println(format!(
+            "  {} Keys added by a newer Claude Code may be listed too",
+            ui.icon_info()
+        ));
+    } else {
+        ui.println(format!(
+            "  {} Profile settings only use known keys",
+            ui.icon_ok()
+        ));
+    }
+    // Only a hint: Claude Code ignores keys it doesn't know
+    true
+}
+
+/// List rendered config directories, warning about stale and orphaned ones
+fn check_rendered(paths: &Paths, ui: &Report) -> bool {
+    let rendered = list_rendered(paths).unwrap_or_default();
+    if rendered.is_empty() {
+        ui.println(format!(
+            "  {} No rendered configs (created by 'ccprof env')",
+            ui.icon_info()
+        ));
+    }
+    for dir in rendered {
+        let when = dir.rendered_at.map_or("unknown".to_string(), |t| {
+            t.format("%Y-%m-%d %H:%M:%S").to_string()
+        });
+        match dir.status {
+            RenderStatus::Fresh => ui.println(format!(
+                "  {} {}: up to date (rendered {})",
+                ui.icon_ok(),
+                dir.profile,
+                when
+            )),
+            RenderStatus::Stale => ui.println(format!(
+                "  {} {}: stale, the profile changed after it was rendered ({}); 'ccprof env {}' refreshes it",
+                ui.icon_warn(),
+                dir.profile,
+                when,
+                dir.profile
+            )),
+            RenderStatus::Orphaned => ui.println(format!(
+                "  {} {}: the profile no longer exists; remove {}",
+                ui.icon_warn(),
+                dir.profile,
+                dir.path.display()
+            )),
+        }
+    }
+    // Content is linked, so a stale render only misses added or removed components
+    true
+}
+
+/// Explain the Developer Mode requirement when Windows denies symlinks
+fn check_windows_symlinks(paths: &Paths, ui: &Report) {
+    let dir = if paths.claude_dir.is_dir() {
+        paths.claude_dir.clone()
+    } else {
+        env::temp_dir()
+    };
+    if crate::fs_utils::symlinks_permitted(&dir) {
+        ui.println(format!("  {} Symlinks permitted", ui.icon_ok()));
+        return;
+    }
+    ui.println(format!(
+        "  {} Symlinks are not permitted for this user; 'ccprof use' copies components instead",
+        ui.icon_warn()
+    ));
+    ui.println(format!(
+        "      {} Turn on Developer Mode (Settings > System > For developers) or run as administrator to use symlinks",
+        ui.icon_info()
+    ));
+}
+
+/// Warn about profiles recorded against a different Claude Code version
+fn check_claude_versions(paths: &Paths, ui: &Report) -> bool {
+    let Some(installed) = crate::claude::installed_version() else {
+        ui.println(format!(
+            "  {} Could not run 'claude --version' (set {} to the binary); skipping",
+            ui.icon_info(),
+            crate::claude::CLAUDE_BIN_ENV
+        ));
+        return true;
+    };
+    ui.println(format!(
+        "  {} Claude Code {} installed",
+        ui.icon_ok(),
+        installed
+    ));
+
+    for name in list_profiles(paths).unwrap_or_default() {
+        let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(&name)) else {
+            continue;
+        };
+        if let Some(recorded) = metadata.claude_version
+            && recorded != installed
+        {
+            ui.println(format!(
+                "  {} {} was created with Claude Code {}; check its settings still apply",
+                ui.icon_warn(),
+                name,
+                recorded
+            ));
+        }
+    }
+    // A different version is worth a look, not a failure
+    true
+}
+
+/// Report the hybrid state of components composed from the shared base
+/// Report (and with `fix`, recover) a switch journal left behind by a crash
+fn check_interrupted_switch(paths: &Paths, ui: &Report, fix: bool) -> bool {
+    // Lock content left by another process: either it is still running or it died
+    if let Some(holder) =
+        OperationLock::holder(&paths.lock_file).filter(|h| h.pid != std::process::id())
+    {
+        let held =
+            OperationLock::acquire_with_timeout(&paths.lock_file, Duration::ZERO, &mut || {})
+                .is_err();
+        ui.println(format!(
+            "  {} {} ccprof operation (pid {}, started {}): {}",
+            ui.icon_info(),
+            if held {
+                "Running"
+            } else {
+                "Stale lock from a finished"
+            },
+            holder.pid,
+            holder.started_at.format("%Y-%m-%d %H:%M:%S"),
+            holder.command
+        ));
+    }
+
+    let journal = match SwitchJournal::read(&paths.journal_file) {
+        Ok(Some(journal)) => journal,
+        Ok(None) => {
+            ui.println(format!("  {} No interrupted switch", ui.icon_ok()));
+            return true;
+        }
+        Err(e) => {
+            ui.println(format!("  {} {}", ui.icon_err(), e));
+            return false;
+        }
+    };
+
+    let done = journal.operations.iter().filter(|op| op.done).count();
+    ui.println(format!(
+        "  {} Switch to '{}' started {} was interrupted ({}/{} components applied)",
+        ui.icon_err(),
+        journal.profile,
+        journal.started_at.format("%Y-%m-%d %H:%M:%S"),
+        done,
+        journal.operations.len()
+    ));
+
+    if !fix {
+        ui.println(format!(
+            "  {} Run 'ccprof doctor --fix' to restore ~/.claude and finish the switch",
+            ui.icon_info()
+        ));
+        return false;
+    }
+
+    match recover(paths) {
+        Ok(Some(Recovery::Completed { profile })) => {
+            ui.println(format!(
+                "  {} Restored the originals and completed the switch to '{}'",
+                ui.icon_ok(),
+                profile
+            ));
+            true
+        }
+        Ok(Some(Recovery::RolledBack { profile })) => {
+            ui.println(format!(
+                "  {} Restored the originals; profile '{}' no longer exists, so the switch was rolled back",
+                ui.icon_ok(),
+                profile
+            ));
+            true
+        }
+        Ok(None) => true,
+        Err(e) => {
+            ui.println(format!("  {} Recovery failed: {:#}", ui.icon_err(), e));
+            false
+        }
+    }
+}
+
+/// Report drift of components copied into ~/.claude by the copy strategy
+/// Compare the profile each live component uses with what state records
+fn check_component_profiles(paths: &Paths, ui: &Report) -> bool {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let owners = component_ownership(paths, &state);
+    if owners.is_empty() {
+        ui.println(format!("  {} No components in ~/.claude", ui.icon_info()));
+        return true;
+    }
+
+    for owner in owners {
+        let recorded = owner
+            .recorded
+            .as_deref()
+            .map_or("nothing".to_string(), |p| format!("profile '{}'", p));
+        if owner.is_consistent() {
+            ui.println(format!(
+                "  {} {} uses {}",
+                ui.icon_ok(),
+                owner.component.display_name(),
+                owner.live.label()
+            ));
+        } else {
+            ui.println(format!(
+                "  {} {} uses {}, but state records {}",
+                ui.icon_warn(),
+                owner.component.display_name(),
+                owner.live.label(),
+                recorded
+            ));
+        }
+    }
+    // Mixed setups are allowed; mismatches are only worth a warning
+    true
+}
+
+fn check_copied_components(paths: &Paths, ui: &Report) -> bool {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if state.copies.is_empty() {
+        ui.println(format!("  {} No copied components", ui.icon_info()));
+        return true;
+    }
+
+    let mut components: Vec<_> = state.copies.keys().copied().collect();
+    components.sort_by_key(|c| c.short_name());
+
+    let mut ok = true;
+    for component in components {
+        let path = component.source_path(paths);
+        match ComponentStatus::detect_with_state(&path, component, &state) {
+            ComponentStatus::ManagedCopy {
+                profile,
+                drifted: false,
+            } => ui.println(format!(
+                "  {} {} matches profile '{}'",
+                ui.icon_ok(),
+                component.display_name(),
+                profile
+            )),
+            ComponentStatus::ManagedCopy {
+                profile,
+                drifted: true,
+            } => ui.println(format!(
+                "  {} {} modified since copied from '{}' (run 'ccprof sync')",
+                ui.icon_warn(),
+                component.display_name(),
+                profile
+            )),
+            ComponentStatus::Missing => {
+                ui.println(format!(
+                    "  {} {} copy is missing from ~/.claude",
+                    ui.icon_err(),
+                    component.display_name()
+                ));
+                ok = false;
+            }
+            other => ui.println(format!(
+                "  {} {} is now a {} (no longer a ccprof copy)",
+                ui.icon_warn(),
+                component.display_name(),
+                other.label()
+            )),
+        }
+    }
+    ok
+}
+
+fn check_composed_components(paths: &Paths, ui: &Report) -> bool {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let Some(profile) = state.default_profile else {
+        ui.println(format!("  {} No active profile set", ui.icon_info()));
+        return true;
+    };
+
+    let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(&profile)) else {
+        ui.println(format!(
+            "  {} Could not read metadata for '{}'",
+            ui.icon_warn(),
+            profile
+        ));
+        return true;
+    };
+
+    let mut composed: Vec<_> = metadata
+        .tracked_files
+        .iter()
+        .filter(|(c, files)| !c.is_file() && !files.is_empty())
+        .collect();
+    if composed.is_empty() {
+        ui.println(format!("  {} No composed components", ui.icon_info()));
+        return true;
+    }
+    composed.sort_by_key(|(c, _)| c.short_name());
+
+    let mut ok = true;
+    for (component, tracked) in composed {
+        let entries = match inspect_composed_dir(paths, &profile, component, tracked) {
+            Ok(Some(entries)) => entries,
+            Ok(None) => {
+                ui.println(format!(
+                    "  {} {} is not a composed directory (run 'ccprof use {}')",
+                    ui.icon_err(),
+                    component.source_path(paths).display(),
+                    profile
+                ));
+                ok = false;
+                continue;
+            }
+            Err(e) => {
+                ui.println(format!(
+                    "  {} Failed to inspect {}: {}",
+                    ui.icon_err(),
+                    component.display_name(),
+                    e
+                ));
+                ok = false;
+                continue;
+            }
+        };
+
+        let from_profile = entries
+            .iter()
+            .filter(|e| e.source == ComposedSource::Profile)
+            .count();
+        let from_base = entries
+            .iter()
+            .filter(|e| e.source == ComposedSource::Base)
+            .count();
+        let problems: Vec<_> = entries.iter().filter(|e| !e.is_expected()).collect();
+
+        let icon = if problems.is_empty() {
+            ui.icon_ok()
+        } else {
+            ui.icon_warn()
+        };
+        ui.println(format!(
+            "  {} {}: {} from profile, {} from shared base",
+            icon,
+            component.display_name(),
+            from_profile,
+            from_base
+        ));
+
+        for entry in problems {
+            let detail = match &entry.source {
+                ComposedSource::Broken => {
+                    ok = false;
+                    "broken or missing link".to_string()
+                }
+                ComposedSource::Local => "local file, not managed by ccprof".to_string(),
+                ComposedSource::Foreign(target) => {
+                    format!("links outside ccprof: {}", target.display())
+                }
+                ComposedSource::Profile => "links into profile but is not tracked".to_string(),
+                ComposedSource::Base => "tracked but links into shared base".to_string(),
+            };
+            ui.println(format!(
+                "      {} {}: {}",
+                ui.icon_warn(),
+                entry.name,
+                detail
+            ));
+        }
+    }
+
+    ok
 }
 
-fn check_step<F>(ui: &Ui, name: &str, check_fn: F)
+fn check_step<F>(ui: &Report, name: &str, check_fn: F) -> bool
 where
     F: FnOnce() -> bool,
 {
     ui.println(ui.bold(format!("Checking {}...", name)));
+    let warnings_before = ui.warnings.get();
     let success = check_fn();
-    if success {
-        // ui.println(format!("{} OK", ui.icon_ok()));
-    } else {
+    let verdict = if !success {
         ui.println(ui.colored("  Issues detected!", AnsiColor::Red));
-    }
+        Verdict::Error
+    } else if ui.warnings.get() > warnings_before {
+        Verdict::Warning
+    } else {
+        Verdict::Passed
+    };
     ui.newline();
-}
\ No newline at end of file
+    ui.checks.borrow_mut().push((name.to_string(), verdict));
+    success
+}
+
+/// Outcome of one doctor check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Passed,
+    Warning,
+    Error,
+}
+
+/// Doctor output: counts the warnings each check prints and can be silenced
+///
+/// The report is doctor's data, so its sections go to stdout along with the
+/// check lines. Derefs to [`Ui`]; only printing and the warning icon are
+/// intercepted.
+struct Report<'a> {
+    ui: &'a Ui,
+    quiet: bool,
+    warnings: Cell<usize>,
+    checks: RefCell<Vec<(String, Verdict)>>,
+}
+
+impl<'a> Report<'a> {
+    fn new(ui: &'a Ui, quiet: bool) -> Self {
+        Self {
+            ui,
+            quiet,
+            warnings: Cell::new(0),
+            checks: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn icon_warn(&self) -> &'static str {
+        self.warnings.set(self.warnings.get() + 1);
+        self.ui.icon_warn()
+    }
+
+    fn println(&self, msg: impl AsRef<str>) {
+        if !self.quiet {
+            self.ui.println(msg);
+        }
+    }
+
+    fn newline(&self) {
+        self.println("");
+    }
+
+    fn section(&self, title: impl AsRef<str>) {
+        self.println(self.ui.bold(title));
+    }
+
+    /// Print the verdict of every check; shown even with `--quiet`
+    fn print_summary(&self) {
+        let checks = self.checks.borrow();
+        let named = |verdict| {
+            checks
+                .iter()
+                .filter(|(_, v)| *v == verdict)
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+        };
+        let (warnings, errors) = (named(Verdict::Warning), named(Verdict::Error));
+        let passed = checks.len() - warnings.len() - errors.len();
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+
+        let line = format!(
+            "{} check{} passed, {} warning{}, {} error{}",
+            passed,
+            plural(passed),
+            warnings.len(),
+            plural(warnings.len()),
+            errors.len(),
+            plural(errors.len())
+        );
+        let color = if !errors.is_empty() {
+            AnsiColor::Red
+        } else if !warnings.is_empty() {
+            AnsiColor::Yellow
+        } else {
+            AnsiColor::Green
+        };
+        self.ui.println(self.ui.bold("Summary"));
+        self.ui.println(self.ui.colored(line, color));
+        if !errors.is_empty() {
+            self.ui.println(format!(
+                "  {} Errors: {}",
+                self.ui.icon_err(),
+                errors.join(", ")
+            ));
+        }
+        if !warnings.is_empty() {
+            self.ui.println(format!(
+                "  {} Warnings: {}",
+                self.ui.icon_warn(),
+                warnings.join(", ")
+            ));
+        }
+    }
+}
+
+impl std::ops::Deref for Report<'_> {
+    type Target = Ui;
+
+    fn deref(&self) -> &Ui {
+        self.ui
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::create_profile_with_components;
+    use crate::switch::{SwitchOptions, switch_to_profile};
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_unparsable_profile_settings_fail_doctor() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let ui = Ui::new(crate::ui::ColorMode::Never, false);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        create_profile_with_components(&paths, "work", HashSet::from([Component::Settings]))
+            .unwrap();
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(run_doctor(
+            &paths,
+            &Config::default(),
+            &ui,
+            false,
+            true,
+            temp_dir.path()
+        ));
+
+        // A typo only warns
+        fs::write(paths.profile_settings("work"), r#"{"permisions": {}}"#).unwrap();
+        let report = Report::new(&ui, true);
+        assert!(check_settings_keys(&paths, &report));
+        assert_eq!(report.warnings.get(), 1);
+        assert!(run_doctor(
+            &paths,
+            &Config::default(),
+            &ui,
+            false,
+            true,
+            temp_dir.path()
+        ));
+
+        fs::write(paths.profile_settings("work"), r#"{"model": "#).unwrap();
+        assert!(!run_doctor(
+            &paths,
+            &Config::default(),
+            &ui,
+            false,
+            true,
+            temp_dir.path()
+        ));
+    }
+
+    #[test]
+    fn test_find_project_overrides_up_to_git_root() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(
+            &paths.claude_settings,
+            r#"{"model": "opus", "env": {}, "hooks": {}}"#,
+        )
+        .unwrap();
+
+        let repo = temp_dir.path().join("repo");
+        let start = repo.join("app/src");
+        fs::create_dir_all(&start).unwrap();
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(repo.join(".claude")).unwrap();
+        fs::create_dir_all(repo.join("app/.claude")).unwrap();
+        fs::write(
+            repo.join(".claude/settings.json"),
+            r#"{"env": {}, "permissions": {}}"#,
+        )
+        .unwrap();
+        fs::write(
+            repo.join("app/.claude/settings.local.json"),
+            r#"{"model": "haiku"}"#,
+        )
+        .unwrap();
+        fs::write(repo.join("app/.claude/settings.json"), "[]").unwrap();
+
+        let found = find_project_overrides(&paths, &start);
+        assert_eq!(
+            found,
+            vec![
+                ProjectOverride {
+                    path: repo.join("app/.claude/settings.json"),
+                    depth: 1,
+                    overridden_keys: None,
+                },
+                ProjectOverride {
+                    path: repo.join("app/.claude/settings.local.json"),
+                    depth: 1,
+                    overridden_keys: Some(vec!["model".to_string()]),
+                },
+                ProjectOverride {
+                    path: repo.join(".claude/settings.json"),
+                    depth: 2,
+                    overridden_keys: Some(vec!["env".to_string()]),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_verdicts_count_warnings() {
+        let ui = Ui::new(crate::ui::ColorMode::Never, false);
+        let report = Report::new(&ui, true);
+        check_step(&report, "Clean", || true);
+        check_step(&report, "Noisy", || {
+            report.println(format!("{} something odd", report.icon_warn()));
+            true
+        });
+        check_step(&report, "Broken", || false);
+
+        assert_eq!(
+            *report.checks.borrow(),
+            vec![
+                ("Clean".to_string(), Verdict::Passed),
+                ("Noisy".to_string(), Verdict::Warning),
+                ("Broken".to_string(), Verdict::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_leftover_temp_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        create_profile_with_components(&paths, "work", HashSet::from([Component::Settings]))
+            .unwrap();
+
+        fs::write(paths.base_dir.join("state.json.tmp"), "{").unwrap();
+        fs::write(paths.profile_dir("work").join("metadata.json.tmp"), "{").unwrap();
+        // Only top-level files count, not content inside components
+        fs::create_dir_all(paths.profile_dir("work").join("agents")).unwrap();
+        fs::write(paths.profile_dir("work").join("agents/notes.tmp"), "").unwrap();
+
+        assert_eq!(
+            leftover_temp_files(&paths),
+            vec![
+                paths.base_dir.join("state.json.tmp"),
+                paths.profile_dir("work").join("metadata.json.tmp"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repair_default_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        for name in ["work", "gone"] {
+            create_profile_with_components(&paths, name, HashSet::from([Component::Settings]))
+                .unwrap();
+        }
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        let set_default = |profile: &str| {
+            LockedState::lock(&paths.state_file)
+                .unwrap()
+                .update(|s| s.default_profile = Some(profile.to_string()))
+                .unwrap();
+        };
+
+        // Deleted by hand: re-pointed to the profile settings.json links into
+        set_default("gone");
+        fs::remove_dir_all(paths.profile_dir("gone")).unwrap();
+        assert_eq!(
+            repair_default_profile(&paths).unwrap().as_deref(),
+            Some("work")
+        );
+
+        // Nothing to re-point to: cleared
+        fs::remove_file(&paths.claude_settings).unwrap();
+        set_default("gone");
+        assert_eq!(repair_default_profile(&paths).unwrap(), None);
+
+        // An existing default is left alone
+        set_default("work");
+        assert_eq!(
+            repair_default_profile(&paths).unwrap().as_deref(),
+            Some("work")
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Choosing and starting the user's editor.
+//!
+//! `editor` in config.toml wins, then `VISUAL`, then `EDITOR`. The command is
+//! split like a shell would, so `EDITOR="code --wait"` works. Without any of
+//! them the system's default application is used: `open -t` on macOS,
+//! `notepad` on Windows and `xdg-open` elsewhere.
+//!
+//! [`edit_safely`] edits a copy instead, so a file that is in use only ever
+//! changes from one valid version to the next.
+//!
+//! While an editor is open on a profile, an [`EditingMarker`] in the profile
+//! directory tells other ccprof processes, so removing or renaming the profile
+//! under the editor needs `--force`.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+use crate::interaction::Interaction;
+use crate::state::LockHolder;
+
+/// Where the editor command came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditorSource {
+    Config,
+    Visual,
+    Editor,
+    /// The system default for this platform
+    Fallback,
+}
+
+impl std::fmt::Display for EditorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Config => "config.toml",
+            Self::Visual => "VISUAL",
+            Self::Editor => "EDITOR",
+            Self::Fallback => "system default",
+        })
+    }
+}
+
+/// An editor command, split into program and arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Editor {
+    pub argv: Vec<String>,
+    pub source: EditorSource,
+    /// `xdg-open` and `notepad` take a single file
+    single_file: bool,
+}
+
+/// The editor for `os` (as in [`std::env::consts::OS`]), given config.toml's
+/// `editor` and the `VISUAL` and `EDITOR` variables
+///
+/// Empty values are skipped; one that doesn't split (an unclosed quote) is an
+/// error rather than being skipped, so the user finds out.
+pub fn resolve_editor(
+    configured: Option<&str>,
+    visual: Option<String>,
+    editor: Option<String>,
+    os: &str,
+) -> Result<Editor> {
+    let candidates = [
+        (EditorSource::Config, configured.map(str::to_string)),
+        (EditorSource::Visual, visual),
+        (EditorSource::Editor, editor),
+    ];
+    for (source, command) in candidates {
+        let Some(command) = command.filter(|c| !c.trim().is_empty()) else {
+            continue;
+        };
+        let argv = shell_words::split(&command).with_context(|| {
+            format!(
+                "Cannot parse the editor command from {}: {}\nHint: Check its quoting.",
+                source, command
+            )
+        })?;
+        return Ok(Editor {
+            argv,
+            source,
+            single_file: false,
+        });
+    }
+
+    let (argv, single_file) = match os {
+        "macos" => (vec!["open", "-t"], false),
+        "windows" => (vec!["notepad"], true),
+        _ => (vec!["xdg-open"], true),
+    };
+    Ok(Editor {
+        argv: argv.into_iter().map(str::to_string).collect(),
+        source: EditorSource::Fallback,
+        single_file,
+    })
+}
+
+/// The editor for this system and environment, `config` naming one first
+pub fn current_editor(config: &Config) -> Result<Editor> {
+    resolve_editor(
+        config.editor.as_deref(),
+        std::env::var("VISUAL").ok(),
+        std::env::var("EDITOR").ok(),
+        std::env::consts::OS,
+    )
+}
+
+impl Editor {
+    /// The command as it would be typed in a shell
+    pub fn command_line(&self) -> String {
+        shell_words::join(&self.argv)
+    }
+
+    /// The commands opening `files`, run one after another
+    pub fn commands(&self, files: &[PathBuf]) -> Vec<Command> {
+        let (program, args) = self
+            .argv
+            .split_first()
+            .expect("editor command is not empty");
+        let command = |files: &[PathBuf]| {
+            let mut command = Command::new(program);
+            command.args(args).args(files);
+            command
+        };
+        if self.single_file {
+            files.chunks(1).map(command).collect()
+        } else {
+            vec![command(files)]
+        }
+    }
+
+    /// Open `files` and wait for the editor to exit
+    pub fn open(&self, files: &[PathBuf]) -> Result<()> {
+        let name = self.command_line();
+        for mut command in self.commands(files) {
+            let status = command.status().with_context(|| {
+                format!(
+                    "Failed to run editor '{}' (from {})\nHint: Set VISUAL or EDITOR, or 'editor' in config.toml.",
+                    name, self.source
+                )
+            })?;
+            if !status.success() {
+                bail!("Editor '{}' exited with non-zero status", name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How [`edit_safely`] ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SafeEdit {
+    /// The edited copy replaced the file
+    Saved { before: String, after: String },
+    /// The editor exited without changing anything
+    Unchanged,
+    /// The user threw away an edit that didn't validate
+    Discarded,
+}
+
+/// Edit `path` through a copy in `scratch_dir`, replacing it only with
+/// content `validate` accepts
+///
+/// When the edited copy fails validation, `interaction` asks whether to edit
+/// it again or discard it. The replacement is written next to the file and
+/// renamed over it, so readers see either the old or the new content.
+pub fn edit_safely(
+    editor: &Editor,
+    path: &Path,
+    scratch_dir: &Path,
+    validate: &dyn Fn(&str) -> Result<()>,
+    interaction: &dyn Interaction,
+) -> Result<SafeEdit> {
+    // A symlinked file is replaced where it lives, keeping the link
+    let target =
+        fs::canonicalize(path).with_context(|| format!("Failed to resolve {}", path.display()))?;
+    let before = fs::read_to_string(&target)
+        .with_context(|| format!("Failed to read {}", target.display()))?;
+
+    let dir = scratch_dir.join(format!(
+        "edit-{}-{}",
+        chrono::Utc::now().format("%Y%m%d_%H%M%S%.f"),
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    // Same file name, so the editor picks the right syntax
+    let copy = dir.join(target.file_name().unwrap_or_default());
+    let result = fs::write(&copy, &before)
+        .with_context(|| format!("Failed to write {}", copy.display()))
+        .and_then(|()| edit_copy(editor, &copy, validate, interaction));
+    let _ = fs::remove_dir_all(&dir);
+
+    let Some(after) = result? else {
+        return Ok(SafeEdit::Discarded);
+    };
+    if after == before {
+        return Ok(SafeEdit::Unchanged);
+    }
+    replace_file(&target, &after)?;
+    Ok(SafeEdit::Saved { before, after })
+}
+
+/// Run the editor on `copy` until its content validates; `None` if discarded
+fn edit_copy(
+    editor: &Editor,
+    copy: &Path,
+    validate: &dyn Fn(&str) -> Result<()>,
+    interaction: &dyn Interaction,
+) -> Result<Option<String>> {
+    let options = [
+        "Edit it again".to_string(),
+        "Discard the changes".to_string(),
+    ];
+    loop {
+        editor.open(&[copy.to_path_buf()])?;
+        let content = fs::read_to_string(copy)
+            .with_context(|| format!("Failed to read {}", copy.display()))?;
+        let Err(e) = validate(&content) else {
+            return Ok(Some(content));
+        };
+        let question = format!("The edit is not valid ({:#}). What now?", e);
+        let choice = interaction
+            .select(&question, &options)
+            .with_context(|| format!("The edit is not valid and was discarded: {:#}", e))?;
+        if choice == 1 {
+            return Ok(None);
+        }
+    }
+}
+
+/// Write `content` to a file beside `target` and rename it over `target`
+fn replace_file(target: &Path, content: &str) -> Result<()> {
+    let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+    let temp = target.with_file_name(format!(".{}.ccprof-edit", file_name));
+    fs::write(&temp, content).with_context(|| format!("Failed to write {}", temp.display()))?;
+    if let Ok(metadata) = fs::metadata(target) {
+        let _ = fs::set_permissions(&temp, metadata.permissions());
+    }
+    crate::audit::record(format!("write {}", target.display()));
+    fs::rename(&temp, target).with_context(|| {
+        let _ = fs::remove_file(&temp);
+        format!("Failed to replace {}", target.display())
+    })
+}
+
+/// File in a profile directory while an editor is open on the profile
+pub const EDITING_MARKER: &str = ".editing";
+
+/// Marks a profile as being edited by this process until dropped
+pub struct EditingMarker {
+    path: PathBuf,
+}
+
+impl EditingMarker {
+    /// Write the marker into `profile_dir`
+    pub fn create(profile_dir: &Path) -> Result<Self> {
+        let path = profile_dir.join(EDITING_MARKER);
+        fs::write(&path, serde_json::to_string_pretty(&LockHolder::current())?)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for EditingMarker {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The process that wrote the marker in `profile_dir`, if there is one
+pub fn read_editing_marker(profile_dir: &Path) -> Option<LockHolder> {
+    let content = fs::read_to_string(profile_dir.join(EDITING_MARKER)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Another process with an editor open on the profile in `profile_dir`
+///
+/// Markers left by processes that are gone don't count; doctor reports them.
+pub fn editing_elsewhere(profile_dir: &Path) -> Option<LockHolder> {
+    read_editing_marker(profile_dir)
+        .filter(|holder| holder.pid != std::process::id() && !holder.is_dead())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(command: &Command) -> Vec<String> {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_editor_precedence_and_splitting() {
+        let files = [PathBuf::from("a.json"), PathBuf::from("b.md")];
+
+        let editor = resolve_editor(None, None, Some("code --wait".into()), "linux").unwrap();
+        assert_eq!(editor.source, EditorSource::Editor);
+        let commands = editor.commands(&files);
+        assert_eq!(commands.len(), 1);
+        assert_eq!(argv(&commands[0]), ["code", "--wait", "a.json", "b.md"]);
+
+        let editor = resolve_editor(
+            None,
+            Some("'/opt/My Editor/bin/ed' -n".into()),
+            Some("vi".into()),
+            "linux",
+        )
+        .unwrap();
+        assert_eq!(editor.source, EditorSource::Visual);
+        assert_eq!(editor.argv, ["/opt/My Editor/bin/ed", "-n"]);
+        assert_eq!(editor.command_line(), "'/opt/My Editor/bin/ed' -n");
+
+        let editor =
+            resolve_editor(Some("hx"), Some("vim".into()), Some("vi".into()), "linux").unwrap();
+        assert_eq!(editor.source, EditorSource::Config);
+        assert_eq!(editor.argv, ["hx"]);
+
+        // Empty values don't count
+        let editor = resolve_editor(Some(" "), Some(String::new()), Some("vi".into()), "linux");
+        assert_eq!(editor.unwrap().argv, ["vi"]);
+
+        let err = resolve_editor(None, None, Some("code \"--wait".into()), "linux").unwrap_err();
+        assert!(err.to_string().contains("from EDITOR"));
+    }
+
+    #[test]
+    fn test_fallback_editor_per_platform() {
+        let files = [PathBuf::from("a.json"), PathBuf::from("b.md")];
+
+        let macos = resolve_editor(None, None, None, "macos").unwrap();
+        assert_eq!(macos.source, EditorSource::Fallback);
+        let commands = macos.commands(&files);
+        assert_eq!(argv(&commands[0]), ["open", "-t", "a.json", "b.md"]);
+
+        // One file per run for openers that take a single file
+        let linux = resolve_editor(None, None, None, "linux").unwrap();
+        let commands: Vec<_> = linux.commands(&files).iter().map(argv).collect();
+        assert_eq!(commands, [["xdg-open", "a.json"], ["xdg-open", "b.md"]]);
+        let windows = resolve_editor(None, None, None, "windows").unwrap();
+        assert_eq!(argv(&windows.commands(&files)[1]), ["notepad", "b.md"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_safe_edit_replaces_only_with_valid_content() {
+        use crate::interaction::{Answer, Headless};
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("settings.json");
+        let scratch = temp_dir.path().join("scratch");
+        fs::write(&file, "{}\n").unwrap();
+        let validate = |text: &str| -> Result<()> {
+            serde_json::from_str::<serde_json::Value>(text)?;
+            Ok(())
+        };
+        // An editor writing `content` into the file it is given
+        let editor = |content: &str| {
+            let script = format!("printf '%s' '{}' > \"$1\"", content);
+            resolve_editor(
+                None,
+                None,
+                Some(shell_words::join(["sh", "-c", &script, "sh"])),
+                "linux",
+            )
+            .unwrap()
+        };
+
+        let saved = edit_safely(
+            &editor("{}\n"),
+            &file,
+            &scratch,
+            &validate,
+            &Headless::new([]),
+        );
+        assert_eq!(saved.unwrap(), SafeEdit::Unchanged);
+
+        let broken = editor("{\"model\":");
+        // Edited once more, then discarded
+        let headless = Headless::new([Answer::Select(0), Answer::Select(1)]);
+        let result = edit_safely(&broken, &file, &scratch, &validate, &headless).unwrap();
+        assert_eq!(result, SafeEdit::Discarded);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{}\n");
+
+        let result = edit_safely(
+            &editor("{\"model\": 1}"),
+            &file,
+            &scratch,
+            &validate,
+            &headless,
+        );
+        assert_eq!(
+            result.unwrap(),
+            SafeEdit::Saved {
+                before: "{}\n".to_string(),
+                after: "{\"model\": 1}".to_string()
+            }
+        );
+        assert_eq!(fs::read_to_string(&file).unwrap(), "{\"model\": 1}");
+        // Nothing is left behind
+        assert_eq!(fs::read_dir(&scratch).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_editing_marker_only_counts_live_other_processes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let profile_dir = temp_dir.path();
+        let marker = EditingMarker::create(profile_dir).unwrap();
+        assert_eq!(
+            read_editing_marker(profile_dir).unwrap().pid,
+            std::process::id()
+        );
+        // This process's own marker doesn't block it
+        assert!(editing_elsewhere(profile_dir).is_none());
+        drop(marker);
+        assert!(!profile_dir.join(EDITING_MARKER).exists());
+
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let holder = LockHolder {
+            pid: child.id(),
+            ..LockHolder::current()
+        };
+        fs::write(
+            profile_dir.join(EDITING_MARKER),
+            serde_json::to_string(&holder).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(editing_elsewhere(profile_dir).unwrap().pid, child.id());
+        child.kill().unwrap();
+        child.wait().unwrap();
+        assert!(editing_elsewhere(profile_dir).is_none());
+        assert!(read_editing_marker(profile_dir).unwrap().is_dead());
+    }
+}
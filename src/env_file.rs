@@ -0,0 +1,164 @@
+//! The Env component: a profile's `env.sh` of shell exports.
+//!
+//! Claude Code never reads it. Switching links it to
+//! `~/.claude-profiles/active.env` for shells to source, `ccprof env` prints
+//! its exports in the syntax of the shell, and `ccprof exec` sets them for the
+//! command it runs. Only `[export] NAME=value` lines are understood, with the
+//! value optionally in single or double quotes; other lines are skipped with a
+//! warning. Values are taken literally, without expanding `$VARIABLES`.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+use crate::components::{Component, ProfileMetadata};
+use crate::paths::Paths;
+
+/// Variables read from an env.sh
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct EnvFile {
+    /// In file order; a later line for the same name wins when they are set
+    pub vars: Vec<(String, String)>,
+    /// One per line skipped or taken differently than a shell would
+    pub warnings: Vec<String>,
+}
+
+/// Parse the content of an env.sh
+pub fn parse(content: &str) -> EnvFile {
+    let mut env = EnvFile::default();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let assignment = line.strip_prefix("export ").map_or(line, str::trim_start);
+        let parsed = assignment
+            .split_once('=')
+            .filter(|(name, _)| is_variable_name(name))
+            .and_then(|(name, raw)| Some((name, parse_value(raw)?)));
+        match parsed {
+            Some((name, (value, expands))) => {
+                if expands {
+                    env.warnings.push(format!(
+                        "line {}: {} is used as written; $ and ` are not expanded",
+                        number + 1,
+                        name
+                    ));
+                }
+                env.vars.push((name.to_string(), value));
+            }
+            None => env.warnings.push(format!(
+                "line {}: skipped, not an 'export NAME=value' line",
+                number + 1
+            )),
+        }
+    }
+    env
+}
+
+/// What a new env.sh holds when there's no active.env to copy it from
+pub const ENV_FILE_TEMPLATE: &str = "# Exports for this profile, sourced from ~/.claude-profiles/active.env\n# export HTTPS_PROXY=http://proxy.example.com:8080\n";
+
+/// Start an env.sh at `path` from [`ENV_FILE_TEMPLATE`]
+pub fn create_env_file(path: &Path) -> Result<()> {
+    crate::audit::record(format!("write {}", path.display()));
+    fs::write(path, ENV_FILE_TEMPLATE)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Profile `name`'s env.sh, or `None` if it doesn't manage one
+pub fn read_profile_env(paths: &Paths, name: &str) -> Result<Option<EnvFile>> {
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    if !metadata.managed_components.contains(&Component::Env) {
+        return Ok(None);
+    }
+    let path = metadata.component_path(paths, name, Component::Env);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(Some(parse(&content)))
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The value of an assignment, and whether a shell would have expanded
+/// something in it; `None` if it isn't a single (possibly quoted) word
+fn parse_value(raw: &str) -> Option<(String, bool)> {
+    // A comment after the value, as in `NAME=value # note`
+    let strip_comment = |rest: &str| {
+        let rest = rest.trim_start();
+        (rest.is_empty() || rest.starts_with('#')).then_some(())
+    };
+    if let Some(rest) = raw.strip_prefix('\'') {
+        let (value, after) = rest.split_once('\'')?;
+        strip_comment(after)?;
+        return Some((value.to_string(), false));
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut expands = false;
+        let mut chars = rest.chars();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => match chars.next()? {
+                    c @ ('"' | '\\' | '$' | '`') => value.push(c),
+                    c => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                },
+                c => {
+                    expands |= c == '$' || c == '`';
+                    value.push(c);
+                }
+            }
+        }
+        strip_comment(chars.as_str())?;
+        return Some((value, expands));
+    }
+    let (value, after) = raw.split_once(char::is_whitespace).unwrap_or((raw, ""));
+    strip_comment(after)?;
+    if value.contains(['\'', '"', '\\', ';', '|', '&']) {
+        return None;
+    }
+    Some((value.to_string(), value.contains(['$', '`'])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exports() {
+        let env = parse(
+            "# proxy for work\n\
+             export ANTHROPIC_MODEL=claude-opus\n\
+             HTTPS_PROXY='http://proxy:8080'  # office\n\
+             export  NOTE=\"say \\\"hi\\\"\"\n\
+             PATH_EXTRA=$HOME/bin\n\
+             alias ll='ls -l'\n\
+             \n",
+        );
+        assert_eq!(
+            env.vars,
+            vec![
+                ("ANTHROPIC_MODEL".to_string(), "claude-opus".to_string()),
+                ("HTTPS_PROXY".to_string(), "http://proxy:8080".to_string()),
+                ("NOTE".to_string(), "say \"hi\"".to_string()),
+                ("PATH_EXTRA".to_string(), "$HOME/bin".to_string()),
+            ]
+        );
+        assert_eq!(env.warnings.len(), 2);
+        assert!(env.warnings[0].starts_with("line 5: PATH_EXTRA"));
+        assert!(env.warnings[1].starts_with("line 6: skipped"));
+    }
+}
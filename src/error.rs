@@ -0,0 +1,184 @@
+//! Errors returned by the library.
+//!
+//! The public functions of [`profiles`](crate::profiles), [`switch`](crate::switch)
+//! and [`state`](crate::state) return [`Error`], so callers can tell a missing
+//! profile from an invalid name without matching on message text:
+//!
+//! ```no_run
+//! use ccprof::Error;
+//! use ccprof::paths::Paths;
+//!
+//! let paths = Paths::from_env()?;
+//! match ccprof::profiles::remove_profile(&paths, "work", &mut || {}) {
+//!     Ok(()) => println!("removed"),
+//!     Err(Error::ProfileNotFound { name, .. }) => println!("no profile named {name}"),
+//!     Err(e) => return Err(e.into()),
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Failures without a variant of their own (I/O errors, unreadable metadata)
+//! are [`Error::Other`], keeping the anyhow context chain for display.
+
+use std::path::PathBuf;
+
+use crate::state::LockHolder;
+
+/// Result of the library's public functions
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What went wrong in a library call
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// No profile with this name, maybe with a close one that exists
+    #[error("Profile '{name}' does not exist.\nHint: {}", not_found_hint(.suggestion.as_deref()))]
+    ProfileNotFound {
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// More than one profile matches a shortened name
+    #[error(
+        "Profile name '{name}' is ambiguous; it matches: {}\nHint: Type more of the name.",
+        .candidates.join(", ")
+    )]
+    AmbiguousName {
+        name: String,
+        candidates: Vec<String>,
+    },
+    /// A profile with this name already exists
+    #[error(
+        "Profile '{name}' already exists.\nHint: Choose a different name, or use 'ccprof edit {name}' to modify it."
+    )]
+    ProfileExists { name: String },
+    /// The name can't be used for a profile
+    #[error("Invalid profile name '{name}': {reason}")]
+    InvalidName { name: String, reason: &'static str },
+    /// A file that must hold JSON doesn't parse
+    #[error("Invalid JSON in file: {}", .path.display())]
+    InvalidJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// Other profiles extend this one, so it can't go away
+    #[error(
+        "Profile '{name}' is extended by: {}\nHint: Remove or change 'extends' in those profiles first.",
+        .children.join(", ")
+    )]
+    ProfileExtended { name: String, children: Vec<String> },
+    /// Another ccprof operation holds the lock file
+    #[error(
+        "Another ccprof operation is in progress{}.\nHint: Wait for it to finish and retry (lock file: {})",
+        crate::state::describe_holder(.holder.clone()),
+        .path.display()
+    )]
+    Locked {
+        path: PathBuf,
+        holder: Option<LockHolder>,
+    },
+    /// ccprof's directory can't be written, so nothing may be changed
+    #[error(
+        "The profiles directory {} is not writable ({reason}).\nHint: Read-only commands such as list, current, inspect, diff and doctor still work; set CCPROF_HOME to a writable directory to make changes.",
+        .path.display()
+    )]
+    NotWritable { path: PathBuf, reason: &'static str },
+    /// Anything else, with its context chain
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Hint for a missing profile: the close name, or where to look
+fn not_found_hint(suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!("Did you mean '{}'?", suggestion),
+        None => "Use 'ccprof list' to see available profiles.".to_string(),
+    }
+}
+
+impl Error {
+    /// Exit status for the CLI: 2 for a missing or ambiguous profile, 3 for
+    /// invalid input
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::ProfileNotFound { .. } | Error::AmbiguousName { .. } => 2,
+            Error::InvalidName { .. } | Error::InvalidJson { .. } | Error::ProfileExists { .. } => {
+                3
+            }
+            _ => 1,
+        }
+    }
+
+    /// The first typed error in `err`'s chain, looking inside [`Error::Other`]
+    pub fn find(err: &anyhow::Error) -> Option<&Error> {
+        err.chain()
+            .filter_map(|cause| cause.downcast_ref::<Error>())
+            .find_map(|e| match e {
+                Error::Other(inner) => Error::find(inner),
+                typed => Some(typed),
+            })
+    }
+}
+
+// Not `#[from]`: a typed error passed through anyhow must come back as itself
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        if (*err).is::<Error>() {
+            return err.downcast().expect("checked above");
+        }
+        Error::Other(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Other(err.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_typed_errors_survive_anyhow() {
+        let missing = || Error::ProfileNotFound {
+            name: "work".to_string(),
+            suggestion: None,
+        };
+
+        // Straight through anyhow and back
+        let err: Error = anyhow::Error::from(missing()).into();
+        assert!(matches!(err, Error::ProfileNotFound { ref name, .. } if name == "work"));
+
+        // Under added context, found inside Other
+        let wrapped: anyhow::Result<()> = Err(missing()).context("Failed to switch");
+        let wrapped = Error::from(wrapped.unwrap_err());
+        assert!(matches!(wrapped, Error::Other(_)));
+        let err = anyhow::Error::from(wrapped).context("Command failed");
+        assert_eq!(Error::find(&err).unwrap().exit_code(), 2);
+        assert_eq!(
+            format!("{:#}", err),
+            format!("Command failed: Failed to switch: {}", missing())
+        );
+
+        assert!(Error::find(&anyhow::anyhow!("plain")).is_none());
+    }
+
+    #[test]
+    fn test_not_found_hint_names_the_suggestion() {
+        let err = Error::ProfileNotFound {
+            name: "wrok".to_string(),
+            suggestion: Some("work".to_string()),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Profile 'wrok' does not exist.\nHint: Did you mean 'work'?"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! Running one command under a temporary profile.
+//!
+//! `ccprof exec <profile> -- <command>` moves the live components the profile
+//! manages aside, switches, runs the command and then puts everything back as
+//! it was, including state.json's record of the active profile and the usage
+//! stats. The layout is restored when the command fails, when ccprof is
+//! interrupted (Ctrl-C and SIGTERM/SIGHUP wait for the command to exit) and by
+//! [`TemporarySwitch`]'s drop if anything in between errors. Only a SIGKILL or
+//! crash can leave it switched, with the originals in the staging directory.
+
+use anyhow::{Context, Result, anyhow, bail};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::components::{Component, ProfileMetadata};
+use crate::fs_utils::{move_path, remove_path};
+use crate::paths::Paths;
+use crate::state::{LockedState, ManagedCopy, State};
+use crate::switch::{
+    ComponentStatus, SwitchOptions, SwitchReport, is_composed_dir, switch_to_profile,
+};
+
+/// How often the running command is checked for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A live component moved aside for the duration of the command
+struct Stashed {
+    live_path: PathBuf,
+    /// `None` if nothing was there
+    stashed_path: Option<PathBuf>,
+}
+
+/// What state.json said before the switch
+///
+/// The usage stats are kept too: a temporary switch doesn't count as a use.
+struct SavedState {
+    default_profile: Option<String>,
+    updated_at: Option<DateTime<Utc>>,
+    active_components: HashMap<Component, String>,
+    copies: HashMap<Component, ManagedCopy>,
+    last_used: HashMap<String, DateTime<Utc>>,
+    switch_count: u64,
+}
+
+/// A switch that is undone by [`TemporarySwitch::restore`] or on drop
+pub struct TemporarySwitch {
+    paths: Paths,
+    stash_dir: PathBuf,
+    stashed: Vec<Stashed>,
+    saved: SavedState,
+    restored: bool,
+}
+
+impl TemporarySwitch {
+    /// Move the profile's live components aside and switch to it
+    ///
+    /// Fails unless every such component is a symlink, a ccprof-composed
+    /// directory, an unchanged copy or missing: regular files would survive
+    /// only in the staging directory if ccprof were killed. `force` moves them
+    /// anyway.
+    pub fn begin(paths: &Paths, name: &str, force: bool) -> Result<(Self, SwitchReport)> {
+        crate::profiles::ensure_profile_exists(paths, name)?;
+        let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+        let state = State::read(&paths.state_file).unwrap_or_default();
+
+        let components: Vec<Component> = Component::all()
+            .into_iter()
+            .filter(|c| metadata.managed_components.contains(c))
+            .collect();
+        if !force {
+            let unsafe_paths: Vec<String> = components
+                .iter()
+                .map(|&c| (c, c.source_path(paths)))
+                .filter(
+                    |(c, live)| match ComponentStatus::detect_with_state(live, *c, &state) {
+                        ComponentStatus::RegularFile | ComponentStatus::Directory => {
+                            !is_composed_dir(paths, live)
+                        }
+                        ComponentStatus::ManagedCopy { drifted, .. } => drifted,
+                        _ => false,
+                    },
+                )
+                .map(|(_, live)| live.display().to_string())
+                .collect();
+            if !unsafe_paths.is_empty() {
+                bail!(
+                    "Cannot guarantee restoring ~/.claude afterwards: {} {} content ccprof doesn't manage.\nHint: Run 'ccprof add <name> --from-current' or 'ccprof sync' first, or use --force.",
+                    unsafe_paths.join(", "),
+                    if unsafe_paths.len() == 1 {
+                        "holds"
+                    } else {
+                        "hold"
+                    }
+                );
+            }
+        }
+
+        let stash_dir = paths.staging_dir.join(format!(
+            "exec-{}-{}",
+            Utc::now().format("%Y%m%d_%H%M%S%.f"),
+            std::process::id()
+        ));
+        fs::create_dir_all(&stash_dir).with_context(|| {
+            format!("Failed to create staging directory {}", stash_dir.display())
+        })?;
+
+        let mut temporary = Self {
+            paths: paths.clone(),
+            stash_dir,
+            stashed: Vec::new(),
+            saved: SavedState {
+                default_profile: state.default_profile,
+                updated_at: state.updated_at,
+                active_components: state.active_components,
+                copies: state.copies,
+                last_used: state.last_used,
+                switch_count: state.switch_count,
+            },
+            restored: false,
+        };
+        for component in components {
+            let live_path = component.source_path(paths);
+            let stashed_path = if fs::symlink_metadata(&live_path).is_ok() {
+                let to = temporary.stash_dir.join(component.short_name());
+                move_path(&live_path, &to)?;
+                Some(to)
+            } else {
+                None
+            };
+            temporary.stashed.push(Stashed {
+                live_path,
+                stashed_path,
+            });
+        }
+
+        // Nothing is left to back up or replace, so the switch only creates
+        let report = switch_to_profile(paths, name, &SwitchOptions::default())?;
+        Ok((temporary, report))
+    }
+
+    /// The profile that was active before, which restoring returns to
+    pub fn previous_profile(&self) -> Option<&str> {
+        self.saved.default_profile.as_deref()
+    }
+
+    /// Put the moved components and state back
+    pub fn restore(mut self) -> Result<()> {
+        self.restore_inner()
+    }
+
+    fn restore_inner(&mut self) -> Result<()> {
+        if self.restored {
+            return Ok(());
+        }
+        self.restored = true;
+
+        let mut errors = Vec::new();
+        for stashed in &self.stashed {
+            let result =
+                remove_path(&stashed.live_path).and_then(|()| match &stashed.stashed_path {
+                    Some(from) => move_path(from, &stashed.live_path),
+                    None => Ok(()),
+                });
+            if let Err(e) = result {
+                errors.push(format!("{:#}", e));
+            }
+        }
+
+        let saved = &self.saved;
+        let state_result = LockedState::lock(&self.paths.state_file).and_then(|mut locked| {
+            locked.update(|s| {
+                s.default_profile = saved.default_profile.clone();
+                s.updated_at = saved.updated_at;
+                s.active_components = saved.active_components.clone();
+                s.copies = saved.copies.clone();
+                s.last_used = saved.last_used.clone();
+                s.switch_count = saved.switch_count;
+            })
+        });
+        if let Err(e) = state_result {
+            errors.push(format!("{:#}", e));
+        }
+
+        if !errors.is_empty() {
+            bail!(
+                "Failed to restore ~/.claude: {}\nHint: The original content is kept in {}.",
+                errors.join("; "),
+                self.stash_dir.display()
+            );
+        }
+        remove_path(&self.stash_dir)
+    }
+}
+
+impl Drop for TemporarySwitch {
+    fn drop(&mut self) {
+        if let Err(e) = self.restore_inner() {
+            eprintln!("Error: {:#}", e);
+        }
+    }
+}
+
+/// Run `command` with inherited stdio and `env` added to its environment, and
+/// return its exit code
+///
+/// While it runs, Ctrl-C only reaches the command (its process group gets it
+/// from the terminal), and SIGTERM or SIGHUP sent to ccprof stop the command,
+/// so the caller always gets to restore. A command killed by a signal exits
+/// with 128 plus the signal number, like in a shell.
+pub fn run_command(command: &[String], env: &[(String, String)]) -> Result<i32> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| anyhow!("No command given\nHint: Put it after '--'."))?;
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let terminated = Arc::new(AtomicBool::new(false));
+    let mut handlers = vec![signal_hook::flag::register(
+        signal_hook::consts::SIGINT,
+        Arc::clone(&interrupted),
+    )?];
+    #[cfg(unix)]
+    let stopping = [signal_hook::consts::SIGTERM, signal_hook::consts::SIGHUP];
+    #[cfg(not(unix))]
+    let stopping = [signal_hook::consts::SIGTERM];
+    for signal in stopping {
+        handlers.push(signal_hook::flag::register(
+            signal,
+            Arc::clone(&terminated),
+        )?);
+    }
+
+    let result = (|| -> Result<i32> {
+        let mut child = Command::new(program)
+            .args(args)
+            .envs(env.iter().cloned())
+            .spawn()
+            .with_context(|| format!("Failed to run '{}'", program))?;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if terminated.swap(false, Ordering::SeqCst) {
+                let _ = child.kill();
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        Ok(exit_code(status))
+    })();
+
+    for handler in handlers {
+        signal_hook::low_level::unregister(handler);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_temporary_switch_restores_layout_and_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        for name in ["work", "experiments"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+
+        // Regular files in ~/.claude are refused without --force
+        let err = TemporarySwitch::begin(&paths, "experiments", false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("Cannot guarantee restoring"));
+        assert!(fs::read_link(&paths.claude_settings).is_err());
+
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        let before = fs::read_link(&paths.claude_settings).unwrap();
+        let state_json = || -> serde_json::Value {
+            serde_json::from_str(&fs::read_to_string(&paths.state_file).unwrap()).unwrap()
+        };
+        let state_before = state_json();
+
+        let (temporary, _) = TemporarySwitch::begin(&paths, "experiments", false).unwrap();
+        assert_eq!(temporary.previous_profile(), Some("work"));
+        assert_eq!(
+            fs::read_link(&paths.claude_settings).unwrap(),
+            paths.profile_dir("experiments").join("settings.json")
+        );
+        let state = State::read(&paths.state_file).unwrap();
+        assert_eq!(state.default_profile.as_deref(), Some("experiments"));
+        assert_eq!(state.switch_count, 2);
+
+        temporary.restore().unwrap();
+        assert_eq!(fs::read_link(&paths.claude_settings).unwrap(), before);
+        // Including the usage stats: the temporary switch doesn't count
+        assert_eq!(state_json(), state_before);
+        let state = State::read(&paths.state_file).unwrap();
+        assert_eq!(state.default_profile.as_deref(), Some("work"));
+        assert_eq!(
+            state
+                .active_components
+                .get(&Component::Settings)
+                .map(String::as_str),
+            Some("work")
+        );
+        assert_eq!(fs::read_dir(&paths.staging_dir).unwrap().count(), 0);
+
+        // Dropping without restoring restores too
+        drop(TemporarySwitch::begin(&paths, "experiments", false).unwrap());
+        assert_eq!(fs::read_link(&paths.claude_settings).unwrap(), before);
+        assert_eq!(state_json(), state_before);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command_returns_exit_code() {
+        let sh = |script: &str| vec!["sh".to_string(), "-c".to_string(), script.to_string()];
+        assert_eq!(run_command(&sh("exit 0"), &[]).unwrap(), 0);
+        assert_eq!(run_command(&sh("exit 7"), &[]).unwrap(), 7);
+        assert_eq!(run_command(&sh("kill -TERM $$"), &[]).unwrap(), 128 + 15);
+        assert!(run_command(&["/nonexistent/ccprof-test".to_string()], &[]).is_err());
+    }
+}
@@ -3,13 +3,148 @@
 //! This module provides common filesystem operations used across the codebase.
 
 use anyhow::{Context, Result, bail};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
 use std::fs;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+/// Environment variable that makes copies keep each file's owner (needs root)
+pub const PRESERVE_OWNER_ENV: &str = "CCPROF_PRESERVE_OWNER";
+
+/// Most threads used to size or copy a directory tree
+const MAX_WORKERS: usize = 8;
+
+/// Fewer files than this are copied on the calling thread
+const PARALLEL_MIN_FILES: usize = 64;
+
+/// Subdirectories found before `dir_size` shares out the rest of the walk
+const PARALLEL_MIN_DIRS: usize = 8;
+
+/// Environment variable setting how many directory levels walks go down
+pub const MAX_DEPTH_ENV: &str = "CCPROF_MAX_DEPTH";
+
+/// Directory levels walked when `CCPROF_MAX_DEPTH` isn't set
+const DEFAULT_MAX_DEPTH: usize = 128;
+
+/// How directory copies treat symlinks found inside the directory
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkMode {
+    /// Recreate the link with the same target
+    #[default]
+    Preserve,
+    /// Copy what the link points to (from --dereference)
+    Follow,
+    /// Leave the link out, with a warning (from --skip-symlinks)
+    Skip,
+}
+
+/// How a directory copy treats symlinks and entries it can't read
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyOptions {
+    /// What to do with symlinks inside (from --dereference and --skip-symlinks)
+    pub symlinks: SymlinkMode,
+    /// Fail lenient copies on unreadable entries too (from --strict)
+    pub strict: bool,
+}
+
+/// What a directory copy left out or copied differently, for the caller to report
+#[must_use]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CopyReport {
+    /// Unreadable entries a lenient copy left out
+    pub skipped: Vec<String>,
+    /// Symlinks skipped or copied as links, and directories too deep to copy
+    pub warnings: Vec<String>,
+}
+
+impl CopyReport {
+    /// Add what another copy reported
+    pub fn extend(&mut self, other: CopyReport) {
+        self.skipped.extend(other.skipped);
+        self.warnings.extend(other.warnings);
+    }
+
+    /// Every warning, the entries left out first
+    pub fn into_warnings(self) -> Vec<String> {
+        let mut warnings = self.skipped;
+        warnings.extend(self.warnings);
+        warnings
+    }
+}
+
+/// Warning for an entry a lenient copy or size left out
+fn unreadable(path: &Path, error: &std::io::Error) -> String {
+    format!("Skipped unreadable {}: {}", path.display(), error)
+}
+
+/// How many levels of subdirectories walks go down, from `CCPROF_MAX_DEPTH`
+pub fn max_depth() -> usize {
+    std::env::var(MAX_DEPTH_ENV)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|depth| *depth > 0)
+        .unwrap_or(DEFAULT_MAX_DEPTH)
+}
+
+/// Warning for a directory below [`max_depth`], which walks leave out
+fn too_deep(path: &Path) -> String {
+    format!(
+        "Skipped {}: more than {} directory levels deep (set {} to go further)",
+        path.display(),
+        max_depth(),
+        MAX_DEPTH_ENV
+    )
+}
+
+/// Warning for a symlink that leads back to a directory containing it
+fn symlink_loop(link: &Path) -> String {
+    format!(
+        "Symlink loop: {} points to a directory containing it",
+        link.display()
+    )
+}
+
+/// Identity of a directory, so a walk notices coming back into it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DirId {
+    #[cfg(unix)]
+    Inode { dev: u64, ino: u64 },
+    #[cfg(not(unix))]
+    Path(PathBuf),
+}
+
+fn dir_id(path: &Path, meta: &fs::Metadata) -> DirId {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let _ = path;
+        DirId::Inode {
+            dev: meta.dev(),
+            ino: meta.ino(),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        DirId::Path(fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+    }
+}
+
+/// Whether following `link`, a symlink in `dir` to a directory, would loop
+///
+/// `ancestors` are the directories the walk is inside. A link to a directory
+/// containing `dir` loops too, even when that is above where the walk started.
+fn link_loops(link: &Path, dir: &Path, meta: &fs::Metadata, ancestors: &[DirId]) -> bool {
+    ancestors.contains(&dir_id(link, meta)) || links_to_ancestor(link, dir)
+}
 
 /// Recursively calculate the total size of a directory in bytes
 ///
 /// This function walks through all files in a directory tree and sums their sizes.
-/// Symbolic links are not followed.
+/// Symbolic links are not followed. Large trees are walked on several threads.
 ///
 /// # Arguments
 /// * `path` - The directory path to calculate size for
@@ -17,23 +152,330 @@ use std::path::Path;
 /// # Returns
 /// Total size in bytes, or an IO error if directory traversal fails
 pub fn dir_size(path: &Path) -> std::io::Result<u64> {
+    dir_size_with(path, None)
+}
+
+/// [`dir_size`], leaving out what can't be read instead of failing
+///
+/// Returns the size of the rest and a warning for each directory or entry
+/// left out, naming it and the error. Links are not followed, but those
+/// pointing back to a directory containing them get a warning too.
+pub fn dir_size_lenient(path: &Path) -> (u64, Vec<String>) {
+    let mut skipped = Vec::new();
+    let total = dir_size_with(path, Some(&mut skipped)).unwrap_or_default();
+    (total, skipped)
+}
+
+/// Size a tree, collecting read errors into `skipped` if given
+fn dir_size_with(path: &Path, mut skipped: Option<&mut Vec<String>>) -> std::io::Result<u64> {
+    // Walk breadth-first until there are enough subtrees to share out
     let mut total = 0;
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let metadata = entry.metadata()?;
+    let mut pending = VecDeque::from([(path.to_path_buf(), 0)]);
+    while pending.len() < PARALLEL_MIN_DIRS {
+        let Some((dir, depth)) = pending.pop_front() else {
+            return Ok(total);
+        };
+        total += sizes_in_dir(&dir, depth, skipped.as_deref_mut(), &mut |subdir| {
+            pending.push_back((subdir, depth + 1))
+        })?;
+    }
+
+    let subtrees = Vec::from(pending);
+    let lenient = skipped.is_some();
+    try_for_each_parallel(
+        &subtrees,
+        0,
+        |(dir, depth)| {
+            let mut warnings = Vec::new();
+            dir_size_sequential(dir, *depth, lenient.then_some(&mut warnings))
+                .map(|size| (size, warnings))
+        },
+        |(size, warnings)| {
+            total += size;
+            if let Some(skipped) = skipped.as_deref_mut() {
+                skipped.extend(warnings);
+            }
+        },
+    )?;
+    Ok(total)
+}
+
+/// [`dir_size`] on the calling thread
+fn dir_size_sequential(
+    path: &Path,
+    depth: usize,
+    mut skipped: Option<&mut Vec<String>>,
+) -> std::io::Result<u64> {
+    let mut subdirs = Vec::new();
+    let mut total = sizes_in_dir(path, depth, skipped.as_deref_mut(), &mut |subdir| {
+        subdirs.push(subdir)
+    })?;
+    for subdir in subdirs {
+        total += dir_size_sequential(&subdir, depth + 1, skipped.as_deref_mut())?;
+    }
+    Ok(total)
+}
+
+/// Total size of the files directly in `dir`, passing each subdirectory to `on_subdir`
+///
+/// `depth` is how many levels below the top `dir` is. With `skipped`, read
+/// errors and directories below [`max_depth`] are added to it rather than
+/// returned. Links are never followed, but with `skipped` the ones leading
+/// back to a directory containing them are reported there as loops.
+fn sizes_in_dir(
+    dir: &Path,
+    depth: usize,
+    mut skipped: Option<&mut Vec<String>>,
+    on_subdir: &mut dyn FnMut(PathBuf),
+) -> std::io::Result<u64> {
+    if depth > max_depth() {
+        return match skipped {
+            Some(skipped) => {
+                skipped.push(too_deep(dir));
+                Ok(0)
+            }
+            None => Err(std::io::Error::other(too_deep(dir))),
+        };
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return skip_or_fail(&mut skipped, dir, e).map(|()| 0),
+    };
+    let mut total = 0;
+    for entry in entries {
+        let found = entry.and_then(|entry| Ok((entry.path(), entry.metadata()?)));
+        let (path, metadata) = match found {
+            Ok(found) => found,
+            Err(e) => {
+                skip_or_fail(&mut skipped, dir, e)?;
+                continue;
+            }
+        };
         if metadata.is_file() {
             total += metadata.len();
         } else if metadata.is_dir() {
-            total += dir_size(&entry.path())?;
+            on_subdir(path);
+        } else if let Some(skipped) = skipped.as_deref_mut()
+            && metadata.is_symlink()
+            && fs::metadata(&path).is_ok_and(|m| m.is_dir())
+            && links_to_ancestor(&path, dir)
+        {
+            skipped.push(symlink_loop(&path));
         }
     }
     Ok(total)
 }
 
+/// Add a read error to `skipped`, or return it when there is no list
+fn skip_or_fail(
+    skipped: &mut Option<&mut Vec<String>>,
+    path: &Path,
+    error: std::io::Error,
+) -> std::io::Result<()> {
+    match skipped {
+        Some(skipped) => {
+            skipped.push(unreadable(path, &error));
+            Ok(())
+        }
+        None => Err(error),
+    }
+}
+
+/// Run `job` on each item, on worker threads once there are `min_parallel` items
+///
+/// `on_ok` gets each result on the calling thread, in completion order. After
+/// a failure, later items are skipped and the error returned is that of the
+/// first failing item in order, as a sequential loop would report.
+fn try_for_each_parallel<T: Sync, R: Send, E: Send>(
+    items: &[T],
+    min_parallel: usize,
+    job: impl Fn(&T) -> Result<R, E> + Sync,
+    mut on_ok: impl FnMut(R),
+) -> Result<(), E> {
+    let workers = thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(MAX_WORKERS)
+        .min(items.len());
+    if items.len() < min_parallel || workers < 2 {
+        for item in items {
+            on_ok(job(item)?);
+        }
+        return Ok(());
+    }
+
+    let next = AtomicUsize::new(0);
+    let failed_at = AtomicUsize::new(usize::MAX);
+    let mut first_error: Option<(usize, E)> = None;
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            let tx = tx.clone();
+            let (next, failed_at, job) = (&next, &failed_at, &job);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= items.len() || index > failed_at.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    let result = job(&items[index]);
+                    if result.is_err() {
+                        failed_at.fetch_min(index, Ordering::Relaxed);
+                    }
+                    if tx.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        for (index, result) in rx {
+            match result {
+                Ok(value) => on_ok(value),
+                Err(e) if first_error.as_ref().is_none_or(|(first, _)| index < *first) => {
+                    first_error = Some((index, e))
+                }
+                Err(_) => {}
+            }
+        }
+    });
+    first_error.map_or(Ok(()), |(_, e)| Err(e))
+}
+
+/// Count the files in a directory tree (1 for a plain file)
+///
+/// Used to size progress bars before a copy; unreadable entries are skipped.
+pub fn count_files(path: &Path) -> u64 {
+    if !path.is_dir() {
+        return u64::from(path.exists());
+    }
+    count_files_below(path, 0)
+}
+
+fn count_files_below(dir: &Path, depth: usize) -> u64 {
+    if depth > max_depth() {
+        return 0;
+    }
+    // Links inside count as one entry, like the link copies make of them
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| {
+                    if e.file_type().is_ok_and(|t| t.is_dir()) {
+                        count_files_below(&e.path(), depth + 1)
+                    } else {
+                        1
+                    }
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// What copying a directory tree would produce
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DirStats {
+    /// Files, counting each symlink kept as a link
+    pub files: u64,
+    /// Subdirectories, not counting the directory itself
+    pub dirs: u64,
+    /// Total size of the files in bytes
+    pub bytes: u64,
+    /// The largest files, biggest first, relative to the directory
+    pub largest: Vec<(PathBuf, u64)>,
+    /// Symlink loops and too-deep directories that were not walked
+    pub warnings: Vec<String>,
+}
+
+/// Count and size a directory tree the way [`copy_dir_recursive`] copies it
+///
+/// Symlinks inside follow `mode`: kept links count as files of no size,
+/// skipped links are left out and followed links count as what they point
+/// to, unless they loop. Up to `top` of the largest files are listed.
+pub fn dir_stats(path: &Path, top: usize, mode: SymlinkMode) -> Result<DirStats> {
+    let mut stats = DirStats::default();
+    let mut sizes = Vec::new();
+    let mut ancestors = Vec::new();
+    collect_stats(path, path, mode, &mut stats, &mut sizes, &mut ancestors)?;
+    sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sizes.truncate(top);
+    stats.largest = sizes;
+    Ok(stats)
+}
+
+fn collect_stats(
+    root: &Path,
+    dir: &Path,
+    mode: SymlinkMode,
+    stats: &mut DirStats,
+    sizes: &mut Vec<(PathBuf, u64)>,
+    ancestors: &mut Vec<DirId>,
+) -> Result<()> {
+    if ancestors.len() > max_depth() {
+        stats.warnings.push(too_deep(dir));
+        return Ok(());
+    }
+    let meta =
+        fs::metadata(dir).with_context(|| format!("Failed to read metadata: {}", dir.display()))?;
+    ancestors.push(dir_id(dir, &meta));
+
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry.context("Failed to read directory entry")?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("Failed to read file type: {}", path.display()))?;
+
+        // None for a link copied as a link, as in plan_copy
+        let meta = if file_type.is_symlink() {
+            match mode {
+                SymlinkMode::Preserve => None,
+                SymlinkMode::Skip => continue,
+                SymlinkMode::Follow => match fs::metadata(&path) {
+                    Ok(meta) if meta.is_dir() && link_loops(&path, dir, &meta, ancestors) => {
+                        stats.warnings.push(symlink_loop(&path));
+                        None
+                    }
+                    target => target.ok(),
+                },
+            }
+        } else {
+            Some(
+                entry
+                    .metadata()
+                    .with_context(|| format!("Failed to read metadata: {}", path.display()))?,
+            )
+        };
+
+        match meta {
+            Some(meta) if meta.is_dir() => {
+                stats.dirs += 1;
+                collect_stats(root, &path, mode, stats, sizes, ancestors)?;
+            }
+            Some(meta) => {
+                stats.files += 1;
+                stats.bytes += meta.len();
+                let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+                sizes.push((relative, meta.len()));
+            }
+            None => stats.files += 1,
+        }
+    }
+    ancestors.pop();
+    Ok(())
+}
+
 /// Recursively copy a directory and all its contents to a new location
 ///
 /// This function creates the destination directory if it doesn't exist and copies
-/// all files and subdirectories from source to destination.
+/// all files and subdirectories from source to destination. Permissions and
+/// modification times are kept (see [`copy_file`]), so hook scripts stay
+/// executable. Symlinks inside are recreated as-is; see
+/// [`copy_dir_recursive_with_progress`] for other [`SymlinkMode`]s.
 ///
 /// # Arguments
 /// * `src` - Source directory path
@@ -44,7 +486,134 @@ pub fn dir_size(path: &Path) -> std::io::Result<u64> {
 /// - Source doesn't exist or is not a directory
 /// - Destination cannot be created
 /// - Any file or directory cannot be copied
-pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
+///
+/// Directories below [`max_depth`] are left out; the returned report warns
+/// about them.
+pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<CopyReport> {
+    copy_dir_recursive_with_progress(src, dst, SymlinkMode::default(), &mut |_| {})
+}
+
+/// [`copy_dir_recursive`], treating symlinks as `mode` says and calling
+/// `on_file` with the size of each file copied
+///
+/// The directory structure is created first; large trees then have their files
+/// copied on several threads, while `on_file` is always called on this one.
+pub fn copy_dir_recursive_with_progress(
+    src: &Path,
+    dst: &Path,
+    mode: SymlinkMode,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    copy_dir_logged(src, dst, mode, false, on_file)
+}
+
+/// [`copy_dir_recursive`], leaving out entries inside that can't be read
+///
+/// The report has a warning for each file or directory left out, naming it
+/// and the error. With `options.strict` this fails on them like the plain
+/// copy does.
+pub fn copy_dir_lenient(src: &Path, dst: &Path, options: CopyOptions) -> Result<CopyReport> {
+    copy_dir_lenient_with_progress(src, dst, options, &mut |_| {})
+}
+
+/// [`copy_dir_lenient`], calling `on_file` with the size of each file copied
+pub fn copy_dir_lenient_with_progress(
+    src: &Path,
+    dst: &Path,
+    options: CopyOptions,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    copy_dir_logged(src, dst, options.symlinks, !options.strict, on_file)
+}
+
+/// Copy a directory tree, logging what was skipped
+fn copy_dir_logged(
+    src: &Path,
+    dst: &Path,
+    mode: SymlinkMode,
+    lenient: bool,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    let mut warnings = Vec::new();
+    let result = copy_dir_with(src, dst, mode, lenient, on_file, &mut warnings);
+    for warning in warnings.iter().chain(result.iter().flatten()) {
+        crate::audit::record(warning);
+    }
+    Ok(CopyReport {
+        skipped: result?,
+        warnings,
+    })
+}
+
+/// Copy a directory tree, treating symlinks inside it as `mode` says
+///
+/// A dangling link never fails the copy: it is recreated (or skipped) and
+/// noted in `warnings`, as is a followed link that would loop. When
+/// `lenient`, files and directories inside that can't be read are left out
+/// too and returned as warnings of their own.
+fn copy_dir_with(
+    src: &Path,
+    dst: &Path,
+    mode: SymlinkMode,
+    lenient: bool,
+    on_file: &mut dyn FnMut(u64),
+    warnings: &mut Vec<String>,
+) -> Result<Vec<String>> {
+    let mut plan = CopyPlan {
+        lenient,
+        ..Default::default()
+    };
+    plan_copy(src, dst, mode, &mut plan, warnings)?;
+    let mut skipped = std::mem::take(&mut plan.skipped);
+    try_for_each_parallel(
+        &plan.files,
+        PARALLEL_MIN_FILES,
+        |(src, dst)| match copy_file(src, dst) {
+            Ok(bytes) => Ok(Ok(bytes)),
+            // Only a file that can't be opened is skipped; write errors still fail
+            Err(e) if lenient => match fs::File::open(src) {
+                Err(open_error) => Ok(Err(unreadable(src, &open_error))),
+                Ok(_) => Err(e),
+            },
+            Err(e) => Err(e),
+        },
+        |copied| match copied {
+            Ok(bytes) => on_file(bytes),
+            Err(warning) => skipped.push(warning),
+        },
+    )?;
+
+    // Last and children first, so read-only directories can still be filled
+    // and their mtimes stick
+    for (dir, meta) in plan.dirs.iter().rev() {
+        copy_attributes(dir, meta)?;
+    }
+    Ok(skipped)
+}
+
+/// What's left to do after [`plan_copy`] has created the directories and links
+#[derive(Default)]
+struct CopyPlan {
+    /// Files to copy, in walk order
+    files: Vec<(PathBuf, PathBuf)>,
+    /// Created directories with their source's metadata, parents first
+    dirs: Vec<(PathBuf, fs::Metadata)>,
+    /// Leave out unreadable entries below the top directory
+    lenient: bool,
+    /// Warnings for the entries left out
+    skipped: Vec<String>,
+    /// Source directories being walked, top first
+    ancestors: Vec<DirId>,
+}
+
+/// Create the directories and links of a copy of `src`, collecting its files
+fn plan_copy(
+    src: &Path,
+    dst: &Path,
+    mode: SymlinkMode,
+    plan: &mut CopyPlan,
+    warnings: &mut Vec<String>,
+) -> Result<()> {
     if !src.exists() {
         bail!("Source directory does not exist: {:?}", src);
     }
@@ -52,25 +621,1291 @@ pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
     if !src.is_dir() {
         bail!("Source is not a directory: {:?}", src);
     }
+    if plan.ancestors.len() > max_depth() {
+        warnings.push(too_deep(src));
+        return Ok(());
+    }
+    let meta = fs::metadata(src).with_context(|| format!("Failed to read metadata: {:?}", src))?;
+    // The top directory must be readable; below it, a lenient copy moves on
+    let lenient = plan.lenient && !plan.dirs.is_empty();
+    let entries = match fs::read_dir(src) {
+        Err(e) if lenient => {
+            plan.skipped.push(unreadable(src, &e));
+            return Ok(());
+        }
+        entries => {
+            entries.with_context(|| format!("Failed to read source directory: {:?}", src))?
+        }
+    };
 
     fs::create_dir_all(dst)
         .with_context(|| format!("Failed to create destination directory: {:?}", dst))?;
+    plan.ancestors.push(dir_id(src, &meta));
+    plan.dirs.push((dst.to_path_buf(), meta));
 
-    for entry in
-        fs::read_dir(src).with_context(|| format!("Failed to read source directory: {:?}", src))?
-    {
-        let entry = entry.context("Failed to read directory entry")?;
+    for entry in entries {
+        let entry = match entry {
+            Err(e) if plan.lenient => {
+                plan.skipped.push(unreadable(src, &e));
+                continue;
+            }
+            entry => entry.context("Failed to read directory entry")?,
+        };
         let src_path = entry.path();
         let dst_path = dst.join(entry.file_name());
+        let file_type = match entry.file_type() {
+            Err(e) if plan.lenient => {
+                plan.skipped.push(unreadable(&src_path, &e));
+                continue;
+            }
+            file_type => {
+                file_type.with_context(|| format!("Failed to read file type: {:?}", src_path))?
+            }
+        };
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
+        if file_type.is_symlink() {
+            match mode {
+                SymlinkMode::Preserve => copy_symlink(&src_path, &dst_path)?,
+                SymlinkMode::Skip => {
+                    warnings.push(format!("Skipped symlink {}", src_path.display()));
+                }
+                SymlinkMode::Follow => match fs::metadata(&src_path) {
+                    Err(_) => {
+                        warnings.push(format!(
+                            "Copied dangling symlink {} as a link",
+                            src_path.display()
+                        ));
+                        copy_symlink(&src_path, &dst_path)?;
+                    }
+                    Ok(meta) if meta.is_dir() => {
+                        if link_loops(&src_path, src, &meta, &plan.ancestors) {
+                            warnings.push(format!(
+                                "Copied symlink {} as a link: it points to a directory containing it",
+                                src_path.display()
+                            ));
+                            copy_symlink(&src_path, &dst_path)?;
+                        } else {
+                            plan_copy(&src_path, &dst_path, mode, plan, warnings)?;
+                        }
+                    }
+                    Ok(_) => plan.files.push((src_path, dst_path)),
+                },
+            }
+        } else if file_type.is_dir() {
+            plan_copy(&src_path, &dst_path, mode, plan, warnings)?;
         } else {
-            fs::copy(&src_path, &dst_path).with_context(|| {
-                format!("Failed to copy file: {:?} -> {:?}", src_path, dst_path)
-            })?;
+            plan.files.push((src_path, dst_path));
         }
     }
+    plan.ancestors.pop();
+    Ok(())
+}
+
+/// Whether the directory `link` resolves to contains `dir`, so following it loops
+fn links_to_ancestor(link: &Path, dir: &Path) -> bool {
+    match (fs::canonicalize(link), fs::canonicalize(dir)) {
+        (Ok(target), Ok(dir)) => dir.starts_with(target),
+        _ => false,
+    }
+}
 
+/// Recreate the symlink `src` at `dst` with the same target
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    let target =
+        fs::read_link(src).with_context(|| format!("Failed to read symlink: {:?}", src))?;
+    #[cfg(unix)]
+    let created = std::os::unix::fs::symlink(&target, dst);
+    #[cfg(windows)]
+    let created = if fs::metadata(src).is_ok_and(|m| m.is_dir()) {
+        std::os::windows::fs::symlink_dir(&target, dst)
+    } else {
+        std::os::windows::fs::symlink_file(&target, dst)
+    };
+    created.with_context(|| format!("Failed to copy symlink: {:?} -> {:?}", src, dst))
+}
+
+/// Copy a file with its permissions and modification time, returning its size
+///
+/// With `CCPROF_PRESERVE_OWNER` set, the owner and group are kept too, which
+/// only works when running as root.
+pub fn copy_file(src: &Path, dst: &Path) -> Result<u64> {
+    let bytes = fs::copy(src, dst)
+        .with_context(|| format!("Failed to copy file: {:?} -> {:?}", src, dst))?;
+    let meta = fs::metadata(src).with_context(|| format!("Failed to read metadata: {:?}", src))?;
+    copy_attributes(dst, &meta)?;
+    Ok(bytes)
+}
+
+/// Give `dst` the owner (if asked for), permissions and mtime in `meta`
+fn copy_attributes(dst: &Path, meta: &fs::Metadata) -> Result<()> {
+    #[cfg(unix)]
+    if std::env::var_os(PRESERVE_OWNER_ENV).is_some_and(|v| !v.is_empty() && v != "0") {
+        use std::os::unix::fs::MetadataExt;
+        std::os::unix::fs::chown(dst, Some(meta.uid()), Some(meta.gid())).with_context(|| {
+            format!(
+                "Failed to preserve the owner of {}\nHint: {} only works when running as root.",
+                dst.display(),
+                PRESERVE_OWNER_ENV
+            )
+        })?;
+    }
+
+    fs::set_permissions(dst, meta.permissions())
+        .with_context(|| format!("Failed to set permissions of {}", dst.display()))?;
+
+    // Best effort: not every platform lets a directory or read-only file be
+    // opened to set its times
+    if let Ok(modified) = meta.modified()
+        && let Ok(file) = fs::File::open(dst)
+    {
+        let _ = file.set_modified(modified);
+    }
     Ok(())
 }
+
+/// Remove a file, directory tree or symlink; missing paths are not an error
+///
+/// Symlinks are removed themselves, never the directory they point to, whether
+/// or not their target exists. Links inside a removed tree aren't followed.
+pub fn remove_path(path: &Path) -> Result<()> {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return Ok(());
+    };
+    crate::audit::record(format!("remove {}", path.display()));
+    if meta.is_dir() {
+        fs::remove_dir_all(path)
+    } else if cfg!(windows) && meta.file_type().is_symlink() {
+        // Windows removes directory symlinks and junctions as directories
+        fs::remove_file(path).or_else(|_| fs::remove_dir(path))
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove {}", path.display()))
+}
+
+/// Replace whatever is at `dst` with a copy of the directory `src`
+///
+/// The copy is made in a sibling `<name>.ccprof-new`; then `dst` is renamed to
+/// `<name>.ccprof-old`, the copy renamed into place and the old one removed.
+/// `dst` is only absent between the two renames, and a failed copy leaves it
+/// untouched. Returns what [`copy_dir_recursive`] reported.
+pub fn replace_dir_atomic(src: &Path, dst: &Path) -> Result<CopyReport> {
+    replace_dir_atomic_with_progress(src, dst, SymlinkMode::default(), &mut |_| {})
+}
+
+/// [`replace_dir_atomic`], copying symlinks as `mode` says and calling
+/// `on_file` with the size of each file copied
+pub fn replace_dir_atomic_with_progress(
+    src: &Path,
+    dst: &Path,
+    mode: SymlinkMode,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    let staged = sibling_with_suffix(dst, "ccprof-new");
+    let old = sibling_with_suffix(dst, "ccprof-old");
+
+    // Either may be left over from an interrupted replacement
+    remove_path(&staged)?;
+    remove_path(&old)?;
+    let report = match copy_dir_recursive_with_progress(src, &staged, mode, on_file) {
+        Ok(report) => report,
+        Err(e) => {
+            let _ = remove_path(&staged);
+            return Err(e);
+        }
+    };
+
+    let had_old = fs::symlink_metadata(dst).is_ok();
+    if had_old && let Err(e) = fs::rename(dst, &old) {
+        let _ = remove_path(&staged);
+        return Err(e).with_context(|| format!("Failed to move {} aside", dst.display()));
+    }
+    if let Err(e) = fs::rename(&staged, dst) {
+        if had_old {
+            let _ = fs::rename(&old, dst);
+        }
+        let _ = remove_path(&staged);
+        return Err(e)
+            .with_context(|| format!("Failed to move new content into {}", dst.display()));
+    }
+
+    // The replacement is in place; a leftover is removed by the next one
+    let _ = remove_path(&old);
+    Ok(report)
+}
+
+/// `dir/name` -> `dir/name.suffix`
+fn sibling_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Move a file, directory or symlink, copying when a rename isn't possible
+///
+/// Renames fail across filesystems, so the fallback copies the content (or
+/// recreates the symlink) and then removes the source. A copy that had to
+/// leave something out fails instead, keeping the source.
+pub fn move_path(from: &Path, to: &Path) -> Result<()> {
+    crate::audit::record(format!("move {} -> {}", from.display(), to.display()));
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    let meta = fs::symlink_metadata(from)
+        .with_context(|| format!("Failed to read metadata: {}", from.display()))?;
+    if meta.file_type().is_symlink() {
+        copy_symlink(from, to)?;
+    } else if meta.is_dir() {
+        let report = copy_dir_recursive(from, to)?;
+        if let Some(warning) = report.into_warnings().into_iter().next() {
+            let _ = remove_path(to);
+            bail!(
+                "Failed to move {} to {}: {}",
+                from.display(),
+                to.display(),
+                warning
+            );
+        }
+    } else {
+        copy_file(from, to)?;
+    }
+    remove_path(from)
+}
+
+/// Lexically normalize a path, resolving `.` and `..` without touching the filesystem
+///
+/// Leading `..` components of a relative path are kept; `..` never climbs above
+/// the root of an absolute path. Windows extended-length prefixes are dropped
+/// (see [`strip_verbatim`]), so `\\?\C:\x` and `C:\x` normalize alike.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let path = strip_verbatim(path);
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other.as_os_str()),
+        }
+    }
+    out
+}
+
+/// Resolve symlinks in `path` as far as it exists
+///
+/// The nearest existing ancestor is canonicalized and the missing rest is
+/// appended, so paths that don't exist yet resolve too. With `keep_last`, the
+/// final component is not followed even if it is a symlink itself.
+pub fn canonicalize_existing(path: &Path, keep_last: bool) -> PathBuf {
+    let path = normalize_path(path);
+    let (head, last) = match (keep_last, path.parent(), path.file_name()) {
+        (true, Some(parent), Some(name)) => (parent.to_path_buf(), Some(name.to_os_string())),
+        _ => (path.clone(), None),
+    };
+    let mut missing = Vec::new();
+    let mut existing = head.as_path();
+    let resolved = loop {
+        if let Ok(canonical) = fs::canonicalize(existing) {
+            break strip_verbatim(&canonical);
+        }
+        match (existing.parent(), existing.file_name()) {
+            (Some(parent), Some(name)) => {
+                missing.push(name.to_os_string());
+                existing = parent;
+            }
+            // Nothing of it exists (or it is relative and the cwd is gone)
+            _ => return path,
+        }
+    };
+    let mut out = resolved;
+    out.extend(missing.iter().rev());
+    out.extend(last);
+    out
+}
+
+/// `\\?\C:\x` -> `C:\x` and `\\?\UNC\server\share` -> `\\server\share`
+///
+/// Windows returns extended-length ("verbatim") paths from `canonicalize`
+/// and some link targets. Other paths, including verbatim ones that have no
+/// plain form such as `\\?\Volume{..}`, are returned unchanged.
+pub fn strip_verbatim(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if let Some(rest) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{}", rest));
+    }
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Create a symlink at `link` pointing at `target`
+///
+/// On Windows, file and directory links differ; a relative `target` is looked
+/// up from the link's directory to tell which one to create.
+pub fn create_symlink(target: impl AsRef<Path>, link: impl AsRef<Path>) -> std::io::Result<()> {
+    let (target, link) = (target.as_ref(), link.as_ref());
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(target, link)
+    }
+
+    #[cfg(windows)]
+    {
+        let is_dir = link.parent().unwrap_or(Path::new("")).join(target).is_dir();
+        if is_dir {
+            std::os::windows::fs::symlink_dir(target, link)
+        } else {
+            std::os::windows::fs::symlink_file(target, link)
+        }
+    }
+}
+
+/// Whether this user may create symlinks in `dir`
+///
+/// Always true on Unix. Windows needs Developer Mode or an elevated prompt.
+pub fn symlinks_permitted(dir: &Path) -> bool {
+    let link = dir.join(format!(".ccprof-symlink-probe-{}", std::process::id()));
+    let _ = fs::remove_file(&link);
+    let created = create_symlink(Path::new("."), &link).is_ok();
+    let _ = remove_path(&link);
+    created
+}
+
+/// Compute the relative path that leads from `from_dir` to `to`
+///
+/// Both paths should be absolute. If they share no common root (e.g. different
+/// Windows drives) `to` is returned unchanged.
+pub fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from = normalize_path(from_dir);
+    let to = normalize_path(to);
+    let from_components: Vec<_> = from.components().collect();
+    let to_components: Vec<_> = to.components().collect();
+
+    let common = from_components
+        .iter()
+        .zip(&to_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return to;
+    }
+
+    let mut out = PathBuf::new();
+    for _ in common..from_components.len() {
+        out.push("..");
+    }
+    for component in &to_components[common..] {
+        out.push(component.as_os_str());
+    }
+    if out.as_os_str().is_empty() {
+        out.push(".");
+    }
+    out
+}
+
+/// Resolve a symlink's target to a normalized path
+///
+/// Relative targets are interpreted from the directory containing the link.
+pub fn resolve_link_target(link: &Path, target: &Path) -> PathBuf {
+    if target.is_absolute() {
+        normalize_path(target)
+    } else {
+        normalize_path(&link.parent().unwrap_or(Path::new("")).join(target))
+    }
+}
+
+/// Longest chain of symlinks followed before giving up
+pub const MAX_LINK_HOPS: usize = 8;
+
+/// Where a chain of symlinks leads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkChain {
+    /// Each target in order; the last one is not a symlink (it may not exist)
+    Resolved(Vec<PathBuf>),
+    /// The chain returns to a link already visited, which ends the list
+    Loop(Vec<PathBuf>),
+    /// Still a symlink after [`MAX_LINK_HOPS`] hops
+    TooLong(Vec<PathBuf>),
+}
+
+impl LinkChain {
+    /// Targets followed, in order
+    pub fn hops(&self) -> &[PathBuf] {
+        match self {
+            Self::Resolved(hops) | Self::Loop(hops) | Self::TooLong(hops) => hops,
+        }
+    }
+
+    /// Render as `link → a → b`
+    pub fn display(&self, link: &Path) -> String {
+        std::iter::once(link)
+            .chain(self.hops().iter().map(PathBuf::as_path))
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(" → ")
+    }
+}
+
+/// Follow `link` through every symlink it leads to, up to [`MAX_LINK_HOPS`]
+///
+/// A path that isn't a symlink resolves to an empty chain.
+pub fn follow_link_chain(link: &Path) -> LinkChain {
+    let mut visited = vec![normalize_path(link)];
+    let mut hops = Vec::new();
+    let mut current = link.to_path_buf();
+
+    while let Ok(target) = fs::read_link(&current) {
+        let next = resolve_link_target(&current, &target);
+        hops.push(next.clone());
+        if visited.contains(&next) {
+            return LinkChain::Loop(hops);
+        }
+        if hops.len() >= MAX_LINK_HOPS && next.is_symlink() {
+            return LinkChain::TooLong(hops);
+        }
+        visited.push(next.clone());
+        current = next;
+    }
+    LinkChain::Resolved(hops)
+}
+
+/// Final target of a chain of symlinks starting at `path` (`path` itself if it isn't one)
+pub fn link_chain_end(path: &Path) -> Option<PathBuf> {
+    match follow_link_chain(path) {
+        LinkChain::Resolved(hops) => {
+            Some(hops.last().cloned().unwrap_or_else(|| path.to_path_buf()))
+        }
+        _ => None,
+    }
+}
+
+/// Top-level differences between two directories
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DirComparison {
+    /// Entries only present in the left directory (sorted)
+    pub only_in_left: Vec<String>,
+    /// Entries only present in the right directory (sorted)
+    pub only_in_right: Vec<String>,
+    /// Entries present in both whose content differs (sorted)
+    pub changed: Vec<String>,
+}
+
+impl DirComparison {
+    pub fn is_identical(&self) -> bool {
+        self.only_in_left.is_empty() && self.only_in_right.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare two directory trees, file by file
+///
+/// Entries are named by their path relative to the roots, with `/` between
+/// components. Directories in both trees are descended into; a directory in
+/// only one is listed as each file below it (or as `name/` when empty). A file
+/// in one tree where the other has a directory counts as changed.
+pub fn compare_dirs(left: &Path, right: &Path) -> Result<DirComparison> {
+    let mut comparison = DirComparison::default();
+    compare_dirs_at(left, right, "", &mut comparison)?;
+    comparison.only_in_left.sort();
+    comparison.only_in_right.sort();
+    comparison.changed.sort();
+    Ok(comparison)
+}
+
+fn compare_dirs_at(
+    left: &Path,
+    right: &Path,
+    relative: &str,
+    out: &mut DirComparison,
+) -> Result<()> {
+    if relative.matches('/').count() > max_depth() {
+        bail!(too_deep(&left.join(relative)));
+    }
+    let names = |root: &Path| -> Result<std::collections::BTreeSet<String>> {
+        let dir = root.join(relative);
+        Ok(fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+            .filter_map(|e| e.ok())
+            .filter_map(|e| e.file_name().to_str().map(String::from))
+            .collect())
+    };
+    let left_names = names(left)?;
+    let right_names = names(right)?;
+    // Symlinks are compared as entries, never followed into
+    let is_dir = |path: &Path| fs::symlink_metadata(path).is_ok_and(|m| m.is_dir());
+
+    for name in left_names.union(&right_names) {
+        let child = format!("{}{}", relative, name);
+        let (left_path, right_path) = (left.join(&child), right.join(&child));
+        match (left_names.contains(name), right_names.contains(name)) {
+            (true, false) => list_files(left, &child, is_dir(&left_path), &mut out.only_in_left)?,
+            (false, true) => {
+                list_files(right, &child, is_dir(&right_path), &mut out.only_in_right)?
+            }
+            _ if is_dir(&left_path) && is_dir(&right_path) => {
+                compare_dirs_at(left, right, &format!("{}/", child), out)?
+            }
+            _ => {
+                if !same_content(&left_path, &right_path) {
+                    out.changed.push(child);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Push `relative`, or if it is a directory every file and empty directory
+/// (as `name/`) below it
+fn list_files(root: &Path, relative: &str, is_dir: bool, out: &mut Vec<String>) -> Result<()> {
+    if !is_dir {
+        out.push(relative.to_string());
+        return Ok(());
+    }
+    let mut entries = vec![PathBuf::from(relative)];
+    collect_entries(root, Path::new(relative), &mut entries)?;
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = root.join(&entry);
+        let name = entry
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if !fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
+            files.push(name);
+        } else if fs::read_dir(&path).is_ok_and(|mut children| children.next().is_none()) {
+            files.push(format!("{}/", name));
+        }
+    }
+    files.sort();
+    out.extend(files);
+    Ok(())
+}
+
+/// Whether two files or directory trees have identical content
+///
+/// Unreadable paths, or a file compared with a directory, count as different.
+pub fn same_content(a: &Path, b: &Path) -> bool {
+    if a.is_dir() != b.is_dir() {
+        return false;
+    }
+    match (hash_path(a), hash_path(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Compute a hex SHA-256 digest of a file's contents
+///
+/// The file is streamed through the hasher, so a large one isn't held in memory.
+pub fn hash_file(path: &Path) -> Result<String> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Compute a deterministic hex SHA-256 digest of a directory tree
+///
+/// Relative paths and file contents are hashed in sorted order, so the digest does
+/// not depend on the order the filesystem returns entries in. Symlinks are hashed
+/// by their target path rather than followed.
+pub fn hash_dir(path: &Path) -> Result<String> {
+    hash_dir_excluding(path, &[])
+}
+
+/// [`hash_dir`], leaving out the top-level entries named in `ignored`
+///
+/// Used to hash a profile directory without ccprof's own files.
+pub fn hash_dir_excluding(path: &Path, ignored: &[&str]) -> Result<String> {
+    let mut entries = Vec::new();
+    collect_entries(path, Path::new(""), &mut entries)?;
+    entries.retain(|relative| {
+        let top = relative.components().next().map(|c| c.as_os_str());
+        !ignored.iter().any(|name| top == Some(name.as_ref()))
+    });
+    digest_entries(path, entries)
+}
+
+/// Digest of the entries under `path`, whatever order they are given in
+fn digest_entries(path: &Path, mut entries: Vec<PathBuf>) -> Result<String> {
+    entries.sort();
+
+    let mut hasher = Sha256::new();
+    for relative in entries {
+        let full = path.join(&relative);
+        let key = relative.to_string_lossy().replace('\\', "/");
+        let meta = fs::symlink_metadata(&full)
+            .with_context(|| format!("Failed to read metadata: {}", full.display()))?;
+
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(&full)?;
+            hasher.update(format!("L\0{}\0{}\0", key, target.display()));
+        } else if meta.is_dir() {
+            hasher.update(format!("D\0{}\0", key));
+        } else {
+            hasher.update(format!("F\0{}\0{}\0", key, hash_file(&full)?));
+        }
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Hash a file or directory with [`hash_file`] / [`hash_dir`]
+pub fn hash_path(path: &Path) -> Result<String> {
+    if path.is_dir() {
+        hash_dir(path)
+    } else {
+        hash_file(path)
+    }
+}
+
+fn collect_entries(root: &Path, relative: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    let dir = root.join(relative);
+    // A partial digest would be wrong, so a runaway tree is an error here
+    if relative.components().count() > max_depth() {
+        bail!(too_deep(&dir));
+    }
+    for entry in fs::read_dir(&dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry.context("Failed to read directory entry")?;
+        let child = relative.join(entry.file_name());
+        let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+        out.push(child.clone());
+        if is_dir {
+            collect_entries(root, &child, out)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_hash_file_matches_the_sha256_of_the_content() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("settings.json");
+        fs::write(&path, "abc").unwrap();
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(hash_file(&temp.path().join("missing.json")).is_err());
+    }
+
+    #[test]
+    fn test_hash_dir_tracks_content_and_layout() {
+        let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        for dir in [&a, &b] {
+            fs::create_dir_all(dir.join("nested")).unwrap();
+            fs::write(dir.join("one.md"), "one").unwrap();
+            fs::write(dir.join("nested/two.md"), "two").unwrap();
+        }
+        assert_eq!(hash_dir(&a).unwrap(), hash_dir(&b).unwrap());
+
+        fs::write(b.join("nested/two.md"), "changed").unwrap();
+        assert_ne!(hash_dir(&a).unwrap(), hash_dir(&b).unwrap());
+
+        // The order entries are read in doesn't matter
+        let mut entries = Vec::new();
+        collect_entries(&a, Path::new(""), &mut entries).unwrap();
+        let digest = digest_entries(&a, entries.clone()).unwrap();
+        entries.reverse();
+        assert_eq!(digest_entries(&a, entries).unwrap(), digest);
+        assert_eq!(hash_dir(&a).unwrap(), digest);
+
+        // Ignored names only count at the top level
+        fs::write(a.join("metadata.json"), "{}").unwrap();
+        assert_eq!(hash_dir_excluding(&a, &["metadata.json"]).unwrap(), digest);
+        fs::write(a.join("nested/metadata.json"), "{}").unwrap();
+        assert_ne!(hash_dir_excluding(&a, &["metadata.json"]).unwrap(), digest);
+    }
+
+    #[test]
+    fn test_compare_dirs_reports_added_removed_and_changed() {
+        let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        fs::create_dir_all(a.join("sub")).unwrap();
+        fs::create_dir_all(b.join("sub")).unwrap();
+        fs::write(a.join("same.md"), "same").unwrap();
+        fs::write(b.join("same.md"), "same").unwrap();
+        fs::write(a.join("edited.md"), "old").unwrap();
+        fs::write(b.join("edited.md"), "new").unwrap();
+        fs::write(a.join("sub/deep.md"), "old").unwrap();
+        fs::write(b.join("sub/deep.md"), "new").unwrap();
+        fs::write(a.join("removed.md"), "").unwrap();
+        fs::write(b.join("added.md"), "").unwrap();
+
+        let comparison = compare_dirs(&a, &b).unwrap();
+        assert_eq!(comparison.only_in_left, vec!["removed.md"]);
+        assert_eq!(comparison.only_in_right, vec!["added.md"]);
+        assert_eq!(comparison.changed, vec!["edited.md", "sub/deep.md"]);
+        assert!(!comparison.is_identical());
+
+        // Nested trees are walked: directories only on one side list their files
+        fs::create_dir_all(a.join("git/hooks")).unwrap();
+        fs::create_dir_all(b.join("git/empty")).unwrap();
+        fs::write(a.join("git/commit.md"), "a").unwrap();
+        fs::write(b.join("git/commit.md"), "a").unwrap();
+        fs::write(a.join("git/hooks/pre.md"), "").unwrap();
+        fs::write(a.join("git/hooks/post.md"), "").unwrap();
+        fs::create_dir(b.join("sub/deep.md.d")).unwrap();
+        fs::write(b.join("edited.md.dir"), "").unwrap();
+        fs::remove_file(b.join("edited.md")).unwrap();
+        fs::create_dir(b.join("edited.md")).unwrap();
+
+        let comparison = compare_dirs(&a, &b).unwrap();
+        assert_eq!(
+            comparison.only_in_left,
+            vec!["git/hooks/post.md", "git/hooks/pre.md", "removed.md"]
+        );
+        assert_eq!(
+            comparison.only_in_right,
+            vec!["added.md", "edited.md.dir", "git/empty/", "sub/deep.md.d/"]
+        );
+        assert_eq!(comparison.changed, vec!["edited.md", "sub/deep.md"]);
+    }
+
+    #[test]
+    fn test_relative_path_and_resolution() {
+        let link_dir = Path::new("/home/me/.claude");
+        let target = Path::new("/home/me/.claude-profiles/profiles/work/settings.json");
+
+        let relative = relative_path(link_dir, target);
+        assert_eq!(
+            relative,
+            Path::new("../.claude-profiles/profiles/work/settings.json")
+        );
+        assert_eq!(
+            resolve_link_target(&link_dir.join("settings.json"), &relative),
+            target
+        );
+
+        assert_eq!(normalize_path(Path::new("/a/./b/../c")), Path::new("/a/c"));
+        assert_eq!(
+            normalize_path(Path::new("../x/../../y")),
+            Path::new("../../y")
+        );
+        assert_eq!(normalize_path(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn test_verbatim_prefixes_are_stripped() {
+        let strip = |p: &str| strip_verbatim(Path::new(p));
+        assert_eq!(strip(r"\\?\C:\Users\me"), Path::new(r"C:\Users\me"));
+        assert_eq!(
+            strip(r"\\?\UNC\server\share\x"),
+            Path::new(r"\\server\share\x")
+        );
+        assert_eq!(
+            strip(r"\\?\Volume{1234}\x"),
+            Path::new(r"\\?\Volume{1234}\x")
+        );
+        assert_eq!(strip("/home/me"), Path::new("/home/me"));
+        assert_eq!(
+            normalize_path(Path::new(r"\\?\C:\Users")),
+            normalize_path(Path::new(r"C:\Users"))
+        );
+
+        let temp = tempfile::TempDir::new().unwrap();
+        if cfg!(unix) {
+            assert!(symlinks_permitted(temp.path()));
+        }
+        // The probe cleans up after itself
+        symlinks_permitted(temp.path());
+        assert_eq!(fs::read_dir(temp.path()).unwrap().count(), 0);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_windows_symlinks_match_target_kind() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path().join("dir");
+        fs::create_dir(&dir).unwrap();
+        fs::write(temp.path().join("file.json"), "{}").unwrap();
+        if !symlinks_permitted(temp.path()) {
+            // Developer Mode is off; doctor explains this
+            return;
+        }
+
+        create_symlink(Path::new("dir"), &temp.path().join("dir-link")).unwrap();
+        create_symlink(Path::new("file.json"), &temp.path().join("file-link")).unwrap();
+        assert!(fs::metadata(temp.path().join("dir-link")).unwrap().is_dir());
+        assert_eq!(
+            fs::read_to_string(temp.path().join("file-link")).unwrap(),
+            "{}"
+        );
+        // Directory links are removed as links, leaving the target
+        remove_path(&temp.path().join("dir-link")).unwrap();
+        assert!(dir.is_dir());
+    }
+
+    #[test]
+    fn test_copy_reports_each_file_size() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("src");
+        fs::create_dir_all(src.join("nested")).unwrap();
+        fs::write(src.join("a.md"), "12345").unwrap();
+        fs::write(src.join("nested/b.md"), "123").unwrap();
+
+        let mut sizes = Vec::new();
+        let report = copy_dir_recursive_with_progress(
+            &src,
+            &temp.path().join("dst"),
+            SymlinkMode::Preserve,
+            &mut |bytes| sizes.push(bytes),
+        )
+        .unwrap();
+        assert_eq!(report, CopyReport::default());
+        sizes.sort();
+        assert_eq!(sizes, vec![3, 5]);
+        assert_eq!(dir_size(&src).unwrap(), 8);
+        assert_eq!(count_files(&src), 2);
+    }
+
+    #[test]
+    fn test_large_tree_size_and_copy() {
+        fn generate(dir: &Path, depth: usize, expected: &mut u64) {
+            fs::create_dir_all(dir).unwrap();
+            for i in 0..3 {
+                let content = "x".repeat(depth * 10 + i);
+                fs::write(dir.join(format!("file{}.md", i)), &content).unwrap();
+                *expected += content.len() as u64;
+            }
+            if depth > 0 {
+                for i in 0..4 {
+                    generate(&dir.join(format!("dir{}", i)), depth - 1, expected);
+                }
+            }
+        }
+
+        // 341 directories and 1023 files
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("plugins");
+        let mut expected = 0;
+        generate(&src, 4, &mut expected);
+
+        let started = std::time::Instant::now();
+        assert_eq!(dir_size(&src).unwrap(), expected);
+        assert_eq!(dir_size_sequential(&src, 0, None).unwrap(), expected);
+        let sized = started.elapsed();
+
+        let started = std::time::Instant::now();
+        let dst = temp.path().join("copy");
+        let (mut files, mut bytes) = (0, 0);
+        let report =
+            copy_dir_recursive_with_progress(&src, &dst, SymlinkMode::Preserve, &mut |size| {
+                files += 1;
+                bytes += size
+            })
+            .unwrap();
+        assert_eq!(report, CopyReport::default());
+        eprintln!(
+            "large tree: sized in {:?}, copied in {:?}",
+            sized,
+            started.elapsed()
+        );
+        assert_eq!((files, bytes), (1023, expected));
+        assert_eq!(hash_dir(&dst).unwrap(), hash_dir(&src).unwrap());
+
+        // A file that can't be written fails the copy, even among many
+        fs::remove_dir_all(&dst).unwrap();
+        fs::create_dir_all(dst.join("dir3/dir2/file1.md")).unwrap();
+        let err = copy_dir_recursive(&src, &dst).unwrap_err();
+        assert!(format!("{:?}", err).contains("dir2/file1.md"), "{err:#}");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_keeps_modes_and_mtimes() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::time::{Duration, SystemTime};
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("hooks");
+        fs::create_dir_all(src.join("lib")).unwrap();
+        let script = src.join("lib/pre.sh");
+        fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o750)).unwrap();
+        fs::set_permissions(src.join("lib"), fs::Permissions::from_mode(0o700)).unwrap();
+        let old = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        fs::File::open(&script).unwrap().set_modified(old).unwrap();
+        fs::File::open(src.join("lib"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let dst = temp.path().join("copy");
+        assert_eq!(
+            copy_dir_recursive(&src, &dst).unwrap(),
+            CopyReport::default()
+        );
+        for path in [dst.join("lib/pre.sh"), dst.join("lib")] {
+            assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), old);
+        }
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dst.join("lib/pre.sh")), 0o750);
+        assert_eq!(mode(&dst.join("lib")), 0o700);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_handles_symlinks_inside() {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let repo = temp.path().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        fs::write(repo.join("reviewer.md"), "review").unwrap();
+        let src = temp.path().join("agents");
+        fs::create_dir_all(&src).unwrap();
+        symlink(repo.join("reviewer.md"), src.join("reviewer.md")).unwrap();
+        symlink(temp.path().join("gone.md"), src.join("dangling.md")).unwrap();
+        symlink(&src, src.join("loop")).unwrap();
+        assert_eq!(dir_size(&src).unwrap(), 0);
+
+        let copy = |mode, name| {
+            let dst = temp.path().join(name);
+            let mut warnings = Vec::new();
+            copy_dir_with(&src, &dst, mode, false, &mut |_| {}, &mut warnings).unwrap();
+            (dst, warnings)
+        };
+
+        let (dst, warnings) = copy(SymlinkMode::Preserve, "preserve");
+        assert_eq!(
+            fs::read_link(dst.join("reviewer.md")).unwrap(),
+            repo.join("reviewer.md")
+        );
+        assert!(fs::symlink_metadata(dst.join("dangling.md")).is_ok());
+        assert!(warnings.is_empty());
+
+        let (dst, warnings) = copy(SymlinkMode::Follow, "follow");
+        assert!(
+            !fs::symlink_metadata(dst.join("reviewer.md"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(
+            fs::read_to_string(dst.join("reviewer.md")).unwrap(),
+            "review"
+        );
+        assert!(fs::symlink_metadata(dst.join("loop")).unwrap().is_symlink());
+        assert_eq!(warnings.len(), 2);
+
+        let (dst, warnings) = copy(SymlinkMode::Skip, "skip");
+        assert_eq!(fs::read_dir(&dst).unwrap().count(), 0);
+        assert_eq!(warnings.len(), 3);
+
+        // The caller gets the warnings back rather than a log line only
+        let report = copy_dir_recursive_with_progress(
+            &src,
+            &temp.path().join("reported"),
+            SymlinkMode::Skip,
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(report.warnings, warnings);
+        assert!(report.skipped.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_lenient_copy_skips_unreadable_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("commands");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("sub/deploy.md"), "deploy").unwrap();
+        // A socket can't be opened for reading, even as root
+        std::os::unix::net::UnixListener::bind(src.join("sub/agent.sock")).unwrap();
+
+        let copy = |strict, name| {
+            let dst = temp.path().join(name);
+            let options = CopyOptions {
+                strict,
+                ..CopyOptions::default()
+            };
+            let result = copy_dir_lenient(&src, &dst, options);
+            (dst, result)
+        };
+
+        let (dst, result) = copy(false, "lenient");
+        let skipped = result.unwrap().skipped;
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].starts_with(&format!(
+            "Skipped unreadable {}: ",
+            src.join("sub/agent.sock").display()
+        )));
+        assert_eq!(
+            fs::read_to_string(dst.join("sub/deploy.md")).unwrap(),
+            "deploy"
+        );
+        assert!(!dst.join("sub/agent.sock").exists());
+
+        let (_, result) = copy(true, "strict");
+        assert!(result.is_err());
+
+        // The top directory itself must be readable
+        let missing = temp.path().join("missing");
+        assert!(
+            copy_dir_lenient(&missing, &temp.path().join("out"), CopyOptions::default()).is_err()
+        );
+        let (size, skipped) = dir_size_lenient(&missing);
+        assert_eq!(size, 0);
+        assert!(skipped[0].contains("missing"));
+        assert_eq!(dir_size_lenient(&src), (6, vec![]));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_walks_stop_at_loops_and_depth() {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("hooks");
+        fs::create_dir_all(src.join("a")).unwrap();
+        fs::create_dir_all(src.join("b")).unwrap();
+        fs::write(src.join("a/pre.sh"), "echo").unwrap();
+        // Neither link points at its own ancestor, but together they loop
+        symlink("../b", src.join("a/to-b")).unwrap();
+        symlink("../a", src.join("b/to-a")).unwrap();
+        symlink("..", src.join("loop")).unwrap();
+
+        let (size, warnings) = dir_size_lenient(&src);
+        assert_eq!(size, 4);
+        assert_eq!(warnings, vec![symlink_loop(&src.join("loop"))]);
+
+        let stats = dir_stats(&src, 1, SymlinkMode::Follow).unwrap();
+        assert!(!stats.warnings.is_empty());
+        let mut warnings = Vec::new();
+        let dst = temp.path().join("copy");
+        copy_dir_with(
+            &src,
+            &dst,
+            SymlinkMode::Follow,
+            false,
+            &mut |_| {},
+            &mut warnings,
+        )
+        .unwrap();
+        // Each link is followed once, then kept as a link
+        assert!(
+            !fs::symlink_metadata(dst.join("a/to-b"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert!(
+            fs::symlink_metadata(dst.join("a/to-b/to-a"))
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+
+        // Below the depth limit, walks stop with a warning; hashing fails
+        let deep = temp.path().join("deep");
+        let bottom = (0..=max_depth()).fold(deep.clone(), |dir, _| dir.join("d"));
+        fs::create_dir_all(&bottom).unwrap();
+        fs::write(bottom.join("too-deep.md"), "x").unwrap();
+        fs::write(deep.join("top.md"), "x").unwrap();
+
+        assert!(dir_size(&deep).is_err());
+        let (size, warnings) = dir_size_lenient(&deep);
+        assert_eq!((size, warnings.len()), (1, 1));
+        assert_eq!(count_files(&deep), 1);
+        assert!(hash_dir(&deep).is_err());
+        let mut warnings = Vec::new();
+        let dst = temp.path().join("deep-copy");
+        copy_dir_with(
+            &deep,
+            &dst,
+            SymlinkMode::Preserve,
+            false,
+            &mut |_| {},
+            &mut warnings,
+        )
+        .unwrap();
+        assert!(warnings[0].contains(MAX_DEPTH_ENV));
+        assert!(dst.join("top.md").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dir_stats_match_the_copy() {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("agents");
+        fs::create_dir_all(src.join("review/deep")).unwrap();
+        fs::write(src.join("small.md"), "a").unwrap();
+        fs::write(src.join("review/reviewer.md"), "x".repeat(400)).unwrap();
+        fs::write(src.join("review/deep/notes.md"), "x".repeat(40)).unwrap();
+        let outside = temp.path().join("shared.md");
+        fs::write(&outside, "x".repeat(1000)).unwrap();
+        symlink(&outside, src.join("shared.md")).unwrap();
+        symlink(&src, src.join("review/loop")).unwrap();
+
+        let stats = dir_stats(&src, 2, SymlinkMode::Preserve).unwrap();
+        assert_eq!((stats.files, stats.dirs, stats.bytes), (5, 2, 441));
+        assert_eq!(
+            stats.largest,
+            vec![
+                (PathBuf::from("review/reviewer.md"), 400),
+                (PathBuf::from("review/deep/notes.md"), 40),
+            ]
+        );
+
+        // Followed links count as their target; loops stay links
+        let stats = dir_stats(&src, 1, SymlinkMode::Follow).unwrap();
+        assert_eq!((stats.files, stats.bytes), (5, 1441));
+        assert_eq!(stats.largest, vec![(PathBuf::from("shared.md"), 1000)]);
+
+        let stats = dir_stats(&src, 0, SymlinkMode::Skip).unwrap();
+        assert_eq!((stats.files, stats.bytes), (3, 441));
+        assert!(stats.largest.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_path_never_follows_links() {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let root = temp.path();
+        let outside = root.join("outside");
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("keep.md"), "keep").unwrap();
+        fs::write(root.join("keep.json"), "{}").unwrap();
+
+        // Missing paths are fine
+        remove_path(&root.join("missing")).unwrap();
+
+        fs::write(root.join("file"), "x").unwrap();
+        remove_path(&root.join("file")).unwrap();
+        assert!(!root.join("file").exists());
+
+        // A tree, with links inside that must not be followed
+        let tree = root.join("tree");
+        fs::create_dir_all(tree.join("sub")).unwrap();
+        fs::write(tree.join("sub/a.md"), "a").unwrap();
+        symlink(&outside, tree.join("sub/dir-link")).unwrap();
+        symlink(root.join("keep.json"), tree.join("file-link")).unwrap();
+        let read_only = tree.join("sub/read-only.md");
+        fs::write(&read_only, "r").unwrap();
+        let mut permissions = fs::metadata(&read_only).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&read_only, permissions).unwrap();
+        remove_path(&tree).unwrap();
+        assert!(fs::symlink_metadata(&tree).is_err());
+
+        for (name, target) in [
+            ("file-link", root.join("keep.json")),
+            ("dir-link", outside.clone()),
+            ("dangling-file", root.join("gone.md")),
+            ("dangling-dir", root.join("gone/")),
+        ] {
+            let link = root.join(name);
+            symlink(&target, &link).unwrap();
+            remove_path(&link).unwrap();
+            assert!(fs::symlink_metadata(&link).is_err(), "{name}");
+        }
+
+        assert_eq!(fs::read_to_string(outside.join("keep.md")).unwrap(), "keep");
+        assert!(root.join("keep.json").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replace_dir_atomic() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let src = temp.path().join("backup");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("new.md"), "new").unwrap();
+        let dst = temp.path().join("agents");
+        fs::create_dir_all(&dst).unwrap();
+        fs::write(dst.join("old.md"), "old").unwrap();
+        // Left over from an interrupted run
+        fs::create_dir_all(temp.path().join("agents.ccprof-new")).unwrap();
+
+        assert_eq!(
+            replace_dir_atomic(&src, &dst).unwrap(),
+            CopyReport::default()
+        );
+        assert_eq!(fs::read_to_string(dst.join("new.md")).unwrap(), "new");
+        assert!(!dst.join("old.md").exists());
+        let names: Vec<_> = fs::read_dir(temp.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names.len(), 2, "{names:?}");
+
+        // A failed copy leaves the destination as it was
+        std::os::unix::net::UnixListener::bind(src.join("broken.sock")).unwrap();
+        fs::write(dst.join("edited.md"), "edited").unwrap();
+        assert!(replace_dir_atomic(&src, &dst).is_err());
+        assert_eq!(fs::read_to_string(dst.join("edited.md")).unwrap(), "edited");
+        assert!(!temp.path().join("agents.ccprof-new").exists());
+
+        // A symlink is replaced, not followed
+        fs::remove_file(src.join("broken.sock")).unwrap();
+        let link = temp.path().join("linked");
+        std::os::unix::fs::symlink(&dst, &link).unwrap();
+        assert_eq!(
+            replace_dir_atomic(&src, &link).unwrap(),
+            CopyReport::default()
+        );
+        assert!(!fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert!(dst.join("edited.md").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_link_chain() {
+        use std::os::unix::fs::symlink;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let dir = temp.path();
+        fs::write(dir.join("real.json"), "{}").unwrap();
+        symlink("real.json", dir.join("b")).unwrap();
+        symlink(dir.join("b"), dir.join("a")).unwrap();
+        symlink("a", dir.join("settings.json")).unwrap();
+
+        assert_eq!(
+            follow_link_chain(&dir.join("settings.json")),
+            LinkChain::Resolved(vec![dir.join("a"), dir.join("b"), dir.join("real.json")])
+        );
+        assert_eq!(
+            link_chain_end(&dir.join("real.json")),
+            Some(dir.join("real.json"))
+        );
+
+        symlink("y", dir.join("x")).unwrap();
+        symlink("x", dir.join("y")).unwrap();
+        let chain = follow_link_chain(&dir.join("x"));
+        assert_eq!(chain, LinkChain::Loop(vec![dir.join("y"), dir.join("x")]));
+        assert_eq!(
+            chain.display(Path::new("x")),
+            format!(
+                "x → {} → {}",
+                dir.join("y").display(),
+                dir.join("x").display()
+            )
+        );
+        assert_eq!(link_chain_end(&dir.join("x")), None);
+    }
+}
@@ -0,0 +1,210 @@
+//! Profile inheritance.
+//!
+//! A profile may name another in `extends` in its metadata. On switch its
+//! effective settings are built by deep-merging the settings.json of every
+//! profile in the chain, base first, so a child only needs the keys it
+//! overrides. The result is written to [`RESOLVED_SETTINGS_FILE`] inside the
+//! child profile, which is what `~/.claude/settings.json` links to.
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::components::{ProfileMetadata, RESOLVED_SETTINGS_FILE};
+use crate::paths::Paths;
+use crate::profiles::{ensure_profile_exists, list_profiles, profile_exists};
+
+/// Profiles `name` inherits from, starting with `name` itself
+///
+/// Fails if a base is missing or the chain loops back on itself.
+pub fn inheritance_chain(paths: &Paths, name: &str) -> Result<Vec<String>> {
+    let mut chain = vec![name.to_string()];
+    let mut current = name.to_string();
+
+    while let Some(base) = ProfileMetadata::read(&paths.profile_dir(&current))?.extends {
+        if chain.contains(&base) {
+            chain.push(base);
+            bail!(
+                "Profile inheritance cycle: {}\nHint: Remove 'extends' from one of these profiles' metadata.json.",
+                chain.join(" → ")
+            );
+        }
+        if !profile_exists(paths, &base) {
+            bail!(
+                "Profile '{}' extends '{}', which does not exist.\nHint: Use 'ccprof list' to see available profiles.",
+                current,
+                base
+            );
+        }
+        chain.push(base.clone());
+        current = base;
+    }
+
+    Ok(chain)
+}
+
+/// Deep-merge `overlay` into `base`; objects merge key by key, anything else is replaced
+pub fn merge_json(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_json(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Effective settings of a profile: its own settings.json merged over its bases'
+pub fn resolve_settings(paths: &Paths, name: &str) -> Result<Value> {
+    let mut resolved = Value::Object(Default::default());
+    for profile in inheritance_chain(paths, name)?.iter().rev() {
+        let path = paths.profile_settings(profile);
+        if !path.exists() {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let settings = crate::jsonc::from_str(&content)
+            .with_context(|| format!("Failed to parse JSON from {}", path.display()))?;
+        merge_json(&mut resolved, settings);
+    }
+    Ok(resolved)
+}
+
+/// The settings.resolved.json a profile that extends another would get
+///
+/// Returns `None` if the profile doesn't extend anything. Nothing is written.
+pub fn resolved_settings_text(paths: &Paths, name: &str) -> Result<Option<String>> {
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    if metadata.extends.is_none() {
+        return Ok(None);
+    }
+    let resolved = resolve_settings(paths, name)?;
+    Ok(Some(serde_json::to_string_pretty(&resolved)?))
+}
+
+/// Write the resolved settings of a profile that extends another
+///
+/// Returns the generated file, or `None` if the profile doesn't extend anything.
+pub fn materialize_settings(paths: &Paths, name: &str) -> Result<Option<PathBuf>> {
+    let Some(resolved) = resolved_settings_text(paths, name)? else {
+        return Ok(None);
+    };
+    let path = paths.profile_dir(name).join(RESOLVED_SETTINGS_FILE);
+    fs::write(&path, resolved).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(Some(path))
+}
+
+/// Make `name` extend `base`, or stop extending anything
+pub fn set_extends(paths: &Paths, name: &str, base: Option<&str>) -> Result<()> {
+    let profile_dir = paths.profile_dir(name);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    let previous = metadata.extends.clone();
+
+    if let Some(base) = base {
+        ensure_profile_exists(paths, base)?;
+    }
+
+    metadata.extends = base.map(str::to_string);
+    metadata.updated_at = Utc::now();
+    metadata.write(&profile_dir)?;
+
+    // Reject the change if it closes a loop
+    if let Err(e) = inheritance_chain(paths, name) {
+        metadata.extends = previous;
+        metadata.write(&profile_dir)?;
+        return Err(e);
+    }
+
+    if base.is_none() {
+        let _ = fs::remove_file(profile_dir.join(RESOLVED_SETTINGS_FILE));
+    }
+    Ok(())
+}
+
+/// Profiles that extend `name` directly
+pub fn children_of(paths: &Paths, name: &str) -> Result<Vec<String>> {
+    let mut children = Vec::new();
+    for profile in list_profiles(paths)? {
+        let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(&profile)) else {
+            continue;
+        };
+        if metadata.extends.as_deref() == Some(name) {
+            children.push(profile);
+        }
+    }
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Component;
+    use crate::profiles::create_profile_with_components;
+    use crate::test_utils::setup_test_paths;
+    use serde_json::json;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    fn setup_profile(paths: &Paths, name: &str, settings: Value) {
+        fs::write(&paths.claude_settings, settings.to_string()).unwrap();
+        create_profile_with_components(paths, name, HashSet::from([Component::Settings])).unwrap();
+    }
+
+    #[test]
+    fn test_child_settings_override_base() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+
+        setup_profile(
+            &paths,
+            "work-base",
+            json!({"model": "sonnet", "env": {"A": "1", "B": "2"}, "hooks": [1, 2]}),
+        );
+        setup_profile(&paths, "client-a", json!({"env": {"B": "3"}, "hooks": [3]}));
+        set_extends(&paths, "client-a", Some("work-base")).unwrap();
+
+        assert_eq!(
+            inheritance_chain(&paths, "client-a").unwrap(),
+            vec!["client-a", "work-base"]
+        );
+        let path = materialize_settings(&paths, "client-a").unwrap().unwrap();
+        let resolved: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(
+            resolved,
+            json!({"model": "sonnet", "env": {"A": "1", "B": "3"}, "hooks": [3]})
+        );
+        assert_eq!(children_of(&paths, "work-base").unwrap(), vec!["client-a"]);
+        assert!(materialize_settings(&paths, "work-base").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_extends_cycle_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+
+        for name in ["a", "b", "c"] {
+            setup_profile(&paths, name, json!({}));
+        }
+        set_extends(&paths, "a", Some("b")).unwrap();
+        set_extends(&paths, "b", Some("c")).unwrap();
+
+        let err = set_extends(&paths, "c", Some("a")).unwrap_err();
+        assert!(err.to_string().contains("c → a → b → c"));
+        // The rejected link isn't kept
+        let metadata = ProfileMetadata::read(&paths.profile_dir("c")).unwrap();
+        assert!(metadata.extends.is_none());
+    }
+}
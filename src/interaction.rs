@@ -0,0 +1,227 @@
+//! User interaction behind a trait, so the library works without a terminal.
+//!
+//! Everything a command asks or tells the user goes through [`Interaction`]:
+//! confirmations, choosing components, picking one of several options,
+//! status messages and progress. On the CLI, [`Ui`](crate::ui::Ui) implements
+//! it with inquire prompts, indicatif spinners and styled output.
+//!
+//! Embedders hand a [`Headless`] to [`Ui::with_interaction`](crate::ui::Ui::with_interaction)
+//! instead: prompts are answered from a list given up front, and messages are
+//! collected rather than printed.
+//!
+//! ```
+//! use std::rc::Rc;
+//! use ccprof::interaction::{Answer, Headless, Interaction};
+//! use ccprof::ui::Ui;
+//!
+//! let headless = Rc::new(Headless::new([Answer::Confirm(true)]));
+//! let ui = Ui::default().with_interaction(headless.clone());
+//! assert!(ui.confirm("Remove profile 'work'?", None, false).unwrap());
+//! ui.ok("Removed profile 'work'");
+//! assert_eq!(headless.messages().len(), 1);
+//! ```
+
+use anyhow::{Result, bail};
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
+
+use crate::components::Component;
+
+/// Kind of a reported message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Ok,
+    Info,
+    Warn,
+    Error,
+    /// Headings, hints and other commentary
+    Note,
+    /// Data a script would consume (tables, lists, diffs)
+    Output,
+    /// A step of a longer operation
+    Progress,
+}
+
+/// A component offered by [`Interaction::select_components`]
+#[derive(Debug, Clone)]
+pub struct ComponentChoice {
+    pub component: Component,
+    /// Text shown for the option
+    pub label: String,
+    /// Whether the option starts out selected
+    pub selected: bool,
+}
+
+/// How commands ask and tell the user things
+pub trait Interaction {
+    /// Ask a yes/no question, with an optional help line
+    fn confirm(&self, msg: &str, help: Option<&str>, default: bool) -> Result<bool>;
+
+    /// Choose any number of components
+    fn select_components(
+        &self,
+        msg: &str,
+        help: &str,
+        choices: &[ComponentChoice],
+    ) -> Result<HashSet<Component>>;
+
+    /// Pick one of `options`, returning its index
+    fn select(&self, msg: &str, options: &[String]) -> Result<usize>;
+
+    /// Report a message
+    fn message(&self, level: Level, text: &str);
+
+    /// Report a step of a longer operation
+    fn progress(&self, text: &str) {
+        self.message(Level::Progress, text);
+    }
+}
+
+/// A prepared answer for [`Headless`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    /// Take the prompt's default (pre-selected components, the first option)
+    Default,
+    Confirm(bool),
+    Components(Vec<Component>),
+    /// Index of the option to pick
+    Select(usize),
+}
+
+/// Answers prompts from a fixed list and records messages
+///
+/// Each prompt takes the next answer; a prompt with no answer left, or with
+/// the wrong kind of answer, is an error rather than a hang.
+#[derive(Debug, Default)]
+pub struct Headless {
+    answers: RefCell<VecDeque<Answer>>,
+    messages: RefCell<Vec<(Level, String)>>,
+}
+
+impl Headless {
+    pub fn new(answers: impl IntoIterator<Item = Answer>) -> Self {
+        Self {
+            answers: RefCell::new(answers.into_iter().collect()),
+            messages: RefCell::default(),
+        }
+    }
+
+    /// Messages reported so far, oldest first
+    pub fn messages(&self) -> Vec<(Level, String)> {
+        self.messages.borrow().clone()
+    }
+
+    fn next_answer(&self, msg: &str) -> Result<Answer> {
+        match self.answers.borrow_mut().pop_front() {
+            Some(answer) => Ok(answer),
+            None => bail!("No answer supplied for prompt: {}", msg),
+        }
+    }
+}
+
+impl Interaction for Headless {
+    fn confirm(&self, msg: &str, _help: Option<&str>, default: bool) -> Result<bool> {
+        match self.next_answer(msg)? {
+            Answer::Default => Ok(default),
+            Answer::Confirm(yes) => Ok(yes),
+            other => bail!("Expected a yes/no answer for '{}', got {:?}", msg, other),
+        }
+    }
+
+    fn select_components(
+        &self,
+        msg: &str,
+        _help: &str,
+        choices: &[ComponentChoice],
+    ) -> Result<HashSet<Component>> {
+        match self.next_answer(msg)? {
+            Answer::Default => Ok(choices
+                .iter()
+                .filter(|c| c.selected)
+                .map(|c| c.component)
+                .collect()),
+            Answer::Components(components) => {
+                if let Some(missing) = components
+                    .iter()
+                    .find(|c| !choices.iter().any(|choice| choice.component == **c))
+                {
+                    bail!(
+                        "Component {} is not offered by '{}'",
+                        missing.display_name(),
+                        msg
+                    );
+                }
+                Ok(components.into_iter().collect())
+            }
+            other => bail!("Expected components for '{}', got {:?}", msg, other),
+        }
+    }
+
+    fn select(&self, msg: &str, options: &[String]) -> Result<usize> {
+        match self.next_answer(msg)? {
+            Answer::Default if !options.is_empty() => Ok(0),
+            Answer::Select(index) if index < options.len() => Ok(index),
+            other => bail!(
+                "Expected one of {} options for '{}', got {:?}",
+                options.len(),
+                msg,
+                other
+            ),
+        }
+    }
+
+    fn message(&self, level: Level, text: &str) {
+        self.messages.borrow_mut().push((level, text.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headless_answers_in_order() {
+        let headless = Headless::new([
+            Answer::Confirm(false),
+            Answer::Default,
+            Answer::Components(vec![Component::Agents]),
+            Answer::Select(1),
+        ]);
+        let choices = [
+            ComponentChoice {
+                component: Component::Settings,
+                label: "settings.json".to_string(),
+                selected: true,
+            },
+            ComponentChoice {
+                component: Component::Agents,
+                label: "agents/".to_string(),
+                selected: false,
+            },
+        ];
+        let options = ["Keep".to_string(), "Remove".to_string()];
+
+        assert!(!headless.confirm("Remove?", None, true).unwrap());
+        assert_eq!(
+            headless.select_components("Which?", "", &choices).unwrap(),
+            HashSet::from([Component::Settings])
+        );
+        assert_eq!(
+            headless.select_components("Which?", "", &choices).unwrap(),
+            HashSet::from([Component::Agents])
+        );
+        assert_eq!(headless.select("Action?", &options).unwrap(), 1);
+
+        // Out of answers
+        assert!(headless.confirm("Remove?", None, true).is_err());
+
+        let headless = Headless::new([Answer::Select(0)]);
+        assert!(headless.confirm("Remove?", None, true).is_err());
+
+        headless.progress("Copying");
+        assert_eq!(
+            headless.messages(),
+            vec![(Level::Progress, "Copying".to_string())]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Journal of in-flight profile switches.
+//!
+//! Before a switch touches `~/.claude`, it writes `~/.claude-profiles/switch.journal`
+//! listing every component it is about to replace and where the original content
+//! is moved aside. Each component is checked off once applied, and the journal is
+//! removed when the switch completes.
+//!
+//! A journal left behind means ccprof was killed mid-switch. `ccprof doctor --fix`
+//! uses it to put the originals back and then re-run the switch.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::components::Component;
+use crate::paths::Paths;
+use crate::state::LockedState;
+use crate::switch::{LinkStyle, SwitchOptions, SwitchStrategy};
+
+/// An in-flight switch, as recorded on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SwitchJournal {
+    /// Profile being switched to
+    pub profile: String,
+    /// Profile that was active before the switch started
+    pub previous_profile: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub strategy: SwitchStrategy,
+    pub link_style: LinkStyle,
+    #[serde(default)]
+    pub skip_missing: bool,
+    /// Directory holding the originals moved out of ~/.claude
+    pub staging_dir: PathBuf,
+    pub operations: Vec<JournalOperation>,
+}
+
+/// One component replaced by the switch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalOperation {
+    pub component: Component,
+    /// Location in ~/.claude
+    pub live_path: PathBuf,
+    /// Where the original is moved before the new content is put in place
+    pub staged_path: PathBuf,
+    /// Whether anything existed at `live_path` before the switch
+    pub original_exists: bool,
+    /// The new content has been put in place
+    pub done: bool,
+}
+
+/// What `recover` did with an interrupted switch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// Originals restored, then the switch was run again successfully
+    Completed { profile: String },
+    /// Originals restored; the target profile is gone so the switch was not retried
+    RolledBack { profile: String },
+}
+
+impl SwitchJournal {
+    /// Read the journal, or `None` if no switch is in flight
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read switch journal: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .with_context(|| format!("Failed to parse switch journal: {}", path.display()))
+    }
+
+    /// Write the journal atomically, flushed to disk
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        let temp_path = path.with_extension("journal.tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to write switch journal: {}", path.display()))
+    }
+
+    /// Undo the filesystem changes of the switch, newest first
+    ///
+    /// Safe to call at any point of an interrupted switch: components that were
+    /// never staged are left alone, staged originals are moved back, and content
+    /// created where nothing existed before is removed.
+    pub fn roll_back(&self) -> Result<()> {
+        for op in self.operations.iter().rev() {
+            if fs::symlink_metadata(&op.staged_path).is_ok() {
+                crate::fs_utils::remove_path(&op.live_path)?;
+                crate::fs_utils::move_path(&op.staged_path, &op.live_path)?;
+            } else if !op.original_exists {
+                crate::fs_utils::remove_path(&op.live_path)?;
+            }
+            // Otherwise the original was never moved and is still in place
+        }
+        remove_staging(&self.staging_dir)
+    }
+}
+
+/// Delete the journal
+pub fn remove(path: &Path) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|| format!("Failed to remove switch journal: {}", path.display()))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn remove_staging(staging_dir: &Path) -> Result<()> {
+    crate::fs_utils::remove_path(staging_dir)
+}
+
+/// Recover from an interrupted switch, if there is one
+///
+/// The originals are restored and the previously active profile is put back in
+/// state, then the switch is run again from scratch if its profile still exists.
+pub fn recover(paths: &Paths) -> Result<Option<Recovery>> {
+    let Some(journal) = SwitchJournal::read(&paths.journal_file)? else {
+        return Ok(None);
+    };
+
+    journal.roll_back()?;
+    let mut locked = LockedState::lock(&paths.state_file)?;
+    locked.update(|s| s.default_profile = journal.previous_profile.clone())?;
+    drop(locked);
+    remove(&paths.journal_file)?;
+
+    if !crate::profiles::profile_exists(paths, &journal.profile) {
+        return Ok(Some(Recovery::RolledBack {
+            profile: journal.profile,
+        }));
+    }
+
+    let options = SwitchOptions {
+        strategy: Some(journal.strategy),
+        link_style: Some(journal.link_style),
+        skip_missing: journal.skip_missing,
+        ..Default::default()
+    };
+    crate::switch::switch_to_profile(paths, &journal.profile, &options)?;
+    Ok(Some(Recovery::Completed {
+        profile: journal.profile,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::create_profile_with_components;
+    use crate::state::State;
+    use crate::switch::switch_to_profile;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_recover_interrupted_switch() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        let agents = paths.claude_dir.join("agents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(agents.join("a.md"), "a").unwrap();
+
+        let components = HashSet::from([Component::Settings, Component::Agents]);
+        create_profile_with_components(&paths, "work", components).unwrap();
+
+        // Simulate a crash after agents was moved aside but before its link existed
+        let staging_dir = paths.staging_dir.join("crashed");
+        fs::create_dir_all(&staging_dir).unwrap();
+        fs::rename(&agents, staging_dir.join("agents")).unwrap();
+        let journal = SwitchJournal {
+            profile: "work".to_string(),
+            previous_profile: None,
+            started_at: Utc::now(),
+            strategy: SwitchStrategy::Symlink,
+            link_style: LinkStyle::Absolute,
+            skip_missing: false,
+            staging_dir: staging_dir.clone(),
+            operations: vec![
+                JournalOperation {
+                    component: Component::Settings,
+                    live_path: paths.claude_settings.clone(),
+                    staged_path: staging_dir.join("settings"),
+                    original_exists: true,
+                    done: false,
+                },
+                JournalOperation {
+                    component: Component::Agents,
+                    live_path: agents.clone(),
+                    staged_path: staging_dir.join("agents"),
+                    original_exists: true,
+                    done: false,
+                },
+            ],
+        };
+        journal.write(&paths.journal_file).unwrap();
+
+        // New switches refuse to run on top of an interrupted one
+        assert!(switch_to_profile(&paths, "work", &SwitchOptions::default()).is_err());
+
+        let recovery = recover(&paths).unwrap();
+        assert_eq!(
+            recovery,
+            Some(Recovery::Completed {
+                profile: "work".to_string()
+            })
+        );
+        assert!(!paths.journal_file.exists());
+        assert!(!staging_dir.exists());
+        assert_eq!(
+            fs::read_link(&agents).unwrap(),
+            paths.profile_dir("work").join("agents")
+        );
+        assert_eq!(
+            State::read(&paths.state_file)
+                .unwrap()
+                .default_profile
+                .as_deref(),
+            Some("work")
+        );
+    }
+
+    #[test]
+    fn test_successful_switch_leaves_no_journal() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+
+        create_profile_with_components(&paths, "work", HashSet::from([Component::Settings]))
+            .unwrap();
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+
+        assert!(!paths.journal_file.exists());
+        assert_eq!(fs::read_dir(&paths.staging_dir).unwrap().count(), 0);
+        assert_eq!(recover(&paths).unwrap(), None);
+    }
+}
@@ -0,0 +1,134 @@
+//! Lenient reading of JSON with comments.
+//!
+//! Claude Code accepts `//` and `/* */` comments and trailing commas in some
+//! settings files, so ccprof reads them too: [`parse`] tries strict JSON first
+//! and falls back to stripping those extensions. Callers can tell from
+//! [`Document::lenient`] when to warn that strict JSON is safer. ccprof never
+//! writes comments back: commands that rewrite a settings file create a new one.
+
+use serde_json::Value;
+
+/// A JSON document and how it had to be read
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub value: Value,
+    /// Comments or trailing commas were removed to parse it
+    pub lenient: bool,
+}
+
+/// Parse `text` as JSON, allowing comments and trailing commas
+///
+/// Errors point at the same line and column as in `text`, since stripped
+/// characters are replaced by spaces.
+pub fn parse(text: &str) -> serde_json::Result<Document> {
+    if let Ok(value) = serde_json::from_str(text) {
+        return Ok(Document {
+            value,
+            lenient: false,
+        });
+    }
+    let value = serde_json::from_str(&strip(text))?;
+    Ok(Document {
+        value,
+        lenient: true,
+    })
+}
+
+/// Parse leniently, for callers that don't care how
+pub fn from_str(text: &str) -> serde_json::Result<Value> {
+    parse(text).map(|doc| doc.value)
+}
+
+/// Blank out comments and trailing commas outside strings
+///
+/// An unterminated `/*` is left alone, so the text still fails to parse.
+pub fn strip(text: &str) -> String {
+    let mut out: Vec<char> = text.chars().collect();
+    let blank = |chars: &mut [char]| {
+        for c in chars.iter_mut().filter(|c| **c != '\n') {
+            *c = ' ';
+        }
+    };
+
+    let mut i = 0;
+    let mut in_string = false;
+    while i < out.len() {
+        match (in_string, out[i], out.get(i + 1)) {
+            (true, '\\', _) => i += 1,
+            (true, '"', _) | (false, '"', _) => in_string = !in_string,
+            (false, '/', Some('/')) => {
+                let end = (i..out.len())
+                    .find(|&j| out[j] == '\n')
+                    .unwrap_or(out.len());
+                blank(&mut out[i..end]);
+                i = end;
+                continue;
+            }
+            (false, '/', Some('*')) => {
+                let Some(end) = (i + 2..out.len().saturating_sub(1))
+                    .find(|&j| out[j] == '*' && out[j + 1] == '/')
+                else {
+                    break;
+                };
+                blank(&mut out[i..end + 2]);
+                i = end + 2;
+                continue;
+            }
+            (false, ',', _) => {
+                // Comments after the comma aren't blanked yet, so look past them
+                if matches!(next_significant(&out[i + 1..]), Some('}' | ']')) {
+                    out[i] = ' ';
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    out.into_iter().collect()
+}
+
+/// The first character that isn't whitespace or inside a comment
+fn next_significant(chars: &[char]) -> Option<char> {
+    let mut i = 0;
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            (c, _) if c.is_whitespace() => i += 1,
+            ('/', Some('/')) => {
+                i = (i..chars.len()).find(|&j| chars[j] == '\n')?;
+            }
+            ('/', Some('*')) => {
+                i = (i + 2..chars.len().saturating_sub(1))
+                    .find(|&j| chars[j] == '*' && chars[j + 1] == '/')?
+                    + 2;
+            }
+            (c, _) => return Some(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_comments_and_trailing_commas_are_tolerated() {
+        let strict = parse(r#"{"a": "// not a comment", "b": [1, 2]}"#).unwrap();
+        assert!(!strict.lenient);
+        assert_eq!(strict.value, json!({"a": "// not a comment", "b": [1, 2]}));
+
+        let text = "{\n  // the model\n  \"model\": \"opus\", /* inline */\n  \"list\": [1, 2, // last\n],\n  \"url\": \"http://x/*y*/\",\n}\n";
+        let doc = parse(text).unwrap();
+        assert!(doc.lenient);
+        assert_eq!(
+            doc.value,
+            json!({"model": "opus", "list": [1, 2], "url": "http://x/*y*/"})
+        );
+
+        // Positions survive stripping
+        let err = parse("{\n  // note\n  \"a\": nope\n}").unwrap_err();
+        assert_eq!(err.line(), 3);
+        assert!(parse("{\"a\": 1} /* unterminated").is_err());
+    }
+}
@@ -7,18 +7,44 @@
 //! # Architecture
 //!
 //! - **Profiles**: Named configurations stored in `~/.claude-profiles/profiles/`.
-//! - **Components**: Parts of the configuration (Settings, Agents, Hooks, Commands).
+//! - **Components**: Parts of the configuration (Settings, Agents, Hooks, Commands, Env).
 //! - **Switching**: Atomically updating symlinks to change the active profile.
 //! - **State**: Tracking the active profile in `state.json`.
 
+pub mod audit;
+pub mod bundle;
+pub mod claude;
 pub mod commands;
 pub mod components;
+pub mod config;
+pub mod dedupe;
 pub mod doctor;
+pub mod editor;
+pub mod env_file;
+pub mod error;
+pub mod exec;
 pub mod fs_utils;
+pub mod inheritance;
+pub mod interaction;
+pub mod journal;
+pub mod jsonc;
+pub mod local;
+pub mod merge;
+pub mod migrate_paths;
 pub mod paths;
+pub mod plan;
 pub mod profiles;
+pub mod redact;
+pub mod render;
+pub mod settings_schema;
+pub mod shared;
 pub mod state;
+pub mod stats;
 pub mod switch;
+pub mod templates;
 #[cfg(test)]
 pub mod test_utils;
-pub mod ui;
\ No newline at end of file
+pub mod ui;
+pub mod upstream;
+
+pub use error::Error;
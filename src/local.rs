@@ -0,0 +1,85 @@
+//! Per-project profiles.
+//!
+//! A `.ccprof` file in a repository names the profile the project wants, one
+//! name on the first line (`#` lines are comments). `ccprof local check`
+//! finds the nearest one above the current directory, for a shell `cd` hook
+//! or direnv to remind about, or switch to, the project's profile.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the marker file
+pub const MARKER_FILE: &str = ".ccprof";
+
+/// A `.ccprof` file and the profile it names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub path: PathBuf,
+    pub profile: String,
+}
+
+/// The nearest `.ccprof` in `start` or a directory above it
+///
+/// Files without a profile name are skipped.
+pub fn find_marker(start: &Path) -> Option<Marker> {
+    start.ancestors().find_map(|dir| {
+        let path = dir.join(MARKER_FILE);
+        let content = fs::read_to_string(&path).ok()?;
+        let profile = content
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'))?
+            .to_string();
+        Some(Marker { path, profile })
+    })
+}
+
+/// Where `local set` writes the marker: the git root above `start`, or `start`
+pub fn marker_dir(start: &Path) -> PathBuf {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .unwrap_or(start)
+        .to_path_buf()
+}
+
+/// Write a `.ccprof` naming `profile` in `dir`, returning its path
+pub fn write_marker(dir: &Path, profile: &str) -> Result<PathBuf> {
+    let path = dir.join(MARKER_FILE);
+    fs::write(&path, format!("{}\n", profile))
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_nearest_marker_wins() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        let nested = repo.join("crates/app");
+        fs::create_dir_all(repo.join(".git")).unwrap();
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_marker(&nested), None);
+
+        assert_eq!(marker_dir(&nested), repo);
+        let path = write_marker(&marker_dir(&nested), "work").unwrap();
+        assert_eq!(
+            find_marker(&nested),
+            Some(Marker {
+                path,
+                profile: "work".to_string()
+            })
+        );
+
+        fs::write(nested.join(MARKER_FILE), "# client work\n\n  acme \n").unwrap();
+        assert_eq!(find_marker(&nested).unwrap().profile, "acme");
+        // An empty marker doesn't hide the one above
+        fs::write(nested.join(MARKER_FILE), "# nothing yet\n").unwrap();
+        assert_eq!(find_marker(&nested).unwrap().profile, "work");
+    }
+}
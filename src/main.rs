@@ -2,17 +2,43 @@ use anyhow::Result;
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::generate;
 use std::io;
+use std::path::PathBuf;
 
 use ccprof::{
-    commands,
+    Error, audit, bundle, commands, config,
+    fs_utils::{CopyOptions, SymlinkMode},
+    merge,
     paths::Paths,
-    ui::{ColorMode, Ui},
+    profiles,
+    redact::Redactor,
+    render::EnvShell,
+    state::{OPERATION_LOCK_TIMEOUT, OperationLock, State, lock_timeout},
+    switch::{self, BackupOptions, LinkStyle, SwitchOptions, SwitchStrategy},
+    ui::{Aborted, ColorMode, OutputFormat, Ui, resolve_pager},
 };
 
 #[derive(Parser)]
 #[command(name = "ccprof")]
 #[command(about = "Claude Code Profile Switcher - manage multiple user settings profiles")]
 #[command(version)]
+#[command(after_help = "Environment:
+  CCPROF_HOME            Profiles, backups and state directory (default: ~/.claude-profiles)
+  CCPROF_CLAUDE_DIR      Claude Code config directory to manage (default: ~/.claude)
+  CLAUDE_CONFIG_DIR      Used when CCPROF_CLAUDE_DIR is unset, as Claude Code does
+  CCPROF_LOCK_TIMEOUT    Seconds to wait for another ccprof process
+  CCPROF_CLAUDE_BIN      Claude Code binary used to record its version (default: claude)
+  CCPROF_LOG_FILE        Append a log of every change ccprof makes to this file
+  CCPROF_PRESERVE_OWNER  Keep file owners when copying (requires root)
+  CCPROF_MAX_DEPTH       Directory levels copies and sizes go down (default: 128)
+  PAGER                  Pager for long output (default: less -FRX)
+  NO_COLOR               Disable colors
+  FORCE_COLOR            Enable colors even when output is piped (also CLICOLOR_FORCE=1)
+
+Exit status:
+  1    Any other error
+  2    Profile not found or ambiguous (or invalid command-line usage)
+  3    Invalid input: bad profile name, invalid JSON, name already taken
+  130  Aborted at a prompt")]
 struct Cli {
     /// Disable colored output
     #[arg(long, global = true)]
@@ -22,6 +48,46 @@ struct Cli {
     #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
     color: ColorMode,
 
+    /// Show full paths in tables instead of shortening them to fit the terminal
+    #[arg(long, global = true)]
+    full_paths: bool,
+
+    /// Never page long output
+    #[arg(long, global = true)]
+    no_pager: bool,
+
+    /// Output format: text, or ndjson for one JSON event per line
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "text")]
+    output: OutputFormat,
+
+    /// Copy what symlinks inside components point to, instead of the links
+    #[arg(long, global = true, conflicts_with = "skip_symlinks")]
+    dereference: bool,
+
+    /// Leave symlinks inside components out of copies, with a warning
+    #[arg(long, global = true)]
+    skip_symlinks: bool,
+
+    /// Stop on files that can't be read instead of leaving them out with a warning
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Print secret-looking values (API keys, tokens) instead of hiding them
+    #[arg(long, global = true)]
+    show_secrets: bool,
+
+    /// Require profile names exactly as they are, not in another case or shortened
+    #[arg(long, global = true)]
+    exact: bool,
+
+    /// Answer yes to every confirmation prompt (needed when stdin is not a terminal)
+    #[arg(long, short, global = true)]
+    yes: bool,
+
+    /// Print what a command would change (paths included) without changing anything
+    #[arg(long, global = true)]
+    dry_run: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -29,15 +95,74 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// List all available profiles
-    List,
+    List {
+        /// Sort order: name, or last-used (most recent first)
+        #[arg(long, value_name = "ORDER", default_value = "name")]
+        sort: profiles::ProfileSort,
+
+        /// Stable output for scripts: name, active|inactive and components, tab-separated
+        #[arg(long)]
+        porcelain: bool,
+    },
 
     /// Show the current/active profile and settings file status
-    Current,
+    Current {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Stable output for scripts: profile and status, tab-separated
+        #[arg(long, conflicts_with = "json")]
+        porcelain: bool,
+    },
+
+    /// Print the selected profile for a shell prompt (nothing when none is selected)
+    Prompt {
+        /// Single token for a starship custom module: the name, with '!' when out of sync
+        #[arg(long)]
+        starship: bool,
+
+        /// Print the starship.toml snippet for the custom module
+        #[arg(long)]
+        print_config: bool,
+    },
+
+    /// Show which components exist in ~/.claude and which profiles track them
+    Components {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show an overview: profiles, disk use, backups, the active profile and switches
+    Stats {
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Show detailed information about a profile
     Inspect {
         /// Name of the profile to inspect
         name: String,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Summarize what switching from the active profile to this one changes
+        #[arg(long, conflicts_with = "json")]
+        compare_active: bool,
+    },
+
+    /// Print the content digest of a profile or one of its components
+    Hash {
+        /// Name of the profile
+        name: String,
+
+        /// Hash only this component (settings, agents, hooks, commands, env)
+        #[arg(long, value_name = "COMPONENT")]
+        component: Option<String>,
     },
 
     /// Add a new profile
@@ -50,15 +175,124 @@ enum Commands {
         from_current: bool,
 
         /// Components to include (skip interactive selection)
-        /// Comma-separated list: settings,agents,hooks,commands
+        /// Comma-separated list: settings,agents,hooks,commands,env
         #[arg(long, value_delimiter = ',')]
         components: Option<Vec<String>>,
+
+        /// Seed the profile from a template instead of ~/.claude
+        #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["from_current", "components", "from_stdin"])]
+        template: Option<String>,
+
+        /// Read settings.json from stdin; --components adds others from ~/.claude
+        #[arg(long, conflicts_with = "from_current")]
+        from_stdin: bool,
+
+        /// Inherit settings from another profile; this profile's settings.json
+        /// only overrides keys (starts empty without --from-current or --template)
+        #[arg(long, value_name = "PROFILE")]
+        extends: Option<String>,
     },
 
     /// Switch to a profile (activate it)
     Use {
         /// Name of the profile to activate
         name: String,
+
+        /// How to put profile content in place: symlink or copy
+        /// (defaults to the strategy set with 'ccprof strategy')
+        #[arg(long, value_name = "STRATEGY")]
+        strategy: Option<SwitchStrategy>,
+
+        /// Write symlinks with absolute or relative targets
+        /// (defaults to the style set with 'ccprof strategy --link-style')
+        #[arg(long, value_name = "STYLE")]
+        link_style: Option<LinkStyle>,
+
+        /// Copy edits found in ~/.claude into the profile before switching
+        #[arg(long, conflicts_with = "yes")]
+        adopt_changes: bool,
+
+        /// Skip components missing from the profile instead of failing
+        #[arg(long)]
+        skip_missing: bool,
+
+        /// With --skip-missing, stop tracking the skipped components in the profile
+        #[arg(long, requires = "skip_missing")]
+        prune_metadata: bool,
+
+        /// Back up replaced content even if it matches the most recent backup
+        #[arg(long)]
+        force_backup: bool,
+
+        /// Fail if symlinks aren't permitted instead of copying (Windows without Developer Mode)
+        #[arg(long)]
+        no_fallback: bool,
+
+        /// Replace symlinks that point outside ccprof's directories (their content is backed up)
+        #[arg(long, short)]
+        force: bool,
+
+        /// Switch even if the profile is already fully active, e.g. to repair it
+        #[arg(long)]
+        always: bool,
+
+        /// Print nothing when the profile is already active
+        #[arg(long, short)]
+        quiet: bool,
+    },
+
+    /// Run a command with a profile active, then switch back (e.g. ccprof exec work -- claude)
+    Exec {
+        /// Profile to run the command with
+        profile: String,
+
+        /// Run even if ~/.claude holds content ccprof doesn't manage, which a
+        /// crash would leave only in the staging directory
+        #[arg(long, short)]
+        force: bool,
+
+        /// Command and arguments, after '--'
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Print shell exports that select a profile through CLAUDE_CONFIG_DIR, leaving ~/.claude alone
+    /// (eval "$(ccprof env work)")
+    Env {
+        /// Profile to render
+        profile: String,
+
+        /// Output syntax: sh, bash, zsh or fish (defaults to $SHELL)
+        #[arg(long)]
+        shell: Option<EnvShell>,
+    },
+
+    /// Show or set the default switch strategy (symlink or copy)
+    Strategy {
+        /// New default strategy; omit to show the current one
+        strategy: Option<SwitchStrategy>,
+
+        /// Default symlink style: absolute, or relative for home directories
+        /// mounted at different paths on different machines
+        #[arg(long, value_name = "STYLE")]
+        link_style: Option<LinkStyle>,
+    },
+
+    /// Write edits made to copied components back into their profile
+    ///
+    /// Only applies to components activated with the copy strategy.
+    Sync,
+
+    /// Track an individual file of a directory component (e.g. commands/deploy.md)
+    ///
+    /// The component's live directory is then composed on switch: tracked files
+    /// link into the profile, all other entries link into the shared base.
+    Track {
+        /// Name of the profile
+        name: String,
+
+        /// Path of the entry to track: <component>/<file>
+        path: String,
     },
 
     /// Open a profile's settings.json in your editor
@@ -66,29 +300,75 @@ enum Commands {
         /// Name of the profile to edit
         name: String,
 
-        /// Modify which components are tracked (comma-separated: settings,agents,hooks,commands)
+        /// Modify which components are tracked (comma-separated: settings,agents,hooks,commands,env)
         /// Omit value for interactive mode
         #[arg(long = "track", value_delimiter = ',', num_args = 0..)]
         track_components: Option<Vec<String>>,
 
-        /// Open a specific component (settings, agents, hooks, commands)
+        /// Open a specific component (settings, agents, hooks, commands, env)
         #[arg(long, short)]
         component: Option<String>,
 
         /// Open all managed components in editor
         #[arg(long)]
         all: bool,
+
+        /// Open the profile's NOTES.md (created if missing)
+        #[arg(long, conflicts_with_all = ["track_components", "component", "all"])]
+        notes: bool,
+
+        /// Edit settings.json through a copy saved only if it is valid JSON
+        /// (always done for the settings ~/.claude links to)
+        #[arg(long, conflicts_with_all = ["track_components", "all", "notes"])]
+        safe: bool,
+    },
+
+    /// Write metadata.json for legacy profiles that only have settings.json
+    Migrate,
+
+    /// Regenerate a profile's metadata.json from the files in it
+    RepairMetadata {
+        /// Profile whose metadata.json is missing or broken
+        name: String,
+    },
+
+    /// Move ccprof's data to $XDG_DATA_HOME/ccprof and config.toml to $XDG_CONFIG_HOME/ccprof
+    MigratePaths,
+
+    /// Find profiles with identical content
+    Dedupe {
+        /// Only report duplicate groups (the default)
+        #[arg(long, conflicts_with = "interactive")]
+        check: bool,
+
+        /// Offer to remove or archive duplicates (profiles in use are kept)
+        #[arg(long, short)]
+        interactive: bool,
     },
 
     /// Run diagnostics on the ccprof setup
-    Doctor,
+    Doctor {
+        /// Repair what can be repaired automatically (e.g. an interrupted switch
+        /// or legacy profiles)
+        #[arg(long)]
+        fix: bool,
+
+        /// Project directory to check for .claude overrides (default: current directory)
+        #[arg(long, value_name = "DIR")]
+        path: Option<PathBuf>,
+
+        /// Print only the summary
+        #[arg(long, short)]
+        quiet: bool,
+    },
 
     /// Remove a profile
     Remove {
         /// Name of the profile to remove
         name: String,
 
-        /// Skip confirmation prompt
+        /// Skip confirmation prompt (same as --yes), and remove the profile
+        /// even while it is open in an editor
         #[arg(long, short)]
         force: bool,
     },
@@ -100,6 +380,10 @@ enum Commands {
 
         /// New name for the profile
         new_name: String,
+
+        /// Rename even while the profile is open in an editor
+        #[arg(long)]
+        force: bool,
     },
 
     /// Compare two profiles
@@ -113,6 +397,115 @@ enum Commands {
         /// Component to compare (default: settings)
         #[arg(long, short, default_value = "settings")]
         component: String,
+
+        /// Compare settings after merging the profiles they extend
+        #[arg(long)]
+        resolved: bool,
+
+        /// Show a unified diff of each changed text file in a directory component
+        #[arg(long)]
+        show_content: bool,
+
+        /// Print nothing; exit with 1 if the profiles differ and 0 if not
+        #[arg(long, short)]
+        quiet: bool,
+
+        /// Leave out a settings key and everything under it, e.g. 'env.*'
+        /// (repeatable; adds to 'ignore' under [diff] in config.toml)
+        #[arg(long, value_name = "KEY")]
+        ignore_key: Vec<String>,
+    },
+
+    /// Merge two profiles' settings and components into a new profile
+    Merge {
+        /// Profile to start from
+        base: String,
+
+        /// Profile merged on top; its values win on conflicts
+        overlay: String,
+
+        /// Name of the new profile
+        #[arg(long, value_name = "NAME")]
+        into: String,
+
+        /// How arrays in both profiles combine: replace (overlay's wins) or union
+        #[arg(long, value_name = "MODE", default_value = "replace")]
+        arrays: merge::ArrayMerge,
+
+        /// Profile to copy a directory component from when both have it: base or overlay
+        #[arg(long, value_name = "SIDE")]
+        prefer: Option<merge::Side>,
+    },
+
+    /// Write profiles to a .tar.gz archive for another machine
+    Export {
+        /// Profile to export (the profiles it extends are included)
+        #[arg(required_unless_present = "all", conflicts_with = "all")]
+        name: Option<String>,
+
+        /// Export every profile
+        #[arg(long)]
+        all: bool,
+
+        /// Include the strategy, link style and last-used times from state.json
+        /// (not what ~/.claude holds on this machine)
+        #[arg(long)]
+        with_state: bool,
+
+        /// Archive to write (default: ccprof-<name>-<date>.tar.gz, or
+        /// ccprof-export-<date>.tar.gz with --all, in the current directory)
+        #[arg(long, short, value_name = "PATH")]
+        file: Option<PathBuf>,
+    },
+
+    /// Add the profiles in an archive written by 'ccprof export'
+    Import {
+        /// Archive to import
+        archive: PathBuf,
+
+        /// Keep profiles that already exist and import the others
+        #[arg(long, alias = "skip-existing", conflicts_with_all = ["overwrite", "rename_on_conflict"])]
+        skip: bool,
+
+        /// Replace profiles that already exist, after copying them to the archive directory
+        #[arg(long, conflicts_with = "rename_on_conflict")]
+        overwrite: bool,
+
+        /// Import profiles whose name is taken as <name>-imported-<n>
+        #[arg(long)]
+        rename_on_conflict: bool,
+    },
+
+    /// Create a profile from one in a git repository (shallow-cloned with git)
+    Clone {
+        /// Repository URL, or anything else 'git clone' accepts
+        url: String,
+
+        /// Name of the new profile (default: the last directory of --path, or the repository name)
+        #[arg(long, value_name = "PROFILE")]
+        name: Option<String>,
+
+        /// Directory in the repository holding the profile (default: its root)
+        #[arg(long, value_name = "SUBDIR")]
+        path: Option<String>,
+    },
+
+    /// Update a cloned profile from its repository, showing the changes first
+    Pull {
+        /// Name of the profile
+        name: String,
+    },
+
+    /// Pin a profile to a project with a .ccprof file
+    Local {
+        #[command(subcommand)]
+        action: LocalCommands,
+    },
+
+    /// Show or edit ~/.claude-profiles/config.toml
+    ConfigFile {
+        #[command(subcommand)]
+        action: ConfigFileCommands,
     },
 
     /// Generate shell completions
@@ -127,85 +520,638 @@ enum Commands {
         #[command(subcommand)]
         action: BackupCommands,
     },
+
+    /// Manage components shared by multiple profiles
+    Shared {
+        #[command(subcommand)]
+        action: SharedCommands,
+    },
+
+    /// Manage templates for new profiles
+    Template {
+        #[command(subcommand)]
+        action: TemplateCommands,
+    },
+}
+
+impl Commands {
+    /// Whether long output may be shown through a pager
+    ///
+    /// Only read-only commands are paged; spinners, prompts and editors need
+    /// the terminal to themselves.
+    fn is_pageable(&self) -> bool {
+        !self.is_mutating()
+            && !matches!(
+                self,
+                Self::Edit { .. }
+                    | Self::ConfigFile {
+                        action: ConfigFileCommands::Edit
+                    }
+                    | Self::Completions { .. }
+                    | Self::Prompt { .. }
+                    | Self::List {
+                        porcelain: true,
+                        ..
+                    }
+                    | Self::Current {
+                        porcelain: true,
+                        ..
+                    }
+            )
+    }
+
+    /// Whether the global `--dry-run` flag previews the command
+    ///
+    /// Read-only commands accept it too, since they change nothing anyway.
+    fn supports_dry_run(&self) -> bool {
+        match self {
+            Self::Use { .. } | Self::Remove { .. } | Self::Rename { .. } | Self::Merge { .. } => {
+                true
+            }
+            Self::Edit {
+                track_components, ..
+            } => track_components.is_some(),
+            Self::Backup { .. } => true,
+            _ => !self.is_mutating(),
+        }
+    }
+
+    /// Whether the command changes profiles, state or ~/.claude
+    ///
+    /// Mutating commands hold the operation lock for their whole run.
+    fn is_mutating(&self) -> bool {
+        match self {
+            Self::List { .. }
+            | Self::Current { .. }
+            | Self::Prompt { .. }
+            | Self::Components { .. }
+            | Self::Stats { .. }
+            | Self::Inspect { .. }
+            | Self::Hash { .. }
+            | Self::Diff { .. }
+            | Self::Export { .. }
+            | Self::ConfigFile { .. }
+            | Self::Completions { .. } => false,
+            Self::Edit {
+                track_components, ..
+            } => track_components.is_some(),
+            Self::Strategy {
+                strategy,
+                link_style,
+            } => strategy.is_some() || link_style.is_some(),
+            Self::Doctor { fix, .. } => *fix,
+            Self::Local { action } => matches!(action, LocalCommands::Check { auto: true }),
+            Self::Dedupe { interactive, .. } => *interactive,
+            Self::Backup { action } => !matches!(action, BackupCommands::List { .. }),
+            Self::Shared { action } => !matches!(action, SharedCommands::List),
+            Self::Template { action } => !matches!(action, TemplateCommands::List),
+            Self::Add { .. }
+            | Self::Use { .. }
+            | Self::Exec { .. }
+            | Self::Env { .. }
+            | Self::Sync
+            | Self::Migrate
+            | Self::RepairMetadata { .. }
+            | Self::MigratePaths
+            | Self::Track { .. }
+            | Self::Import { .. }
+            | Self::Clone { .. }
+            | Self::Pull { .. }
+            | Self::Merge { .. }
+            | Self::Remove { .. }
+            | Self::Rename { .. } => true,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum SharedCommands {
+    /// List shared items and the profiles referencing them
+    List,
+
+    /// Create a shared item from ~/.claude or from a profile
+    Create {
+        /// Name of the shared item (e.g. agents-common)
+        name: String,
+
+        /// Directory component to share (agents, hooks, commands)
+        #[arg(long, short)]
+        component: String,
+
+        /// Copy the content from this profile instead of ~/.claude
+        #[arg(long)]
+        from: Option<String>,
+    },
+
+    /// Make a profile reference a shared item
+    Link {
+        /// Name of the profile
+        profile: String,
+
+        /// Name of the shared item
+        name: String,
+
+        /// Component the shared item provides
+        #[arg(long, short)]
+        component: String,
+    },
+
+    /// Stop a profile referencing a shared item (it gets its own copy)
+    Unlink {
+        /// Name of the profile
+        profile: String,
+
+        /// Component to unlink
+        #[arg(long, short)]
+        component: String,
+    },
+
+    /// Remove a shared item (refused while still referenced)
+    Remove {
+        /// Name of the shared item
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigFileCommands {
+    /// Print the effective configuration, defaults included
+    Show,
+
+    /// Open config.toml in the editor, starting it from a commented template
+    Edit,
+}
+
+#[derive(Subcommand)]
+enum LocalCommands {
+    /// Write a .ccprof naming the profile in the repository root (or the current directory)
+    Set {
+        /// Profile the project uses
+        profile: String,
+    },
+
+    /// Compare the nearest .ccprof with the active profile (for a shell cd hook or direnv)
+    Check {
+        /// Switch to the project's profile instead of printing a reminder
+        #[arg(long)]
+        auto: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TemplateCommands {
+    /// List templates and the components they provide
+    List,
+
+    /// Save a copy of a profile as a template
+    Save {
+        /// Profile to copy
+        profile: String,
+
+        /// Name of the template
+        name: String,
+    },
 }
 
 #[derive(Subcommand)]
 enum BackupCommands {
     /// List all backups
-    List,
+    List {
+        /// Only backups of this component (settings, agents, hooks, commands, env)
+        #[arg(long, value_name = "COMPONENT")]
+        component: Option<String>,
+
+        /// Output as JSON
+        #[arg(long)]
+        json: bool,
+    },
 
     /// Restore a backup
     Restore {
         /// Backup identifier (use 'ccprof backup list' to see available backups)
         id: String,
+
+        /// Write the backup here instead of over the live component (no prompt
+        /// unless the path is inside ~/.claude or ccprof's directory)
+        #[arg(long, value_name = "PATH")]
+        to: Option<PathBuf>,
     },
 
     /// Clean old backups
     Clean {
-        /// Number of backups to keep per component
-        #[arg(long, default_value = "5")]
-        keep: usize,
+        /// Number of backups to keep per component (default: backups.keep_per_component in config.toml)
+        #[arg(long)]
+        keep: Option<usize>,
     },
 }
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let paths = Paths::new()?;
-    let ui = Ui::new(cli.color, cli.no_color);
+    let paths = Paths::from_env()?;
+    // Runs on every shell prompt: skip the config, lock and journal checks
+    if let Commands::Prompt {
+        starship,
+        print_config,
+    } = cli.command
+    {
+        let ui = Ui::new(cli.color, cli.no_color).with_output(cli.output);
+        return commands::prompt(&paths, &ui, starship, print_config);
+    }
+    let (config, config_warnings) = config::load(&paths.config_file);
+    let pager = cli.command.is_pageable().then(|| {
+        resolve_pager(
+            cli.no_pager || !config.paging,
+            config.pager.as_deref(),
+            std::env::var("PAGER").ok(),
+        )
+    });
+    let ui = Ui::new(cli.color, cli.no_color)
+        .with_full_paths(cli.full_paths)
+        .with_output(cli.output)
+        .with_theme(config.theme.clone())
+        .with_pager(pager.flatten())
+        .with_assume_yes(cli.yes || config.assume_yes)
+        .with_exact_names(cli.exact)
+        .with_redactor(if cli.show_secrets {
+            Redactor::disabled()
+        } else {
+            config.redact.clone()
+        });
+    for warning in config_warnings {
+        ui.warn(warning);
+    }
+    let copy = CopyOptions {
+        symlinks: if cli.dereference {
+            SymlinkMode::Follow
+        } else if cli.skip_symlinks {
+            SymlinkMode::Skip
+        } else {
+            SymlinkMode::Preserve
+        },
+        strict: cli.strict,
+    };
+    let mut backup = BackupOptions {
+        keep: config.backups_keep,
+        copy,
+        ..BackupOptions::default()
+    };
+
+    // A journal left behind means a switch was killed half-way
+    if paths.journal_file.exists() && !matches!(cli.command, Commands::Doctor { .. }) {
+        ui.err("A profile switch was interrupted; run 'ccprof doctor --fix' to restore ~/.claude.");
+    }
+
+    // Switching to the profile that is fully active would change nothing, so
+    // it skips the lock and backups; any switch option asks for the full switch
+    let already_active = match &cli.command {
+        Commands::Use {
+            name,
+            strategy: None,
+            link_style: None,
+            adopt_changes: false,
+            skip_missing: false,
+            force_backup: false,
+            no_fallback: false,
+            force: false,
+            always: false,
+            ..
+        } => profiles::resolve_name(&paths, name, cli.exact)
+            .ok()
+            .filter(|name| switch::is_fully_active(&paths, name)),
+        _ => None,
+    };
+    if cli.dry_run && !cli.command.supports_dry_run() {
+        anyhow::bail!(
+            "This command doesn't support --dry-run.\nHint: It previews use, remove, rename, merge, edit --track, backup restore and backup clean."
+        );
+    }
+    // A dry run changes nothing, so it needs neither the lock nor write access
+    let mutating = cli.command.is_mutating() && already_active.is_none() && !cli.dry_run;
+
+    // Fail once, up front, rather than on the first write
+    if mutating {
+        paths.ensure_writable()?;
+    } else if State::read(&paths.state_file).is_ok() && State::needs_migration(&paths.state_file) {
+        // Reading migrates the file wherever it can be written
+        ui.note(format!(
+            "{} uses an older format and is not writable, so it was read without migrating it.",
+            paths.state_file.display()
+        ));
+    }
 
-    match cli.command {
-        Commands::List => commands::list(&paths, &ui),
-        Commands::Current => commands::current(&paths, &ui),
-        Commands::Inspect { name } => commands::inspect(&paths, &name, &ui),
+    // Serialize mutating commands; released when main returns
+    let _lock = if mutating {
+        Some(commands::with_lock_spinner(&ui, |on_wait| {
+            OperationLock::acquire_with_timeout(
+                &paths.lock_file,
+                lock_timeout(OPERATION_LOCK_TIMEOUT),
+                on_wait,
+            )
+        })?)
+    } else {
+        None
+    };
+
+    if mutating {
+        if let Err(e) = audit::init_from_env() {
+            ui.warn(format!("{:#}", e));
+        }
+        let args: Vec<String> = std::env::args().collect();
+        audit::record(format!("command: {}", args.join(" ")));
+        backup.command = Some(args[1..].join(" "));
+    }
+
+    // Set by `exec` to the exit status of the command it ran, and by `diff`
+    let mut exit_code = 0;
+    // `diff` exits with 1 for "different", like diff(1), so its errors need another
+    let failure_code = if matches!(cli.command, Commands::Diff { .. }) {
+        2
+    } else {
+        1
+    };
+    let result = match cli.command {
+        Commands::List { sort, porcelain } => commands::list(&paths, &ui, sort, porcelain),
+        Commands::Current { json, porcelain } => commands::current(&paths, &ui, json, porcelain),
+        Commands::Prompt { .. } => unreachable!("handled before loading the config"),
+        Commands::Components { json } => commands::components(&paths, &ui, json),
+        Commands::Stats { json } => commands::stats(&paths, &ui, json),
+        Commands::Inspect {
+            name,
+            json,
+            compare_active,
+        } => commands::inspect(&paths, &name, &ui, json, compare_active, copy.symlinks),
+        Commands::Hash { name, component } => {
+            commands::hash(&paths, &name, component.as_deref(), &ui)
+        }
         Commands::Add {
             name,
             from_current,
             components,
+            template,
+            from_stdin,
+            extends,
+        } => {
+            let source = if let Some(template) = &template {
+                commands::ProfileSource::Template(template)
+            } else if from_stdin {
+                commands::ProfileSource::Stdin(components)
+            } else if from_current {
+                commands::ProfileSource::Current(components)
+            } else if extends.is_some() {
+                commands::ProfileSource::Empty
+            } else {
+                anyhow::bail!(
+                    "Specify where the profile comes from: --from-current, --from-stdin, --template <template> or --extends <profile>"
+                );
+            };
+            commands::add(&paths, &name, &ui, source, extends.as_deref(), copy)
+        }
+        Commands::Use {
+            name,
+            strategy,
+            link_style,
+            adopt_changes,
+            skip_missing,
+            prune_metadata,
+            force_backup,
+            no_fallback,
+            force,
+            quiet,
+            ..
         } => {
-            if !from_current {
-                anyhow::bail!("Currently only --from-current is supported for adding profiles");
+            if let Some(name) = already_active {
+                if !quiet {
+                    ui.ok(format!("Profile '{}' is already active", name));
+                }
+                Ok(())
+            } else {
+                let on_drift = if adopt_changes {
+                    commands::DriftAction::Adopt
+                } else if ui.assume_yes {
+                    commands::DriftAction::Proceed
+                } else {
+                    commands::DriftAction::Prompt
+                };
+                let options = SwitchOptions {
+                    strategy,
+                    link_style,
+                    skip_missing,
+                    prune_metadata,
+                    backup: BackupOptions {
+                        force: force_backup,
+                        ..backup.clone()
+                    },
+                    no_fallback,
+                    force,
+                };
+                commands::use_profile(&paths, &name, &ui, &options, on_drift, cli.dry_run)
             }
-            commands::add(&paths, &name, &ui, components)
         }
-        Commands::Use { name } => commands::use_profile(&paths, &name, &ui),
+        Commands::Exec {
+            profile,
+            force,
+            command,
+        } => commands::exec(&paths, &profile, &command, force, &ui).map(|code| {
+            exit_code = code;
+        }),
+        Commands::Env { profile, shell } => commands::env(&paths, &profile, shell, &ui),
+        Commands::Strategy {
+            strategy,
+            link_style,
+        } => commands::strategy(&paths, &ui, strategy, link_style),
+        Commands::Sync => commands::sync(&paths, &ui),
+        Commands::Track { name, path } => commands::track(&paths, &name, &path, &ui),
         Commands::Edit {
             name,
             track_components,
             component,
             all,
+            notes,
+            safe,
         } => {
-            if let Some(comps) = track_components {
+            if notes {
+                commands::edit_notes(&paths, &config, &name, &ui)
+            } else if let Some(comps) = track_components {
                 // Modify tracked components
-                commands::edit_components(&paths, &name, &ui, Some(comps))
+                commands::edit_components(&paths, &name, &ui, Some(comps), cli.dry_run)
             } else if all {
                 // Open all managed components
-                commands::edit_all_components(&paths, &name, &ui)
+                commands::edit_all_components(&paths, &config, &name, &ui)
             } else if let Some(comp) = component {
                 // Open specific component
-                commands::edit_component(&paths, &name, &comp, &ui)
+                commands::edit_component(&paths, &config, &name, &comp, &ui, safe)
             } else {
                 // Default: open settings.json
-                commands::edit(&paths, &name, &ui)
+                commands::edit(&paths, &config, &name, &ui, safe)
             }
         }
-        Commands::Doctor => commands::doctor(&paths, &ui),
-        Commands::Remove { name, force } => commands::remove(&paths, &name, &ui, force),
-        Commands::Rename { old_name, new_name } => {
-            commands::rename(&paths, &old_name, &new_name, &ui)
+        Commands::Migrate => commands::migrate(&paths, &ui),
+        Commands::RepairMetadata { name } => commands::repair_metadata(&paths, &name, &ui),
+        Commands::MigratePaths => commands::migrate_paths(&ui),
+        Commands::Dedupe { interactive, .. } => commands::dedupe(&paths, &ui, interactive),
+        Commands::Doctor { fix, path, quiet } => {
+            commands::doctor(&paths, &config, &ui, fix, quiet, path.as_deref())
+        }
+        Commands::Remove { name, force } => {
+            commands::remove(&paths, &name, &ui, force, cli.dry_run)
         }
+        Commands::Rename {
+            old_name,
+            new_name,
+            force,
+        } => commands::rename(&paths, &old_name, &new_name, force, cli.dry_run, &ui),
         Commands::Diff {
             profile1,
             profile2,
             component,
-        } => commands::diff(&paths, &profile1, &profile2, &component, &ui),
+            resolved,
+            show_content,
+            quiet,
+            ignore_key,
+        } => {
+            let options = commands::DiffOptions {
+                resolved,
+                show_content,
+                quiet,
+                ignore: config
+                    .diff_ignore
+                    .iter()
+                    .cloned()
+                    .chain(ignore_key)
+                    .collect(),
+            };
+            commands::diff(&paths, &profile1, &profile2, &component, &options, &ui)
+                .map(|differs| exit_code = i32::from(differs))
+        }
+        Commands::Merge {
+            base,
+            overlay,
+            into,
+            arrays,
+            prefer,
+        } => commands::merge(
+            &paths,
+            &base,
+            &overlay,
+            &into,
+            &merge::MergeOptions { arrays, prefer },
+            cli.dry_run,
+            &ui,
+        ),
+        Commands::Export {
+            name,
+            with_state,
+            file,
+            ..
+        } => commands::export(&paths, name.as_deref(), with_state, file.as_deref(), &ui),
+        Commands::Import {
+            archive,
+            skip,
+            overwrite,
+            rename_on_conflict,
+        } => {
+            let policy = if overwrite {
+                bundle::ConflictPolicy::Overwrite
+            } else if rename_on_conflict {
+                bundle::ConflictPolicy::Rename
+            } else if skip {
+                bundle::ConflictPolicy::Skip
+            } else {
+                bundle::ConflictPolicy::Fail
+            };
+            commands::import(&paths, &archive, policy, &ui)
+        }
+        Commands::Clone { url, name, path } => {
+            commands::clone(&paths, &url, name.as_deref(), path.as_deref(), &ui)
+        }
+        Commands::Pull { name } => commands::pull(&paths, &name, &ui),
+        Commands::Local { action } => match action {
+            LocalCommands::Set { profile } => commands::local_set(&paths, &profile, &ui),
+            LocalCommands::Check { auto } => commands::local_check(&paths, auto, &backup, &ui),
+        },
+        Commands::ConfigFile { action } => match action {
+            ConfigFileCommands::Show => commands::config_file_show(&paths, &config, &ui),
+            ConfigFileCommands::Edit => commands::config_file_edit(&paths, &config, &ui),
+        },
         Commands::Completions { shell } => {
             generate(shell, &mut Cli::command(), "ccprof", &mut io::stdout());
             Ok(())
         }
         Commands::Backup { action } => match action {
-            BackupCommands::List => commands::backup_list(&paths, &ui),
-            BackupCommands::Restore { id } => commands::backup_restore(&paths, &id, &ui),
-            BackupCommands::Clean { keep } => commands::backup_clean(&paths, keep, &ui),
+            BackupCommands::List { component, json } => {
+                commands::backup_list(&paths, &ui, component.as_deref(), json)
+            }
+            BackupCommands::Restore { id, to } => commands::backup_restore(
+                &paths,
+                &id,
+                to.as_deref(),
+                cli.dry_run,
+                copy.symlinks,
+                &ui,
+            ),
+            BackupCommands::Clean { keep } => {
+                let keep = keep.unwrap_or(config.backups_keep);
+                commands::backup_clean(&paths, keep, cli.dry_run, &ui)
+            }
         },
+        Commands::Shared { action } => match action {
+            SharedCommands::List => commands::shared_list(&paths, &ui),
+            SharedCommands::Create {
+                name,
+                component,
+                from,
+            } => commands::shared_create(&paths, &name, &component, from.as_deref(), &ui),
+            SharedCommands::Link {
+                profile,
+                name,
+                component,
+            } => commands::shared_link(&paths, &profile, &name, &component, &ui),
+            SharedCommands::Unlink { profile, component } => {
+                commands::shared_unlink(&paths, &profile, &component, &ui)
+            }
+            SharedCommands::Remove { name } => commands::shared_remove(&paths, &name, &ui),
+        },
+        Commands::Template { action } => match action {
+            TemplateCommands::List => commands::template_list(&paths, &ui),
+            TemplateCommands::Save { profile, name } => {
+                commands::template_save(&paths, &profile, &name, &ui)
+            }
+        },
+    };
+    if mutating {
+        audit::record_outcome(&result);
+    }
+    ui.finish_paging();
+    match &result {
+        Ok(()) => ui.event("done", serde_json::json!({"ok": true})),
+        Err(e) => ui.event(
+            "done",
+            serde_json::json!({"ok": false, "error": format!("{:#}", e)}),
+        ),
+    }
+    if result
+        .as_ref()
+        .is_err_and(|e| e.chain().any(|cause| cause.is::<Aborted>()))
+    {
+        ui.err("Aborted by user");
+        drop(_lock);
+        std::process::exit(130);
+    }
+    if let Some(code) = result
+        .as_ref()
+        .err()
+        .map(|e| Error::find(e).map_or(1, Error::exit_code))
+        .map(|code| if code == 1 { failure_code } else { code })
+        .filter(|&code| code != 1)
+    {
+        // What returning the error would print, with a more specific status
+        eprintln!("Error: {:?}", result.unwrap_err());
+        drop(_lock);
+        std::process::exit(code);
+    }
+    if exit_code != 0 && result.is_ok() {
+        drop(_lock);
+        std::process::exit(exit_code);
     }
+    result
 }
@@ -0,0 +1,429 @@
+//! Merging two profiles into a new one.
+//!
+//! `ccprof merge <base> <overlay> --into <name>` deep-merges the effective
+//! settings of both profiles (overlay wins on conflicts) and takes each
+//! directory component from whichever profile has it. [`plan_merge`] works out
+//! every decision without touching disk, so `--dry-run` can show them before
+//! [`apply_merge`] writes the new profile.
+
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs;
+
+use crate::components::{Component, ProfileMetadata};
+use crate::error::Error;
+use crate::fs_utils::{CopyReport, copy_dir_recursive, copy_file};
+use crate::inheritance::resolve_settings;
+use crate::paths::Paths;
+use crate::profiles::{ensure_profile_exists, remove_on_error, validate_profile_name};
+
+/// How arrays present in both settings documents are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayMerge {
+    /// The overlay's array replaces the base's
+    #[default]
+    Replace,
+    /// The base's items, then the overlay's items not already present
+    Union,
+}
+
+impl std::str::FromStr for ArrayMerge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "replace" => Ok(Self::Replace),
+            "union" => Ok(Self::Union),
+            _ => Err(format!(
+                "invalid array mode: {} (expected replace or union)",
+                s
+            )),
+        }
+    }
+}
+
+/// One of the two profiles being merged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Base,
+    Overlay,
+}
+
+impl std::str::FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "base" => Ok(Self::Base),
+            "overlay" => Ok(Self::Overlay),
+            _ => Err(format!("invalid side: {} (expected base or overlay)", s)),
+        }
+    }
+}
+
+/// Choices for [`plan_merge`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MergeOptions {
+    pub arrays: ArrayMerge,
+    /// Profile to take a directory component from when both have it
+    pub prefer: Option<Side>,
+}
+
+/// What happened to one settings key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Only the base has it
+    FromBase,
+    /// Only the overlay has it
+    FromOverlay,
+    /// Both have different values; the overlay's is kept
+    OverlayWins,
+    /// Both have different arrays, combined
+    Union,
+}
+
+/// A settings key the two profiles don't agree on
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyDecision {
+    /// Dotted path of the key
+    pub key: String,
+    pub base: Option<Value>,
+    pub overlay: Option<Value>,
+    pub decision: Decision,
+}
+
+/// Where the merged profile takes a directory component from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentDecision {
+    pub component: Component,
+    pub from: Side,
+    /// Both profiles have it and `prefer` chose
+    pub conflict: bool,
+}
+
+/// Everything [`apply_merge`] will do
+#[derive(Debug, Clone)]
+pub struct MergePlan {
+    pub base: String,
+    pub overlay: String,
+    /// Merged settings, or `None` if neither profile manages settings
+    pub settings: Option<Value>,
+    pub decisions: Vec<KeyDecision>,
+    pub components: Vec<ComponentDecision>,
+}
+
+/// Work out how `overlay` merges over `base`
+///
+/// Settings are the effective ones, after following `extends`, so the merged
+/// profile stands on its own. Fails if both profiles have a directory
+/// component and `options.prefer` doesn't say which to take.
+pub fn plan_merge(
+    paths: &Paths,
+    base: &str,
+    overlay: &str,
+    options: &MergeOptions,
+) -> Result<MergePlan> {
+    ensure_profile_exists(paths, base)?;
+    ensure_profile_exists(paths, overlay)?;
+    let base_meta = ProfileMetadata::read(&paths.profile_dir(base))?;
+    let overlay_meta = ProfileMetadata::read(&paths.profile_dir(overlay))?;
+    let manages = |meta: &ProfileMetadata, c| meta.managed_components.contains(&c);
+
+    let settings_of = |name: &str, meta: &ProfileMetadata| -> Result<Option<Value>> {
+        if manages(meta, Component::Settings) {
+            resolve_settings(paths, name).map(Some)
+        } else {
+            Ok(None)
+        }
+    };
+    let base_settings = settings_of(base, &base_meta)?;
+    let overlay_settings = settings_of(overlay, &overlay_meta)?;
+    let mut decisions = Vec::new();
+    let settings = match (base_settings, overlay_settings) {
+        (None, None) => None,
+        (base, overlay) => {
+            let empty = || Value::Object(Default::default());
+            Some(merge_values(
+                &base.unwrap_or_else(empty),
+                &overlay.unwrap_or_else(empty),
+                "",
+                options.arrays,
+                &mut decisions,
+            ))
+        }
+    };
+
+    let mut components = Vec::new();
+    let mut conflicts = Vec::new();
+    for component in Component::all() {
+        if component == Component::Settings {
+            continue;
+        }
+        let from = match (
+            manages(&base_meta, component),
+            manages(&overlay_meta, component),
+        ) {
+            (false, false) => continue,
+            (true, false) => Side::Base,
+            (false, true) => Side::Overlay,
+            (true, true) => match options.prefer {
+                Some(side) => side,
+                None => {
+                    conflicts.push(component.short_name());
+                    continue;
+                }
+            },
+        };
+        components.push(ComponentDecision {
+            component,
+            from,
+            conflict: manages(&base_meta, component) && manages(&overlay_meta, component),
+        });
+    }
+    if !conflicts.is_empty() {
+        bail!(
+            "Both '{}' and '{}' have {}.\nHint: Pass --prefer base or --prefer overlay to choose which to copy.",
+            base,
+            overlay,
+            conflicts.join(", ")
+        );
+    }
+
+    Ok(MergePlan {
+        base: base.to_string(),
+        overlay: overlay.to_string(),
+        settings,
+        decisions,
+        components,
+    })
+}
+
+/// Create profile `into` from a plan, returning what copying its components
+/// reported
+pub fn apply_merge(paths: &Paths, plan: &MergePlan, into: &str) -> Result<CopyReport> {
+    validate_profile_name(into)?;
+    let profile_dir = paths.profile_dir(into);
+    if profile_dir.exists() {
+        return Err(Error::ProfileExists {
+            name: into.to_string(),
+        }
+        .into());
+    }
+
+    fs::create_dir_all(&profile_dir).with_context(|| {
+        format!(
+            "Failed to create profile directory: {}",
+            profile_dir.display()
+        )
+    })?;
+    crate::audit::record(format!("create {}", profile_dir.display()));
+    remove_on_error(&profile_dir, || {
+        let mut managed = HashSet::new();
+        let mut metadata = ProfileMetadata::new(HashSet::new());
+        let mut report = CopyReport::default();
+
+        if let Some(settings) = &plan.settings {
+            let path = paths.profile_settings(into);
+            fs::write(&path, serde_json::to_string_pretty(settings)?)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            managed.insert(Component::Settings);
+        }
+
+        for decision in &plan.components {
+            let source = match decision.from {
+                Side::Base => &plan.base,
+                Side::Overlay => &plan.overlay,
+            };
+            let source_meta = ProfileMetadata::read(&paths.profile_dir(source))?;
+            let component = decision.component;
+            // A shared reference stays a reference rather than becoming a copy
+            if let Some(source_ref) = source_meta.sources.get(&component) {
+                metadata.sources.insert(component, source_ref.clone());
+            } else {
+                let from = source_meta.component_path(paths, source, component);
+                if from.exists() {
+                    crate::audit::record(format!(
+                        "copy {} -> {}",
+                        from.display(),
+                        component.profile_path(paths, into).display()
+                    ));
+                    let to = component.profile_path(paths, into);
+                    if from.is_dir() {
+                        report.extend(copy_dir_recursive(&from, &to)?);
+                    } else {
+                        copy_file(&from, &to)?;
+                    }
+                }
+            }
+            if let Some(files) = source_meta.tracked_files.get(&component) {
+                metadata.tracked_files.insert(component, files.clone());
+            }
+            managed.insert(component);
+        }
+
+        metadata.managed_components = managed;
+        metadata.write(&profile_dir)?;
+        Ok(report)
+    })
+}
+
+/// Merge `overlay` over `base`, recording each key where they differ
+fn merge_values(
+    base: &Value,
+    overlay: &Value,
+    path: &str,
+    arrays: ArrayMerge,
+    decisions: &mut Vec<KeyDecision>,
+) -> Value {
+    let mut record = |key: String, decision, base: Option<&Value>, overlay: Option<&Value>| {
+        decisions.push(KeyDecision {
+            key,
+            base: base.cloned(),
+            overlay: overlay.cloned(),
+            decision,
+        })
+    };
+
+    match (base, overlay) {
+        (Value::Object(b), Value::Object(o)) => {
+            let child = |key: &str| {
+                if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                }
+            };
+            let mut merged = b.clone();
+            for (key, value) in o {
+                let value = match b.get(key) {
+                    Some(existing) => merge_values(existing, value, &child(key), arrays, decisions),
+                    None => {
+                        decisions.push(KeyDecision {
+                            key: child(key),
+                            base: None,
+                            overlay: Some(value.clone()),
+                            decision: Decision::FromOverlay,
+                        });
+                        value.clone()
+                    }
+                };
+                merged.insert(key.clone(), value);
+            }
+            for (key, value) in b.iter().filter(|(key, _)| !o.contains_key(*key)) {
+                decisions.push(KeyDecision {
+                    key: child(key),
+                    base: Some(value.clone()),
+                    overlay: None,
+                    decision: Decision::FromBase,
+                });
+            }
+            Value::Object(merged)
+        }
+        _ if base == overlay => overlay.clone(),
+        (Value::Array(b), Value::Array(o)) if arrays == ArrayMerge::Union => {
+            record(path.to_string(), Decision::Union, Some(base), Some(overlay));
+            let mut merged = b.clone();
+            merged.extend(o.iter().filter(|item| !b.contains(item)).cloned());
+            Value::Array(merged)
+        }
+        _ => {
+            record(
+                path.to_string(),
+                Decision::OverlayWins,
+                Some(base),
+                Some(overlay),
+            );
+            overlay.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::create_profile_with_components;
+    use crate::test_utils::setup_test_paths;
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    fn setup_profile(paths: &Paths, name: &str, settings: Value, agents: bool) {
+        fs::write(&paths.claude_settings, settings.to_string()).unwrap();
+        let mut components = HashSet::from([Component::Settings]);
+        if agents {
+            fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+            fs::write(paths.claude_dir.join("agents/helper.md"), name).unwrap();
+            components.insert(Component::Agents);
+        }
+        create_profile_with_components(paths, name, components).unwrap();
+    }
+
+    #[test]
+    fn test_merge_settings_and_components() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+
+        setup_profile(
+            &paths,
+            "base",
+            json!({"model": "sonnet", "permissions": {"allow": ["Read"]}, "env": {"A": "1"}}),
+            true,
+        );
+        setup_profile(
+            &paths,
+            "overlay",
+            json!({"model": "opus", "permissions": {"allow": ["Bash"]}, "statusLine": {}}),
+            true,
+        );
+
+        // Both have agents
+        let err = plan_merge(&paths, "base", "overlay", &MergeOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("--prefer"));
+
+        let options = MergeOptions {
+            arrays: ArrayMerge::Union,
+            prefer: Some(Side::Overlay),
+        };
+        let plan = plan_merge(&paths, "base", "overlay", &options).unwrap();
+        assert_eq!(
+            plan.settings,
+            Some(json!({
+                "model": "opus",
+                "permissions": {"allow": ["Read", "Bash"]},
+                "env": {"A": "1"},
+                "statusLine": {},
+            }))
+        );
+        let decided: Vec<_> = plan
+            .decisions
+            .iter()
+            .map(|d| (d.key.as_str(), d.decision))
+            .collect();
+        assert_eq!(
+            decided,
+            vec![
+                ("model", Decision::OverlayWins),
+                ("permissions.allow", Decision::Union),
+                ("statusLine", Decision::FromOverlay),
+                ("env", Decision::FromBase),
+            ]
+        );
+
+        assert_eq!(
+            apply_merge(&paths, &plan, "combined").unwrap(),
+            CopyReport::default()
+        );
+        let metadata = ProfileMetadata::read(&paths.profile_dir("combined")).unwrap();
+        assert_eq!(
+            metadata.managed_components,
+            HashSet::from([Component::Settings, Component::Agents])
+        );
+        assert_eq!(
+            fs::read_to_string(paths.profile_dir("combined").join("agents/helper.md")).unwrap(),
+            "overlay"
+        );
+        assert!(apply_merge(&paths, &plan, "combined").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Moving ccprof's data to the XDG layout.
+//!
+//! `ccprof migrate-paths` moves `~/.claude-profiles` to `$XDG_DATA_HOME/ccprof`
+//! and its `config.toml` to `$XDG_CONFIG_HOME/ccprof`, then leaves a symlink at
+//! the old location. The symlink keeps older ccprof versions and scripts
+//! working, and tells [`Paths`] to pick the XDG layout from then on. Symlinks
+//! in `~/.claude` and in rendered config directories are re-pointed, since
+//! ccprof only treats links into its current directory as its own.
+
+use anyhow::{Context, Result, bail};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::components::Component;
+use crate::fs_utils::{create_symlink, move_path, relative_path, remove_path, resolve_link_target};
+use crate::paths::Paths;
+
+/// What [`migrate_paths`] did
+#[derive(Debug, Default)]
+pub struct PathsMigration {
+    /// Where config.toml went, if there was one
+    pub config_file: Option<PathBuf>,
+    /// Symlinks re-pointed into the new directory
+    pub relinked: Vec<PathBuf>,
+}
+
+/// Move the data at `from` (home layout) to `to` (XDG layout)
+pub fn migrate_paths(from: &Paths, to: &Paths) -> Result<PathsMigration> {
+    if fs::symlink_metadata(&from.base_dir).is_ok_and(|m| m.file_type().is_symlink()) {
+        bail!(
+            "{} is already a symlink; the data was migrated before",
+            from.base_dir.display()
+        );
+    }
+    if !from.base_dir.is_dir() {
+        bail!(
+            "Nothing to migrate: {} does not exist",
+            from.base_dir.display()
+        );
+    }
+    if fs::read_dir(&to.base_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        bail!(
+            "{} already exists and is not empty\nHint: Move it aside or remove it first.",
+            to.base_dir.display()
+        );
+    }
+    if from.journal_file.exists() {
+        bail!("A switch was interrupted.\nHint: Run 'ccprof doctor --fix' to recover it first.");
+    }
+
+    let mut report = PathsMigration::default();
+    if let Some(parent) = to.base_dir.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+    }
+    remove_path(&to.base_dir)?;
+    move_path(&from.base_dir, &to.base_dir)?;
+
+    let moved_config = to.base_dir.join("config.toml");
+    if moved_config.exists() && moved_config != to.config_file {
+        if let Some(parent) = to.config_file.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+        }
+        move_path(&moved_config, &to.config_file)?;
+        report.config_file = Some(to.config_file.clone());
+    }
+
+    create_symlink(&to.base_dir, &from.base_dir).with_context(|| {
+        format!(
+            "Failed to create symlink from {} to {}",
+            from.base_dir.display(),
+            to.base_dir.display()
+        )
+    })?;
+
+    // ~/.claude, then every rendered directory standing in for it
+    let mut claude_dirs = vec![to.claude_dir.clone()];
+    if let Ok(entries) = fs::read_dir(&to.rendered_dir) {
+        claude_dirs.extend(entries.filter_map(|e| Some(e.ok()?.path())));
+    }
+    for claude_dir in claude_dirs {
+        let dir_paths = Paths::with_dirs(&to.base_dir, &claude_dir);
+        for component in Component::all() {
+            let live = component.source_path(&dir_paths);
+            let is_dir = fs::symlink_metadata(&live).is_ok_and(|m| m.is_dir());
+            // Composed directories hold one link per entry
+            let links = if is_dir {
+                fs::read_dir(&live)?
+                    .filter_map(|e| Some(e.ok()?.path()))
+                    .collect()
+            } else {
+                vec![live]
+            };
+            for link in links {
+                if retarget_link(&link, &from.base_dir, &to.base_dir)? {
+                    report.relinked.push(link);
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Point a symlink into `old` at the same entry under `new`, keeping a
+/// relative target relative; returns whether it was changed
+fn retarget_link(link: &Path, old: &Path, new: &Path) -> Result<bool> {
+    let Ok(raw) = fs::read_link(link) else {
+        return Ok(false);
+    };
+    let resolved = resolve_link_target(link, &raw);
+    let Ok(rest) = resolved.strip_prefix(old) else {
+        return Ok(false);
+    };
+    let mut target = new.join(rest);
+    if raw.is_relative()
+        && let Some(parent) = link.parent()
+    {
+        target = relative_path(parent, &target);
+    }
+
+    remove_path(link)?;
+    crate::audit::record(format!(
+        "symlink {} -> {}",
+        link.display(),
+        target.display()
+    ));
+    create_symlink(&target, link).with_context(|| {
+        format!(
+            "Failed to create symlink from {} to {}",
+            link.display(),
+            target.display()
+        )
+    })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::switch::{LinkStyle, SwitchOptions, switch_to_profile};
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_migrate_moves_data_and_relinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let claude_dir = temp_dir.path().join(".claude");
+        let from = Paths::with_dirs(&temp_dir.path().join(".claude-profiles"), &claude_dir);
+        let mut to = Paths::with_dirs(&temp_dir.path().join("share/ccprof"), &claude_dir);
+        to.config_file = temp_dir.path().join("config/ccprof/config.toml");
+
+        from.ensure_dirs().unwrap();
+        fs::create_dir_all(&claude_dir).unwrap();
+        fs::write(&from.claude_settings, "{}").unwrap();
+        fs::write(&from.config_file, "theme = \"plain\"\n").unwrap();
+        crate::profiles::create_profile_with_components(
+            &from,
+            "work",
+            HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        let options = SwitchOptions {
+            link_style: Some(LinkStyle::Relative),
+            ..Default::default()
+        };
+        switch_to_profile(&from, "work", &options).unwrap();
+
+        let report = migrate_paths(&from, &to).unwrap();
+        assert_eq!(
+            report.config_file.as_deref(),
+            Some(to.config_file.as_path())
+        );
+        assert_eq!(report.relinked, vec![to.claude_settings.clone()]);
+        assert!(to.config_file.exists());
+        assert!(to.profile_dir("work").join("metadata.json").exists());
+        assert_eq!(fs::read_link(&from.base_dir).unwrap(), to.base_dir);
+
+        let target = fs::read_link(&to.claude_settings).unwrap();
+        assert!(target.is_relative());
+        assert_eq!(
+            resolve_link_target(&to.claude_settings, &target),
+            to.profile_dir("work").join("settings.json")
+        );
+
+        let err = migrate_paths(&from, &to).unwrap_err();
+        assert!(err.to_string().contains("already a symlink"));
+    }
+}
@@ -6,10 +6,23 @@
 //!
 //! The `Paths` struct acts as a "single source of truth" for locations,
 //! ensuring consistency across the application.
+//!
+//! [`Paths::from_env`] lets environment variables relocate both roots:
+//! `CCPROF_HOME` replaces `~/.claude-profiles`, and `CCPROF_CLAUDE_DIR` (or
+//! Claude Code's own `CLAUDE_CONFIG_DIR`) replaces `~/.claude`. A
+//! `CLAUDE_CONFIG_DIR` set by `ccprof env` points at a rendered profile, not a
+//! Claude directory to manage, so it is ignored.
+//!
+//! With the XDG layout ([`Layout::Xdg`]), data lives in `$XDG_DATA_HOME/ccprof`
+//! and `config.toml` in `$XDG_CONFIG_HOME/ccprof`. It is chosen by
+//! `CCPROF_LAYOUT=xdg`, or automatically once `ccprof migrate-paths` has moved
+//! the data there and left a symlink at `~/.claude-profiles`.
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use directories::BaseDirs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::components::Component;
 
 /// All computed paths used by ccprof
 #[derive(Debug, Clone)]
@@ -20,8 +33,30 @@ pub struct Paths {
     pub profiles_dir: PathBuf,
     /// ~/.claude-profiles/backups
     pub backups_dir: PathBuf,
+    /// ~/.claude-profiles/base (shared content for composed directory components)
+    pub shared_base_dir: PathBuf,
+    /// ~/.claude-profiles/shared (components referenced by multiple profiles)
+    pub shared_dir: PathBuf,
+    /// ~/.claude-profiles/templates (seeds for new profiles)
+    pub templates_dir: PathBuf,
+    /// ~/.claude-profiles/archive (profiles set aside, e.g. by `dedupe`)
+    pub archive_dir: PathBuf,
+    /// ~/.claude-profiles/.staging (originals moved aside during a switch)
+    pub staging_dir: PathBuf,
+    /// ~/.claude-profiles/rendered (config directories for `ccprof env`)
+    pub rendered_dir: PathBuf,
     /// ~/.claude-profiles/state.json
     pub state_file: PathBuf,
+    /// ~/.claude-profiles/switch.journal (present only while a switch is in flight)
+    pub journal_file: PathBuf,
+    /// ~/.claude-profiles/.lock (held by mutating commands)
+    pub lock_file: PathBuf,
+    /// ~/.claude-profiles/config.toml (user preferences such as the UI theme)
+    pub config_file: PathBuf,
+    /// ~/.claude-profiles/active.env (the active profile's env.sh, for shells to source)
+    pub active_env_file: PathBuf,
+    /// Which of the two directory layouts these paths follow
+    pub layout: Layout,
 
     /// ~/.claude
     pub claude_dir: PathBuf,
@@ -29,23 +64,165 @@ pub struct Paths {
     pub claude_settings: PathBuf,
 }
 
+/// Overrides the ccprof base directory (~/.claude-profiles)
+pub const CCPROF_HOME_ENV: &str = "CCPROF_HOME";
+/// Overrides the Claude directory (~/.claude); takes precedence over [`CLAUDE_CONFIG_DIR_ENV`]
+pub const CCPROF_CLAUDE_DIR_ENV: &str = "CCPROF_CLAUDE_DIR";
+/// Claude Code's own override of its config directory
+pub const CLAUDE_CONFIG_DIR_ENV: &str = "CLAUDE_CONFIG_DIR";
+/// Chooses the directory layout when `CCPROF_HOME` isn't set: `home` or `xdg`
+pub const CCPROF_LAYOUT_ENV: &str = "CCPROF_LAYOUT";
+
+/// Where ccprof keeps its own files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// Everything in ~/.claude-profiles (or `CCPROF_HOME`)
+    #[default]
+    Home,
+    /// Data in `$XDG_DATA_HOME/ccprof`, config.toml in `$XDG_CONFIG_HOME/ccprof`
+    Xdg,
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Home => write!(f, "home"),
+            Self::Xdg => write!(f, "xdg"),
+        }
+    }
+}
+
+/// The layout to use: `CCPROF_LAYOUT` if set, otherwise XDG once the data has
+/// been migrated there (the old directory is gone or a symlink)
+fn choose_layout(setting: Option<&str>, home_base: &Path, xdg_base: &Path) -> Result<Layout> {
+    match setting {
+        Some("home") => Ok(Layout::Home),
+        Some("xdg") => Ok(Layout::Xdg),
+        Some(other) => bail!(
+            "Invalid {} '{}'\nHint: Use 'home' or 'xdg'.",
+            CCPROF_LAYOUT_ENV,
+            other
+        ),
+        None => {
+            let migrated = std::fs::symlink_metadata(home_base)
+                .map_or(true, |meta| meta.file_type().is_symlink());
+            if migrated && xdg_base.is_dir() {
+                Ok(Layout::Xdg)
+            } else {
+                Ok(Layout::Home)
+            }
+        }
+    }
+}
+
 impl Paths {
+    /// Default locations under the user's home directory
     pub fn new() -> Result<Self> {
-        let base_dirs = BaseDirs::new().context("Could not determine user base directories")?;
-        let home = base_dirs.home_dir();
+        Self::from_lookup(|_| None)
+    }
 
-        let base_dir = home.join(".claude-profiles");
-        let claude_dir = home.join(".claude");
+    /// Default locations, overridden by `CCPROF_HOME`, `CCPROF_CLAUDE_DIR`,
+    /// `CLAUDE_CONFIG_DIR` and `CCPROF_LAYOUT`
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|key| std::env::var_os(key).map(PathBuf::from))
+    }
 
-        Ok(Self {
+    /// Like [`Self::from_env`], but in `layout` whatever is set up on disk
+    ///
+    /// Fails if `CCPROF_HOME` is set, since it fixes the location.
+    pub fn from_env_with_layout(layout: Layout) -> Result<Self> {
+        if std::env::var_os(CCPROF_HOME_ENV).is_some_and(|v| !v.is_empty()) {
+            bail!(
+                "{} is set, so ccprof's location doesn't depend on the layout",
+                CCPROF_HOME_ENV
+            );
+        }
+        let name = layout.to_string();
+        Self::from_lookup(|key| match key {
+            CCPROF_LAYOUT_ENV => Some(PathBuf::from(&name)),
+            _ => std::env::var_os(key).map(PathBuf::from),
+        })
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<PathBuf>) -> Result<Self> {
+        let var = |key: &str| {
+            lookup(key)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| std::path::absolute(&p).with_context(|| format!("Invalid {}", key)))
+                .transpose()
+        };
+        let home = || -> Result<PathBuf> {
+            let base_dirs = BaseDirs::new().context("Could not determine user base directories")?;
+            Ok(base_dirs.home_dir().to_path_buf())
+        };
+
+        // XDG directories must be absolute; relative values are ignored
+        let xdg = |key: &str, default: &str| -> Result<PathBuf> {
+            match lookup(key).filter(|p| p.is_absolute()) {
+                Some(dir) => Ok(dir),
+                None => Ok(home()?.join(default)),
+            }
+        };
+
+        let mut config_file = None;
+        let (base_dir, layout) = match var(CCPROF_HOME_ENV)? {
+            Some(dir) => (dir, Layout::Home),
+            None => {
+                let home_base = home()?.join(".claude-profiles");
+                let xdg_base = xdg("XDG_DATA_HOME", ".local/share")?.join("ccprof");
+                let setting = lookup(CCPROF_LAYOUT_ENV)
+                    .map(|v| v.to_string_lossy().trim().to_lowercase())
+                    .filter(|v| !v.is_empty());
+                match choose_layout(setting.as_deref(), &home_base, &xdg_base)? {
+                    Layout::Home => (home_base, Layout::Home),
+                    Layout::Xdg => {
+                        config_file =
+                            Some(xdg("XDG_CONFIG_HOME", ".config")?.join("ccprof/config.toml"));
+                        (xdg_base, Layout::Xdg)
+                    }
+                }
+            }
+        };
+        let config_dir =
+            var(CLAUDE_CONFIG_DIR_ENV)?.filter(|dir| !dir.starts_with(base_dir.join("rendered")));
+        let claude_dir = match var(CCPROF_CLAUDE_DIR_ENV)?.or(config_dir) {
+            Some(dir) => dir,
+            None => home()?.join(".claude"),
+        };
+
+        let mut paths = Self::with_dirs(&base_dir, &claude_dir);
+        paths.layout = layout;
+        if let Some(config_file) = config_file {
+            paths.config_file = config_file;
+        }
+        Ok(paths)
+    }
+
+    /// Paths rooted at an explicit base directory and Claude directory
+    pub fn with_dirs(base_dir: &Path, claude_dir: &Path) -> Self {
+        let base_dir = base_dir.to_path_buf();
+        let claude_dir = claude_dir.to_path_buf();
+
+        Self {
             profiles_dir: base_dir.join("profiles"),
             backups_dir: base_dir.join("backups"),
+            shared_base_dir: base_dir.join("base"),
+            shared_dir: base_dir.join("shared"),
+            templates_dir: base_dir.join("templates"),
+            archive_dir: base_dir.join("archive"),
+            staging_dir: base_dir.join(".staging"),
+            rendered_dir: base_dir.join("rendered"),
             state_file: base_dir.join("state.json"),
+            journal_file: base_dir.join("switch.journal"),
+            lock_file: base_dir.join(".lock"),
+            config_file: base_dir.join("config.toml"),
+            active_env_file: base_dir.join("active.env"),
+            layout: Layout::Home,
             base_dir,
 
             claude_settings: claude_dir.join("settings.json"),
             claude_dir,
-        })
+        }
     }
 
     /// Ensure that the base directories exist
@@ -62,6 +239,48 @@ impl Paths {
         Ok(())
     }
 
+    /// Fail with [`Error::NotWritable`](crate::error::Error::NotWritable)
+    /// unless ccprof can write to its directory and the profiles in it
+    ///
+    /// A base directory that doesn't exist yet is checked at the nearest
+    /// parent that does. Errors other than a read-only file system or a
+    /// denied permission are left for the command itself to report.
+    pub fn ensure_writable(&self) -> crate::error::Result<()> {
+        let Some(existing) = self.base_dir.ancestors().find(|dir| dir.is_dir()) else {
+            return Ok(());
+        };
+        for dir in [existing, self.profiles_dir.as_path()] {
+            if !dir.is_dir() {
+                continue;
+            }
+            let probe = dir.join(format!(".ccprof-write-check-{}", std::process::id()));
+            let result = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&probe);
+            let reason = match result {
+                Ok(_) => {
+                    let _ = std::fs::remove_file(&probe);
+                    continue;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::ReadOnlyFilesystem => {
+                    "read-only file system"
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => "permission denied",
+                Err(_) => continue,
+            };
+            return Err(crate::error::Error::NotWritable {
+                path: if dir == existing {
+                    self.base_dir.clone()
+                } else {
+                    dir.to_path_buf()
+                },
+                reason,
+            });
+        }
+        Ok(())
+    }
+
     /// Get path to a specific profile directory
     pub fn profile_dir(&self, name: &str) -> PathBuf {
         self.profiles_dir.join(name)
@@ -72,11 +291,167 @@ impl Paths {
         self.profile_dir(name).join("settings.json")
     }
 
+    /// Get path to a profile's notes file
+    pub fn profile_notes(&self, name: &str) -> PathBuf {
+        self.profile_dir(name).join(crate::components::NOTES_FILE)
+    }
+
+    /// Get path to the shared base content for a composed component
+    pub fn shared_base(&self, component: &Component) -> PathBuf {
+        self.shared_base_dir.join(component.short_name())
+    }
+
+    /// Get path to the config directory `ccprof env` renders for a profile
+    pub fn rendered_profile_dir(&self, name: &str) -> PathBuf {
+        self.rendered_dir.join(name)
+    }
+
+    /// Get path to a shared component item
+    pub fn shared_item(&self, name: &str) -> PathBuf {
+        self.shared_dir.join(name)
+    }
+
+    /// Get path to a template directory
+    pub fn template_dir(&self, name: &str) -> PathBuf {
+        self.templates_dir.join(name)
+    }
+
+    /// Check if a path is a location ccprof links into (profiles, shared items, shared base)
+    pub fn is_managed_target(&self, path: &Path) -> bool {
+        self.is_in_profiles_dir(path)
+            || within(path, &self.shared_dir).is_some()
+            || within(path, &self.shared_base_dir).is_some()
+    }
+
     /// Check if a path is inside the profiles directory
-    pub fn is_in_profiles_dir(&self, path: &std::path::Path) -> bool {
-        // Canonicalize paths to resolve symlinks and absolute paths if possible
-        // But for symlinks we might be checking the target.
-        // Simple prefix check:
-        path.starts_with(&self.profiles_dir)
+    pub fn is_in_profiles_dir(&self, path: &Path) -> bool {
+        within(path, &self.profiles_dir).is_some()
+    }
+}
+
+/// `path` relative to `dir`, if it lies inside it
+///
+/// When the literal prefix doesn't match, both are compared with symlinks
+/// resolved, so a path reached through another name for the same directory
+/// (e.g. a `/home/me` automount of `/Users/me`) still counts. The last
+/// component of `path` is not followed: a link into `dir` that points
+/// elsewhere is still in `dir`.
+pub fn within(path: &Path, dir: &Path) -> Option<PathBuf> {
+    if let Ok(rest) = path.strip_prefix(dir) {
+        return Some(rest.to_path_buf());
+    }
+    let path = crate::fs_utils::canonicalize_existing(path, true);
+    let dir = crate::fs_utils::canonicalize_existing(dir, false);
+    path.strip_prefix(&dir).ok().map(Path::to_path_buf)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_overrides_and_precedence() {
+        let lookup = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| PathBuf::from(v))
+            }
+        };
+
+        let paths = Paths::from_lookup(lookup(&[
+            (CCPROF_HOME_ENV, "/data/profiles"),
+            (CLAUDE_CONFIG_DIR_ENV, "/data/claude"),
+        ]))
+        .unwrap();
+        assert_eq!(paths.base_dir, PathBuf::from("/data/profiles"));
+        assert_eq!(paths.state_file, PathBuf::from("/data/profiles/state.json"));
+        assert_eq!(
+            paths.claude_settings,
+            PathBuf::from("/data/claude/settings.json")
+        );
+
+        // CCPROF_CLAUDE_DIR wins over CLAUDE_CONFIG_DIR; empty values are ignored
+        let paths = Paths::from_lookup(lookup(&[
+            (CCPROF_HOME_ENV, ""),
+            (CCPROF_CLAUDE_DIR_ENV, "/ccprof/claude"),
+            (CLAUDE_CONFIG_DIR_ENV, "/data/claude"),
+        ]))
+        .unwrap();
+        assert_eq!(paths.claude_dir, PathBuf::from("/ccprof/claude"));
+        assert_eq!(paths.base_dir, Paths::new().unwrap().base_dir);
+
+        // A shell set up by `ccprof env` still manages the real ~/.claude
+        let paths = Paths::from_lookup(lookup(&[
+            (CCPROF_HOME_ENV, "/data/profiles"),
+            (CLAUDE_CONFIG_DIR_ENV, "/data/profiles/rendered/work"),
+        ]))
+        .unwrap();
+        assert_eq!(paths.claude_dir, Paths::new().unwrap().claude_dir);
+
+        let paths = Paths::from_lookup(lookup(&[
+            (CCPROF_LAYOUT_ENV, "XDG"),
+            ("XDG_DATA_HOME", "/xdg/data"),
+            ("XDG_CONFIG_HOME", "relative/is/ignored"),
+        ]))
+        .unwrap();
+        assert_eq!(paths.layout, Layout::Xdg);
+        assert_eq!(
+            paths.state_file,
+            PathBuf::from("/xdg/data/ccprof/state.json")
+        );
+        assert!(paths.config_file.ends_with(".config/ccprof/config.toml"));
+        assert!(Paths::from_lookup(lookup(&[(CCPROF_LAYOUT_ENV, "flat")])).is_err());
+    }
+
+    #[test]
+    fn test_layout_follows_migrated_data() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let home_base = temp_dir.path().join(".claude-profiles");
+        let xdg_base = temp_dir.path().join("share/ccprof");
+        let choose = |setting| choose_layout(setting, &home_base, &xdg_base).unwrap();
+        assert_eq!(choose(None), Layout::Home);
+
+        std::fs::create_dir_all(&home_base).unwrap();
+        std::fs::create_dir_all(&xdg_base).unwrap();
+        // Both exist: the old directory still holds the data
+        assert_eq!(choose(None), Layout::Home);
+        assert_eq!(choose(Some("xdg")), Layout::Xdg);
+
+        // `migrate-paths` leaves a symlink behind
+        std::fs::remove_dir(&home_base).unwrap();
+        crate::fs_utils::create_symlink(&xdg_base, &home_base).unwrap();
+        assert_eq!(choose(None), Layout::Xdg);
+        assert_eq!(choose(Some("home")), Layout::Home);
+    }
+
+    #[test]
+    fn test_profiles_dir_reached_through_another_name() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        // `alias` is another name for `real`, like an automounted home
+        let real = temp_dir.path().join("real");
+        let alias = temp_dir.path().join("alias");
+        std::fs::create_dir_all(real.join(".claude-profiles/profiles/work")).unwrap();
+        crate::fs_utils::create_symlink(&real, &alias).unwrap();
+
+        for (base, other) in [(&real, &alias), (&alias, &real)] {
+            let paths = Paths::with_dirs(&base.join(".claude-profiles"), &base.join(".claude"));
+            let settings = other.join(".claude-profiles/profiles/work/settings.json");
+            assert!(paths.is_in_profiles_dir(&settings));
+            // Targets that don't exist yet resolve through their existing ancestors
+            assert!(paths.is_in_profiles_dir(&other.join(".claude-profiles/profiles/new/x")));
+            assert_eq!(
+                within(&settings, &paths.profiles_dir),
+                Some(PathBuf::from("work/settings.json"))
+            );
+            assert!(!paths.is_in_profiles_dir(&other.join("elsewhere/settings.json")));
+        }
+
+        // A link inside the profiles dir counts even when it points out of it
+        let outside = temp_dir.path().join("outside.json");
+        std::fs::write(&outside, "{}").unwrap();
+        let link = real.join(".claude-profiles/profiles/work/settings.json");
+        crate::fs_utils::create_symlink(&outside, &link).unwrap();
+        let paths = Paths::with_dirs(&alias.join(".claude-profiles"), &alias.join(".claude"));
+        assert!(paths.is_in_profiles_dir(&link));
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,55 @@
+//! Changes a mutating command would make, for `--dry-run`.
+//!
+//! Commands that support the global `--dry-run` flag compute their
+//! [`Change`]s with the same checks the real operation runs, print them and
+//! stop before anything on disk or in state.json is touched.
+
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+
+/// One step of a planned operation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Change {
+    /// A file or directory deleted
+    Remove { path: PathBuf },
+    /// A file or directory renamed or moved
+    Move { from: PathBuf, to: PathBuf },
+    /// A symlink created, or re-pointed, at `link`
+    Link { link: PathBuf, target: PathBuf },
+    /// Content copied over, replacing what is at `to`
+    Copy { from: PathBuf, to: PathBuf },
+    /// A file ccprof writes itself (metadata.json, a backup)
+    Write { path: PathBuf },
+    /// An entry of state.json updated
+    State { detail: String },
+}
+
+impl Change {
+    /// The verb printed for the change
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Change::Remove { .. } => "remove",
+            Change::Move { .. } => "move",
+            Change::Link { .. } => "link",
+            Change::Copy { .. } => "copy",
+            Change::Write { .. } => "write",
+            Change::State { .. } => "state",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<6} ", self.verb())?;
+        match self {
+            Change::Remove { path } | Change::Write { path } => write!(f, "{}", path.display()),
+            Change::Move { from, to } | Change::Copy { from, to } => {
+                write!(f, "{} → {}", from.display(), to.display())
+            }
+            Change::Link { link, target } => write!(f, "{} → {}", link.display(), target.display()),
+            Change::State { detail } => write!(f, "{}", detail),
+        }
+    }
+}
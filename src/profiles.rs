@@ -9,20 +9,28 @@
 //! 
 //! It interacts directly with the filesystem to manage the `~/.claude-profiles/profiles/` directory.
 
-use anyhow::{Context, Result, bail};
-use chrono::Utc;
+use anyhow::{Context, anyhow};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use std::collections::HashSet;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::components::{Component, ProfileMetadata};
+use crate::error::{Error, Result};
+use crate::fs_utils::{
+    CopyOptions, CopyReport, DirStats, SymlinkMode, copy_dir_lenient_with_progress,
+    copy_dir_recursive,
+};
 use crate::paths::Paths;
-use crate::fs_utils::copy_dir_recursive;
+use crate::plan::Change;
+use crate::settings_schema::edit_distance;
+use crate::state::{LockedState, State};
 
 /// List available profiles
+///
+/// Nothing is created, so this works on a read-only file system too.
 pub fn list_profiles(paths: &Paths) -> Result<Vec<String>> {
-    paths.ensure_dirs()?;
-
     let mut profiles = Vec::new();
     if paths.profiles_dir.exists() {
         for entry in fs::read_dir(&paths.profiles_dir)? {
@@ -40,59 +48,502 @@ pub fn list_profiles(paths: &Paths) -> Result<Vec<String>> {
     Ok(profiles)
 }
 
+/// What [`repair_metadata`] wrote into a profile's metadata.json
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataRepair {
+    /// Components the profile now manages: those in its directory, plus any
+    /// the old file took from a shared item
+    pub components: Vec<Component>,
+    pub created_at: DateTime<Utc>,
+    /// `created_at` came from the old file, not the directory's mtime
+    pub created_at_kept: bool,
+    /// Other fields recovered from the old file
+    pub salvaged: Vec<&'static str>,
+}
+
+/// Regenerate a missing or unreadable metadata.json from the profile directory
+///
+/// Returns `None`, writing nothing, when the existing file reads fine. Fields
+/// of the old file that still parse one by one are kept; components are
+/// inferred from which of settings.json, agents/, hooks/ and commands/ exist.
+pub fn repair_metadata(paths: &Paths, name: &str) -> Result<Option<MetadataRepair>> {
+    ensure_profile_exists(paths, name)?;
+    let profile_dir = paths.profile_dir(name);
+    let path = profile_dir.join("metadata.json");
+    if path.exists() && ProfileMetadata::read(&profile_dir).is_ok() {
+        return Ok(None);
+    }
+
+    let old: Option<serde_json::Value> = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok());
+    let mut salvaged = Vec::new();
+    let mut metadata = ProfileMetadata::new(HashSet::new());
+    metadata.sources = salvage(old.as_ref(), "sources", &mut salvaged).unwrap_or_default();
+    metadata.tracked_files =
+        salvage(old.as_ref(), "tracked_files", &mut salvaged).unwrap_or_default();
+    metadata.extends = salvage(old.as_ref(), "extends", &mut salvaged);
+    metadata.claude_version = salvage(old.as_ref(), "claude_version", &mut salvaged);
+    metadata.upstream = salvage(old.as_ref(), "upstream", &mut salvaged);
+
+    let components: Vec<Component> = Component::all()
+        .into_iter()
+        .filter(|c| metadata.sources.contains_key(c) || c.profile_path(paths, name).exists())
+        .collect();
+    metadata.managed_components = components.iter().copied().collect();
+
+    let kept_created_at: Option<DateTime<Utc>> =
+        salvage(old.as_ref(), "created_at", &mut Vec::new());
+    let created_at_kept = kept_created_at.is_some();
+    metadata.created_at = match kept_created_at {
+        Some(created_at) => created_at,
+        None => fs::metadata(&profile_dir)
+            .and_then(|m| m.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or(metadata.created_at),
+    };
+
+    // Written beside the old file and renamed over it
+    let temp = path.with_extension("json.tmp");
+    metadata.write_to(&temp)?;
+    crate::audit::record(format!("write {}", path.display()));
+    fs::rename(&temp, &path).with_context(|| format!("Failed to replace {}", path.display()))?;
+
+    Ok(Some(MetadataRepair {
+        components,
+        created_at: metadata.created_at,
+        created_at_kept,
+        salvaged,
+    }))
+}
+
+/// Field `key` of an unreadable metadata.json, if it parses on its own;
+/// recorded in `salvaged` unless it is empty
+fn salvage<T: serde::de::DeserializeOwned>(
+    old: Option<&serde_json::Value>,
+    key: &'static str,
+    salvaged: &mut Vec<&'static str>,
+) -> Option<T> {
+    let value = old?.get(key)?;
+    let parsed = serde_json::from_value(value.clone()).ok()?;
+    let empty = value.is_null() || value.as_object().is_some_and(|o| o.is_empty());
+    if !empty {
+        salvaged.push(key);
+    }
+    Some(parsed)
+}
+
+/// Profiles without metadata.json, which `migrate_legacy_profiles` upgrades
+pub fn legacy_profiles(paths: &Paths) -> Result<Vec<String>> {
+    Ok(list_profiles(paths)?
+        .into_iter()
+        .filter(|name| ProfileMetadata::is_legacy(&paths.profile_dir(name)))
+        .collect())
+}
+
+/// Order of the profiles returned by [`list_profile_entries`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProfileSort {
+    /// Alphabetical
+    #[default]
+    Name,
+    /// Most recently used first, never-used profiles last
+    LastUsed,
+}
+
+impl std::str::FromStr for ProfileSort {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "last-used" => Ok(Self::LastUsed),
+            _ => Err(format!(
+                "invalid sort order: {} (expected name or last-used)",
+                s
+            )),
+        }
+    }
+}
+
+/// A profile as `ccprof list` shows it
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileListEntry {
+    pub name: String,
+    /// Managed components, sorted by short name; `None` if the metadata can't be read
+    pub components: Option<Vec<Component>>,
+    /// No metadata.json yet (see [`migrate_legacy_profiles`])
+    pub legacy: bool,
+    /// metadata.json was written by a migration
+    pub migrated: bool,
+    pub last_used: Option<DateTime<Utc>>,
+    /// Selected in state.json
+    pub active: bool,
+}
+
+/// Every profile with what `ccprof list` shows about it, in `sort` order
+pub fn list_profile_entries(paths: &Paths, sort: ProfileSort) -> Result<Vec<ProfileListEntry>> {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let mut entries: Vec<_> = list_profiles(paths)?
+        .into_iter()
+        .map(|name| {
+            let dir = paths.profile_dir(&name);
+            let metadata = ProfileMetadata::read(&dir).ok();
+            let components = metadata.as_ref().map(|m| {
+                let mut components: Vec<_> = m.managed_components.iter().copied().collect();
+                components.sort_by_key(|c| c.short_name());
+                components
+            });
+            ProfileListEntry {
+                components,
+                legacy: ProfileMetadata::is_legacy(&dir),
+                migrated: metadata.is_some_and(|m| m.migration.is_some()),
+                last_used: state.last_used.get(&name).copied(),
+                active: state.default_profile.as_deref() == Some(name.as_str()),
+                name,
+            }
+        })
+        .collect();
+    if sort == ProfileSort::LastUsed {
+        entries.sort_by_key(|e| std::cmp::Reverse(e.last_used));
+    }
+    Ok(entries)
+}
+
+/// A profile's details as `ccprof inspect` shows them
+#[derive(Debug, Serialize)]
+pub struct ProfileInspection {
+    pub name: String,
+    pub metadata: ProfileMetadata,
+    pub last_used: Option<DateTime<Utc>>,
+    /// Profiles it extends, nearest first
+    pub extends: Vec<String>,
+    /// Why the `extends` chain can't be resolved
+    pub extends_error: Option<String>,
+    /// Profiles extending this one
+    pub extended_by: Vec<String>,
+    /// Managed components, in [`Component::all`] order
+    pub components: Vec<ComponentInspection>,
+    /// NOTES.md, trimmed; `None` if missing or blank
+    pub notes: Option<String>,
+}
+
+/// A managed component of a [`ProfileInspection`]
+#[derive(Debug, Serialize)]
+pub struct ComponentInspection {
+    pub component: Component,
+    /// Where the content lives, in the shared item for shared components
+    pub path: PathBuf,
+    /// Name of the shared item referenced
+    pub shared: Option<String>,
+    /// Size in bytes; `None` if the path is missing
+    pub bytes: Option<u64>,
+    /// Counts and largest files of a directory component
+    pub contents: Option<DirStats>,
+}
+
+/// Gather what `ccprof inspect` shows about a profile
+///
+/// Directory components are walked, listing up to `largest` of their biggest
+/// files and treating symlinks inside as `symlinks` says.
+pub fn inspect_profile(
+    paths: &Paths,
+    name: &str,
+    largest: usize,
+    symlinks: SymlinkMode,
+) -> Result<ProfileInspection> {
+    ensure_profile_exists(paths, name)?;
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+
+    let components = Component::all()
+        .into_iter()
+        .filter(|c| metadata.managed_components.contains(c))
+        .map(|component| {
+            let path = metadata.component_path(paths, name, component);
+            let contents = if path.is_dir() {
+                Some(crate::fs_utils::dir_stats(&path, largest, symlinks)?)
+            } else {
+                None
+            };
+            let bytes = match &contents {
+                Some(stats) => Some(stats.bytes),
+                None => fs::metadata(&path).ok().map(|m| m.len()),
+            };
+            Ok(ComponentInspection {
+                component,
+                shared: metadata.shared_source(component).map(str::to_string),
+                path,
+                bytes,
+                contents,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let (extends, extends_error) = match metadata.extends {
+        Some(_) => match crate::inheritance::inheritance_chain(paths, name) {
+            Ok(chain) => (chain[1..].to_vec(), None),
+            Err(e) => (Vec::new(), Some(e.to_string())),
+        },
+        None => (Vec::new(), None),
+    };
+    let state = State::read(&paths.state_file).unwrap_or_default();
+
+    Ok(ProfileInspection {
+        name: name.to_string(),
+        last_used: state.last_used.get(name).copied(),
+        extends,
+        extends_error,
+        extended_by: crate::inheritance::children_of(paths, name)?,
+        components,
+        notes: fs::read_to_string(paths.profile_notes(name))
+            .ok()
+            .map(|notes| notes.trim().to_string())
+            .filter(|notes| !notes.is_empty()),
+        metadata,
+    })
+}
+
+/// Write metadata.json for every legacy profile, returning the migrated names
+pub fn migrate_legacy_profiles(paths: &Paths) -> Result<Vec<String>> {
+    let mut migrated = Vec::new();
+    for name in legacy_profiles(paths)? {
+        let dir = paths.profile_dir(&name);
+        ProfileMetadata::migrate_legacy(&dir)
+            .with_context(|| format!("Failed to migrate profile '{}'", name))?;
+        migrated.push(name);
+    }
+    Ok(migrated)
+}
+
 /// Check if a profile exists
 pub fn profile_exists(paths: &Paths, name: &str) -> bool {
     paths.profile_dir(name).exists()
 }
 
-/// Validate profile name
+/// Fail with [`Error::ProfileNotFound`] unless the profile exists
+pub fn ensure_profile_exists(paths: &Paths, name: &str) -> Result<()> {
+    if !profile_exists(paths, name) {
+        return Err(not_found(name, &list_profiles(paths).unwrap_or_default()));
+    }
+    Ok(())
+}
+
+/// The profile `input` names, ignoring case, or the only profile starting with it
 ///
-/// Only allows alphanumeric characters, underscores, and hyphens.
-pub fn validate_profile_name(name: &str) -> Result<()> {
-    if name.is_empty() {
-        bail!("Profile name cannot be empty");
+/// A profile named exactly `input` always wins. Several matches fail with
+/// [`Error::AmbiguousName`], none with [`Error::ProfileNotFound`] suggesting a
+/// close name. The listed names are returned rather than `input`, since a
+/// case-insensitive filesystem would find `Work/` for `work/` too. With
+/// `exact` (from --exact), only the name as typed is accepted, for scripts.
+pub fn resolve_name(paths: &Paths, input: &str, exact: bool) -> Result<String> {
+    let profiles = list_profiles(paths)?;
+    if profiles.iter().any(|p| p == input) {
+        return Ok(input.to_string());
     }
+    if !exact {
+        let lower = input.to_lowercase();
+        let same: Vec<&String> = profiles
+            .iter()
+            .filter(|p| p.to_lowercase() == lower)
+            .collect();
+        let candidates = if same.is_empty() {
+            prefix_matches(input, &profiles)
+        } else {
+            same
+        };
+        match candidates.as_slice() {
+            [] => {}
+            [only] => return Ok(only.to_string()),
+            _ => {
+                return Err(Error::AmbiguousName {
+                    name: input.to_string(),
+                    candidates: candidates.into_iter().cloned().collect(),
+                });
+            }
+        }
+    }
+    Err(not_found(input, &profiles))
+}
 
-    if name.chars().count() > 64 {
-        bail!("Profile name cannot be longer than 64 characters");
+fn not_found(name: &str, profiles: &[String]) -> Error {
+    Error::ProfileNotFound {
+        name: name.to_string(),
+        suggestion: suggest_name(name, profiles),
     }
+}
 
-    // Allow a-z, A-Z, 0-9, -, _
-    if !name
+/// The `profiles` starting with `input`, ignoring case
+fn prefix_matches<'a>(input: &str, profiles: &'a [String]) -> Vec<&'a String> {
+    if input.is_empty() {
+        return Vec::new();
+    }
+    let lower = input.to_lowercase();
+    profiles
+        .iter()
+        .filter(|p| p.to_lowercase().starts_with(&lower))
+        .collect()
+}
+
+/// The profile `input` was most likely meant to be: the closest within two
+/// edits, or else the only one it is a prefix of
+fn suggest_name(input: &str, profiles: &[String]) -> Option<String> {
+    let input_lower = input.to_lowercase();
+    profiles
+        .iter()
+        .map(|p| (edit_distance(&input_lower, &p.to_lowercase()), p))
+        .filter(|&(distance, _)| distance <= 2)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, p)| p)
+        .or_else(|| match prefix_matches(input, profiles).as_slice() {
+            [only] => Some(*only),
+            _ => None,
+        })
+        .cloned()
+}
+
+/// Validate profile name
+///
+/// Only allows alphanumeric characters, underscores, and hyphens.
+pub fn validate_profile_name(name: &str) -> Result<()> {
+    let reason = if name.is_empty() {
+        "it cannot be empty"
+    } else if name.chars().count() > 64 {
+        "it cannot be longer than 64 characters"
+    } else if !name
         .chars()
         .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
     {
-        bail!(
-            "Invalid profile name '{}'.\n\n Only alphanumeric characters, hyphens (-), and underscores (_) are allowed.",
-            name
-        );
-    }
-
-    Ok(())
+        // Allow a-z, A-Z, 0-9, -, _
+        "only alphanumeric characters, hyphens (-), and underscores (_) are allowed"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidName {
+        name: name.to_string(),
+        reason,
+    })
 }
 
 /// Create a new profile with specific components
+///
+/// Unreadable files inside directory components are left out; the returned
+/// warnings name them (see [`copy_dir_lenient`](crate::fs_utils::copy_dir_lenient)).
 pub fn create_profile_with_components(
     paths: &Paths,
     name: &str,
     components: HashSet<Component>,
-) -> Result<()> {
+) -> Result<Vec<String>> {
+    create_profile_with_components_with_progress(
+        paths,
+        name,
+        components,
+        CopyOptions::default(),
+        &mut |_| {},
+    )
+    .map(CopyReport::into_warnings)
+}
+
+/// [`create_profile_with_components`], copying directories as `copy` says and
+/// calling `on_file` with the size of each file copied
+///
+/// Returns what the copies reported.
+pub fn create_profile_with_components_with_progress(
+    paths: &Paths,
+    name: &str,
+    components: HashSet<Component>,
+    copy: CopyOptions,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    validate_profile_name(name)?;
+    let profile_dir = paths.profile_dir(name);
+
+    if profile_dir.exists() {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        });
+    }
+
+    fs::create_dir_all(&profile_dir)
+        .with_context(|| format!("Failed to create profile directory: {}", profile_dir.display()))?;
+    crate::audit::record(format!("create {}", profile_dir.display()));
+
+    remove_on_error(&profile_dir, || {
+        let report = copy_components_into(paths, name, &components, copy, on_file)?;
+        ProfileMetadata::new(components.clone()).write(&profile_dir)?;
+        Ok(report)
+    })
+}
+
+/// Create a profile whose settings.json holds `settings`, copying the other
+/// `components` from ~/.claude
+///
+/// `settings` is written as given, so the caller validates it. Returns the
+/// managed components and what the copies reported.
+pub fn create_profile_from_settings(
+    paths: &Paths,
+    name: &str,
+    settings: &str,
+    mut components: HashSet<Component>,
+    copy: CopyOptions,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<(HashSet<Component>, CopyReport)> {
     validate_profile_name(name)?;
     let profile_dir = paths.profile_dir(name);
 
     if profile_dir.exists() {
-        bail!("Profile directory already exists: {}", profile_dir.display());
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        });
     }
 
     fs::create_dir_all(&profile_dir)
         .with_context(|| format!("Failed to create profile directory: {}", profile_dir.display()))?;
+    crate::audit::record(format!("create {}", profile_dir.display()));
+
+    remove_on_error(&profile_dir, || {
+        components.remove(&Component::Settings);
+        let report = copy_components_into(paths, name, &components, copy, on_file)?;
+        let settings_path = paths.profile_settings(name);
+        fs::write(&settings_path, settings)
+            .with_context(|| format!("Failed to write {}", settings_path.display()))?;
+
+        components.insert(Component::Settings);
+        ProfileMetadata::new(components.clone()).write(&profile_dir)?;
+        Ok((components, report))
+    })
+}
+
+/// Run `build` on a freshly created profile directory, removing it if `build` fails
+///
+/// Keeps a half-copied profile from showing up in `list` after an error.
+pub(crate) fn remove_on_error<T, E>(
+    profile_dir: &Path,
+    build: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    build().inspect_err(|_| {
+        let _ = fs::remove_dir_all(profile_dir);
+    })
+}
 
-    // Copy selected components from source
-    for component in &components {
+/// Copy the current version of each component into a new profile
+///
+/// Returns what the copies of directory components reported.
+fn copy_components_into(
+    paths: &Paths,
+    name: &str,
+    components: &HashSet<Component>,
+    copy: CopyOptions,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<CopyReport> {
+    let mut report = CopyReport::default();
+    for component in components {
         let source = component.source_path(paths);
         let target = component.profile_path(paths, name);
 
         if !source.exists() {
+            if *component == Component::Env {
+                crate::env_file::create_env_file(&target)?;
+            }
             // Warn but continue? Or just skip?
             // For now, if user explicitly selected it, we might want to create an empty version or skip
             // But usually we're creating FROM current, so if current doesn't exist, we can't copy.
@@ -102,15 +553,18 @@ pub fn create_profile_with_components(
         }
 
         if source.is_dir() {
-            copy_dir_recursive(&source, &target)?;
+            report.extend(copy_dir_lenient_with_progress(
+                &source, &target, copy, on_file,
+            )?);
         } else {
-            fs::copy(&source, &target).with_context(|| {
+            let bytes = fs::copy(&source, &target).with_context(|| {
                 format!(
                     "Failed to copy {} to {}",
                     source.display(),
                     target.display()
                 )
             })?;
+            on_file(bytes);
         }
 
         // Validate if it's settings.json
@@ -119,85 +573,326 @@ pub fn create_profile_with_components(
         }
     }
 
-    // Create metadata
-    let metadata = ProfileMetadata {
-        version: env!("CARGO_PKG_VERSION").to_string(),
-        created_at: Utc::now(),
-        updated_at: Utc::now(),
-        managed_components: components,
-        migration: None,
-    };
-    metadata.write(&profile_dir)?;
+    Ok(report)
+}
 
-    Ok(())
+/// Create a profile managing only an empty settings.json
+///
+/// Used for profiles that get their settings from the profile they extend.
+pub fn create_empty_profile(paths: &Paths, name: &str) -> Result<HashSet<Component>> {
+    validate_profile_name(name)?;
+    let profile_dir = paths.profile_dir(name);
+
+    if profile_dir.exists() {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        });
+    }
+
+    fs::create_dir_all(&profile_dir)
+        .with_context(|| format!("Failed to create profile directory: {}", profile_dir.display()))?;
+    crate::audit::record(format!("create {}", profile_dir.display()));
+    remove_on_error(&profile_dir, || {
+        let settings = paths.profile_settings(name);
+        fs::write(&settings, "{}\n")
+            .with_context(|| format!("Failed to write {}", settings.display()))?;
+
+        let components = HashSet::from([Component::Settings]);
+        ProfileMetadata::new(components.clone()).write(&profile_dir)?;
+        Ok(components)
+    })
+}
+
+/// Record the installed Claude Code version in a profile's metadata
+///
+/// Leaves the metadata alone if the version can't be determined.
+pub fn record_claude_version(paths: &Paths, name: &str) -> Result<()> {
+    let Some(version) = crate::claude::installed_version() else {
+        return Ok(());
+    };
+    let profile_dir = paths.profile_dir(name);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    metadata.claude_version = Some(version);
+    Ok(metadata.write(&profile_dir)?)
 }
 
-/// Update which components a profile manages
+/// Update which components a profile manages, returning what copying the
+/// added ones reported
 pub fn update_profile_components(
     paths: &Paths,
     name: &str,
     new_components: HashSet<Component>,
-) -> Result<()> {
+) -> Result<CopyReport> {
     let profile_dir = paths.profile_dir(name);
     let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    let mut report = CopyReport::default();
+
+    // Copy added components from source
+    for (source, target) in added_component_copies(paths, name, &metadata, &new_components) {
+        if source.is_dir() {
+            report.extend(copy_dir_recursive(&source, &target)?);
+        } else {
+            fs::copy(&source, &target)?;
+        }
+    }
+    if let Some(env) = new_env_file(paths, name, &metadata, &new_components) {
+        crate::env_file::create_env_file(&env)?;
+    }
 
-    // Identify added components
-    let added: HashSet<_> = new_components
-        .difference(&metadata.managed_components)
+    // Update metadata
+    metadata.managed_components = new_components;
+    metadata.updated_at = Utc::now();
+    metadata.write(&profile_dir)?;
+
+    Ok(report)
+}
+
+/// What [`update_profile_components`] would change, without changing it
+pub fn plan_profile_components(
+    paths: &Paths,
+    name: &str,
+    new_components: &HashSet<Component>,
+) -> Result<Vec<Change>> {
+    let profile_dir = paths.profile_dir(name);
+    let metadata = ProfileMetadata::read(&profile_dir)?;
+    let mut changes: Vec<Change> = added_component_copies(paths, name, &metadata, new_components)
+        .into_iter()
+        .map(|(from, to)| Change::Copy { from, to })
         .collect();
+    if let Some(path) = new_env_file(paths, name, &metadata, new_components) {
+        changes.push(Change::Write { path });
+    }
+    changes.push(Change::Write {
+        path: profile_dir.join("metadata.json"),
+    });
+    Ok(changes)
+}
 
-    // Copy added components from source
-    for component in added {
+/// Live components copied into the profile when it starts managing them:
+/// those in ~/.claude that the profile has no copy of yet
+fn added_component_copies(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    new_components: &HashSet<Component>,
+) -> Vec<(PathBuf, PathBuf)> {
+    Component::all()
+        .into_iter()
+        .filter(|c| new_components.contains(c) && !metadata.managed_components.contains(c))
+        .map(|c| (c.source_path(paths), c.profile_path(paths, name)))
+        .filter(|(source, target)| source.exists() && !target.exists())
+        .collect()
+}
+
+/// The env.sh started from a template when the profile starts managing Env
+/// and there's no active.env to copy
+fn new_env_file(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    new_components: &HashSet<Component>,
+) -> Option<PathBuf> {
+    let path = Component::Env.profile_path(paths, name);
+    (new_components.contains(&Component::Env)
+        && !metadata.managed_components.contains(&Component::Env)
+        && !Component::Env.source_path(paths).exists()
+        && !path.exists())
+    .then_some(path)
+}
+
+/// Track a single top-level entry of a directory component
+///
+/// The first time a component is composed, the shared base is seeded from the
+/// current `~/.claude` content. The entry is copied into the profile if the profile
+/// doesn't already have its own version. Returns what the copies reported.
+pub fn track_file(
+    paths: &Paths,
+    name: &str,
+    component: Component,
+    file: &str,
+) -> Result<CopyReport> {
+    if component.is_file() {
+        return Err(anyhow!(
+            "Component '{}' is a single file.\nHint: Only directory components (agents, hooks, commands) can track individual files.",
+            component.short_name()
+        )
+        .into());
+    }
+
+    if file.is_empty() || file == "." || file == ".." || file.contains(['/', '\\']) {
+        return Err(anyhow!(
+            "Invalid tracked path '{}/{}'.\nHint: Only top-level entries of a component can be tracked, e.g. commands/deploy.md",
+            component.short_name(),
+            file
+        )
+        .into());
+    }
+
+    let profile_dir = paths.profile_dir(name);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    let mut report = CopyReport::default();
+
+    // Seed the shared base on first use
+    let base_dir = paths.shared_base(&component);
+    if !base_dir.exists() {
         let source = component.source_path(paths);
-        let target = component.profile_path(paths, name);
+        if source.is_dir() {
+            report.extend(copy_dir_recursive(&source, &base_dir)?);
+        } else {
+            fs::create_dir_all(&base_dir)
+                .with_context(|| format!("Failed to create shared base: {}", base_dir.display()))?;
+        }
+    }
 
-        if source.exists() && !target.exists() {
-            if source.is_dir() {
-                copy_dir_recursive(&source, &target)?;
-            } else {
-                fs::copy(&source, &target)?;
-            }
+    // Give the profile its own copy of the entry
+    let component_dir = component.profile_path(paths, name);
+    fs::create_dir_all(&component_dir)
+        .with_context(|| format!("Failed to create directory: {}", component_dir.display()))?;
+
+    let target = component_dir.join(file);
+    if !target.exists() {
+        let seed = base_dir.join(file);
+        if seed.is_dir() {
+            report.extend(copy_dir_recursive(&seed, &target)?);
+        } else if seed.exists() {
+            fs::copy(&seed, &target).with_context(|| {
+                format!("Failed to copy {} to {}", seed.display(), target.display())
+            })?;
+        } else {
+            return Err(anyhow!(
+                "'{}/{}' not found in profile '{}' or in the shared base.\nHint: Create it at {} first.",
+                component.short_name(),
+                file,
+                name,
+                target.display()
+            )
+            .into());
         }
     }
 
-    // Update metadata
-    metadata.managed_components = new_components;
+    metadata.managed_components.insert(component);
+    let files = metadata.tracked_files.entry(component).or_default();
+    if !files.iter().any(|f| f == file) {
+        files.push(file.to_string());
+        files.sort();
+    }
     metadata.updated_at = Utc::now();
     metadata.write(&profile_dir)?;
 
-    Ok(())
+    Ok(report)
 }
 
 /// Remove a profile
-pub fn remove_profile(paths: &Paths, name: &str) -> Result<()> {
-    let profile_dir = paths.profile_dir(name);
+///
+/// `on_wait` is called if another process holds the state lock.
+pub fn remove_profile(paths: &Paths, name: &str, on_wait: &mut dyn FnMut()) -> Result<()> {
+    ensure_profile_exists(paths, name)?;
+    ensure_not_extended(paths, name)?;
 
-    if !profile_dir.exists() {
-        bail!("Profile '{}' does not exist", name);
+    crate::fs_utils::remove_path(&paths.profile_dir(name))?;
+
+    forget_profile(paths, name, on_wait)
+}
+
+/// What [`remove_profile`] would change, without changing it
+pub fn plan_remove_profile(paths: &Paths, name: &str) -> Result<Vec<Change>> {
+    ensure_profile_exists(paths, name)?;
+    ensure_not_extended(paths, name)?;
+
+    let mut changes = vec![Change::Remove {
+        path: paths.profile_dir(name),
+    }];
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if remembers_profile(&state, name) {
+        changes.push(Change::State {
+            detail: format!("forget '{}' (last used, active components)", name),
+        });
     }
+    Ok(changes)
+}
 
-    fs::remove_dir_all(&profile_dir).with_context(|| {
+/// Move a profile into the archive directory, out of `list_profiles`
+///
+/// Returns where the profile now lives. `on_wait` is called if another process
+/// holds the state lock.
+pub fn archive_profile(paths: &Paths, name: &str, on_wait: &mut dyn FnMut()) -> Result<PathBuf> {
+    ensure_profile_exists(paths, name)?;
+    ensure_not_extended(paths, name)?;
+    let profile_dir = paths.profile_dir(name);
+
+    let archived = archive_destination(paths, name);
+    fs::create_dir_all(&paths.archive_dir).with_context(|| {
         format!(
-            "Failed to remove profile directory: {}",
-            profile_dir.display()
+            "Failed to create archive directory: {}",
+            paths.archive_dir.display()
         )
     })?;
+    crate::fs_utils::move_path(&profile_dir, &archived)?;
 
+    forget_profile(paths, name, on_wait)?;
+    Ok(archived)
+}
+
+/// Where a profile set aside goes: its name in the archive directory, with a
+/// timestamp appended if that is taken
+pub(crate) fn archive_destination(paths: &Paths, name: &str) -> PathBuf {
+    let archived = paths.archive_dir.join(name);
+    if archived.exists() {
+        return paths
+            .archive_dir
+            .join(format!("{}.{}", name, Utc::now().format("%Y%m%d_%H%M%S")));
+    }
+    archived
+}
+
+fn ensure_not_extended(paths: &Paths, name: &str) -> Result<()> {
+    let children = crate::inheritance::children_of(paths, name)?;
+    if !children.is_empty() {
+        return Err(Error::ProfileExtended {
+            name: name.to_string(),
+            children,
+        });
+    }
+    Ok(())
+}
+
+/// Whether state.json has entries [`forget_profile`] drops
+fn remembers_profile(state: &State, name: &str) -> bool {
+    state.last_used.contains_key(name) || state.active_components.values().any(|p| p == name)
+}
+
+/// Drop state entries of a profile that is gone
+fn forget_profile(paths: &Paths, name: &str, on_wait: &mut dyn FnMut()) -> Result<()> {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if remembers_profile(&state, name) {
+        LockedState::lock_or_wait(&paths.state_file, on_wait)?.update(|s| {
+            s.last_used.remove(name);
+            s.active_components.retain(|_, p| p != name);
+        })?;
+    }
     Ok(())
 }
 
 /// Rename a profile
-pub fn rename_profile(paths: &Paths, old_name: &str, new_name: &str) -> Result<()> {
+///
+/// `on_wait` is called if another process holds the state lock.
+pub fn rename_profile(
+    paths: &Paths,
+    old_name: &str,
+    new_name: &str,
+    on_wait: &mut dyn FnMut(),
+) -> Result<()> {
     let old_dir = paths.profile_dir(old_name);
     let new_dir = paths.profile_dir(new_name);
+    ensure_renamable(paths, old_name, new_name)?;
 
-    if !old_dir.exists() {
-        bail!("Profile '{}' does not exist", old_name);
-    }
-    if new_dir.exists() {
-        bail!("Profile '{}' already exists", new_name);
-    }
+    let children = crate::inheritance::children_of(paths, old_name)?;
 
+    crate::audit::record(format!(
+        "move {} -> {}",
+        old_dir.display(),
+        new_dir.display()
+    ));
     fs::rename(&old_dir, &new_dir).with_context(|| {
         format!(
             "Failed to rename '{}' to '{}'",
@@ -206,16 +901,87 @@ pub fn rename_profile(paths: &Paths, old_name: &str, new_name: &str) -> Result<(
         )
     })?;
 
+    // Profiles extending the old name follow the rename
+    for child in children {
+        let child_dir = paths.profile_dir(&child);
+        let mut metadata = ProfileMetadata::read(&child_dir)?;
+        metadata.extends = Some(new_name.to_string());
+        metadata.updated_at = Utc::now();
+        metadata.write(&child_dir)?;
+    }
+
+    // Every state reference moves in one transaction
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if refers_to_profile(&state, old_name) {
+        LockedState::lock_or_wait(&paths.state_file, on_wait)?.update(|s| {
+            if s.default_profile.as_deref() == Some(old_name) {
+                s.default_profile = Some(new_name.to_string());
+            }
+            if let Some(used) = s.last_used.remove(old_name) {
+                s.last_used.insert(new_name.to_string(), used);
+            }
+            for copy in s.copies.values_mut().filter(|c| c.profile == old_name) {
+                copy.profile = new_name.to_string();
+            }
+            for profile in s.active_components.values_mut().filter(|p| *p == old_name) {
+                *profile = new_name.to_string();
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+/// What [`rename_profile`] would change, without changing it
+pub fn plan_rename_profile(paths: &Paths, old_name: &str, new_name: &str) -> Result<Vec<Change>> {
+    ensure_renamable(paths, old_name, new_name)?;
+
+    let mut changes = vec![Change::Move {
+        from: paths.profile_dir(old_name),
+        to: paths.profile_dir(new_name),
+    }];
+    // Written after the move, so at the children's own (unchanged) paths
+    for child in crate::inheritance::children_of(paths, old_name)? {
+        changes.push(Change::Write {
+            path: paths.profile_dir(&child).join("metadata.json"),
+        });
+    }
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if refers_to_profile(&state, old_name) {
+        changes.push(Change::State {
+            detail: format!("references to '{}' become '{}'", old_name, new_name),
+        });
+    }
+    Ok(changes)
+}
+
+fn ensure_renamable(paths: &Paths, old_name: &str, new_name: &str) -> Result<()> {
+    ensure_profile_exists(paths, old_name)?;
+    if paths.profile_dir(new_name).exists() {
+        return Err(Error::ProfileExists {
+            name: new_name.to_string(),
+        });
+    }
     Ok(())
 }
 
-/// Validate that a file contains valid JSON
+/// Whether state.json mentions the profile anywhere [`rename_profile`] updates
+fn refers_to_profile(state: &State, name: &str) -> bool {
+    state.default_profile.as_deref() == Some(name)
+        || state.last_used.contains_key(name)
+        || state.copies.values().any(|c| c.profile == name)
+        || state.active_components.values().any(|p| p == name)
+}
+
+/// Validate that a file contains valid JSON (comments and trailing commas allowed)
 pub fn validate_json_file(path: &Path) -> Result<()> {
     let content = fs::read_to_string(path)
         .with_context(|| format!("Failed to read file: {}", path.display()))?;
 
-    serde_json::from_str::<serde_json::Value>(&content)
-        .with_context(|| format!("Invalid JSON in file: {}", path.display()))?;
+    crate::jsonc::parse(&content).map_err(|source| Error::InvalidJson {
+        path: path.to_path_buf(),
+        source,
+    })?;
 
     Ok(())
 }
@@ -236,6 +1002,49 @@ mod tests {
         assert!(validate_profile_name("invalid name").is_err());
         assert!(validate_profile_name("test/profile").is_err());
         assert!(validate_profile_name("emoji😊").is_err());
+        assert!(matches!(
+            validate_profile_name(&"x".repeat(65)),
+            Err(Error::InvalidName { reason, .. }) if reason.contains("64")
+        ));
+    }
+
+    #[test]
+    fn test_resolve_name_expands_prefixes_and_suggests() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        for name in ["work", "personal", "presentation"] {
+            fs::create_dir_all(paths.profile_dir(name)).unwrap();
+        }
+
+        assert_eq!(resolve_name(&paths, "work", false).unwrap(), "work");
+        assert_eq!(resolve_name(&paths, "pers", false).unwrap(), "personal");
+        assert_eq!(resolve_name(&paths, "Work", false).unwrap(), "work");
+        assert_eq!(resolve_name(&paths, "PRES", false).unwrap(), "presentation");
+        assert!(matches!(
+            resolve_name(&paths, "p", false),
+            Err(Error::AmbiguousName { candidates, .. }) if candidates == ["personal", "presentation"]
+        ));
+        // An exact name wins over the longer ones it starts
+        fs::create_dir_all(paths.profile_dir("pres")).unwrap();
+        assert_eq!(resolve_name(&paths, "pres", false).unwrap(), "pres");
+
+        let suggestion = |input| match resolve_name(&paths, input, false) {
+            Err(Error::ProfileNotFound { suggestion, .. }) => suggestion,
+            other => panic!("expected ProfileNotFound, got {:?}", other),
+        };
+        assert_eq!(suggestion("wrok").as_deref(), Some("work"));
+        assert_eq!(suggestion("personl").as_deref(), Some("personal"));
+        assert_eq!(suggestion("experiments"), None);
+
+        // --exact takes only the name as typed
+        assert_eq!(resolve_name(&paths, "work", true).unwrap(), "work");
+        assert!(matches!(
+            resolve_name(&paths, "Work", true),
+            Err(Error::ProfileNotFound { .. })
+        ));
+
+        let err = ensure_profile_exists(&paths, "wokr").unwrap_err();
+        assert!(err.to_string().ends_with("Hint: Did you mean 'work'?"));
     }
 
     #[test]
@@ -267,6 +1076,71 @@ mod tests {
         assert!(metadata.managed_components.contains(&Component::Agents));
     }
 
+    #[test]
+    fn test_create_profile_from_given_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        fs::write(paths.claude_dir.join("agents/review.md"), "agent").unwrap();
+
+        let settings = "{\n  // rendered for CI\n  \"model\": \"sonnet\"\n}\n";
+        let (components, report) = create_profile_from_settings(
+            &paths,
+            "ci",
+            settings,
+            HashSet::from([Component::Settings, Component::Agents]),
+            CopyOptions::default(),
+            &mut |_| {},
+        )
+        .unwrap();
+        assert_eq!(report, CopyReport::default());
+        assert_eq!(
+            components,
+            HashSet::from([Component::Settings, Component::Agents])
+        );
+        // Written as given, not taken from ~/.claude
+        assert_eq!(
+            fs::read_to_string(paths.profile_settings("ci")).unwrap(),
+            settings
+        );
+        assert!(paths.profile_dir("ci").join("agents/review.md").exists());
+        let metadata = ProfileMetadata::read(&paths.profile_dir("ci")).unwrap();
+        assert_eq!(metadata.managed_components, components);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_create_leaves_no_residue() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        let agents_dir = paths.claude_dir.join("agents");
+        fs::create_dir(&agents_dir).unwrap();
+        fs::write(agents_dir.join("a.md"), "agent").unwrap();
+        // A socket can't be read, even as root, so it is left out
+        std::os::unix::net::UnixListener::bind(agents_dir.join("broken.sock")).unwrap();
+
+        let components = HashSet::from([Component::Settings, Component::Agents]);
+        let skipped = create_profile_with_components(&paths, "work", components.clone()).unwrap();
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].contains("broken.sock"), "{}", skipped[0]);
+        let agents = paths.profile_dir("work").join("agents");
+        assert!(agents.join("a.md").exists());
+        assert!(!agents.join("broken.sock").exists());
+
+        // Invalid settings fail the whole profile
+        fs::write(&paths.claude_settings, "{ not json").unwrap();
+        assert!(create_profile_with_components(&paths, "half", components).is_err());
+
+        assert!(!paths.profile_dir("half").exists());
+        assert_eq!(list_profiles(&paths).unwrap(), vec!["work"]);
+    }
+
     #[test]
     fn test_rename_profile() {
         let temp_dir = TempDir::new().unwrap();
@@ -280,9 +1154,259 @@ mod tests {
         components.insert(Component::Settings);
         create_profile_with_components(&paths, "old-name", components).unwrap();
 
-        rename_profile(&paths, "old-name", "new-name").unwrap();
+        rename_profile(&paths, "old-name", "new-name", &mut || {}).unwrap();
 
         assert!(!paths.profile_dir("old-name").exists());
         assert!(paths.profile_dir("new-name").exists());
+        assert!(matches!(
+            rename_profile(&paths, "old-name", "other", &mut || {}),
+            Err(Error::ProfileNotFound { name, .. }) if name == "old-name"
+        ));
+    }
+
+    #[test]
+    fn test_last_used_follows_rename_and_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+
+        let components = HashSet::from([Component::Settings]);
+        create_profile_with_components(&paths, "old-name", components.clone()).unwrap();
+        create_profile_with_components(&paths, "other", components).unwrap();
+        crate::switch::switch_to_profile(&paths, "old-name", &Default::default()).unwrap();
+        crate::switch::switch_to_profile(&paths, "other", &Default::default()).unwrap();
+        let used = State::read(&paths.state_file).unwrap().last_used;
+        assert!(used["old-name"] <= used["other"]);
+
+        rename_profile(&paths, "old-name", "new-name", &mut || {}).unwrap();
+        let state = State::read(&paths.state_file).unwrap();
+        assert!(!state.last_used.contains_key("old-name"));
+        assert_eq!(state.last_used["new-name"], used["old-name"]);
+
+        remove_profile(&paths, "new-name", &mut || {}).unwrap();
+        let state = State::read(&paths.state_file).unwrap();
+        assert_eq!(state.last_used.keys().collect::<Vec<_>>(), vec!["other"]);
+    }
+
+    #[test]
+    fn test_repair_metadata_infers_components_and_keeps_what_parses() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        create_profile_with_components(
+            &paths,
+            "work",
+            HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
+        fs::write(paths.profile_settings("work"), "{}").unwrap();
+        fs::create_dir_all(paths.profile_dir("work").join("agents")).unwrap();
+        let metadata_path = paths.profile_dir("work").join("metadata.json");
+        assert_eq!(repair_metadata(&paths, "work").unwrap(), None);
+
+        // A field of the wrong type, with created_at still readable
+        fs::write(
+            &metadata_path,
+            r#"{"created_at": "2024-01-02T03:04:05Z", "managed_components": 7, "claude_version": "1.0.3"}"#,
+        )
+        .unwrap();
+        assert!(ProfileMetadata::read(&paths.profile_dir("work")).is_err());
+        let repair = repair_metadata(&paths, "work").unwrap().unwrap();
+        assert_eq!(
+            repair.components,
+            vec![Component::Settings, Component::Agents]
+        );
+        assert!(repair.created_at_kept);
+        assert_eq!(repair.salvaged, vec!["claude_version"]);
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        assert_eq!(
+            metadata.created_at.to_rfc3339(),
+            "2024-01-02T03:04:05+00:00"
+        );
+        assert_eq!(metadata.claude_version.as_deref(), Some("1.0.3"));
+        assert!(!paths.profile_dir("work").join("metadata.json.tmp").exists());
+
+        // Truncated: nothing to keep, so the directory's mtime stands in
+        fs::write(&metadata_path, r#"{"created_at": "2024"#).unwrap();
+        let repair = repair_metadata(&paths, "work").unwrap().unwrap();
+        assert!(!repair.created_at_kept);
+        assert!(repair.salvaged.is_empty());
+        assert!(ProfileMetadata::read(&paths.profile_dir("work")).is_ok());
+    }
+
+    #[test]
+    fn test_plans_match_the_operations_without_running_them() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        let components = HashSet::from([Component::Settings]);
+        create_profile_with_components(&paths, "work", components.clone()).unwrap();
+        create_profile_with_components(&paths, "other", components).unwrap();
+        crate::switch::switch_to_profile(&paths, "work", &Default::default()).unwrap();
+        let state_before = fs::read_to_string(&paths.state_file).unwrap();
+
+        assert_eq!(
+            plan_rename_profile(&paths, "work", "job").unwrap(),
+            vec![
+                Change::Move {
+                    from: paths.profile_dir("work"),
+                    to: paths.profile_dir("job"),
+                },
+                Change::State {
+                    detail: "references to 'work' become 'job'".to_string(),
+                },
+            ]
+        );
+        assert!(matches!(
+            plan_rename_profile(&paths, "work", "other"),
+            Err(Error::ProfileExists { .. })
+        ));
+        // Nothing refers to 'other' in state.json
+        assert_eq!(
+            plan_remove_profile(&paths, "other").unwrap(),
+            vec![Change::Remove {
+                path: paths.profile_dir("other"),
+            }]
+        );
+        let new = HashSet::from([Component::Settings, Component::Agents]);
+        assert_eq!(
+            plan_profile_components(&paths, "other", &new).unwrap(),
+            vec![
+                Change::Copy {
+                    from: paths.claude_dir.join("agents"),
+                    to: paths.profile_dir("other").join("agents"),
+                },
+                Change::Write {
+                    path: paths.profile_dir("other").join("metadata.json"),
+                },
+            ]
+        );
+
+        assert!(paths.profile_dir("work").exists());
+        assert!(!paths.profile_dir("other").join("agents").exists());
+        assert_eq!(fs::read_to_string(&paths.state_file).unwrap(), state_before);
+    }
+
+    #[test]
+    fn test_list_entries_and_inspection() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(paths.claude_dir.join("agents/review.md"), "review").unwrap();
+
+        create_profile_with_components(&paths, "alpha", HashSet::from([Component::Settings]))
+            .unwrap();
+        let components = HashSet::from([Component::Agents, Component::Settings]);
+        create_profile_with_components(&paths, "beta", components).unwrap();
+        crate::switch::switch_to_profile(&paths, "beta", &Default::default()).unwrap();
+
+        let entries = list_profile_entries(&paths, ProfileSort::LastUsed).unwrap();
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, vec!["beta", "alpha"]);
+        assert!(entries[0].active && entries[0].last_used.is_some());
+        assert_eq!(
+            entries[0].components,
+            Some(vec![Component::Agents, Component::Settings])
+        );
+        assert!(!entries[1].active && entries[1].last_used.is_none());
+
+        let inspection = inspect_profile(&paths, "beta", 1, SymlinkMode::Preserve).unwrap();
+        let kinds: Vec<_> = inspection.components.iter().map(|c| c.component).collect();
+        assert_eq!(kinds, vec![Component::Settings, Component::Agents]);
+        assert_eq!(inspection.components[0].bytes, Some(2));
+        let agents = inspection.components[1].contents.as_ref().unwrap();
+        assert_eq!((agents.files, agents.bytes), (1, 6));
+        assert_eq!(inspection.last_used, entries[0].last_used);
+        assert!(inspection.extended_by.is_empty() && inspection.notes.is_none());
+        assert!(matches!(
+            inspect_profile(&paths, "gamma", 1, SymlinkMode::Preserve),
+            Err(Error::ProfileNotFound { .. })
+        ));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_track_file_seeds_base_and_copies_entry() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        let live = paths.claude_dir.join("commands");
+        fs::create_dir_all(&live).unwrap();
+        fs::write(live.join("deploy.md"), "deploy").unwrap();
+        fs::write(paths.claude_dir.join("settings.json"), "{}").unwrap();
+
+        create_profile_with_components(&paths, "work", HashSet::from([Component::Settings]))
+            .unwrap();
+        assert_eq!(
+            track_file(&paths, "work", Component::Commands, "deploy.md").unwrap(),
+            CopyReport::default()
+        );
+
+        assert!(
+            paths
+                .shared_base(&Component::Commands)
+                .join("deploy.md")
+                .exists()
+        );
+        assert!(
+            paths
+                .profile_dir("work")
+                .join("commands/deploy.md")
+                .exists()
+        );
+
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        assert!(metadata.managed_components.contains(&Component::Commands));
+        assert_eq!(
+            metadata.tracked_files_for(Component::Commands).unwrap(),
+            ["deploy.md".to_string()]
+        );
+
+        // Nested paths, file components and unknown entries are rejected
+        assert!(track_file(&paths, "work", Component::Commands, "git/deploy.md").is_err());
+        assert!(track_file(&paths, "work", Component::Settings, "x").is_err());
+        assert!(track_file(&paths, "work", Component::Commands, "missing.md").is_err());
+    }
+
+    #[test]
+    fn test_listing_leaves_legacy_profiles_untouched() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let legacy = paths.profile_dir("old");
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("settings.json"), "{}").unwrap();
+
+        assert_eq!(list_profiles(&paths).unwrap(), vec!["old"]);
+        assert_eq!(legacy_profiles(&paths).unwrap(), vec!["old"]);
+        assert!(!legacy.join("metadata.json").exists());
+
+        assert_eq!(migrate_legacy_profiles(&paths).unwrap(), vec!["old"]);
+        let metadata = ProfileMetadata::read(&legacy).unwrap();
+        assert_eq!(metadata.migration.unwrap().original_version, "0.1.0");
+        assert!(legacy_profiles(&paths).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_archived_profile_leaves_the_list() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.profile_dir("old")).unwrap();
+        ProfileMetadata::new(HashSet::new())
+            .write(&paths.profile_dir("old"))
+            .unwrap();
+        fs::create_dir_all(paths.archive_dir.join("old")).unwrap();
+
+        // An earlier archive of the same name is kept
+        let archived = archive_profile(&paths, "old", &mut || {}).unwrap();
+        assert_ne!(archived, paths.archive_dir.join("old"));
+        assert!(archived.join("metadata.json").exists());
+        assert!(list_profiles(&paths).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Hiding secret-looking values in output.
+//!
+//! Settings often hold API keys (`env.ANTHROPIC_API_KEY`, `apiKeyHelper`), and
+//! `diff` output ends up pasted into bug reports. A value is treated as secret
+//! when any key on its path contains one of the patterns, ignoring case. The
+//! patterns come from `redact` in config.toml; `--show-secrets` turns it off.
+
+/// Patterns used when config.toml doesn't set `redact`
+pub const DEFAULT_PATTERNS: &[&str] = &["key", "token", "secret", "password", "authorization"];
+
+/// Marker shown in place of a secret, after its prefix
+const REDACTED: &str = "…REDACTED";
+
+/// Prefix shown for a secret without one of its own
+const DEFAULT_PREFIX: &str = "sk-";
+
+/// Which keys hold secrets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redactor {
+    /// Lowercase substrings of secret keys
+    patterns: Vec<String>,
+}
+
+impl Default for Redactor {
+    fn default() -> Self {
+        Self::new(DEFAULT_PATTERNS.iter().copied())
+    }
+}
+
+impl Redactor {
+    pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|p| p.as_ref().to_lowercase())
+                .filter(|p| !p.is_empty())
+                .collect(),
+        }
+    }
+
+    /// The key patterns, lowercased
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    /// Redact nothing
+    pub fn disabled() -> Self {
+        Self {
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Whether `key` looks like it names a secret
+    pub fn is_secret_key(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.patterns.iter().any(|p| key.contains(p.as_str()))
+    }
+
+    /// Whether any key of a dotted path (`env.ANTHROPIC_API_KEY`) names a secret
+    pub fn is_secret_path(&self, path: &str) -> bool {
+        path.split('.').any(|key| self.is_secret_key(key))
+    }
+
+    /// Hide the value of a `NAME=value` or `name: value` line with a secret name
+    pub fn redact_line(&self, line: &str) -> String {
+        let Some(split) = line.find(['=', ':']) else {
+            return line.to_string();
+        };
+        let (name, rest) = line.split_at(split);
+        let bare = name
+            .trim()
+            .trim_start_matches("export ")
+            .trim_matches(['"', '\'']);
+        let value = rest[1..].trim();
+        if value.is_empty() || bare.contains(char::is_whitespace) || !self.is_secret_key(bare) {
+            return line.to_string();
+        }
+        let separator = if rest.starts_with('=') { "=" } else { ": " };
+        format!("{}{}{}", name, separator, redact_str(value))
+    }
+}
+
+/// `sk-ant-api03-…` becomes `sk-…REDACTED`
+///
+/// Another short prefix (`ghp-…`) is kept in place of `sk-` to tell which kind
+/// of key it is without giving anything away.
+pub fn redact_str(value: &str) -> String {
+    let prefix = match value.find('-') {
+        Some(end) if (1..=3).contains(&end) && value.len() > end + 1 => &value[..=end],
+        _ => DEFAULT_PREFIX,
+    };
+    format!("{}{}", prefix, REDACTED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_values_are_redacted() {
+        let redactor = Redactor::default();
+        assert!(redactor.is_secret_path("env.ANTHROPIC_API_KEY"));
+        assert!(redactor.is_secret_path("apiKeyHelper"));
+        assert!(!redactor.is_secret_path("permissions.allow"));
+
+        assert_eq!(redact_str("sk-ant-api03-abcdef"), "sk-…REDACTED");
+        assert_eq!(redact_str("ghp-abcdef"), "ghp-…REDACTED");
+        assert_eq!(redact_str("/usr/local/bin/get-key"), "sk-…REDACTED");
+        assert_eq!(redact_str("8080"), "sk-…REDACTED");
+
+        assert_eq!(
+            redactor.redact_line("export ANTHROPIC_API_KEY=sk-ant-123"),
+            "export ANTHROPIC_API_KEY=sk-…REDACTED"
+        );
+        assert_eq!(
+            redactor.redact_line("Token: hunter2"),
+            "Token: sk-…REDACTED"
+        );
+        assert_eq!(
+            redactor.redact_line("see the key rotation doc: later"),
+            "see the key rotation doc: later"
+        );
+
+        // Configured patterns replace the defaults
+        let custom = Redactor::new(["Cookie"]);
+        assert!(custom.is_secret_key("session_cookie"));
+        assert!(!custom.is_secret_key("api_key"));
+        assert!(!Redactor::disabled().is_secret_key("api_key"));
+    }
+}
@@ -0,0 +1,272 @@
+//! Rendered config directories for `CLAUDE_CONFIG_DIR`.
+//!
+//! Instead of switching `~/.claude`, `ccprof env <profile>` points Claude Code's
+//! `CLAUDE_CONFIG_DIR` at `~/.claude-profiles/rendered/<profile>/`, a directory
+//! holding the profile's components as symlinks, laid out like `~/.claude`.
+//! Only the components are ccprof's: whatever Claude Code writes there itself
+//! (history, caches) is kept across renders. A render is refreshed when the
+//! profile's metadata is newer than it.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::components::{Component, ProfileMetadata};
+use crate::paths::Paths;
+use crate::profiles::{ensure_profile_exists, profile_exists};
+use crate::switch::{ComponentStatus, LinkStyle, link_component};
+
+/// File in a rendered directory recording when it was rendered
+pub const RENDER_RECORD_FILE: &str = ".ccprof-render.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RenderRecord {
+    profile: String,
+    rendered_at: DateTime<Utc>,
+}
+
+impl RenderRecord {
+    fn read(dir: &Path) -> Option<Self> {
+        let content = fs::read_to_string(dir.join(RENDER_RECORD_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+}
+
+/// How a rendered directory compares with its profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStatus {
+    /// Rendered since the profile last changed
+    Fresh,
+    /// The profile changed after it was rendered (or the record is missing)
+    Stale,
+    /// The profile no longer exists
+    Orphaned,
+}
+
+/// A directory under `rendered/`
+#[derive(Debug, Clone)]
+pub struct RenderedDir {
+    pub profile: String,
+    pub path: PathBuf,
+    pub rendered_at: Option<DateTime<Utc>>,
+    pub status: RenderStatus,
+}
+
+/// Status of the rendered directory of `name`, which must exist
+fn render_status(paths: &Paths, name: &str, dir: &Path) -> (Option<DateTime<Utc>>, RenderStatus) {
+    let rendered_at = RenderRecord::read(dir).map(|r| r.rendered_at);
+    if !profile_exists(paths, name) {
+        return (rendered_at, RenderStatus::Orphaned);
+    }
+    let updated_at = ProfileMetadata::read(&paths.profile_dir(name))
+        .ok()
+        .map(|m| m.updated_at);
+    let status = match (rendered_at, updated_at) {
+        (Some(rendered), Some(updated)) if rendered >= updated => RenderStatus::Fresh,
+        _ => RenderStatus::Stale,
+    };
+    (rendered_at, status)
+}
+
+/// Render `name` if its directory is missing or stale, returning the directory
+/// and whether it was (re)rendered
+pub fn render_profile(paths: &Paths, name: &str) -> Result<(PathBuf, bool)> {
+    ensure_profile_exists(paths, name)?;
+    // Regenerate inherited settings so edits to a base are picked up
+    crate::inheritance::materialize_settings(paths, name)?;
+
+    let dir = paths.rendered_profile_dir(name);
+    if dir.is_dir() && render_status(paths, name, &dir).1 == RenderStatus::Fresh {
+        return Ok((dir, false));
+    }
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create directory {}", dir.display()))?;
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    // The rendered directory stands in for ~/.claude
+    let render_paths = Paths::with_dirs(&paths.base_dir, &dir);
+    for component in Component::all().into_iter().filter(|c| c.in_claude_dir()) {
+        let live = component.source_path(&render_paths);
+        if metadata.managed_components.contains(&component) {
+            link_component(
+                &render_paths,
+                name,
+                &metadata,
+                &component,
+                LinkStyle::Absolute,
+            )?;
+        } else if ComponentStatus::detect(&live).target().is_some() {
+            // Left from a render before the profile stopped managing it
+            crate::fs_utils::remove_path(&live)?;
+        }
+    }
+
+    let record = RenderRecord {
+        profile: name.to_string(),
+        rendered_at: Utc::now(),
+    };
+    let record_path = dir.join(RENDER_RECORD_FILE);
+    fs::write(&record_path, serde_json::to_string_pretty(&record)?)
+        .with_context(|| format!("Failed to write {}", record_path.display()))?;
+    Ok((dir, true))
+}
+
+/// Every rendered directory, sorted by profile name
+pub fn list_rendered(paths: &Paths) -> Result<Vec<RenderedDir>> {
+    if !paths.rendered_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut rendered = Vec::new();
+    for entry in fs::read_dir(&paths.rendered_dir)
+        .with_context(|| format!("Failed to read {}", paths.rendered_dir.display()))?
+    {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let profile = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let (rendered_at, status) = render_status(paths, &profile, &path);
+        rendered.push(RenderedDir {
+            profile,
+            path,
+            rendered_at,
+            status,
+        });
+    }
+    rendered.sort_by(|a, b| a.profile.cmp(&b.profile));
+    Ok(rendered)
+}
+
+/// Profile whose rendered directory `CLAUDE_CONFIG_DIR` selects in this shell
+pub fn shell_profile(paths: &Paths) -> Option<String> {
+    let dir = PathBuf::from(std::env::var_os(crate::paths::CLAUDE_CONFIG_DIR_ENV)?);
+    let name = dir.strip_prefix(&paths.rendered_dir).ok()?.iter().next()?;
+    Some(name.to_string_lossy().to_string())
+}
+
+/// Shell syntax for `ccprof env` output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnvShell {
+    /// sh, bash, zsh and other POSIX shells
+    #[default]
+    Posix,
+    Fish,
+}
+
+impl std::str::FromStr for EnvShell {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "sh" | "bash" | "zsh" | "posix" => Ok(Self::Posix),
+            "fish" => Ok(Self::Fish),
+            other => Err(format!(
+                "unknown shell '{}' (expected sh, bash, zsh or fish)",
+                other
+            )),
+        }
+    }
+}
+
+impl EnvShell {
+    /// The shell named by `$SHELL`, defaulting to POSIX syntax
+    pub fn detect() -> Self {
+        std::env::var_os("SHELL")
+            .and_then(|shell| {
+                Path::new(&shell)
+                    .file_name()
+                    .and_then(|name| name.to_str()?.parse().ok())
+            })
+            .unwrap_or_default()
+    }
+
+    /// A line setting `name` to `value`
+    pub fn export(self, name: &str, value: &str) -> String {
+        match self {
+            Self::Posix => format!("export {}='{}'", name, value.replace('\'', r"'\''")),
+            Self::Fish => format!(
+                "set -gx {} '{}'",
+                name,
+                value.replace('\\', r"\\").replace('\'', r"\'")
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_render_links_components_and_refreshes_when_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
+
+        let (dir, rendered) = render_profile(&paths, "work").unwrap();
+        assert!(rendered);
+        assert_eq!(dir, paths.rendered_dir.join("work"));
+        assert_eq!(
+            fs::read_link(dir.join("settings.json")).unwrap(),
+            paths.profile_dir("work").join("settings.json")
+        );
+        assert!(fs::read_link(dir.join("agents")).is_ok());
+        // ~/.claude is never touched
+        assert!(fs::read_link(&paths.claude_settings).is_err());
+
+        // Claude Code's own files survive re-renders
+        fs::write(dir.join("history.jsonl"), "x").unwrap();
+        assert!(!render_profile(&paths, "work").unwrap().1);
+
+        let profile_dir = paths.profile_dir("work");
+        let mut metadata = ProfileMetadata::read(&profile_dir).unwrap();
+        metadata.managed_components.remove(&Component::Agents);
+        metadata.updated_at = Utc::now();
+        metadata.write(&profile_dir).unwrap();
+        let listed = list_rendered(&paths).unwrap();
+        assert_eq!(listed[0].status, RenderStatus::Stale);
+
+        assert!(render_profile(&paths, "work").unwrap().1);
+        assert!(fs::symlink_metadata(dir.join("agents")).is_err());
+        assert!(dir.join("history.jsonl").exists());
+        assert_eq!(
+            list_rendered(&paths).unwrap()[0].status,
+            RenderStatus::Fresh
+        );
+
+        fs::remove_dir_all(&profile_dir).unwrap();
+        assert_eq!(
+            list_rendered(&paths).unwrap()[0].status,
+            RenderStatus::Orphaned
+        );
+    }
+
+    #[test]
+    fn test_export_lines_quote_values() {
+        assert_eq!(
+            EnvShell::Posix.export("CLAUDE_CONFIG_DIR", "/home/o'neil/x"),
+            r"export CLAUDE_CONFIG_DIR='/home/o'\''neil/x'"
+        );
+        assert_eq!(
+            EnvShell::Fish.export("CLAUDE_CONFIG_DIR", "/home/o'neil/x"),
+            r"set -gx CLAUDE_CONFIG_DIR '/home/o\'neil/x'"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Checking settings.json against the keys Claude Code knows.
+//!
+//! Catches typos like `permisions` that Claude Code silently ignores. The check
+//! only ever warns: Claude Code adds keys over time, so an unknown key may just
+//! be newer than this list.
+
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+/// JSON type a settings key holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsonType {
+    String,
+    Number,
+    Bool,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    fn of(value: &Value) -> Option<JsonType> {
+        match value {
+            Value::String(_) => Some(JsonType::String),
+            Value::Number(_) => Some(JsonType::Number),
+            Value::Bool(_) => Some(JsonType::Bool),
+            Value::Array(_) => Some(JsonType::Array),
+            Value::Object(_) => Some(JsonType::Object),
+            Value::Null => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            JsonType::String => "a string",
+            JsonType::Number => "a number",
+            JsonType::Bool => "true or false",
+            JsonType::Array => "a list",
+            JsonType::Object => "an object",
+        }
+    }
+}
+
+/// Top-level keys of settings.json and their types; add new keys here
+pub const KNOWN_KEYS: &[(&str, JsonType)] = &[
+    ("$schema", JsonType::String),
+    ("alwaysThinkingEnabled", JsonType::Bool),
+    ("apiKeyHelper", JsonType::String),
+    ("awsAuthRefresh", JsonType::String),
+    ("awsCredentialExport", JsonType::String),
+    ("cleanupPeriodDays", JsonType::Number),
+    ("companyAnnouncements", JsonType::Array),
+    ("disableAllHooks", JsonType::Bool),
+    ("disabledMcpjsonServers", JsonType::Array),
+    ("enableAllProjectMcpServers", JsonType::Bool),
+    ("enabledMcpjsonServers", JsonType::Array),
+    ("enabledPlugins", JsonType::Object),
+    ("env", JsonType::Object),
+    ("extraKnownMarketplaces", JsonType::Object),
+    ("forceLoginMethod", JsonType::String),
+    ("forceLoginOrgUUID", JsonType::String),
+    ("hooks", JsonType::Object),
+    ("includeCoAuthoredBy", JsonType::Bool),
+    ("model", JsonType::String),
+    ("otelHeadersHelper", JsonType::String),
+    ("outputStyle", JsonType::String),
+    ("permissions", JsonType::Object),
+    ("sandbox", JsonType::Object),
+    ("spinnerTipsEnabled", JsonType::Bool),
+    ("statusLine", JsonType::Object),
+];
+
+/// A problem found in settings.json
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsIssue {
+    /// The file holds something other than a JSON object
+    NotAnObject,
+    /// A key not in [`KNOWN_KEYS`], with the closest known key if one is close
+    UnknownKey {
+        key: String,
+        suggestion: Option<&'static str>,
+    },
+    /// A known key holding the wrong type of value
+    WrongType {
+        key: String,
+        expected: JsonType,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for SettingsIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsIssue::NotAnObject => write!(f, "settings are not a JSON object"),
+            SettingsIssue::UnknownKey {
+                key,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown key '{}' (did you mean '{}'?)", key, suggestion),
+            SettingsIssue::UnknownKey {
+                key,
+                suggestion: None,
+            } => write!(f, "unknown key '{}'", key),
+            SettingsIssue::WrongType {
+                key,
+                expected,
+                found,
+            } => write!(f, "'{}' should be {}, not {}", key, expected.name(), found),
+        }
+    }
+}
+
+/// Check the top-level keys of a settings document
+pub fn check_settings(settings: &Value) -> Vec<SettingsIssue> {
+    let Some(object) = settings.as_object() else {
+        return vec![SettingsIssue::NotAnObject];
+    };
+
+    let mut issues = Vec::new();
+    for (key, value) in object {
+        match KNOWN_KEYS.iter().find(|(known, _)| known == key) {
+            None => issues.push(SettingsIssue::UnknownKey {
+                key: key.clone(),
+                suggestion: closest_key(key),
+            }),
+            Some((_, expected)) => {
+                let found = JsonType::of(value);
+                if found != Some(*expected) {
+                    issues.push(SettingsIssue::WrongType {
+                        key: key.clone(),
+                        expected: *expected,
+                        found: found.map_or("null", JsonType::name),
+                    });
+                }
+            }
+        }
+    }
+    issues
+}
+
+/// Read and check a settings file
+pub fn check_settings_file(path: &Path) -> Result<Vec<SettingsIssue>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let settings = crate::jsonc::from_str(&content)
+        .with_context(|| format!("Invalid JSON in file: {}", path.display()))?;
+    Ok(check_settings(&settings))
+}
+
+/// The known key within a few edits of `key`, ignoring case
+fn closest_key(key: &str) -> Option<&'static str> {
+    let key = key.to_lowercase();
+    let max_distance = (key.chars().count() / 4).clamp(1, 3);
+    KNOWN_KEYS
+        .iter()
+        .map(|(known, _)| (edit_distance(&key, &known.to_lowercase()), *known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known)| known)
+}
+
+/// Edits (insert, delete, substitute, swap neighbours) turning `a` into `b`
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows for i-2, i-1 and i
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (prev[j] + 1).min(row[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = std::mem::replace(&mut prev, row);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_typos_and_wrong_types_are_reported() {
+        let settings = json!({
+            "permisions": {},
+            "modle": "opus",
+            "Hooks": {},
+            "myCustomThing": 1,
+            "cleanupPeriodDays": "30",
+            "env": null,
+            "model": "opus",
+        });
+        let messages: Vec<String> = check_settings(&settings)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "unknown key 'Hooks' (did you mean 'hooks'?)",
+                "'cleanupPeriodDays' should be a number, not a string",
+                "'env' should be an object, not null",
+                "unknown key 'modle' (did you mean 'model'?)",
+                "unknown key 'myCustomThing'",
+                "unknown key 'permisions' (did you mean 'permissions'?)",
+            ]
+        );
+
+        assert!(check_settings(&json!({"model": "opus", "env": {}})).is_empty());
+        assert_eq!(check_settings(&json!([])), vec![SettingsIssue::NotAnObject]);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
@@ -0,0 +1,270 @@
+//! Shared components.
+//!
+//! A shared item is a directory component stored once under
+//! `~/.claude-profiles/shared/<name>/` and referenced from any number of profiles
+//! via `sources` in their metadata (`"agents": "shared:agents-common"`).
+//!
+//! Switching to a profile that references a shared item symlinks the live
+//! component at the shared location, so edits made through any profile are seen
+//! by all of them.
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::components::{Component, ProfileMetadata, SHARED_SOURCE_PREFIX};
+use crate::fs_utils::{CopyReport, copy_dir_recursive};
+use crate::paths::Paths;
+use crate::profiles::{list_profiles, validate_profile_name};
+
+/// A shared item and the profiles referencing it
+#[derive(Debug, Clone)]
+pub struct SharedItem {
+    pub name: String,
+    pub path: PathBuf,
+    /// (profile, component) pairs referencing this item
+    pub references: Vec<(String, Component)>,
+}
+
+/// List all shared items with their referencing profiles
+pub fn list_shared(paths: &Paths) -> Result<Vec<SharedItem>> {
+    let mut items = Vec::new();
+    if !paths.shared_dir.exists() {
+        return Ok(items);
+    }
+
+    for entry in fs::read_dir(&paths.shared_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            items.push(SharedItem {
+                name: name.to_string(),
+                references: shared_references(paths, name)?,
+                path: path.clone(),
+            });
+        }
+    }
+
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(items)
+}
+
+/// Find every (profile, component) pair referencing a shared item
+pub fn shared_references(paths: &Paths, name: &str) -> Result<Vec<(String, Component)>> {
+    let mut references = Vec::new();
+    for profile in list_profiles(paths)? {
+        let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(&profile)) else {
+            continue;
+        };
+        for component in Component::all() {
+            if metadata.shared_source(component) == Some(name) {
+                references.push((profile.clone(), component));
+            }
+        }
+    }
+    Ok(references)
+}
+
+/// Create a shared item from a profile's component or from the live `~/.claude` content
+///
+/// Returns the item's directory with what copying the content reported.
+pub fn create_shared(
+    paths: &Paths,
+    name: &str,
+    component: Component,
+    from_profile: Option<&str>,
+) -> Result<(PathBuf, CopyReport)> {
+    validate_profile_name(name)?;
+    ensure_directory_component(component)?;
+
+    let item = paths.shared_item(name);
+    if item.exists() {
+        bail!("Shared item '{}' already exists", name);
+    }
+
+    let source = match from_profile {
+        Some(profile) => {
+            let metadata = ProfileMetadata::read(&paths.profile_dir(profile))?;
+            metadata.component_path(paths, profile, component)
+        }
+        None => component.source_path(paths),
+    };
+
+    if source.is_dir() {
+        let report = copy_dir_recursive(&source, &item)?;
+        return Ok((item, report));
+    }
+    fs::create_dir_all(&item)
+        .with_context(|| format!("Failed to create shared item: {}", item.display()))?;
+    Ok((item, CopyReport::default()))
+}
+
+/// Make a profile reference a shared item for one of its components
+pub fn link_shared(paths: &Paths, profile: &str, component: Component, name: &str) -> Result<()> {
+    ensure_directory_component(component)?;
+    if !paths.shared_item(name).is_dir() {
+        bail!(
+            "Shared item '{}' does not exist.\nHint: Use 'ccprof shared list' to see available items.",
+            name
+        );
+    }
+
+    let profile_dir = paths.profile_dir(profile);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    metadata.managed_components.insert(component);
+    metadata
+        .sources
+        .insert(component, format!("{}{}", SHARED_SOURCE_PREFIX, name));
+    metadata.updated_at = Utc::now();
+    metadata.write(&profile_dir)
+}
+
+/// Stop referencing a shared item, giving the profile its own copy again
+///
+/// If the profile has no copy of the component yet, the shared content is copied in
+/// so the component stays managed; what that copy reported is returned.
+pub fn unlink_shared(paths: &Paths, profile: &str, component: Component) -> Result<CopyReport> {
+    let profile_dir = paths.profile_dir(profile);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+
+    let Some(name) = metadata.shared_source(component).map(str::to_string) else {
+        bail!(
+            "Profile '{}' does not reference a shared item for {}",
+            profile,
+            component.short_name()
+        );
+    };
+
+    let own_copy = component.profile_path(paths, profile);
+    let shared = paths.shared_item(&name);
+    let report = if !own_copy.exists() && shared.is_dir() {
+        copy_dir_recursive(&shared, &own_copy)?
+    } else {
+        CopyReport::default()
+    };
+
+    metadata.sources.remove(&component);
+    metadata.updated_at = Utc::now();
+    metadata.write(&profile_dir)?;
+    Ok(report)
+}
+
+/// Remove a shared item, refusing while any profile still references it
+pub fn remove_shared(paths: &Paths, name: &str) -> Result<()> {
+    let item = paths.shared_item(name);
+    if !item.exists() {
+        bail!("Shared item '{}' does not exist", name);
+    }
+
+    let references = shared_references(paths, name)?;
+    if !references.is_empty() {
+        let users: Vec<String> = references
+            .iter()
+            .map(|(profile, component)| format!("{} ({})", profile, component.short_name()))
+            .collect();
+        bail!(
+            "Shared item '{}' is still referenced by: {}\nHint: Use 'ccprof shared unlink <profile> --component <component>' first.",
+            name,
+            users.join(", ")
+        );
+    }
+
+    crate::fs_utils::remove_path(&item)
+}
+
+fn ensure_directory_component(component: Component) -> Result<()> {
+    if component.is_file() {
+        bail!(
+            "Component '{}' is a single file.\nHint: Only directory components (agents, hooks, commands) can be shared.",
+            component.short_name()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::create_profile_with_components;
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use tempfile::TempDir;
+
+    fn setup_agents(paths: &Paths) {
+        paths.ensure_dirs().unwrap();
+        let agents = paths.claude_dir.join("agents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(agents.join("reviewer.md"), "review").unwrap();
+    }
+
+    #[test]
+    fn test_shared_item_lifecycle() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        setup_agents(&paths);
+
+        for profile in ["work", "personal"] {
+            create_profile_with_components(&paths, profile, HashSet::from([Component::Agents]))
+                .unwrap();
+        }
+
+        let (_, report) = create_shared(&paths, "agents-common", Component::Agents, None).unwrap();
+        assert_eq!(report, CopyReport::default());
+        link_shared(&paths, "work", Component::Agents, "agents-common").unwrap();
+        link_shared(&paths, "personal", Component::Agents, "agents-common").unwrap();
+
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        assert_eq!(
+            metadata.component_path(&paths, "work", Component::Agents),
+            paths.shared_item("agents-common")
+        );
+
+        let items = list_shared(&paths).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].references.len(), 2);
+
+        // Still referenced: refuse
+        assert!(remove_shared(&paths, "agents-common").is_err());
+
+        assert_eq!(
+            unlink_shared(&paths, "work", Component::Agents).unwrap(),
+            CopyReport::default()
+        );
+        assert_eq!(
+            unlink_shared(&paths, "personal", Component::Agents).unwrap(),
+            CopyReport::default()
+        );
+        remove_shared(&paths, "agents-common").unwrap();
+        assert!(!paths.shared_item("agents-common").exists());
+    }
+
+    #[test]
+    fn test_switch_links_shared_location() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        setup_agents(&paths);
+
+        create_profile_with_components(&paths, "work", HashSet::from([Component::Agents])).unwrap();
+        let (_, report) =
+            create_shared(&paths, "agents-common", Component::Agents, Some("work")).unwrap();
+        assert_eq!(report, CopyReport::default());
+        link_shared(&paths, "work", Component::Agents, "agents-common").unwrap();
+
+        crate::switch::switch_to_profile(&paths, "work", &Default::default()).unwrap();
+
+        let target = fs::read_link(paths.claude_dir.join("agents")).unwrap();
+        assert_eq!(target, paths.shared_item("agents-common"));
+    }
+
+    #[test]
+    fn test_shared_rejects_file_components() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        assert!(create_shared(&paths, "settings-common", Component::Settings, None).is_err());
+    }
+}
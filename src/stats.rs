@@ -0,0 +1,132 @@
+//! Overview of ccprof's data for `ccprof stats`.
+//!
+//! [`collect_stats`] gathers what the other commands show piecemeal: the
+//! profiles and the disk they use, backups per component, the active profile
+//! and the switch counter in state.json.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::components::Component;
+use crate::fs_utils::dir_size_lenient;
+use crate::paths::Paths;
+use crate::profiles::list_profiles;
+use crate::state::State;
+use crate::switch::list_backups;
+
+/// Everything `ccprof stats` shows
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub profiles: usize,
+    /// Bytes used by the profiles directory
+    pub profiles_size: u64,
+    /// One entry per component, including those without backups
+    pub backups: Vec<ComponentBackups>,
+    pub oldest_backup: Option<DateTime<Utc>>,
+    pub newest_backup: Option<DateTime<Utc>>,
+    /// Selected in state.json
+    pub active_profile: Option<String>,
+    /// When the active profile was switched to
+    pub active_since: Option<DateTime<Utc>>,
+    /// Switches recorded in state.json; older versions of ccprof didn't count
+    pub switches: u64,
+}
+
+/// Backups of one component
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentBackups {
+    /// Short name, e.g. "settings"
+    pub component: &'static str,
+    pub count: usize,
+    pub size: u64,
+}
+
+/// Gather [`Stats`]; unreadable files are left out of the sizes
+pub fn collect_stats(paths: &Paths) -> Result<Stats> {
+    let profiles = list_profiles(paths)?;
+    let profiles_size = profiles
+        .iter()
+        .map(|name| dir_size_lenient(&paths.profile_dir(name)).0)
+        .sum();
+
+    let all_backups = list_backups(paths, None);
+    let backups = Component::all()
+        .into_iter()
+        .map(|component| {
+            let of_component = all_backups
+                .iter()
+                .filter(|b| b.component == Some(component.short_name()));
+            ComponentBackups {
+                component: component.short_name(),
+                count: of_component.clone().count(),
+                size: of_component.map(|b| b.size).sum(),
+            }
+        })
+        .collect();
+
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let active_since = state
+        .default_profile
+        .as_ref()
+        .and_then(|name| state.last_used.get(name).copied().or(state.updated_at));
+    Ok(Stats {
+        profiles: profiles.len(),
+        profiles_size,
+        backups,
+        // Most recent first
+        oldest_backup: all_backups.last().map(|b| b.timestamp),
+        newest_backup: all_backups.first().map(|b| b.timestamp),
+        active_profile: state.default_profile,
+        active_since,
+        switches: state.switch_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::switch::{SwitchOptions, switch_to_profile};
+    use crate::test_utils::setup_test_paths;
+    use std::collections::HashSet;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_collect_stats_counts_profiles_backups_and_switches() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        for name in ["work", "home"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+
+        let stats = collect_stats(&paths).unwrap();
+        assert_eq!(stats.profiles, 2);
+        assert!(stats.profiles_size > 0);
+        assert!(stats.backups.iter().all(|b| b.count == 0));
+        assert_eq!(stats.oldest_backup, None);
+        assert_eq!(stats.active_profile, None);
+        assert_eq!(stats.switches, 0);
+
+        // The first switch backs up the regular settings.json
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        switch_to_profile(&paths, "home", &SwitchOptions::default()).unwrap();
+        let stats = collect_stats(&paths).unwrap();
+        let settings = &stats.backups[0];
+        assert_eq!((settings.component, settings.count), ("settings", 1));
+        assert!(settings.size > 0);
+        assert!(stats.oldest_backup.is_some());
+        assert_eq!(stats.oldest_backup, stats.newest_backup);
+        assert_eq!(stats.active_profile.as_deref(), Some("home"));
+        assert!(stats.active_since.is_some());
+        assert_eq!(stats.switches, 2);
+    }
+}
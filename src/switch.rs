@@ -5,38 +5,272 @@
 //! - Backing up existing configuration files.
 //! - Creating symbolic links to the target profile's components.
 //! - Handling edge cases like broken symlinks or missing files.
+//! - Composing directory components from a shared base and individually tracked files.
 //! - Cleaning up old backups.
 
-use anyhow::{Context, Result, bail};
-use chrono::Utc;
+use anyhow::{Context, anyhow, bail};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
 
 use crate::components::{Component, ProfileMetadata};
+use crate::error::Result;
+use crate::fs_utils::CopyReport;
+use crate::journal::{JournalOperation, SwitchJournal};
 use crate::paths::Paths;
-use crate::state::LockedState;
+use crate::plan::Change;
+use crate::state::{LockedState, ManagedCopy, State};
 
-/// Number of backups to keep per component type
-const MAX_BACKUPS: usize = 10;
+/// Backups a switch keeps per component unless config.toml says otherwise
+pub const DEFAULT_BACKUPS_KEEP: usize = 10;
+
+/// How profile content is put in place in ~/.claude
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SwitchStrategy {
+    /// Symlink ~/.claude entries at the profile (default)
+    #[default]
+    Symlink,
+    /// Copy profile content into ~/.claude, for systems where symlinks don't work
+    Copy,
+}
+
+impl std::str::FromStr for SwitchStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "symlink" => Ok(Self::Symlink),
+            "copy" => Ok(Self::Copy),
+            _ => Err(format!(
+                "invalid switch strategy: {} (expected symlink or copy)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SwitchStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Symlink => write!(f, "symlink"),
+            Self::Copy => write!(f, "copy"),
+        }
+    }
+}
+
+/// How symlinks into the profiles directory are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStyle {
+    /// Absolute targets (default)
+    #[default]
+    Absolute,
+    /// Targets relative to the link, for home directories mounted at different paths
+    Relative,
+}
+
+impl std::str::FromStr for LinkStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            _ => Err(format!(
+                "invalid link style: {} (expected absolute or relative)",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for LinkStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Absolute => write!(f, "absolute"),
+            Self::Relative => write!(f, "relative"),
+        }
+    }
+}
+
+/// Options controlling a profile switch
+#[derive(Debug, Clone, Default)]
+pub struct SwitchOptions {
+    /// Strategy for this switch; `None` uses the default persisted in state
+    pub strategy: Option<SwitchStrategy>,
+    /// Symlink style for this switch; `None` uses the default persisted in state
+    pub link_style: Option<LinkStyle>,
+    /// Skip components missing from the profile instead of failing
+    pub skip_missing: bool,
+    /// With `skip_missing`, also drop skipped components from the profile metadata
+    pub prune_metadata: bool,
+    /// How replaced content is backed up
+    pub backup: BackupOptions,
+    /// Fail instead of copying when the OS denies creating symlinks (Windows
+    /// without Developer Mode)
+    pub no_fallback: bool,
+    /// Replace symlinks pointing outside ccprof's directories (their content is backed up)
+    pub force: bool,
+}
+
+/// How backups of replaced content are written and rotated
+#[derive(Debug, Clone)]
+pub struct BackupOptions {
+    /// Back up replaced content even if the newest backup is identical
+    pub force: bool,
+    /// Backups kept per component when rotating (`backups.keep_per_component`)
+    pub keep: usize,
+    /// Arguments of the ccprof command taking the backup, for its manifest
+    pub command: Option<String>,
+    /// How directories are copied into the backup
+    pub copy: crate::fs_utils::CopyOptions,
+}
+
+impl Default for BackupOptions {
+    fn default() -> Self {
+        Self {
+            force: false,
+            keep: DEFAULT_BACKUPS_KEEP,
+            command: None,
+            copy: crate::fs_utils::CopyOptions::default(),
+        }
+    }
+}
+
+/// Outcome of a successful switch
+#[derive(Debug, Default)]
+pub struct SwitchReport {
+    /// Components put in place, in switch order
+    pub components: Vec<ComponentOutcome>,
+    /// Backups created for replaced user content (identical backups are not repeated)
+    pub backups: Vec<PathBuf>,
+    /// Profile that was active before the switch
+    pub previous_profile: Option<String>,
+    /// Managed components missing from the profile, left untouched in ~/.claude
+    pub skipped: Vec<Component>,
+    /// Whether skipped components were dropped from the profile metadata
+    pub pruned: bool,
+}
+
+/// How a component was put in place by a switch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComponentAction {
+    /// Symlinked to the profile (or a shared item)
+    Linked,
+    /// Directory composed from per-entry symlinks (tracked files)
+    Composed,
+    /// Profile content copied into ~/.claude
+    Copied,
+}
+
+impl ComponentAction {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Linked => "Linked",
+            Self::Composed => "Composed",
+            Self::Copied => "Copied",
+        }
+    }
+}
+
+/// What a switch did to one component
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentOutcome {
+    pub component: Component,
+    pub action: ComponentAction,
+    /// Content the live path now provides
+    pub target: PathBuf,
+    /// Backup holding the replaced user content, if there was any
+    pub backup: Option<PathBuf>,
+}
+
+/// Progress of a switch, reported by [`switch_to_profile_with_progress`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwitchEvent {
+    /// About to back up a component holding `files` files
+    BackupStarted { component: Component, files: u64 },
+    /// One more file of the component was copied into the backup
+    FileBackedUp { component: Component },
+    /// The component was backed up to `backup`
+    BackedUp {
+        component: Component,
+        backup: PathBuf,
+    },
+    /// The newest backup, `backup`, already holds identical content
+    BackupUnchanged {
+        component: Component,
+        backup: PathBuf,
+    },
+    /// The backup just made left out the unreadable entries in `skipped`
+    BackupIncomplete {
+        component: Component,
+        skipped: Vec<String>,
+    },
+    /// Copying the component, into a backup or ~/.claude, skipped a symlink,
+    /// copied one as a link or stopped at a directory too deep
+    CopyWarning {
+        component: Component,
+        warning: String,
+    },
+    /// Symlinks are not permitted, so the component is copied instead
+    SymlinkFallback { component: Component },
+    /// The profile's content is in place for the component
+    Applied {
+        component: Component,
+        action: ComponentAction,
+    },
+    /// Another process holds the state lock; the switch waits for it
+    WaitingForLock,
+}
 
 /// Represents the status of the ~/.claude/settings.json file
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum SettingsStatus {
     Missing,
     RegularFile,
-    Symlink { target: PathBuf },
-    BrokenSymlink { target: PathBuf },
+    Symlink {
+        target: PathBuf,
+    },
+    BrokenSymlink {
+        target: PathBuf,
+    },
+    /// A regular file that ccprof copied from a profile (copy strategy)
+    ManagedCopy {
+        profile: String,
+        drifted: bool,
+    },
 }
 
 impl SettingsStatus {
+    /// Detect status, recognizing regular files that are ccprof-managed copies
+    pub fn detect_with_state(path: &Path, state: &State) -> Self {
+        match Self::detect(path) {
+            Self::RegularFile => match managed_copy_status(state, Component::Settings, path) {
+                Some((profile, drifted)) => Self::ManagedCopy { profile, drifted },
+                None => Self::RegularFile,
+            },
+            other => other,
+        }
+    }
+
     pub fn detect(path: &Path) -> Self {
         // Use symlink_metadata to check if it's a symlink without following it
         match fs::symlink_metadata(path) {
             Ok(meta) => {
                 if meta.file_type().is_symlink() {
                     match fs::read_link(path) {
-                        Ok(target) => Self::Symlink { target },
+                        Ok(target) => {
+                            let target = crate::fs_utils::resolve_link_target(path, &target);
+                            if path.exists() {
+                                Self::Symlink { target }
+                            } else {
+                                Self::BrokenSymlink { target }
+                            }
+                        }
                         Err(_) => {
                             // Can't read link target?
                             Self::BrokenSymlink {
@@ -65,254 +299,3210 @@ impl SettingsStatus {
     }
 
     pub fn is_profile_symlink(&self, paths: &Paths) -> bool {
-        match self {
-            Self::Symlink { target } => {
-                // Check if target is inside profiles directory
-                // We need to resolve absolute paths for accurate comparison
-                // But generally checking prefix is enough
-                paths.is_in_profiles_dir(target)
-            }
-            _ => false,
+        self.profile_target(paths).is_some()
+    }
+
+    /// Where the symlink leads inside the profiles directory
+    ///
+    /// Chains of symlinks are followed, so `settings.json → a → <profile file>` counts.
+    pub fn profile_target(&self, paths: &Paths) -> Option<PathBuf> {
+        let Self::Symlink { target } = self else {
+            return None;
+        };
+        if paths.is_in_profiles_dir(target) {
+            return Some(target.clone());
         }
+        crate::fs_utils::link_chain_end(target).filter(|end| paths.is_in_profiles_dir(end))
     }
 }
 
+/// One component of a planned switch
+struct PlannedComponent {
+    component: Component,
+    /// Location in ~/.claude
+    live_path: PathBuf,
+    /// Content the live path should provide (profile, shared item)
+    content_path: PathBuf,
+    /// Live content is a user's regular file or directory and must be backed up
+    needs_backup: bool,
+    /// Target of a live symlink ccprof doesn't manage, replaced with `force`
+    foreign_target: Option<PathBuf>,
+}
+
 /// Switch to a specific profile
-pub fn switch_to_profile(paths: &Paths, name: &str) -> Result<()> {
-    if !crate::profiles::profile_exists(paths, name) {
-        bail!("Profile '{}' does not exist", name);
+///
+/// The switch runs in three phases so a failure never leaves ~/.claude half on
+/// the old profile: every component is planned and validated first, then all
+/// backups are taken, then components are applied one by one. Each live path is
+/// moved aside before it is replaced, and if any step (or the state update)
+/// fails, the components applied so far are put back as they were.
+pub fn switch_to_profile(
+    paths: &Paths,
+    name: &str,
+    options: &SwitchOptions,
+) -> Result<SwitchReport> {
+    switch_to_profile_with_progress(paths, name, options, &mut |_| {})
+}
+
+/// What [`switch_to_profile`] would change, without changing it
+///
+/// The same checks run as for the switch. Inherited settings aren't
+/// regenerated, so the plan links the settings.resolved.json generated last.
+pub fn plan_switch_changes(
+    paths: &Paths,
+    name: &str,
+    options: &SwitchOptions,
+) -> Result<Vec<Change>> {
+    ensure_profile_exists(paths, name)?;
+    ensure_no_interrupted_switch(paths)?;
+
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let strategy = options.strategy.or(state.strategy).unwrap_or_default();
+    let plan = plan_switch(paths, name, &metadata, &state, strategy, options)?;
+
+    let mut changes = Vec::new();
+    for planned in &plan.components {
+        if planned.needs_backup {
+            changes.push(Change::Copy {
+                from: planned.live_path.clone(),
+                to: paths.backups_dir.clone(),
+            });
+        }
+        changes.push(
+            match component_action(&metadata, planned.component, strategy) {
+                ComponentAction::Linked | ComponentAction::Composed => Change::Link {
+                    link: planned.live_path.clone(),
+                    target: planned.content_path.clone(),
+                },
+                ComponentAction::Copied => Change::Copy {
+                    from: planned.content_path.clone(),
+                    to: planned.live_path.clone(),
+                },
+            },
+        );
+    }
+    changes.push(Change::State {
+        detail: format!("select profile '{}'", name),
+    });
+    Ok(changes)
+}
+
+fn ensure_no_interrupted_switch(paths: &Paths) -> Result<()> {
+    if let Some(journal) = SwitchJournal::read(&paths.journal_file)? {
+        return Err(anyhow!(
+            "A previous switch to '{}' was interrupted.\nHint: Run 'ccprof doctor --fix' to recover it first.",
+            journal.profile
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Fail unless `name` is a profile, pointing out templates used by mistake
+fn ensure_profile_exists(paths: &Paths, name: &str) -> Result<()> {
+    if !crate::profiles::profile_exists(paths, name)
+        && crate::templates::template_exists(paths, name)
+    {
+        return Err(anyhow!(
+            "'{}' is a template, not a profile.\nHint: Create a profile from it with 'ccprof add <name> --template {}'.",
+            name,
+            name
+        )
+        .into());
     }
+    crate::profiles::ensure_profile_exists(paths, name)
+}
+
+/// [`switch_to_profile`], reporting each step to `on_event` as it happens
+pub fn switch_to_profile_with_progress(
+    paths: &Paths,
+    name: &str,
+    options: &SwitchOptions,
+    on_event: &mut dyn FnMut(SwitchEvent),
+) -> Result<SwitchReport> {
+    ensure_profile_exists(paths, name)?;
+    ensure_no_interrupted_switch(paths)?;
 
     let profile_dir = paths.profile_dir(name);
     let metadata = ProfileMetadata::read(&profile_dir)?;
+    // Regenerate inherited settings so edits to a base are picked up
+    crate::inheritance::materialize_settings(paths, name)?;
 
-    // 1. Process each managed component
-    for component in &metadata.managed_components {
-        let source_path = component.source_path(paths);
-        let target_path = component.profile_path(paths, name);
-
-        // Ensure target exists in profile (it should if metadata is correct)
-        if !target_path.exists() {
-            // Warn? Fail?
-            // If it's missing in profile, we can't link to it.
-            eprintln!(
-                "Warning: Component {} missing in profile {}, skipping.",
-                component.display_name(),
-                name
-            );
-            continue;
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let strategy = options.strategy.or(state.strategy).unwrap_or_default();
+    let style = options.link_style.or(state.link_style).unwrap_or_default();
+
+    // 1. Plan and validate every component before touching ~/.claude
+    let SwitchPlan {
+        components: plan,
+        skipped,
+    } = plan_switch(paths, name, &metadata, &state, strategy, options)?;
+    let prune = options.prune_metadata && !skipped.is_empty();
+
+    // 2. Back up user content that is about to be replaced
+    let mut backups = HashMap::new();
+    let mut created = Vec::new();
+    for planned in plan.iter().filter(|p| p.needs_backup) {
+        let component = planned.component;
+        on_event(SwitchEvent::BackupStarted {
+            component,
+            files: crate::fs_utils::count_files(&planned.live_path),
+        });
+        let backup = backup_existing_file_with_progress(
+            &planned.live_path,
+            paths,
+            component.short_name(),
+            planned.foreign_target.as_deref(),
+            &options.backup,
+            &mut |_| on_event(SwitchEvent::FileBackedUp { component }),
+        )?;
+        match backup {
+            Some(BackupOutcome::Unchanged(backup)) => {
+                on_event(SwitchEvent::BackupUnchanged {
+                    component,
+                    backup: backup.clone(),
+                });
+                backups.insert(component, backup);
+            }
+            Some(BackupOutcome::Created {
+                path: backup,
+                report,
+            }) => {
+                on_event(SwitchEvent::BackedUp {
+                    component,
+                    backup: backup.clone(),
+                });
+                if !report.skipped.is_empty() {
+                    on_event(SwitchEvent::BackupIncomplete {
+                        component,
+                        skipped: report.skipped,
+                    });
+                }
+                for warning in report.warnings {
+                    on_event(SwitchEvent::CopyWarning { component, warning });
+                }
+                created.push(backup.clone());
+                backups.insert(component, backup);
+            }
+            None => {}
+        }
+    }
+
+    // 3. Apply, rolling back on any failure
+    let mut transaction = SwitchTransaction::begin(
+        paths,
+        name,
+        state.default_profile.clone(),
+        &plan,
+        strategy,
+        style,
+        options.skip_missing,
+    )?;
+    let mut outcomes = Vec::with_capacity(plan.len());
+    let result = (|| -> anyhow::Result<()> {
+        let mut copies = HashMap::new();
+        for (index, planned) in plan.iter().enumerate() {
+            transaction.stage(index)?;
+            let applied = apply_component(paths, name, &metadata, planned, strategy, style);
+            let (action, copy) = match applied {
+                // Composed directories are made of symlinks; there is nothing to copy
+                Err(e)
+                    if !options.no_fallback
+                        && is_symlink_denied(&e)
+                        && component_action(&metadata, planned.component, strategy)
+                            == ComponentAction::Linked =>
+                {
+                    on_event(SwitchEvent::SymlinkFallback {
+                        component: planned.component,
+                    });
+                    (
+                        ComponentAction::Copied,
+                        Some(copy_planned(paths, name, planned)?),
+                    )
+                }
+                other => other?,
+            };
+            if let Some((copy, report)) = copy {
+                for warning in report.into_warnings() {
+                    on_event(SwitchEvent::CopyWarning {
+                        component: planned.component,
+                        warning,
+                    });
+                }
+                copies.insert(planned.component, copy);
+            }
+            transaction.mark_done(index)?;
+
+            on_event(SwitchEvent::Applied {
+                component: planned.component,
+                action,
+            });
+            outcomes.push(ComponentOutcome {
+                component: planned.component,
+                action,
+                target: planned.content_path.clone(),
+                backup: backups.get(&planned.component).cloned(),
+            });
+        }
+
+        // Re-detect every component; some filesystems accept a symlink that then
+        // resolves somewhere else
+        for (planned, outcome) in plan.iter().zip(&outcomes) {
+            verify_component(
+                paths,
+                name,
+                &metadata,
+                planned,
+                outcome.action,
+                copies.get(&planned.component),
+            )?;
+        }
+
+        if prune {
+            let mut pruned = ProfileMetadata::read(&profile_dir)?;
+            for component in &skipped {
+                pruned.managed_components.remove(component);
+                pruned.tracked_files.remove(component);
+                pruned.sources.remove(component);
+            }
+            pruned.updated_at = Utc::now();
+            pruned.write(&profile_dir)?;
+        }
+
+        let mut locked = LockedState::lock_or_wait(&paths.state_file, &mut || {
+            on_event(SwitchEvent::WaitingForLock)
+        })?;
+        locked.update(|s| {
+            let now = Utc::now();
+            s.default_profile = Some(name.to_string());
+            s.updated_at = Some(now);
+            s.last_used.insert(name.to_string(), now);
+            s.switch_count += 1;
+            for planned in &plan {
+                s.active_components
+                    .insert(planned.component, name.to_string());
+            }
+            for planned in &plan {
+                s.copies.remove(&planned.component);
+            }
+            s.copies.extend(copies);
+        })?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            transaction.commit()?;
+            Ok(SwitchReport {
+                components: outcomes,
+                backups: created,
+                previous_profile: state.default_profile,
+                skipped,
+                pruned: prune,
+            })
         }
+        Err(e) => match transaction.rollback() {
+            Ok(()) => Err(e
+                .context(format!(
+                    "Failed to switch to profile '{}'; ~/.claude was restored",
+                    name
+                ))
+                .into()),
+            Err(rollback_err) => Err(e
+                .context(format!(
+                    "Failed to switch to profile '{}', and restoring ~/.claude also failed: {:#}",
+                    name, rollback_err
+                ))
+                .into()),
+        },
+    }
+}
+
+/// Live user content that a switch would replace but differs from the profile
+#[derive(Debug, Clone)]
+pub struct LiveDrift {
+    pub component: Component,
+    /// Location in ~/.claude
+    pub live_path: PathBuf,
+    /// The profile's copy the live content would be replaced with
+    pub profile_path: PathBuf,
+    /// Where adopting copies the live content; differs from `profile_path` for
+    /// generated settings, whose edits belong in the profile's own settings.json
+    pub adopt_path: PathBuf,
+}
 
-        create_component_symlink(&source_path, &target_path, component, &paths.backups_dir)?;
+/// Find regular files and directories in ~/.claude with edits the profile lacks
+///
+/// These are the components a switch would back up and replace; the backup keeps
+/// the content, but the edits no longer apply once the switch is done. Nothing
+/// is written: inherited settings are compared as the switch would resolve them.
+pub fn detect_live_drift(
+    paths: &Paths,
+    name: &str,
+    options: &SwitchOptions,
+) -> Result<Vec<LiveDrift>> {
+    ensure_profile_exists(paths, name)?;
+    let resolved = crate::inheritance::resolved_settings_text(paths, name)?;
+
+    let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let strategy = options.strategy.or(state.strategy).unwrap_or_default();
+
+    Ok(
+        plan_switch(paths, name, &metadata, &state, strategy, options)?
+            .components
+            .into_iter()
+            .filter(|p| {
+                p.needs_backup
+                    && match (&resolved, p.component) {
+                        (Some(resolved), Component::Settings) => {
+                            fs::read(&p.live_path).ok().as_deref() != Some(resolved.as_bytes())
+                        }
+                        _ => !crate::fs_utils::same_content(&p.live_path, &p.content_path),
+                    }
+            })
+            .map(|p| LiveDrift {
+                component: p.component,
+                live_path: p.live_path,
+                profile_path: p.content_path,
+                adopt_path: metadata.component_path(paths, name, p.component),
+            })
+            .collect(),
+    )
+}
+
+/// Copy drifted live content into the profile so the switch keeps it,
+/// returning what the copy reported
+pub fn adopt_live_drift(drift: &LiveDrift) -> Result<CopyReport> {
+    if drift.component == Component::Settings {
+        crate::profiles::validate_json_file(&drift.live_path).with_context(|| {
+            format!(
+                "Refusing to adopt {}: it is not valid JSON",
+                drift.live_path.display()
+            )
+        })?;
     }
 
-    // 2. Update state
-    let mut locked = LockedState::lock(&paths.state_file)?;
-    locked.update(|s| {
-        s.default_profile = Some(name.to_string());
-        s.updated_at = Some(Utc::now());
+    crate::fs_utils::remove_path(&drift.adopt_path)?;
+    crate::audit::record(format!(
+        "copy {} -> {}",
+        drift.live_path.display(),
+        drift.adopt_path.display()
+    ));
+    if drift.live_path.is_dir() {
+        return Ok(crate::fs_utils::copy_dir_recursive(
+            &drift.live_path,
+            &drift.adopt_path,
+        )?);
+    }
+    fs::copy(&drift.live_path, &drift.adopt_path).with_context(|| {
+        format!(
+            "Failed to copy {} to {}",
+            drift.live_path.display(),
+            drift.adopt_path.display()
+        )
     })?;
+    Ok(CopyReport::default())
+}
 
-    Ok(())
+/// Validated components of a switch
+struct SwitchPlan {
+    components: Vec<PlannedComponent>,
+    /// Components missing from the profile, skipped with `skip_missing`
+    skipped: Vec<Component>,
 }
 
-/// Create a symlink for a component, handling backups
-pub fn create_component_symlink(
-    link_path: &Path,
-    target_path: &Path,
-    component: &Component,
-    backups_dir: &Path,
-) -> Result<()> {
-    let status = ComponentStatus::detect(link_path);
+fn plan_switch(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    state: &State,
+    strategy: SwitchStrategy,
+    options: &SwitchOptions,
+) -> anyhow::Result<SwitchPlan> {
+    let mut plan = Vec::new();
+    let mut skipped = Vec::new();
 
-    match status {
-        ComponentStatus::Missing => {
-            // Just create symlink
-            make_symlink(target_path, link_path)?;
+    // Fixed order keeps switches (and rollbacks) deterministic
+    for component in Component::all() {
+        if !metadata.managed_components.contains(&component) {
+            continue;
         }
-        ComponentStatus::RegularFile | ComponentStatus::Directory => {
-            // Backup then replace
-            backup_existing_file(link_path, backups_dir, component.short_name())?;
-            if link_path.is_dir() {
-                fs::remove_dir_all(link_path)?;
-            } else {
-                fs::remove_file(link_path)?;
+        let live_path = component.source_path(paths);
+        let content_path = metadata.link_path(paths, name, component);
+
+        // Ensure target exists in profile (it should if metadata is correct);
+        // inherited settings are generated by the switch itself
+        let generated = component == Component::Settings && metadata.extends.is_some();
+        if !generated && !content_path.exists() {
+            if options.skip_missing {
+                skipped.push(component);
+                continue;
             }
-            make_symlink(target_path, link_path)?;
+            bail!(
+                "Component {} is missing from profile '{}' ({})\nHint: Use --skip-missing to switch without it.",
+                component.display_name(),
+                name,
+                content_path.display()
+            );
         }
-        ComponentStatus::Symlink { .. } => {
-            // Remove old link and create new one
-            fs::remove_file(link_path)?; // remove_file removes the symlink itself
-            make_symlink(target_path, link_path)?;
+
+        let composed = metadata.shared_source(component).is_none()
+            && metadata.tracked_files_for(component).is_some()
+            && !component.is_file();
+        if composed {
+            if strategy == SwitchStrategy::Copy {
+                bail!(
+                    "Component {} tracks individual files, which requires the symlink strategy",
+                    component.display_name()
+                );
+            }
+            for file in metadata.tracked_files_for(component).unwrap_or_default() {
+                let target = content_path.join(file);
+                if fs::symlink_metadata(&target).is_err() {
+                    bail!(
+                        "Tracked file '{}/{}' is missing from the profile ({})",
+                        component.short_name(),
+                        file,
+                        target.display()
+                    );
+                }
+            }
         }
-        ComponentStatus::BrokenSymlink { .. } => {
-            fs::remove_file(link_path)?;
-            make_symlink(target_path, link_path)?;
+
+        // Symlinks into locations ccprof doesn't manage belong to another tool
+        let foreign_target = match ComponentStatus::detect(&live_path) {
+            ComponentStatus::Symlink { target } | ComponentStatus::BrokenSymlink { target }
+                if !paths.is_managed_target(&target)
+                    && !crate::fs_utils::link_chain_end(&target)
+                        .is_some_and(|end| paths.is_managed_target(&end)) =>
+            {
+                Some(target)
+            }
+            _ => None,
+        };
+        if let Some(target) = &foreign_target
+            && !options.force
+        {
+            bail!(
+                "{} at {} is a symlink to {}, which ccprof doesn't manage\nHint: Use --force to back up its content and replace it.",
+                component.display_name(),
+                live_path.display(),
+                target.display()
+            );
         }
-    }
 
-    Ok(())
-}
+        // Our own composed directories and untouched copies are safe to replace
+        let needs_backup = match ComponentStatus::detect(&live_path) {
+            ComponentStatus::Symlink { .. } => foreign_target.is_some(),
+            ComponentStatus::Directory if is_composed_dir(paths, &live_path) => false,
+            ComponentStatus::RegularFile | ComponentStatus::Directory => !matches!(
+                managed_copy_status(state, component, &live_path),
+                Some((_, false))
+            ),
+            _ => false,
+        };
 
-#[derive(Debug)]
-pub enum ComponentStatus {
-    Missing,
-    RegularFile,
-    Directory,
-    Symlink { target: PathBuf },
-    BrokenSymlink { target: PathBuf },
+        plan.push(PlannedComponent {
+            component,
+            live_path,
+            content_path,
+            needs_backup,
+            foreign_target,
+        });
+    }
+
+    Ok(SwitchPlan {
+        components: plan,
+        skipped,
+    })
 }
 
-impl ComponentStatus {
-    pub fn detect(path: &Path) -> Self {
-        // Check if it's a symlink first
-        if let Ok(target) = fs::read_link(path) {
-            // It is a symlink. Is it broken?
-            if path.exists() {
-                Self::Symlink { target }
-            } else {
-                Self::BrokenSymlink { target }
+/// Check that a component applied by the switch really provides the profile content
+fn verify_component(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    planned: &PlannedComponent,
+    action: ComponentAction,
+    copy: Option<&ManagedCopy>,
+) -> anyhow::Result<()> {
+    let component = planned.component;
+    let live = &planned.live_path;
+    match action {
+        ComponentAction::Linked => {
+            let expected = crate::fs_utils::normalize_path(&planned.content_path);
+            let resolves = |p: &Path| fs::canonicalize(p).ok();
+            match ComponentStatus::detect(live) {
+                ComponentStatus::Symlink { target }
+                    if target == expected && resolves(live) == resolves(&expected) => {}
+                ComponentStatus::Symlink { target } | ComponentStatus::BrokenSymlink { target } => {
+                    bail!(
+                        "{} symlink at {} resolves to {}, expected {}",
+                        component.display_name(),
+                        live.display(),
+                        resolves(live).unwrap_or(target).display(),
+                        expected.display()
+                    );
+                }
+                _ => bail!(
+                    "{} at {} is not a symlink after switching",
+                    component.display_name(),
+                    live.display()
+                ),
             }
-        } else if path.exists() {
-            if path.is_dir() {
-                Self::Directory
-            } else {
-                Self::RegularFile
+        }
+        ComponentAction::Composed => {
+            let tracked = metadata.tracked_files_for(component).unwrap_or_default();
+            let entries = inspect_composed_dir(paths, name, &component, tracked)?;
+            if !entries.is_some_and(|e| e.iter().all(|e| e.is_expected())) {
+                bail!(
+                    "{} at {} was not composed as expected",
+                    component.display_name(),
+                    live.display()
+                );
+            }
+        }
+        ComponentAction::Copied => {
+            let hash = crate::fs_utils::hash_path(live)?;
+            if copy.is_none_or(|c| c.hash != hash) {
+                bail!(
+                    "{} at {} does not match the copied profile content",
+                    component.display_name(),
+                    live.display()
+                );
             }
-        } else {
-            Self::Missing
         }
     }
+    Ok(())
 }
 
-fn make_symlink(target: &Path, link: &Path) -> Result<()> {
-    // Create parent dir if missing
-    if let Some(parent) = link.parent() {
-        fs::create_dir_all(parent)?;
+/// How `apply_component` puts a component in place
+fn component_action(
+    metadata: &ProfileMetadata,
+    component: Component,
+    strategy: SwitchStrategy,
+) -> ComponentAction {
+    match strategy {
+        SwitchStrategy::Copy => ComponentAction::Copied,
+        SwitchStrategy::Symlink
+            if !component.is_file()
+                && metadata.shared_source(component).is_none()
+                && metadata.tracked_files_for(component).is_some() =>
+        {
+            ComponentAction::Composed
+        }
+        SwitchStrategy::Symlink => ComponentAction::Linked,
     }
+}
 
-    #[cfg(unix)]
-    symlink(target, link).with_context(|| {
-        format!(
-            "Failed to create symlink from {} to {}",
-            link.display(),
-            target.display()
-        )
-    })?;
+/// Put one planned component in place; the live path has already been staged
+fn apply_component(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    planned: &PlannedComponent,
+    strategy: SwitchStrategy,
+    style: LinkStyle,
+) -> anyhow::Result<(ComponentAction, Option<(ManagedCopy, CopyReport)>)> {
+    let action = component_action(metadata, planned.component, strategy);
+    match action {
+        ComponentAction::Copied => Ok((action, Some(copy_planned(paths, name, planned)?))),
+        ComponentAction::Linked | ComponentAction::Composed => {
+            link_component(paths, name, metadata, &planned.component, style)?;
+            Ok((action, None))
+        }
+    }
+}
 
-    #[cfg(windows)]
-    std::os::windows::fs::symlink_file(target, link).with_context(|| {
-        format!(
-            "Failed to create symlink from {} to {}",
-            link.display(),
-            target.display()
-        )
-    })?;
+fn copy_planned(
+    paths: &Paths,
+    name: &str,
+    planned: &PlannedComponent,
+) -> anyhow::Result<(ManagedCopy, CopyReport)> {
+    let (hash, report) = copy_component(
+        &planned.live_path,
+        &planned.content_path,
+        &planned.component,
+        paths,
+    )?;
+    let copy = ManagedCopy {
+        profile: name.to_string(),
+        hash,
+    };
+    Ok((copy, report))
+}
 
-    Ok(())
+/// Windows error for a symlink created without SeCreateSymbolicLinkPrivilege
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+/// Whether creating a symlink failed because the OS doesn't allow it for this user
+fn is_symlink_denied(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .and_then(|e| e.raw_os_error())
+            .is_some_and(|code| cfg!(windows) && code == ERROR_PRIVILEGE_NOT_HELD)
+    })
 }
 
-// Fixed version of backup_component that actually does the work
-// We need to inject paths or the backup directory.
-// Refactoring `create_component_symlink` to take `backups_dir`.
+/// A switch in progress, journaled so it can be undone even after a crash
+struct SwitchTransaction {
+    journal: SwitchJournal,
+    journal_file: PathBuf,
+}
 
-pub fn backup_existing_file(path: &Path, backups_dir: &Path, name_prefix: &str) -> Result<()> {
-    if !path.exists() {
-        return Ok(());
+impl SwitchTransaction {
+    /// Record the planned operations before anything in ~/.claude changes
+    fn begin(
+        paths: &Paths,
+        profile: &str,
+        previous_profile: Option<String>,
+        plan: &[PlannedComponent],
+        strategy: SwitchStrategy,
+        link_style: LinkStyle,
+        skip_missing: bool,
+    ) -> anyhow::Result<Self> {
+        let staging_dir = paths.staging_dir.join(format!(
+            "{}-{}",
+            Utc::now().format("%Y%m%d_%H%M%S%.f"),
+            std::process::id()
+        ));
+        fs::create_dir_all(&staging_dir).with_context(|| {
+            format!(
+                "Failed to create staging directory {}",
+                staging_dir.display()
+            )
+        })?;
+
+        let operations = plan
+            .iter()
+            .map(|p| JournalOperation {
+                component: p.component,
+                live_path: p.live_path.clone(),
+                staged_path: staging_dir.join(p.component.short_name()),
+                original_exists: fs::symlink_metadata(&p.live_path).is_ok(),
+                done: false,
+            })
+            .collect();
+
+        let journal = SwitchJournal {
+            profile: profile.to_string(),
+            previous_profile,
+            started_at: Utc::now(),
+            strategy,
+            link_style,
+            skip_missing,
+            staging_dir,
+            operations,
+        };
+        journal.write(&paths.journal_file)?;
+
+        Ok(Self {
+            journal,
+            journal_file: paths.journal_file.clone(),
+        })
     }
 
-    if !backups_dir.exists() {
-        fs::create_dir_all(backups_dir)?;
+    /// Move the live path's current content (if any) out of the way
+    fn stage(&mut self, index: usize) -> anyhow::Result<()> {
+        let op = &self.journal.operations[index];
+        if op.original_exists {
+            crate::fs_utils::move_path(&op.live_path, &op.staged_path)?;
+        }
+        Ok(())
     }
 
-    let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
-    let backup_name = format!("{}.{}.bak", name_prefix, timestamp);
-    let backup_path = backups_dir.join(backup_name);
+    /// Check off an applied operation
+    fn mark_done(&mut self, index: usize) -> anyhow::Result<()> {
+        self.journal.operations[index].done = true;
+        self.journal.write(&self.journal_file)
+    }
 
-    if path.is_dir() {
-        crate::fs_utils::copy_dir_recursive(path, &backup_path)?;
-    } else {
-        fs::copy(path, &backup_path)?;
+    /// Discard the staged originals after a successful switch
+    fn commit(self) -> anyhow::Result<()> {
+        crate::journal::remove_staging(&self.journal.staging_dir)?;
+        crate::journal::remove(&self.journal_file)
     }
 
-    // Rotate backups
-    cleanup_old_backups(backups_dir, name_prefix)?;
+    /// Undo applied components in reverse order, restoring their originals
+    fn rollback(self) -> anyhow::Result<()> {
+        self.journal.roll_back()?;
+        crate::journal::remove(&self.journal_file)
+    }
+}
 
-    Ok(())
+/// Check whether live content is a ccprof-managed copy
+///
+/// Returns the profile it was copied from and whether it has drifted since, or
+/// `None` if state has no copy recorded for the component or the path is gone.
+pub fn managed_copy_status(
+    state: &State,
+    component: Component,
+    path: &Path,
+) -> Option<(String, bool)> {
+    let copy = state.copies.get(&component)?;
+    if fs::symlink_metadata(path).ok()?.file_type().is_symlink() {
+        return None;
+    }
+    let drifted = crate::fs_utils::hash_path(path).map_or(true, |hash| hash != copy.hash);
+    Some((copy.profile.clone(), drifted))
 }
 
-fn cleanup_old_backups(backups_dir: &Path, name_prefix: &str) -> Result<()> {
-    let mut backups: Vec<_> = fs::read_dir(backups_dir)?
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_name()
-                .to_str()
-                .is_some_and(|n| n.starts_with(name_prefix) && n.ends_with(".bak"))
-        })
-        .collect();
+/// Copy a profile component into ~/.claude and return the content hash, with
+/// what the copies reported
+fn copy_component(
+    link_path: &Path,
+    content_path: &Path,
+    component: &Component,
+    paths: &Paths,
+) -> anyhow::Result<(String, CopyReport)> {
+    let mut report = CopyReport::default();
+    if matches!(
+        ComponentStatus::detect(link_path),
+        ComponentStatus::RegularFile
+            | ComponentStatus::Directory
+            | ComponentStatus::ManagedCopy { .. }
+    ) && let Some(BackupOutcome::Created {
+        report: backed_up, ..
+    }) = backup_existing_file(link_path, paths, component.short_name())?
+    {
+        report.extend(backed_up);
+    }
 
-    if backups.len() <= MAX_BACKUPS {
-        return Ok(());
+    crate::audit::record(format!(
+        "copy {} -> {}",
+        content_path.display(),
+        link_path.display()
+    ));
+    if let Some(parent) = link_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if content_path.is_dir() {
+        // Whatever was there (already backed up) is swapped out in one rename
+        report.extend(crate::fs_utils::replace_dir_atomic(
+            content_path,
+            link_path,
+        )?);
+    } else {
+        crate::fs_utils::remove_path(link_path)?;
+        fs::copy(content_path, link_path).with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                content_path.display(),
+                link_path.display()
+            )
+        })?;
     }
 
-    // Sort by modified time (oldest first)
-    backups.sort_by_key(|b| b.metadata().and_then(|m| m.modified()).ok());
+    Ok((crate::fs_utils::hash_path(link_path)?, report))
+}
+
+/// Write edits made to copy-mode components back into their profiles
+///
+/// Returns the components whose drifted live content was synced, with what
+/// copying them reported. `on_wait` is called if another process holds the
+/// state lock.
+pub fn sync_copies(
+    paths: &Paths,
+    on_wait: &mut dyn FnMut(),
+) -> Result<(Vec<(Component, String)>, CopyReport)> {
+    let mut locked = LockedState::lock_or_wait(&paths.state_file, on_wait)?;
+    let mut synced = Vec::new();
+    let mut report = CopyReport::default();
+    let mut new_hashes = Vec::new();
 
-    // Remove oldest
-    let to_remove = backups.len() - MAX_BACKUPS;
-    for entry in backups.iter().take(to_remove) {
-        let path = entry.path();
-        if path.is_dir() {
-            fs::remove_dir_all(&path)?;
+    let mut copies: Vec<_> = locked.state.copies.iter().collect();
+    copies.sort_by_key(|(c, _)| c.short_name());
+
+    for (component, copy) in copies {
+        let live = component.source_path(paths);
+        let Some((_, true)) = managed_copy_status(&locked.state, *component, &live) else {
+            continue;
+        };
+        if !crate::profiles::profile_exists(paths, &copy.profile) {
+            return Err(anyhow!(
+                "Profile '{}' no longer exists; cannot sync {}",
+                copy.profile,
+                component.display_name()
+            )
+            .into());
+        }
+
+        let metadata = ProfileMetadata::read(&paths.profile_dir(&copy.profile))?;
+        if *component == Component::Settings && metadata.extends.is_some() {
+            return Err(anyhow!(
+                "Settings of profile '{}' are generated from the profiles it extends; cannot sync them.\nHint: Edit the profile's own settings.json with 'ccprof edit {}' instead.",
+                copy.profile,
+                copy.profile
+            )
+            .into());
+        }
+        let destination = metadata.component_path(paths, &copy.profile, *component);
+        crate::audit::record(format!(
+            "copy {} -> {}",
+            live.display(),
+            destination.display()
+        ));
+        if live.is_dir() {
+            report.extend(crate::fs_utils::replace_dir_atomic(&live, &destination)?);
         } else {
-            fs::remove_file(&path)?;
+            crate::fs_utils::remove_path(&destination)?;
+            fs::copy(&live, &destination).with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    live.display(),
+                    destination.display()
+                )
+            })?;
         }
+
+        new_hashes.push((*component, crate::fs_utils::hash_path(&live)?));
+        synced.push((*component, copy.profile.clone()));
     }
 
-    Ok(())
-}
+    locked.update(|s| {
+        for (component, hash) in new_hashes {
+            if let Some(copy) = s.copies.get_mut(&component) {
+                copy.hash = hash;
+            }
+        }
+    })?;
 
-// Redefine internal create helper that uses Paths?
-// Actually, `create_component_symlink` uses `backup_component` which was empty/broken above.
-// I will fix `create_component_symlink` to find the backup dir.
-// Since I can't easily change the signature everywhere without breaking tests/callers in this edit...
-// Wait, I CAN change the signature, I'm editing the whole file.
+    Ok((synced, report))
+}
 
+/// Link one managed component of a profile into ~/.claude
+///
+/// Directory components with individually tracked files are composed; everything
+/// else is symlinked as a whole.
+pub fn link_component(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    component: &Component,
+    style: LinkStyle,
+) -> Result<()> {
+    let source_path = component.source_path(paths);
+    let target_path = metadata.link_path(paths, name, *component);
 
+    if metadata.shared_source(*component).is_some() {
+        return create_component_symlink(&source_path, &target_path, component, paths, style);
+    }
 
-#[cfg(test)]
+    match metadata.tracked_files_for(*component) {
+        Some(tracked) if !component.is_file() => {
+            compose_component_dir(paths, &source_path, &target_path, tracked, component, style)
+        }
+        _ => create_component_symlink(&source_path, &target_path, component, paths, style),
+    }
+}
+
+/// Create a symlink for a component, handling backups
+pub fn create_component_symlink(
+    link_path: &Path,
+    target_path: &Path,
+    component: &Component,
+    paths: &Paths,
+    style: LinkStyle,
+) -> Result<()> {
+    let status = ComponentStatus::detect(link_path);
+
+    match status {
+        ComponentStatus::Missing => {
+            // Just create symlink
+            make_symlink(target_path, link_path, style)?;
+        }
+        ComponentStatus::RegularFile
+        | ComponentStatus::Directory
+        | ComponentStatus::ManagedCopy { .. } => {
+            // Backup then replace
+            backup_existing_file(link_path, paths, component.short_name())?;
+            crate::fs_utils::remove_path(link_path)?;
+            make_symlink(target_path, link_path, style)?;
+        }
+        ComponentStatus::Symlink { .. } | ComponentStatus::BrokenSymlink { .. } => {
+            // Remove old link (not what it points to) and create new one
+            crate::fs_utils::remove_path(link_path)?;
+            make_symlink(target_path, link_path, style)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compose a live directory component from the shared base and a profile
+///
+/// The live directory becomes a real directory containing only symlinks: entries
+/// listed in `tracked` point into the profile's copy, every other entry of the
+/// shared base points into the base.
+pub fn compose_component_dir(
+    paths: &Paths,
+    link_path: &Path,
+    profile_component_dir: &Path,
+    tracked: &[String],
+    component: &Component,
+    style: LinkStyle,
+) -> Result<()> {
+    // Validate before touching anything in ~/.claude
+    for file in tracked {
+        let target = profile_component_dir.join(file);
+        if fs::symlink_metadata(&target).is_err() {
+            return Err(anyhow!(
+                "Tracked file '{}/{}' is missing from the profile ({})",
+                component.short_name(),
+                file,
+                target.display()
+            )
+            .into());
+        }
+    }
+
+    match ComponentStatus::detect(link_path) {
+        ComponentStatus::Missing => {}
+        ComponentStatus::Directory if is_composed_dir(paths, link_path) => {
+            // Previously composed: it only holds our own symlinks, rebuild in place
+            for entry in fs::read_dir(link_path)? {
+                crate::fs_utils::remove_path(&entry?.path())?;
+            }
+        }
+        ComponentStatus::RegularFile
+        | ComponentStatus::Directory
+        | ComponentStatus::ManagedCopy { .. } => {
+            backup_existing_file(link_path, paths, component.short_name())?;
+            crate::fs_utils::remove_path(link_path)?;
+        }
+        ComponentStatus::Symlink { .. } | ComponentStatus::BrokenSymlink { .. } => {
+            crate::fs_utils::remove_path(link_path)?;
+        }
+    }
+
+    fs::create_dir_all(link_path)
+        .with_context(|| format!("Failed to create directory {}", link_path.display()))?;
+
+    let base_dir = paths.shared_base(component);
+    if base_dir.is_dir() {
+        for entry in fs::read_dir(&base_dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            if tracked.iter().any(|t| file_name == t.as_str()) {
+                continue;
+            }
+            make_symlink(&entry.path(), &link_path.join(&file_name), style)?;
+        }
+    }
+
+    for file in tracked {
+        make_symlink(
+            &profile_component_dir.join(file),
+            &link_path.join(file),
+            style,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Check whether a live directory was composed by ccprof
+///
+/// A composed directory contains nothing but symlinks into the profiles
+/// directory or the shared base.
+pub fn is_composed_dir(paths: &Paths, path: &Path) -> bool {
+    let Ok(entries) = fs::read_dir(path) else {
+        return false;
+    };
+
+    entries.filter_map(|e| e.ok()).all(|entry| {
+        let path = entry.path();
+        fs::read_link(&path).is_ok_and(|target| {
+            paths.is_managed_target(&crate::fs_utils::resolve_link_target(&path, &target))
+        })
+    })
+}
+
+/// Where an entry of a composed directory currently comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ComposedSource {
+    /// Symlink into the profile's own copy
+    Profile,
+    /// Symlink into the shared base
+    Base,
+    /// Real file or directory living only in ~/.claude
+    Local,
+    /// Symlink whose target no longer exists
+    Broken,
+    /// Symlink pointing somewhere ccprof doesn't manage
+    Foreign(PathBuf),
+}
+
+/// A single entry of a composed directory and where it should come from
+#[derive(Debug, Clone)]
+pub struct ComposedEntry {
+    pub name: String,
+    pub tracked: bool,
+    pub source: ComposedSource,
+}
+
+impl ComposedEntry {
+    /// Whether the entry comes from where the profile says it should
+    pub fn is_expected(&self) -> bool {
+        match self.source {
+            ComposedSource::Profile => self.tracked,
+            ComposedSource::Base => !self.tracked,
+            _ => false,
+        }
+    }
+}
+
+/// Inspect a composed directory component for diagnostics
+///
+/// Returns `None` when the live path isn't a directory at all (e.g. it is still a
+/// plain symlink from before the component was composed).
+pub fn inspect_composed_dir(
+    paths: &Paths,
+    profile: &str,
+    component: &Component,
+    tracked: &[String],
+) -> Result<Option<Vec<ComposedEntry>>> {
+    let link_path = component.source_path(paths);
+    if !matches!(
+        ComponentStatus::detect(&link_path),
+        ComponentStatus::Directory
+    ) {
+        return Ok(None);
+    }
+
+    let profile_component_dir = component.profile_path(paths, profile);
+    let base_dir = paths.shared_base(component);
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&link_path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+
+        let source = match fs::read_link(&path)
+            .map(|target| crate::fs_utils::resolve_link_target(&path, &target))
+        {
+            Ok(_) if !path.exists() => ComposedSource::Broken,
+            Ok(target) if target.starts_with(&profile_component_dir) => ComposedSource::Profile,
+            Ok(target) if target.starts_with(&base_dir) => ComposedSource::Base,
+            Ok(target) => ComposedSource::Foreign(target),
+            Err(_) => ComposedSource::Local,
+        };
+
+        entries.push(ComposedEntry {
+            tracked: tracked.contains(&name),
+            name,
+            source,
+        });
+    }
+
+    // Tracked files that aren't present at all are problems too
+    for file in tracked {
+        if !entries.iter().any(|e| &e.name == file) {
+            entries.push(ComposedEntry {
+                name: file.clone(),
+                tracked: true,
+                source: ComposedSource::Broken,
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Some(entries))
+}
+
+/// Where a live component's content actually comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", content = "name", rename_all = "snake_case")]
+pub enum LiveSource {
+    Missing,
+    /// Linked into, composed from, or copied from a profile
+    Profile(String),
+    /// Linked to a shared item
+    Shared(String),
+    /// User content or a link ccprof doesn't manage
+    Unmanaged,
+}
+
+impl LiveSource {
+    /// Work out which profile (if any) provides a live component
+    pub fn detect(paths: &Paths, component: Component, state: &State) -> Self {
+        let path = component.source_path(paths);
+        match ComponentStatus::detect_with_state(&path, component, state) {
+            ComponentStatus::Missing => Self::Missing,
+            ComponentStatus::ManagedCopy { profile, .. } => Self::Profile(profile),
+            ComponentStatus::Symlink { target } | ComponentStatus::BrokenSymlink { target } => {
+                match Self::from_target(paths, &target) {
+                    // Follow links into ccprof's directories made by other tools
+                    Self::Unmanaged => crate::fs_utils::link_chain_end(&target)
+                        .map_or(Self::Unmanaged, |end| Self::from_target(paths, &end)),
+                    source => source,
+                }
+            }
+            ComponentStatus::Directory if is_composed_dir(paths, &path) => {
+                // Tracked entries link into the profile; the rest into the shared base
+                fs::read_dir(&path)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| {
+                        let link = e.path();
+                        let target = fs::read_link(&link).ok()?;
+                        match Self::from_target(
+                            paths,
+                            &crate::fs_utils::resolve_link_target(&link, &target),
+                        ) {
+                            profile @ Self::Profile(_) => Some(profile),
+                            _ => None,
+                        }
+                    })
+                    .next()
+                    .unwrap_or(Self::Unmanaged)
+            }
+            _ => Self::Unmanaged,
+        }
+    }
+
+    fn from_target(paths: &Paths, target: &Path) -> Self {
+        let first = |base: &Path| {
+            let rest = crate::paths::within(target, base)?;
+            let name = rest.components().next()?.as_os_str().to_str()?;
+            Some(name.to_string())
+        };
+        if let Some(profile) = first(&paths.profiles_dir) {
+            Self::Profile(profile)
+        } else if let Some(shared) = first(&paths.shared_dir) {
+            Self::Shared(shared)
+        } else {
+            Self::Unmanaged
+        }
+    }
+
+    /// Profile providing the content, if any
+    pub fn profile(&self) -> Option<&str> {
+        match self {
+            Self::Profile(profile) => Some(profile),
+            _ => None,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Missing => "missing".to_string(),
+            Self::Profile(profile) => format!("profile '{}'", profile),
+            Self::Shared(shared) => format!("shared item '{}'", shared),
+            Self::Unmanaged => "not managed".to_string(),
+        }
+    }
+}
+
+/// A live component compared with the profile state records for it
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentOwnership {
+    pub component: Component,
+    pub live: LiveSource,
+    /// Profile state says the component was switched to
+    pub recorded: Option<String>,
+}
+
+impl ComponentOwnership {
+    /// Whether the live component matches what state records
+    pub fn is_consistent(&self) -> bool {
+        match &self.live {
+            // Shared items are referenced by the recorded profile's metadata
+            LiveSource::Shared(_) => self.recorded.is_some(),
+            live => live.profile() == self.recorded.as_deref(),
+        }
+    }
+}
+
+/// Compare every live component with state, skipping ones absent from both
+///
+/// State written before per-component tracking only has `default_profile`,
+/// which is then assumed for every component present in ~/.claude.
+pub fn component_ownership(paths: &Paths, state: &State) -> Vec<ComponentOwnership> {
+    Component::all()
+        .into_iter()
+        .filter_map(|component| {
+            let live = LiveSource::detect(paths, component, state);
+            let recorded = if state.active_components.is_empty() {
+                state
+                    .default_profile
+                    .clone()
+                    .filter(|_| live != LiveSource::Missing)
+            } else {
+                state.active_components.get(&component).cloned()
+            };
+            if live == LiveSource::Missing && recorded.is_none() {
+                return None;
+            }
+            Some(ComponentOwnership {
+                component,
+                live,
+                recorded,
+            })
+        })
+        .collect()
+}
+
+/// What `ccprof current` reports
+#[derive(Debug, Serialize)]
+pub struct CurrentStatus {
+    /// Profile selected in state.json
+    pub selected: Option<String>,
+    /// The selected profile has since been deleted
+    pub selected_missing: bool,
+    pub last_switched: Option<DateTime<Utc>>,
+    pub settings: SettingsStatus,
+    /// Profile the settings symlink leads into
+    pub linked_profile: Option<String>,
+    /// The settings symlink leads into the profile state records for settings
+    /// (true when it doesn't lead into any profile)
+    pub in_sync: bool,
+    pub strategy: SwitchStrategy,
+    pub components: Vec<ComponentOwnership>,
+    /// Each component the selected profile manages, against what switching
+    /// to it sets up
+    pub profile_components: Vec<ProfileComponent>,
+}
+
+/// How a live component compares with what its profile expects there
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ComponentHealth {
+    /// Linked to, composed from or copied from the profile
+    Live,
+    Missing,
+    /// A symlink whose target is gone
+    Broken {
+        target: PathBuf,
+    },
+    /// A symlink to somewhere else
+    Elsewhere {
+        target: PathBuf,
+    },
+    /// A copy modified since the switch
+    Modified,
+    /// A composed directory with entries missing or misdirected
+    Incomplete {
+        problems: usize,
+    },
+    /// Content that didn't come from the profile
+    Replaced {
+        found: String,
+    },
+}
+
+impl ComponentHealth {
+    pub fn is_live(&self) -> bool {
+        matches!(self, Self::Live)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Self::Live => "ok".to_string(),
+            Self::Missing => "missing".to_string(),
+            Self::Broken { target } => format!("broken symlink → {}", target.display()),
+            Self::Elsewhere { target } => format!("symlink → {}", target.display()),
+            Self::Modified => "copy modified (run 'ccprof sync')".to_string(),
+            Self::Incomplete { problems } => format!("{} entries not as expected", problems),
+            Self::Replaced { found } => format!("replaced by {}", found),
+        }
+    }
+}
+
+/// A component of a profile and its health
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileComponent {
+    pub component: Component,
+    /// Where the profile keeps its content
+    pub expected: PathBuf,
+    pub health: ComponentHealth,
+}
+
+/// Check every component `name` manages against ~/.claude
+pub fn profile_health(
+    paths: &Paths,
+    name: &str,
+    metadata: &ProfileMetadata,
+    state: &State,
+) -> Vec<ProfileComponent> {
+    let same = |a: &Path, b: &Path| {
+        crate::fs_utils::canonicalize_existing(a, false)
+            == crate::fs_utils::canonicalize_existing(b, false)
+    };
+    Component::all()
+        .into_iter()
+        .filter(|c| metadata.managed_components.contains(c))
+        .map(|component| {
+            let expected = metadata.link_path(paths, name, component);
+            let live = component.source_path(paths);
+            let tracked = metadata
+                .tracked_files_for(component)
+                .filter(|_| metadata.shared_source(component).is_none() && !component.is_file());
+            let health = match ComponentStatus::detect_with_state(&live, component, state) {
+                ComponentStatus::Missing => ComponentHealth::Missing,
+                ComponentStatus::Symlink { target } if same(&target, &expected) => {
+                    ComponentHealth::Live
+                }
+                ComponentStatus::Symlink { target } => ComponentHealth::Elsewhere { target },
+                ComponentStatus::BrokenSymlink { target } => ComponentHealth::Broken { target },
+                ComponentStatus::ManagedCopy { profile, drifted } if profile == name => {
+                    if drifted {
+                        ComponentHealth::Modified
+                    } else {
+                        ComponentHealth::Live
+                    }
+                }
+                ComponentStatus::ManagedCopy { profile, .. } => ComponentHealth::Replaced {
+                    found: format!("a copy of profile '{}'", profile),
+                },
+                ComponentStatus::Directory if tracked.is_some() => {
+                    match inspect_composed_dir(paths, name, &component, tracked.unwrap_or_default())
+                    {
+                        Ok(Some(entries)) => {
+                            match entries.iter().filter(|e| !e.is_expected()).count() {
+                                0 => ComponentHealth::Live,
+                                problems => ComponentHealth::Incomplete { problems },
+                            }
+                        }
+                        _ => ComponentHealth::Replaced {
+                            found: "a directory".to_string(),
+                        },
+                    }
+                }
+                other => ComponentHealth::Replaced {
+                    found: format!("a {}", other.label()),
+                },
+            };
+            ProfileComponent {
+                component,
+                expected,
+                health,
+            }
+        })
+        .collect()
+}
+
+/// Whether switching to `name` again would change nothing: state selects it
+/// and every component it manages is live
+///
+/// A profile extending another also needs its generated settings to match
+/// what its bases resolve to now.
+pub fn is_fully_active(paths: &Paths, name: &str) -> bool {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    if state.default_profile.as_deref() != Some(name) || paths.journal_file.exists() {
+        return false;
+    }
+    let Ok(metadata) = ProfileMetadata::read(&paths.profile_dir(name)) else {
+        return false;
+    };
+    if metadata.extends.is_some() {
+        let generated = paths
+            .profile_dir(name)
+            .join(crate::components::RESOLVED_SETTINGS_FILE);
+        let current = fs::read_to_string(generated)
+            .ok()
+            .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+        if current.is_none() || current != crate::inheritance::resolve_settings(paths, name).ok() {
+            return false;
+        }
+    }
+    profile_health(paths, name, &metadata, &state)
+        .iter()
+        .all(|c| c.health.is_live())
+}
+
+/// The selected profile as a shell prompt shows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PromptStatus {
+    pub profile: String,
+    /// The live settings belong to the profile
+    pub in_sync: bool,
+}
+
+/// The selected profile and whether ~/.claude/settings.json still belongs to it
+///
+/// Runs on every shell prompt, so it reads only state.json and the settings
+/// symlink: no metadata, no hashing. A copied settings file counts as in sync
+/// when state says it came from the profile, even if it was edited since.
+pub fn prompt_status(paths: &Paths) -> Option<PromptStatus> {
+    let state = State::read(&paths.state_file).ok()?;
+    let profile = state.default_profile?;
+    let in_sync = match state.active_components.get(&Component::Settings) {
+        Some(owner) if *owner != profile => false,
+        // The profile doesn't manage settings
+        None if !state.active_components.is_empty() => true,
+        _ => match state.copies.get(&Component::Settings) {
+            Some(copy) => copy.profile == profile,
+            None => fs::read_link(&paths.claude_settings).is_ok_and(|target| {
+                let target = crate::fs_utils::normalize_path(&paths.claude_dir.join(target));
+                target.parent() == Some(paths.profile_dir(&profile).as_path())
+            }),
+        },
+    };
+    Some(PromptStatus { profile, in_sync })
+}
+
+/// Gather the selected profile and where each live component comes from
+pub fn current_status(paths: &Paths) -> CurrentStatus {
+    let state = State::read(&paths.state_file).unwrap_or_default();
+    let settings = SettingsStatus::detect_with_state(&paths.claude_settings, &state);
+    let linked_profile = settings.profile_target(paths).and_then(|target| {
+        let rest = crate::paths::within(&target, &paths.profiles_dir)?;
+        let name = rest.components().next()?.as_os_str().to_str()?;
+        Some(name.to_string())
+    });
+    // Before per-component tracking only default_profile was recorded
+    let settings_owner = if state.active_components.is_empty() {
+        state.default_profile.as_ref()
+    } else {
+        state.active_components.get(&Component::Settings)
+    };
+    CurrentStatus {
+        in_sync: linked_profile
+            .as_ref()
+            .is_none_or(|linked| Some(linked) == settings_owner),
+        selected_missing: state
+            .default_profile
+            .as_deref()
+            .is_some_and(|p| !crate::profiles::profile_exists(paths, p)),
+        selected: state.default_profile.clone(),
+        last_switched: state.updated_at,
+        linked_profile,
+        strategy: state.strategy.unwrap_or_default(),
+        components: component_ownership(paths, &state),
+        profile_components: state
+            .default_profile
+            .as_deref()
+            .and_then(|name| {
+                let metadata = ProfileMetadata::read(&paths.profile_dir(name)).ok()?;
+                Some(profile_health(paths, name, &metadata, &state))
+            })
+            .unwrap_or_default(),
+        settings,
+    }
+}
+
+#[derive(Debug)]
+pub enum ComponentStatus {
+    Missing,
+    RegularFile,
+    Directory,
+    Symlink {
+        target: PathBuf,
+    },
+    BrokenSymlink {
+        target: PathBuf,
+    },
+    /// A file or directory that ccprof copied from a profile (copy strategy)
+    ManagedCopy {
+        profile: String,
+        drifted: bool,
+    },
+}
+
+impl ComponentStatus {
+    /// Detect status, recognizing ccprof-managed copies recorded in state
+    pub fn detect_with_state(path: &Path, component: Component, state: &State) -> Self {
+        match Self::detect(path) {
+            Self::RegularFile | Self::Directory => {
+                match managed_copy_status(state, component, path) {
+                    Some((profile, drifted)) => Self::ManagedCopy { profile, drifted },
+                    None => Self::detect(path),
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Short human-readable label for tables and JSON output
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Missing => "missing",
+            Self::RegularFile => "file",
+            Self::Directory => "directory",
+            Self::Symlink { .. } => "symlink",
+            Self::BrokenSymlink { .. } => "broken symlink",
+            Self::ManagedCopy { drifted: false, .. } => "copy",
+            Self::ManagedCopy { drifted: true, .. } => "drifted copy",
+        }
+    }
+
+    /// Link target, if this is a symlink (working or broken)
+    pub fn target(&self) -> Option<&Path> {
+        match self {
+            Self::Symlink { target } | Self::BrokenSymlink { target } => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn detect(path: &Path) -> Self {
+        // Check if it's a symlink first
+        if let Ok(target) = fs::read_link(path) {
+            let target = crate::fs_utils::resolve_link_target(path, &target);
+            // It is a symlink. Is it broken?
+            if path.exists() {
+                Self::Symlink { target }
+            } else {
+                Self::BrokenSymlink { target }
+            }
+        } else if path.exists() {
+            if path.is_dir() {
+                Self::Directory
+            } else {
+                Self::RegularFile
+            }
+        } else {
+            Self::Missing
+        }
+    }
+}
+
+fn make_symlink(target: &Path, link: &Path, style: LinkStyle) -> anyhow::Result<()> {
+    // Create parent dir if missing
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let relative;
+    let target = match (style, link.parent()) {
+        (LinkStyle::Relative, Some(parent)) => {
+            relative = crate::fs_utils::relative_path(parent, target);
+            relative.as_path()
+        }
+        _ => target,
+    };
+
+    crate::fs_utils::create_symlink(target, link).with_context(|| {
+        format!(
+            "Failed to create symlink from {} to {}",
+            link.display(),
+            target.display()
+        )
+    })?;
+
+    crate::audit::record(format!(
+        "symlink {} -> {}",
+        link.display(),
+        target.display()
+    ));
+    Ok(())
+}
+
+// Fixed version of backup_component that actually does the work
+// We need to inject paths or the backup directory.
+// Refactoring `create_component_symlink` to take `backups_dir`.
+
+pub fn backup_existing_file(
+    path: &Path,
+    paths: &Paths,
+    name_prefix: &str,
+) -> Result<Option<BackupOutcome>> {
+    backup_existing_file_with_progress(
+        path,
+        paths,
+        name_prefix,
+        None,
+        &BackupOptions::default(),
+        &mut |_| {},
+    )
+}
+
+/// Result of backing up a file or directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackupOutcome {
+    /// A new backup was written, with what copying a directory into it reported;
+    /// unreadable entries it left out (`report.skipped`) make it incomplete
+    Created { path: PathBuf, report: CopyReport },
+    /// The newest existing backup already has identical content; nothing was written
+    Unchanged(PathBuf),
+}
+
+impl BackupOutcome {
+    /// Backup holding the content
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Created { path, .. } | Self::Unchanged(path) => path,
+        }
+    }
+}
+
+/// [`backup_existing_file`], calling `on_file` with the size of each file copied
+///
+/// When `path` is a symlink, the content it resolves to is backed up, and
+/// `link_target` is recorded in the backup name as `.from-<target>`, with `/`
+/// escaped as `%2F`. Unless `options.force` is set, no backup is written when the newest backup with the
+/// same prefix already has identical content, so repeated switches don't push
+/// useful backups out of the rotation. Each new backup gets a
+/// [`BackupManifest`], and only `options.keep` backups with the prefix are
+/// kept. Returns `None` if there was nothing to back up.
+pub fn backup_existing_file_with_progress(
+    path: &Path,
+    paths: &Paths,
+    name_prefix: &str,
+    link_target: Option<&Path>,
+    options: &BackupOptions,
+    on_file: &mut dyn FnMut(u64),
+) -> Result<Option<BackupOutcome>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let backups_dir = &paths.backups_dir;
+    if !backups_dir.exists() {
+        fs::create_dir_all(backups_dir)?;
+    }
+
+    if !options.force
+        && let Some(latest) = latest_backup(backups_dir, name_prefix)?
+    {
+        let unchanged = crate::fs_utils::hash_path(path)
+            .and_then(|h| Ok(h == crate::fs_utils::hash_path(&latest)?))
+            .unwrap_or(false);
+        if unchanged {
+            return Ok(Some(BackupOutcome::Unchanged(latest)));
+        }
+    }
+
+    let timestamp = Utc::now().format("%Y%m%d_%H%M%S").to_string();
+    let origin = link_target.map(|target| format!(".from-{}", encode_backup_origin(target)));
+    // A second backup within the same second gets `-2`, then `-3`, ...
+    let backup_name = (1..)
+        .map(|n| {
+            let counter = if n == 1 {
+                String::new()
+            } else {
+                format!("-{}", n)
+            };
+            format!(
+                "{}.{}{}{}.bak",
+                name_prefix,
+                timestamp,
+                counter,
+                origin.as_deref().unwrap_or_default()
+            )
+        })
+        .find(|name| fs::symlink_metadata(backups_dir.join(name)).is_err())
+        .expect("some backup name is free");
+    let backup_path = backups_dir.join(&backup_name);
+
+    let report = if path.is_dir() {
+        crate::fs_utils::copy_dir_lenient_with_progress(path, &backup_path, options.copy, on_file)?
+    } else {
+        on_file(fs::copy(path, &backup_path)?);
+        CopyReport::default()
+    };
+    crate::audit::record(format!(
+        "backup {} -> {}",
+        path.display(),
+        backup_path.display()
+    ));
+    if let Some(component) = component_for_backup(&backup_name) {
+        let manifest = BackupManifest {
+            component,
+            created_at: Utc::now(),
+            profile: State::read(&paths.state_file)
+                .ok()
+                .and_then(|s| s.default_profile),
+            command: options.command.clone(),
+            source: path.to_path_buf(),
+            link_target: link_target.map(Path::to_path_buf),
+        };
+        manifest.write(&backup_path)?;
+    }
+
+    // Rotate backups
+    cleanup_old_backups(backups_dir, name_prefix, options.keep)?;
+
+    Ok(Some(BackupOutcome::Created {
+        path: backup_path,
+        report,
+    }))
+}
+
+/// Why and from where a backup was taken, stored next to it as `<backup>.json`
+///
+/// Backups from before manifests existed have none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub component: Component,
+    pub created_at: DateTime<Utc>,
+    /// Default profile when the backup was taken
+    pub profile: Option<String>,
+    /// Arguments of the ccprof command that took it
+    pub command: Option<String>,
+    /// Live path that was backed up
+    pub source: PathBuf,
+    /// Target of the symlink that `source` was, if it was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<PathBuf>,
+}
+
+/// Suffix of manifest files after the backup's name
+pub const BACKUP_MANIFEST_SUFFIX: &str = ".json";
+
+impl BackupManifest {
+    /// Where the manifest of `backup` is stored
+    pub fn path_for(backup: &Path) -> PathBuf {
+        let mut name = backup.as_os_str().to_os_string();
+        name.push(BACKUP_MANIFEST_SUFFIX);
+        PathBuf::from(name)
+    }
+
+    /// The manifest of `backup`, if it has a readable one
+    pub fn read(backup: &Path) -> Option<Self> {
+        let content = fs::read_to_string(Self::path_for(backup)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn write(&self, backup: &Path) -> anyhow::Result<()> {
+        let path = Self::path_for(backup);
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Remove a backup and its manifest
+pub fn remove_backup(backup: &Path) -> anyhow::Result<()> {
+    crate::fs_utils::remove_path(backup)?;
+    crate::fs_utils::remove_path(&BackupManifest::path_for(backup))
+}
+
+/// Characters escaped in backup names: separators, and what Windows forbids in file names
+const BACKUP_NAME_ESCAPES: &[(char, &str)] = &[
+    ('%', "%25"),
+    ('/', "%2F"),
+    ('\\', "%2F"),
+    (':', "%3A"),
+    ('*', "%2A"),
+    ('?', "%3F"),
+    ('"', "%22"),
+    ('<', "%3C"),
+    ('>', "%3E"),
+    ('|', "%7C"),
+];
+
+/// Escape a path for use in a backup file name (reversed by [`decode_backup_origin`])
+fn encode_backup_origin(target: &Path) -> String {
+    let target = crate::fs_utils::strip_verbatim(target);
+    let mut encoded = String::new();
+    for c in target.to_string_lossy().chars() {
+        match BACKUP_NAME_ESCAPES
+            .iter()
+            .find(|(escaped, _)| *escaped == c)
+        {
+            Some((_, code)) => encoded.push_str(code),
+            None => encoded.push(c),
+        }
+    }
+    encoded
+}
+
+/// Original symlink target recorded in a backup name, if the backup was taken of one
+///
+/// Separators come back as this platform's.
+pub fn decode_backup_origin(backup_name: &str) -> Option<PathBuf> {
+    let mut rest = backup_name.strip_suffix(".bak")?.split_once(".from-")?.1;
+    let mut decoded = String::new();
+    while let Some((before, after)) = rest.split_once('%') {
+        decoded.push_str(before);
+        let code = after.get(..2).map(|hex| format!("%{}", hex));
+        match BACKUP_NAME_ESCAPES
+            .iter()
+            .find(|(_, escape)| Some(*escape) == code.as_deref())
+        {
+            Some(('/', _)) => decoded.push(std::path::MAIN_SEPARATOR),
+            Some((c, _)) => decoded.push(*c),
+            None => {
+                decoded.push('%');
+                rest = after;
+                continue;
+            }
+        }
+        rest = &after[2..];
+    }
+    decoded.push_str(rest);
+    Some(PathBuf::from(decoded))
+}
+
+/// Backups named `<name_prefix>.<timestamp>.bak`, oldest first
+fn backups_with_prefix(backups_dir: &Path, name_prefix: &str) -> anyhow::Result<Vec<fs::DirEntry>> {
+    let prefix = format!("{}.", name_prefix);
+    let mut backups: Vec<_> = fs::read_dir(backups_dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .is_some_and(|n| n.starts_with(&prefix) && n.ends_with(".bak"))
+        })
+        .collect();
+    backups.sort_by_key(|b| (backup_taken_at(&b.path()), b.file_name()));
+    Ok(backups)
+}
+
+/// Most recent backup with the given prefix
+fn latest_backup(backups_dir: &Path, name_prefix: &str) -> anyhow::Result<Option<PathBuf>> {
+    Ok(backups_with_prefix(backups_dir, name_prefix)?
+        .last()
+        .map(|e| e.path()))
+}
+
+/// Component a backup file name belongs to
+///
+/// Accepts both `settings.<timestamp>.bak` and the older `settings.json.<timestamp>.bak`.
+pub fn component_for_backup(backup_name: &str) -> Option<Component> {
+    Component::all().into_iter().find(|c| {
+        backup_name.starts_with(&format!("{}.", c.short_name())) && backup_name.ends_with(".bak")
+    })
+}
+
+/// When a backup was taken, from the `%Y%m%d_%H%M%S` (UTC) stamp in its name,
+/// and its `-N` counter (0 without one) for ordering backups of the same second
+///
+/// `None` for names without a parsable stamp.
+pub fn backup_timestamp(backup_name: &str) -> Option<(DateTime<Utc>, u32)> {
+    let component = component_for_backup(backup_name)?;
+    let rest = backup_name
+        .strip_prefix(component.short_name())?
+        .strip_prefix('.')?
+        .strip_suffix(".bak")?;
+    let rest = rest.strip_prefix("json.").unwrap_or(rest);
+    let stamp = rest.split_once(".from-").map_or(rest, |(stamp, _)| stamp);
+    let (stamp, counter) = match stamp.rsplit_once('-') {
+        Some((stamp, counter)) => (stamp, counter.parse().ok()?),
+        None => (stamp, 0),
+    };
+    let taken_at = chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%d_%H%M%S").ok()?;
+    Some((taken_at.and_utc(), counter))
+}
+
+/// When the backup at `path` was taken: the stamp in its name, or its
+/// modification time for names without one
+pub fn backup_taken_at(path: &Path) -> Option<(DateTime<Utc>, u32)> {
+    let name = path.file_name()?.to_str()?;
+    backup_timestamp(name).or_else(|| {
+        let modified = fs::symlink_metadata(path).ok()?.modified().ok()?;
+        Some((modified.into(), 0))
+    })
+}
+
+/// A backup as `backup list` and `stats` show it
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupInfo {
+    pub id: String,
+    /// Short name, e.g. "settings"
+    pub component: Option<&'static str>,
+    pub timestamp: DateTime<Utc>,
+    pub size: u64,
+    pub path: PathBuf,
+    pub profile: Option<String>,
+    /// Command line that took it
+    pub reason: Option<String>,
+}
+
+/// Backups in the backups directory, optionally only those of `component`,
+/// most recent first
+pub fn list_backups(paths: &Paths, component: Option<Component>) -> Vec<BackupInfo> {
+    let Ok(entries) = fs::read_dir(&paths.backups_dir) else {
+        return Vec::new();
+    };
+    let mut backups: Vec<_> = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let id = e.file_name().to_str()?.to_string();
+            if !id.ends_with(".bak")
+                || component.is_some_and(|c| component_for_backup(&id) != Some(c))
+            {
+                return None;
+            }
+            let metadata = e.metadata().ok()?;
+            let (timestamp, _) = backup_taken_at(&e.path())?;
+            let size = if metadata.is_file() {
+                metadata.len()
+            } else {
+                crate::fs_utils::dir_size(&e.path()).unwrap_or(0)
+            };
+            // Backups from before manifests only have their name to go on
+            let manifest = BackupManifest::read(&e.path());
+            Some(BackupInfo {
+                component: component_for_backup(&id).map(|c| c.short_name()),
+                timestamp,
+                size,
+                path: e.path(),
+                profile: manifest.as_ref().and_then(|m| m.profile.clone()),
+                reason: manifest.and_then(|m| m.command),
+                id,
+            })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
+    backups
+}
+
+/// Subdirectory of the backups directory holding entries `doctor --fix` set aside
+pub const BACKUP_QUARANTINE_DIR: &str = "quarantine";
+
+/// What an entry in the backups directory turned out to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupEntryKind {
+    Valid(Component),
+    /// Not named after a component, or not ending in `.bak`
+    UnknownPrefix,
+    /// Named after a component, but without a `%Y%m%d_%H%M%S` timestamp
+    UnparsableTimestamp,
+    /// A file or directory with no content
+    Empty,
+    /// The [`BackupManifest`] of a backup that exists
+    Manifest,
+}
+
+/// Classify an entry of the backups directory by its name and size
+pub fn classify_backup(path: &Path) -> BackupEntryKind {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    if let Some(backup) = name.strip_suffix(BACKUP_MANIFEST_SUFFIX)
+        && path.with_file_name(backup).exists()
+    {
+        return BackupEntryKind::Manifest;
+    }
+    let Some(component) = component_for_backup(name) else {
+        return BackupEntryKind::UnknownPrefix;
+    };
+    if backup_timestamp(name).is_none() {
+        return BackupEntryKind::UnparsableTimestamp;
+    }
+
+    let size = if path.is_dir() {
+        crate::fs_utils::dir_size(path).unwrap_or(0)
+    } else {
+        fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+    };
+    if size == 0 {
+        BackupEntryKind::Empty
+    } else {
+        BackupEntryKind::Valid(component)
+    }
+}
+
+/// Backups with `name_prefix` beyond the `keep` most recent, oldest first
+pub fn backups_beyond(
+    backups_dir: &Path,
+    name_prefix: &str,
+    keep: usize,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let backups = backups_with_prefix(backups_dir, name_prefix)?;
+    let to_remove = backups.len().saturating_sub(keep);
+    Ok(backups.iter().take(to_remove).map(|e| e.path()).collect())
+}
+
+fn cleanup_old_backups(backups_dir: &Path, name_prefix: &str, keep: usize) -> anyhow::Result<()> {
+    for path in backups_beyond(backups_dir, name_prefix, keep)? {
+        remove_backup(&path)?;
+    }
+    Ok(())
+}
+
+// Redefine internal create helper that uses Paths?
+// Actually, `create_component_symlink` uses `backup_component` which was empty/broken above.
+// I will fix `create_component_symlink` to find the backup dir.
+// Since I can't easily change the signature everywhere without breaking tests/callers in this edit...
+// Wait, I CAN change the signature, I'm editing the whole file.
+
+
+
+#[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs_utils::create_symlink;
     use crate::test_utils::setup_test_paths;
     use tempfile::TempDir;
 
     #[test]
-    fn test_settings_status_detect() {
+    fn test_backup_origin_names_are_portable() {
+        let name = format!(
+            "settings.20260101_000000.from-{}.bak",
+            encode_backup_origin(Path::new(r"\\?\C:\tools\a%b\settings.json"))
+        );
+        assert_eq!(
+            name,
+            "settings.20260101_000000.from-C%3A%2Ftools%2Fa%25b%2Fsettings.json.bak"
+        );
+        assert!(!name.contains([':', '?', '\\', '/']));
+        let sep = std::path::MAIN_SEPARATOR;
+        assert_eq!(
+            decode_backup_origin(&name),
+            Some(PathBuf::from(format!(
+                "C:{sep}tools{sep}a%b{sep}settings.json"
+            )))
+        );
+        // Names written before ':' was escaped, and stray '%'
+        assert_eq!(
+            decode_backup_origin("agents.20250101_000000.from-%2Fopt%2Fx%zz.bak"),
+            Some(PathBuf::from(format!("{sep}opt{sep}x%zz")))
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_verbatim_link_target_counts_as_profile_link() {
+        let temp_dir = TempDir::new().unwrap();
+        // Long (not 8.3) names, as canonicalize returns them
+        let root = crate::fs_utils::strip_verbatim(&fs::canonicalize(temp_dir.path()).unwrap());
+        let paths = Paths::with_dirs(&root.join(".claude-profiles"), &root.join(".claude"));
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        fs::remove_file(&paths.claude_settings).unwrap();
+
+        let verbatim = fs::canonicalize(paths.profile_settings("work")).unwrap();
+        assert!(verbatim.to_string_lossy().starts_with(r"\\?\"));
+        if create_symlink(&verbatim, &paths.claude_settings).is_err() {
+            // Developer Mode is off; doctor explains this
+            return;
+        }
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert_eq!(
+            status.profile_target(&paths),
+            Some(paths.profile_settings("work"))
+        );
+    }
+
+    #[test]
+    fn test_links_through_another_name_are_recognized() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        fs::remove_file(&paths.claude_settings).unwrap();
+
+        // ~/.claude links into the profiles dir by another name
+        let alias = temp_dir.path().join("alias");
+        create_symlink(&paths.base_dir, &alias).unwrap();
+        create_symlink(
+            alias.join("profiles/work/settings.json"),
+            &paths.claude_settings,
+        )
+        .unwrap();
+
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert!(status.is_profile_symlink(&paths));
+        assert_eq!(
+            current_status(&paths).linked_profile.as_deref(),
+            Some("work")
+        );
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(
+            fs::read_dir(&paths.backups_dir)
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(true)
+        );
+    }
+
+    #[test]
+    fn test_settings_status_detect() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        // 1. Missing
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert!(matches!(status, SettingsStatus::Missing));
+
+        // 2. Regular file
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert!(matches!(status, SettingsStatus::RegularFile));
+
+        // 3. Symlink
+        let profile_dir = paths.profile_dir("test");
+        fs::create_dir_all(&profile_dir).unwrap();
+        let target = profile_dir.join("settings.json");
+        fs::write(&target, "{}").unwrap();
+
+        fs::remove_file(&paths.claude_settings).unwrap();
+        make_symlink(&target, &paths.claude_settings, LinkStyle::Absolute).unwrap();
+
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert!(matches!(status, SettingsStatus::Symlink { .. }));
+        assert!(status.is_profile_symlink(&paths));
+    }
+
+    #[test]
+    fn test_switch_composes_tracked_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        // Live commands dir with two entries becomes the shared base
+        let live = paths.claude_dir.join("commands");
+        fs::create_dir_all(&live).unwrap();
+        fs::write(live.join("deploy.md"), "shared deploy").unwrap();
+        fs::write(live.join("review.md"), "shared review").unwrap();
+
+        fs::create_dir_all(paths.profile_dir("work")).unwrap();
+        ProfileMetadata::new(Default::default())
+            .write(&paths.profile_dir("work"))
+            .unwrap();
+        assert_eq!(
+            crate::profiles::track_file(&paths, "work", Component::Commands, "deploy.md").unwrap(),
+            CopyReport::default()
+        );
+        fs::write(
+            paths.profile_dir("work").join("commands/deploy.md"),
+            "work deploy",
+        )
+        .unwrap();
+
+        let report = switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        let outcome = report
+            .components
+            .iter()
+            .find(|o| o.component == Component::Commands)
+            .unwrap();
+        assert_eq!(outcome.action, ComponentAction::Composed);
+
+        // Live dir is a real directory of symlinks
+        assert!(matches!(
+            ComponentStatus::detect(&live),
+            ComponentStatus::Directory
+        ));
+        assert!(is_composed_dir(&paths, &live));
+        assert_eq!(
+            fs::read_to_string(live.join("deploy.md")).unwrap(),
+            "work deploy"
+        );
+        assert_eq!(
+            fs::read_to_string(live.join("review.md")).unwrap(),
+            "shared review"
+        );
+        assert!(
+            fs::read_link(live.join("review.md"))
+                .unwrap()
+                .starts_with(&paths.shared_base_dir)
+        );
+
+        // Original live content was backed up before composing
+        assert!(outcome.backup.as_ref().is_some_and(|b| b.exists()));
+        assert_eq!(report.previous_profile, None);
+
+        // Switching again rebuilds in place without another backup
+        let report = switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(report.backups.is_empty());
+        assert_eq!(report.previous_profile.as_deref(), Some("work"));
+
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        let tracked = metadata.tracked_files_for(Component::Commands).unwrap();
+        let entries = inspect_composed_dir(&paths, "work", &Component::Commands, tracked)
+            .unwrap()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|e| e.is_expected()));
+    }
+
+    #[test]
+    fn test_copy_strategy_detects_drift_and_syncs() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "a"}"#).unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        fs::remove_file(&paths.claude_settings).unwrap();
+
+        let options = SwitchOptions {
+            strategy: Some(SwitchStrategy::Copy),
+            ..Default::default()
+        };
+        switch_to_profile(&paths, "work", &options).unwrap();
+
+        // A real file, recognized as an untouched copy
+        let state = State::read(&paths.state_file).unwrap();
+        assert!(
+            !fs::symlink_metadata(&paths.claude_settings)
+                .unwrap()
+                .is_symlink()
+        );
+        assert!(matches!(
+            SettingsStatus::detect_with_state(&paths.claude_settings, &state),
+            SettingsStatus::ManagedCopy { drifted: false, .. }
+        ));
+
+        // Edit the live copy: drift is detected and sync writes it back
+        fs::write(&paths.claude_settings, r#"{"model": "b"}"#).unwrap();
+        assert!(matches!(
+            SettingsStatus::detect_with_state(&paths.claude_settings, &state),
+            SettingsStatus::ManagedCopy { drifted: true, .. }
+        ));
+        let (synced, _) = sync_copies(&paths, &mut || {}).unwrap();
+        assert_eq!(synced, vec![(Component::Settings, "work".to_string())]);
+        assert_eq!(
+            fs::read_to_string(paths.profile_settings("work")).unwrap(),
+            r#"{"model": "b"}"#
+        );
+        assert!(sync_copies(&paths, &mut || {}).unwrap().0.is_empty());
+
+        // Switching back to symlinks replaces the clean copy without a backup
+        let backups_before = fs::read_dir(&paths.backups_dir).unwrap().count();
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(
+            fs::symlink_metadata(&paths.claude_settings)
+                .unwrap()
+                .is_symlink()
+        );
+        assert_eq!(
+            fs::read_dir(&paths.backups_dir).unwrap().count(),
+            backups_before
+        );
+        assert!(State::read(&paths.state_file).unwrap().copies.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_switch_restores_original_layout() {
         let temp_dir = TempDir::new().unwrap();
         let paths = setup_test_paths(&temp_dir);
         paths.ensure_dirs().unwrap();
+        let agents = paths.claude_dir.join("agents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "live"}"#).unwrap();
+        fs::write(agents.join("live.md"), "live").unwrap();
 
-        // 1. Missing
-        let status = SettingsStatus::detect(&paths.claude_settings);
-        assert!(matches!(status, SettingsStatus::Missing));
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
 
-        // 2. Regular file
+        // Settings is applied first, then copying agents fails on a socket
+        std::os::unix::net::UnixListener::bind(
+            paths.profile_dir("work").join("agents/broken.sock"),
+        )
+        .unwrap();
+
+        let options = SwitchOptions {
+            strategy: Some(SwitchStrategy::Copy),
+            ..Default::default()
+        };
+        assert!(switch_to_profile(&paths, "work", &options).is_err());
+
+        assert_eq!(
+            fs::read_to_string(&paths.claude_settings).unwrap(),
+            r#"{"model": "live"}"#
+        );
+        assert_eq!(fs::read_to_string(agents.join("live.md")).unwrap(), "live");
+        assert_eq!(fs::read_dir(&agents).unwrap().count(), 1);
+        assert!(
+            State::read(&paths.state_file)
+                .unwrap()
+                .default_profile
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_state_failure_rolls_back_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
         fs::create_dir_all(&paths.claude_dir).unwrap();
         fs::write(&paths.claude_settings, "{}").unwrap();
-        let status = SettingsStatus::detect(&paths.claude_settings);
-        assert!(matches!(status, SettingsStatus::RegularFile));
 
-        // 3. Symlink
-        let profile_dir = paths.profile_dir("test");
-        fs::create_dir_all(&profile_dir).unwrap();
-        let target = profile_dir.join("settings.json");
-        fs::write(&target, "{}").unwrap();
+        for profile in ["old", "new"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                profile,
+                std::collections::HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+        switch_to_profile(&paths, "old", &SwitchOptions::default()).unwrap();
+
+        // The state file can't be written, so the switch fails after linking
+        fs::remove_file(&paths.state_file).unwrap();
+        fs::create_dir_all(&paths.state_file).unwrap();
+        assert!(switch_to_profile(&paths, "new", &SwitchOptions::default()).is_err());
+
+        assert_eq!(
+            fs::read_link(&paths.claude_settings).unwrap(),
+            paths.profile_settings("old")
+        );
+    }
+
+    #[test]
+    fn test_detect_and_adopt_live_drift() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "a"}"#).unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        let options = SwitchOptions::default();
+
+        // Identical live content is not drift
+        assert!(
+            detect_live_drift(&paths, "work", &options)
+                .unwrap()
+                .is_empty()
+        );
+
+        fs::write(&paths.claude_settings, r#"{"model": "edited"}"#).unwrap();
+        let drift = detect_live_drift(&paths, "work", &options).unwrap();
+        assert_eq!(drift.len(), 1);
+        assert_eq!(drift[0].component, Component::Settings);
+
+        assert_eq!(adopt_live_drift(&drift[0]).unwrap(), CopyReport::default());
+        assert_eq!(
+            fs::read_to_string(paths.profile_settings("work")).unwrap(),
+            r#"{"model": "edited"}"#
+        );
+        assert!(
+            detect_live_drift(&paths, "work", &options)
+                .unwrap()
+                .is_empty()
+        );
+
+        // A symlinked component is never drift
+        switch_to_profile(&paths, "work", &options).unwrap();
+        assert!(
+            detect_live_drift(&paths, "work", &options)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_verify_component_rejects_misresolved_link() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+
+        let planned = PlannedComponent {
+            component: Component::Settings,
+            live_path: paths.claude_settings.clone(),
+            content_path: paths.profile_settings("work"),
+            needs_backup: false,
+            foreign_target: None,
+        };
+        let verify = || {
+            verify_component(
+                &paths,
+                "work",
+                &metadata,
+                &planned,
+                ComponentAction::Linked,
+                None,
+            )
+        };
+
+        // A regular file is not a link
+        assert!(verify().is_err());
+
+        // A link that resolves somewhere other than the profile
+        let elsewhere = temp_dir.path().join("elsewhere.json");
+        fs::write(&elsewhere, "{}").unwrap();
+        fs::remove_file(&paths.claude_settings).unwrap();
+        create_symlink(&elsewhere, &paths.claude_settings).unwrap();
+        let err = verify().unwrap_err().to_string();
+        assert!(err.contains("elsewhere.json"), "{err}");
+
+        fs::remove_file(&paths.claude_settings).unwrap();
+        create_symlink(paths.profile_settings("work"), &paths.claude_settings).unwrap();
+        assert!(verify().is_ok());
+    }
+
+    #[test]
+    fn test_symlink_denied_is_only_the_windows_privilege_error() {
+        let denied =
+            anyhow::Error::new(std::io::Error::from_raw_os_error(ERROR_PRIVILEGE_NOT_HELD))
+                .context("Failed to create symlink");
+        assert_eq!(is_symlink_denied(&denied), cfg!(windows));
+
+        let other = anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::NotFound))
+            .context("Failed to create symlink");
+        assert!(!is_symlink_denied(&other));
+    }
+
+    #[test]
+    fn test_foreign_symlinks_require_force() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        let agents = paths.claude_dir.join("agents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(agents.join("a.md"), "a").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
+
+        // Another tool owns the live content: settings via an absolute link,
+        // agents via a relative one
+        let tool_dir = temp_dir.path().join("tool");
+        fs::create_dir_all(tool_dir.join("agents")).unwrap();
+        fs::write(tool_dir.join("settings.json"), r#"{"tool": true}"#).unwrap();
+        fs::write(tool_dir.join("agents/tool.md"), "tool").unwrap();
+        fs::remove_file(&paths.claude_settings).unwrap();
+        fs::remove_dir_all(&agents).unwrap();
+        create_symlink(tool_dir.join("settings.json"), &paths.claude_settings).unwrap();
+        create_symlink("../tool/agents", &agents).unwrap();
+
+        let err = switch_to_profile(&paths, "work", &SwitchOptions::default())
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains(&tool_dir.join("settings.json").display().to_string()),
+            "{err}"
+        );
+        assert_eq!(
+            fs::read_link(&paths.claude_settings).unwrap(),
+            tool_dir.join("settings.json")
+        );
+
+        let options = SwitchOptions {
+            force: true,
+            ..Default::default()
+        };
+        let report = switch_to_profile(&paths, "work", &options).unwrap();
+        assert_eq!(report.backups.len(), 2);
+
+        for (component, origin) in [
+            (Component::Settings, tool_dir.join("settings.json")),
+            (Component::Agents, tool_dir.join("agents")),
+        ] {
+            let backup = report
+                .components
+                .iter()
+                .find(|o| o.component == component)
+                .and_then(|o| o.backup.clone())
+                .unwrap();
+            let backup_name = backup.file_name().unwrap().to_str().unwrap();
+            assert_eq!(component_for_backup(backup_name), Some(component));
+            assert_eq!(decode_backup_origin(backup_name), Some(origin));
+            // The resolved content was backed up, not the link
+            assert!(!fs::symlink_metadata(&backup).unwrap().is_symlink());
+        }
+        let settings_backup = report.components[0].backup.as_ref().unwrap();
+        assert_eq!(
+            fs::read_to_string(settings_backup).unwrap(),
+            r#"{"tool": true}"#
+        );
+
+        // The tool's own files are left alone
+        assert_eq!(
+            fs::read_to_string(tool_dir.join("agents/tool.md")).unwrap(),
+            "tool"
+        );
+        assert_eq!(
+            fs::read_link(&agents).unwrap(),
+            paths.profile_dir("work").join("agents")
+        );
+    }
+
+    #[test]
+    fn test_identical_backups_are_not_repeated() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let file = temp_dir.path().join("settings.json");
+        fs::write(&file, "{}").unwrap();
+
+        let backup = |force| {
+            let options = BackupOptions {
+                force,
+                ..BackupOptions::default()
+            };
+            backup_existing_file_with_progress(
+                &file,
+                &paths,
+                "settings",
+                None,
+                &options,
+                &mut |_| {},
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        let first = backup(false);
+        assert!(matches!(first, BackupOutcome::Created { .. }));
+        assert_eq!(
+            backup(false),
+            BackupOutcome::Unchanged(first.path().to_path_buf())
+        );
+        assert!(matches!(backup(true), BackupOutcome::Created { .. }));
+
+        fs::write(&file, r#"{"changed": true}"#).unwrap();
+        let changed = backup(false);
+        assert!(matches!(changed, BackupOutcome::Created { .. }));
+        assert_eq!(
+            fs::read_to_string(changed.path()).unwrap(),
+            r#"{"changed": true}"#
+        );
+    }
+
+    #[test]
+    fn test_backups_within_one_second_get_their_own_names() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let agents = temp_dir.path().join("agents");
+        fs::create_dir(&agents).unwrap();
+
+        let mut written = Vec::new();
+        for file in ["first.md", "second.md", "third.md"] {
+            fs::write(agents.join(file), file).unwrap();
+            let outcome = backup_existing_file_with_progress(
+                &agents,
+                &paths,
+                "agents",
+                None,
+                &BackupOptions {
+                    force: true,
+                    ..BackupOptions::default()
+                },
+                &mut |_| {},
+            )
+            .unwrap()
+            .unwrap();
+            written.push(outcome.path().to_path_buf());
+        }
+
+        // Each backup kept exactly what was live when it was taken
+        for (i, backup) in written.iter().enumerate() {
+            assert_eq!(fs::read_dir(backup).unwrap().count(), i + 1);
+            assert_eq!(
+                classify_backup(backup),
+                BackupEntryKind::Valid(Component::Agents)
+            );
+            assert_eq!(
+                BackupManifest::read(backup).unwrap().component,
+                Component::Agents
+            );
+        }
+        let unique: std::collections::HashSet<_> = written.iter().collect();
+        assert_eq!(unique.len(), 3);
+    }
+
+    #[test]
+    fn test_rotation_follows_names_not_mtimes() {
+        let temp_dir = TempDir::new().unwrap();
+        let backups_dir = temp_dir.path().join("backups");
+        fs::create_dir(&backups_dir).unwrap();
+        // Written newest first, so modification times run the other way
+        let names: Vec<String> = (0..DEFAULT_BACKUPS_KEEP + 2)
+            .rev()
+            .map(|day| format!("settings.202501{:02}_120000.bak", day + 1))
+            .collect();
+        for name in &names {
+            fs::write(backups_dir.join(name), "{}").unwrap();
+        }
+        fs::write(backups_dir.join("settings.20250101_120000-2.bak"), "{}").unwrap();
+
+        cleanup_old_backups(&backups_dir, "settings", DEFAULT_BACKUPS_KEEP).unwrap();
+        let mut kept: Vec<String> = backups_with_prefix(&backups_dir, "settings")
+            .unwrap()
+            .iter()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(kept.len(), DEFAULT_BACKUPS_KEEP);
+        assert_eq!(kept.pop().unwrap(), names[0]);
+        assert_eq!(kept[0], "settings.20250103_120000.bak");
+
+        assert_eq!(
+            backup_timestamp("agents.20250101_120000-3.from-%2Fx.bak"),
+            Some((
+                chrono::NaiveDate::from_ymd_opt(2025, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(12, 0, 0)
+                    .unwrap()
+                    .and_utc(),
+                3
+            ))
+        );
+        assert_eq!(backup_timestamp("agents.20250101_120000-x.bak"), None);
+        assert_eq!(backup_timestamp("agents.copy.bak"), None);
+    }
+
+    #[test]
+    fn test_backups_get_a_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        LockedState::lock(&paths.state_file)
+            .unwrap()
+            .update(|s| s.default_profile = Some("work".to_string()))
+            .unwrap();
+
+        let outcome = backup_existing_file(&paths.claude_settings, &paths, "settings")
+            .unwrap()
+            .unwrap();
+        let backup = outcome.path();
+        let manifest = BackupManifest::read(backup).unwrap();
+        assert_eq!(manifest.component, Component::Settings);
+        assert_eq!(manifest.profile.as_deref(), Some("work"));
+        assert_eq!(manifest.source, paths.claude_settings);
+        assert_eq!(manifest.link_target, None);
+
+        // The manifest is not a stray entry, unless its backup is gone
+        let manifest_path = BackupManifest::path_for(backup);
+        assert_eq!(classify_backup(&manifest_path), BackupEntryKind::Manifest);
+        assert_eq!(
+            backups_with_prefix(&paths.backups_dir, "settings")
+                .unwrap()
+                .len(),
+            1
+        );
+        remove_backup(backup).unwrap();
+        assert!(!manifest_path.exists());
+        fs::write(&manifest_path, "{}").unwrap();
+        assert_eq!(
+            classify_backup(&manifest_path),
+            BackupEntryKind::UnknownPrefix
+        );
+    }
+
+    #[test]
+    fn test_backup_options_name_the_command_and_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        let file = temp_dir.path().join("settings.json");
+        fs::write(&file, "{}").unwrap();
+        let options = BackupOptions {
+            force: true,
+            keep: 2,
+            command: Some("use work".to_string()),
+            ..BackupOptions::default()
+        };
+
+        let mut last = None;
+        for _ in 0..3 {
+            let outcome = backup_existing_file_with_progress(
+                &file,
+                &paths,
+                "settings",
+                None,
+                &options,
+                &mut |_| {},
+            )
+            .unwrap()
+            .unwrap();
+            last = Some(outcome.path().to_path_buf());
+        }
+
+        let manifest = BackupManifest::read(&last.unwrap()).unwrap();
+        assert_eq!(manifest.command.as_deref(), Some("use work"));
+        assert_eq!(
+            backups_with_prefix(&paths.backups_dir, "settings")
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_component_for_backup() {
+        assert_eq!(
+            component_for_backup("settings.20250101_101010.bak"),
+            Some(Component::Settings)
+        );
+        assert_eq!(
+            component_for_backup("settings.json.20250101_101010.bak"),
+            Some(Component::Settings)
+        );
+        assert_eq!(
+            component_for_backup("agents.20250101_101010.bak"),
+            Some(Component::Agents)
+        );
+        assert_eq!(component_for_backup("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_symlink_chain_into_profile_is_managed() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        for name in ["work", "home"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                std::collections::HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
 
+        // settings.json → elsewhere/a → <work profile>/settings.json, made by another tool
+        let elsewhere = temp_dir.path().join("elsewhere");
+        fs::create_dir_all(&elsewhere).unwrap();
+        create_symlink(paths.profile_settings("work"), elsewhere.join("a")).unwrap();
         fs::remove_file(&paths.claude_settings).unwrap();
-        make_symlink(&target, &paths.claude_settings).unwrap();
+        create_symlink(elsewhere.join("a"), &paths.claude_settings).unwrap();
 
         let status = SettingsStatus::detect(&paths.claude_settings);
-        assert!(matches!(status, SettingsStatus::Symlink { .. }));
         assert!(status.is_profile_symlink(&paths));
+        assert_eq!(
+            LiveSource::detect(&paths, Component::Settings, &State::default()).profile(),
+            Some("work")
+        );
+        // Not treated as another tool's link, so no --force is needed
+        switch_to_profile(&paths, "home", &SwitchOptions::default()).unwrap();
+    }
+
+    #[test]
+    fn test_classify_backup() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let entry = |name: &str, content: &str| {
+            let path = dir.join(name);
+            fs::write(&path, content).unwrap();
+            classify_backup(&path)
+        };
+
+        assert_eq!(
+            entry("settings.20250101_101010.bak", "{}"),
+            BackupEntryKind::Valid(Component::Settings)
+        );
+        assert_eq!(
+            entry("settings.json.20250101_101010.bak", "{}"),
+            BackupEntryKind::Valid(Component::Settings)
+        );
+        assert_eq!(
+            entry("hooks.20250101_101010.from-%2Fsrc.bak", "x"),
+            BackupEntryKind::Valid(Component::Hooks)
+        );
+        assert_eq!(
+            entry("hooks.20250101_101010-2.from-%2Fsrc.bak", "x"),
+            BackupEntryKind::Valid(Component::Hooks)
+        );
+        assert_eq!(entry(".settings.swp", "x"), BackupEntryKind::UnknownPrefix);
+        assert_eq!(entry("settings.old", "x"), BackupEntryKind::UnknownPrefix);
+        assert_eq!(
+            entry("agents.yesterday.bak", "x"),
+            BackupEntryKind::UnparsableTimestamp
+        );
+        assert_eq!(
+            entry("settings.bak", "x"),
+            BackupEntryKind::UnparsableTimestamp
+        );
+        assert_eq!(
+            entry("settings.20250101_101011.bak", ""),
+            BackupEntryKind::Empty
+        );
+
+        fs::create_dir(dir.join("copied-by-hand")).unwrap();
+        assert_eq!(
+            classify_backup(&dir.join("copied-by-hand")),
+            BackupEntryKind::UnknownPrefix
+        );
+    }
+
+    #[test]
+    fn test_switch_reports_progress_events() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        let agents = paths.claude_dir.join("agents");
+        fs::create_dir_all(&agents).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(agents.join("a.md"), "a").unwrap();
+        fs::write(agents.join("b.md"), "b").unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
+
+        let mut events = Vec::new();
+        switch_to_profile_with_progress(&paths, "work", &SwitchOptions::default(), &mut |e| {
+            events.push(e)
+        })
+        .unwrap();
+
+        let backed_up = |events: &[SwitchEvent], component| {
+            events.iter().any(|e| {
+                matches!(e, SwitchEvent::BackedUp { component: c, backup } if *c == component && backup.exists())
+            })
+        };
+        assert!(backed_up(&events, Component::Settings));
+        assert!(backed_up(&events, Component::Agents));
+        assert!(events.contains(&SwitchEvent::BackupStarted {
+            component: Component::Agents,
+            files: 2
+        }));
+        let agent_files = events
+            .iter()
+            .filter(|e| {
+                **e == SwitchEvent::FileBackedUp {
+                    component: Component::Agents,
+                }
+            })
+            .count();
+        assert_eq!(agent_files, 2);
+
+        // Backups all happen before anything is applied
+        let applied: Vec<_> = events
+            .iter()
+            .skip_while(|e| !matches!(e, SwitchEvent::Applied { .. }))
+            .collect();
+        assert_eq!(applied.len(), 2);
+        assert!(applied.iter().all(|e| matches!(
+            e,
+            SwitchEvent::Applied {
+                action: ComponentAction::Linked,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn test_switch_reports_waiting_for_the_state_lock() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+
+        let held = LockedState::lock(&paths.state_file).unwrap();
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(300));
+            drop(held);
+        });
+        let mut events = Vec::new();
+        switch_to_profile_with_progress(&paths, "work", &SwitchOptions::default(), &mut |e| {
+            events.push(e)
+        })
+        .unwrap();
+        release.join().unwrap();
+        assert!(events.contains(&SwitchEvent::WaitingForLock));
+    }
+
+    #[test]
+    fn test_missing_component_is_strict_unless_skipped() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        let hooks = paths.claude_dir.join("hooks");
+        fs::create_dir_all(&hooks).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        fs::write(hooks.join("pre.sh"), "echo").unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings, Component::Hooks]),
+        )
+        .unwrap();
+        fs::remove_dir_all(paths.profile_dir("work").join("hooks")).unwrap();
+
+        // Default: refuse, leaving everything as it was
+        assert!(switch_to_profile(&paths, "work", &SwitchOptions::default()).is_err());
+        assert!(
+            !fs::symlink_metadata(&paths.claude_settings)
+                .unwrap()
+                .is_symlink()
+        );
+
+        let options = SwitchOptions {
+            skip_missing: true,
+            prune_metadata: true,
+            ..Default::default()
+        };
+        let report = switch_to_profile(&paths, "work", &options).unwrap();
+        assert_eq!(report.skipped, vec![Component::Hooks]);
+        assert!(report.pruned);
+
+        // Settings switched, the live hooks dir untouched, metadata pruned
+        assert!(
+            fs::symlink_metadata(&paths.claude_settings)
+                .unwrap()
+                .is_symlink()
+        );
+        assert!(hooks.join("pre.sh").is_file());
+        let metadata = ProfileMetadata::read(&paths.profile_dir("work")).unwrap();
+        assert!(!metadata.managed_components.contains(&Component::Hooks));
+    }
+
+    #[test]
+    fn test_relative_links_are_detected_as_profile_links() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+
+        let options = SwitchOptions {
+            link_style: Some(LinkStyle::Relative),
+            ..Default::default()
+        };
+        switch_to_profile(&paths, "work", &options).unwrap();
+
+        let raw = fs::read_link(&paths.claude_settings).unwrap();
+        assert_eq!(
+            raw,
+            Path::new("../.claude-profiles/profiles/work/settings.json")
+        );
+
+        // `..` components are resolved before comparing against the profiles dir
+        let status = SettingsStatus::detect(&paths.claude_settings);
+        assert!(status.is_profile_symlink(&paths));
+        match status {
+            SettingsStatus::Symlink { target } => {
+                assert_eq!(target, paths.profile_settings("work"))
+            }
+            other => panic!("expected symlink, got {:?}", other),
+        }
+
+        // A dangling relative link is reported as broken
+        fs::remove_file(paths.profile_settings("work")).unwrap();
+        assert!(matches!(
+            SettingsStatus::detect(&paths.claude_settings),
+            SettingsStatus::BrokenSymlink { .. }
+        ));
+    }
+
+    #[test]
+    fn test_prompt_status_reads_state_and_link() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        assert_eq!(prompt_status(&paths), None);
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        let options = SwitchOptions {
+            link_style: Some(LinkStyle::Relative),
+            ..Default::default()
+        };
+        switch_to_profile(&paths, "work", &options).unwrap();
+        let status = |in_sync| PromptStatus {
+            profile: "work".to_string(),
+            in_sync,
+        };
+        assert_eq!(prompt_status(&paths), Some(status(true)));
+
+        // Replaced by hand
+        fs::remove_file(&paths.claude_settings).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        assert_eq!(prompt_status(&paths), Some(status(false)));
+    }
+
+    #[test]
+    fn test_current_status_notices_link_into_another_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        for name in ["work", "personal"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                std::collections::HashSet::from([Component::Settings]),
+            )
+            .unwrap();
+        }
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(current_status(&paths).in_sync);
+
+        // Re-pointed by hand
+        fs::remove_file(&paths.claude_settings).unwrap();
+        create_symlink(paths.profile_settings("personal"), &paths.claude_settings).unwrap();
+        let status = current_status(&paths);
+        assert_eq!(status.selected.as_deref(), Some("work"));
+        assert_eq!(status.linked_profile.as_deref(), Some("personal"));
+        assert!(!status.in_sync);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_current_status_checks_every_profile_component() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::create_dir_all(paths.claude_dir.join("commands")).unwrap();
+        fs::write(&paths.claude_settings, "{}").unwrap();
+        for name in ["work", "personal"] {
+            crate::profiles::create_profile_with_components(
+                &paths,
+                name,
+                std::collections::HashSet::from([
+                    Component::Settings,
+                    Component::Agents,
+                    Component::Commands,
+                ]),
+            )
+            .unwrap();
+        }
+        let options = SwitchOptions {
+            link_style: Some(LinkStyle::Relative),
+            ..Default::default()
+        };
+        switch_to_profile(&paths, "work", &options).unwrap();
+        let health = || -> Vec<(Component, ComponentHealth)> {
+            current_status(&paths)
+                .profile_components
+                .into_iter()
+                .map(|c| (c.component, c.health))
+                .collect()
+        };
+        assert!(health().iter().all(|(_, h)| h.is_live()));
+
+        // Settings stay fine while the other two break
+        let agents = Component::Agents.source_path(&paths);
+        fs::remove_file(&agents).unwrap();
+        let commands = Component::Commands.source_path(&paths);
+        fs::remove_file(&commands).unwrap();
+        let elsewhere = paths.profile_dir("personal").join("commands");
+        create_symlink(&elsewhere, &commands).unwrap();
+        assert_eq!(
+            health(),
+            vec![
+                (Component::Settings, ComponentHealth::Live),
+                (Component::Agents, ComponentHealth::Missing),
+                (
+                    Component::Commands,
+                    ComponentHealth::Elsewhere { target: elsewhere }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fully_active_profile_needs_everything_live() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(paths.claude_dir.join("agents")).unwrap();
+        fs::write(&paths.claude_settings, r#"{"model": "opus"}"#).unwrap();
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "work",
+            std::collections::HashSet::from([Component::Settings, Component::Agents]),
+        )
+        .unwrap();
+        assert!(!is_fully_active(&paths, "work"));
+
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+        assert!(is_fully_active(&paths, "work"));
+
+        fs::remove_file(Component::Agents.source_path(&paths)).unwrap();
+        assert!(!is_fully_active(&paths, "work"));
+        switch_to_profile(&paths, "work", &SwitchOptions::default()).unwrap();
+
+        // A child is stale once its base changes
+        crate::profiles::create_empty_profile(&paths, "child").unwrap();
+        crate::inheritance::set_extends(&paths, "child", Some("work")).unwrap();
+        switch_to_profile(&paths, "child", &SwitchOptions::default()).unwrap();
+        assert!(is_fully_active(&paths, "child"));
+        assert!(!is_fully_active(&paths, "work"));
+        fs::write(paths.profile_settings("work"), r#"{"model": "sonnet"}"#).unwrap();
+        assert!(!is_fully_active(&paths, "child"));
+    }
+
+    #[test]
+    fn test_switch_links_resolved_settings_of_extending_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        fs::create_dir_all(&paths.claude_dir).unwrap();
+        fs::write(
+            &paths.claude_settings,
+            r#"{"model": "sonnet", "theme": "dark"}"#,
+        )
+        .unwrap();
+
+        crate::profiles::create_profile_with_components(
+            &paths,
+            "base",
+            std::collections::HashSet::from([Component::Settings]),
+        )
+        .unwrap();
+        crate::profiles::create_empty_profile(&paths, "child").unwrap();
+        fs::write(paths.profile_settings("child"), r#"{"model": "opus"}"#).unwrap();
+        crate::inheritance::set_extends(&paths, "child", Some("base")).unwrap();
+
+        let options = SwitchOptions::default();
+        switch_to_profile(&paths, "child", &options).unwrap();
+        let resolved = paths
+            .profile_dir("child")
+            .join(crate::components::RESOLVED_SETTINGS_FILE);
+        assert_eq!(fs::read_link(&paths.claude_settings).unwrap(), resolved);
+        let live: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&paths.claude_settings).unwrap()).unwrap();
+        assert_eq!(live, serde_json::json!({"model": "opus", "theme": "dark"}));
+
+        // Fixing the base reaches the child on the next switch
+        fs::write(paths.profile_settings("base"), r#"{"theme": "light"}"#).unwrap();
+        switch_to_profile(&paths, "child", &options).unwrap();
+        let live: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&paths.claude_settings).unwrap()).unwrap();
+        assert_eq!(live, serde_json::json!({"model": "opus", "theme": "light"}));
+    }
+}
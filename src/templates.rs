@@ -0,0 +1,187 @@
+//! Profile templates.
+//!
+//! A template is stored under `~/.claude-profiles/templates/<name>/` with the
+//! same layout as a profile (component files plus `metadata.json`). New
+//! profiles can be seeded from a template with `ccprof add <name> --template <tpl>`.
+//!
+//! Templates live outside the profiles directory, so they are never listed as
+//! profiles and can't be switched to directly.
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::components::{Component, ProfileMetadata};
+use crate::error::Error;
+use crate::fs_utils::{CopyReport, copy_dir_recursive};
+use crate::paths::Paths;
+use crate::profiles::{
+    ensure_profile_exists, remove_on_error, validate_json_file, validate_profile_name,
+};
+
+/// A saved template and the components it provides
+#[derive(Debug)]
+pub struct Template {
+    pub name: String,
+    pub path: PathBuf,
+    pub metadata: ProfileMetadata,
+}
+
+/// List all templates, sorted by name
+pub fn list_templates(paths: &Paths) -> Result<Vec<Template>> {
+    let mut templates = Vec::new();
+    if !paths.templates_dir.exists() {
+        return Ok(templates);
+    }
+
+    for entry in fs::read_dir(&paths.templates_dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            templates.push(Template {
+                name: name.to_string(),
+                metadata: ProfileMetadata::read(&path)?,
+                path: path.clone(),
+            });
+        }
+    }
+
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Check if a template exists
+pub fn template_exists(paths: &Paths, name: &str) -> bool {
+    paths.template_dir(name).is_dir()
+}
+
+/// Save a copy of a profile as a template
+///
+/// Shared references and individually tracked files are kept, so profiles
+/// created from the template use the same shared items. Returns the template
+/// directory with what the copy reported.
+pub fn save_template(paths: &Paths, profile: &str, name: &str) -> Result<(PathBuf, CopyReport)> {
+    validate_profile_name(name)?;
+    ensure_profile_exists(paths, profile)?;
+
+    let template = paths.template_dir(name);
+    if template.exists() {
+        bail!(
+            "Template '{}' already exists.\nHint: Use 'ccprof template list' to see existing templates.",
+            name
+        );
+    }
+
+    let profile_dir = paths.profile_dir(profile);
+    let metadata = ProfileMetadata::read(&profile_dir)?;
+    let report = copy_dir_recursive(&profile_dir, &template)?;
+    crate::fs_utils::remove_path(&template.join(crate::editor::EDITING_MARKER))?;
+    write_fresh_metadata(metadata, &template)?;
+
+    Ok((template, report))
+}
+
+/// Create a new profile from a template, returning the components it manages
+/// with what the copy reported
+pub fn create_profile_from_template(
+    paths: &Paths,
+    name: &str,
+    template: &str,
+) -> Result<(HashSet<Component>, CopyReport)> {
+    validate_profile_name(name)?;
+    let source = paths.template_dir(template);
+    if !source.is_dir() {
+        bail!(
+            "Template '{}' does not exist.\nHint: Use 'ccprof template list' to see available templates.",
+            template
+        );
+    }
+
+    let profile_dir = paths.profile_dir(name);
+    if profile_dir.exists() {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        }
+        .into());
+    }
+
+    let metadata = ProfileMetadata::read(&source)?;
+    crate::audit::record(format!(
+        "copy {} -> {}",
+        source.display(),
+        profile_dir.display()
+    ));
+    remove_on_error(&profile_dir, || {
+        let report = copy_dir_recursive(&source, &profile_dir)?;
+
+        let settings = paths.profile_settings(name);
+        if metadata.managed_components.contains(&Component::Settings) && settings.exists() {
+            validate_json_file(&settings)?;
+        }
+
+        let components = metadata.managed_components.clone();
+        write_fresh_metadata(metadata, &profile_dir)?;
+        Ok((components, report))
+    })
+}
+
+/// Write metadata copied from elsewhere with timestamps reset to now
+fn write_fresh_metadata(mut metadata: ProfileMetadata, dir: &Path) -> Result<()> {
+    let now = Utc::now();
+    metadata.created_at = now;
+    metadata.updated_at = now;
+    metadata.version = env!("CARGO_PKG_VERSION").to_string();
+    metadata.migration = None;
+    metadata
+        .write(dir)
+        .with_context(|| format!("Failed to write metadata in {}", dir.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::{create_profile_with_components, list_profiles};
+    use crate::test_utils::setup_test_paths;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_template_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+
+        fs::create_dir_all(paths.claude_dir.join("hooks")).unwrap();
+        fs::write(&paths.claude_settings, r#"{"permissions": {}}"#).unwrap();
+        fs::write(paths.claude_dir.join("hooks/pre.sh"), "echo hi").unwrap();
+        create_profile_with_components(
+            &paths,
+            "client",
+            HashSet::from([Component::Settings, Component::Hooks]),
+        )
+        .unwrap();
+
+        let (_, report) = save_template(&paths, "client", "starter").unwrap();
+        assert_eq!(report, CopyReport::default());
+        assert!(save_template(&paths, "client", "starter").is_err());
+
+        let (components, _) = create_profile_from_template(&paths, "acme", "starter").unwrap();
+        assert_eq!(
+            components,
+            HashSet::from([Component::Settings, Component::Hooks])
+        );
+        assert_eq!(
+            fs::read_to_string(paths.profile_dir("acme").join("hooks/pre.sh")).unwrap(),
+            "echo hi"
+        );
+
+        // Templates are not profiles
+        assert_eq!(list_profiles(&paths).unwrap(), vec!["acme", "client"]);
+        let templates = list_templates(&paths).unwrap();
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].name, "starter");
+    }
+}
@@ -11,12 +11,8 @@ use tempfile::TempDir;
 /// This creates a complete directory structure for ccprof within the temp directory,
 /// mimicking the real ~/.claude-profiles/ and ~/.claude/ layout.
 pub fn setup_test_paths(temp_dir: &TempDir) -> Paths {
-    Paths {
-        base_dir: temp_dir.path().join(".claude-profiles"),
-        profiles_dir: temp_dir.path().join(".claude-profiles/profiles"),
-        backups_dir: temp_dir.path().join(".claude-profiles/backups"),
-        state_file: temp_dir.path().join(".claude-profiles/state.json"),
-        claude_dir: temp_dir.path().join(".claude"),
-        claude_settings: temp_dir.path().join(".claude/settings.json"),
-    }
+    Paths::with_dirs(
+        &temp_dir.path().join(".claude-profiles"),
+        &temp_dir.path().join(".claude"),
+    )
 }
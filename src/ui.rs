@@ -1,18 +1,51 @@
 //! UI module for ccprof - centralized styling, color detection, tables, spinners.
 //!
+//! # Output streams
+//! Data (tables, JSON, lists a script would consume) goes to stdout via
+//! [`Ui::println`]. Everything else - labels, sections, hints, warnings,
+//! spinners and progress - goes to stderr, so `ccprof list | grep work` and
+//! `--json` output stay clean.
+//!
+//! Read-only commands on a terminal buffer their output and, when it is taller
+//! than the terminal, show it through a pager (see [`Ui::with_pager`]).
+//!
+//! With `--output ndjson` stdout carries only newline-delimited JSON events:
+//! labelled messages become `message` events, data lines become `output`
+//! events, commands add their own (e.g. `backup`), and spinners, sections and
+//! hints are dropped.
+//!
 //! # No-color detection (in priority order):
 //! 1. `--no-color` CLI flag (highest priority)
 //! 2. `NO_COLOR` environment variable (any value)
-//! 3. `TERM=dumb` environment variable
-//! 4. Non-TTY stdout (detected via anstream)
+//! 3. `CLICOLOR_FORCE=1` or `FORCE_COLOR` (non-empty, not `0`) force color on
+//!    in auto mode, even when stdout is piped
+//! 4. `TERM=dumb` environment variable
+//! 5. Non-TTY stdout (detected via anstream)
+//!
+//! With [`Ui::with_interaction`] prompts, messages and data lines go to an
+//! [`Interaction`] instead of the terminal; see [`crate::interaction`].
+//!
+//! Long paths in tables are shortened to fit the terminal unless `--full-paths`
+//! is given; output that isn't a terminal always gets full paths.
 
 use anstream::{eprintln, println};
 use anstyle::{AnsiColor, Color, Style};
-use comfy_table::{Cell, ContentArrangement, Table, presets};
+use comfy_table::{Cell, CellAlignment, ContentArrangement, Table, presets};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::io::IsTerminal;
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::io::{IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
 use std::time::Duration;
 
+use crate::interaction::{ComponentChoice, Interaction, Level};
+use crate::redact::Redactor;
+
+/// Pager used when neither config.toml nor `PAGER` names one
+pub const DEFAULT_PAGER: &str = "less -FRX";
+
 /// Color mode for output
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ColorMode {
@@ -38,13 +71,220 @@ impl std::str::FromStr for ColorMode {
     }
 }
 
+/// Output format for commands
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable text, tables and spinners
+    #[default]
+    Text,
+    /// One JSON event per line on stdout
+    Ndjson,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!(
+                "invalid output format: {} (expected text or ndjson)",
+                s
+            )),
+        }
+    }
+}
+
+/// Which icons to print
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IconStyle {
+    /// Unicode icons when colors are enabled, ASCII otherwise
+    #[default]
+    Auto,
+    /// Always Unicode (✓, ⚠, ✗, •)
+    Unicode,
+    /// Always ASCII ([OK], [!], [X], -), e.g. for screen readers
+    Ascii,
+}
+
+impl std::fmt::Display for IconStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Unicode => "unicode",
+            Self::Ascii => "ascii",
+        })
+    }
+}
+
+impl std::str::FromStr for IconStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "unicode" => Ok(Self::Unicode),
+            "ascii" => Ok(Self::Ascii),
+            _ => Err(format!(
+                "invalid icon style: {} (expected auto, unicode or ascii)",
+                s
+            )),
+        }
+    }
+}
+
+/// Border style of bordered tables
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableStyle {
+    /// Unicode borders when colors are enabled, Markdown otherwise
+    #[default]
+    Auto,
+    /// Unicode box-drawing borders
+    Unicode,
+    /// `+--+` ASCII borders
+    Ascii,
+    /// Markdown pipes
+    Markdown,
+}
+
+impl std::fmt::Display for TableStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Auto => "auto",
+            Self::Unicode => "unicode",
+            Self::Ascii => "ascii",
+            Self::Markdown => "markdown",
+        })
+    }
+}
+
+impl std::str::FromStr for TableStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Self::Auto),
+            "unicode" => Ok(Self::Unicode),
+            "ascii" => Ok(Self::Ascii),
+            "markdown" => Ok(Self::Markdown),
+            _ => Err(format!(
+                "invalid table style: {} (expected auto, unicode, ascii or markdown)",
+                s
+            )),
+        }
+    }
+}
+
+/// Accent colors a theme can pick, by name
+const ACCENT_COLORS: [(&str, AnsiColor); 7] = [
+    ("cyan", AnsiColor::Cyan),
+    ("blue", AnsiColor::Blue),
+    ("green", AnsiColor::Green),
+    ("magenta", AnsiColor::Magenta),
+    ("yellow", AnsiColor::Yellow),
+    ("red", AnsiColor::Red),
+    ("white", AnsiColor::White),
+];
+
+/// Name of an accent color, as config.toml spells it
+pub fn accent_name(color: AnsiColor) -> &'static str {
+    ACCENT_COLORS
+        .iter()
+        .find(|(_, c)| *c == color)
+        .map_or("cyan", |(name, _)| name)
+}
+
+/// Parse an accent color name
+pub fn parse_accent(s: &str) -> Result<AnsiColor, String> {
+    ACCENT_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        .map(|(_, color)| *color)
+        .ok_or_else(|| {
+            let names: Vec<&str> = ACCENT_COLORS.iter().map(|(name, _)| *name).collect();
+            format!(
+                "invalid accent color: {} (expected {})",
+                s,
+                names.join(", ")
+            )
+        })
+}
+
+/// Icons, accent color and table borders, from `[theme]` in config.toml
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    pub icons: IconStyle,
+    /// Color of INFO labels, spinners and progress bars
+    pub accent: AnsiColor,
+    pub table: TableStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            icons: IconStyle::Auto,
+            accent: AnsiColor::Cyan,
+            table: TableStyle::Auto,
+        }
+    }
+}
+
 /// UI context holding resolved display settings
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Ui {
     /// Whether colors are enabled
     pub color_enabled: bool,
     /// Whether spinners are enabled (requires TTY + color)
     pub spinner_enabled: bool,
+    /// Whether table paths are shown in full (from --full-paths)
+    pub full_paths: bool,
+    /// Text or event-stream output (from --output)
+    pub output: OutputFormat,
+    /// Icon, color and table preferences
+    pub theme: Theme,
+    /// Output held back for the pager, if paging
+    pager: Option<Pager>,
+    /// Whether confirmations are answered yes without asking (from --yes)
+    pub assume_yes: bool,
+    /// Whether profile names are only accepted as typed (from --exact)
+    pub exact_names: bool,
+    /// Which values are hidden as secrets (nothing with --show-secrets)
+    pub redactor: Redactor,
+    /// Where prompts and messages go instead of the terminal, if anywhere
+    interaction: Option<Rc<dyn Interaction>>,
+}
+
+/// The user cancelled a prompt (Esc or Ctrl-C); ccprof exits with status 130
+#[derive(Debug)]
+pub struct Aborted;
+
+impl std::fmt::Display for Aborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Aborted by user")
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+/// Map a cancelled prompt to [`Aborted`], keeping other prompt failures as errors
+fn prompt_result<T>(result: Result<T, inquire::InquireError>) -> anyhow::Result<T> {
+    match result {
+        Ok(value) => Ok(value),
+        Err(
+            inquire::InquireError::OperationCanceled | inquire::InquireError::OperationInterrupted,
+        ) => Err(Aborted.into()),
+        Err(e) => Err(anyhow::Error::new(e).context("Prompt failed")),
+    }
+}
+
+/// A pager command and the output collected for it
+#[derive(Debug, Clone)]
+struct Pager {
+    command: String,
+    /// Whether stderr lines go to the pager too (stderr is the same terminal)
+    include_stderr: bool,
+    buffer: Rc<RefCell<String>>,
 }
 
 impl Default for Ui {
@@ -59,45 +299,165 @@ impl Ui {
     /// Priority:
     /// 1. `force_no_color` (from --no-color flag)
     /// 2. `NO_COLOR` env var
-    /// 3. `TERM=dumb`
-    /// 4. TTY detection (for Auto mode)
+    /// 3. `CLICOLOR_FORCE` / `FORCE_COLOR` (for Auto mode)
+    /// 4. `TERM=dumb`
+    /// 5. TTY detection (for Auto mode)
     pub fn new(mode: ColorMode, force_no_color: bool) -> Self {
-        let color_enabled = Self::resolve_color(mode, force_no_color);
         let is_tty = std::io::stdout().is_terminal();
+        let color_enabled =
+            resolve_color(mode, force_no_color, |key| std::env::var(key).ok(), is_tty);
         let spinner_enabled = color_enabled && is_tty;
 
-        // Configure anstream's color choice globally
-        if !color_enabled {
-            anstream::ColorChoice::write_global(anstream::ColorChoice::Never);
-        }
+        // Configure anstream's color choice globally, so forced color
+        // survives a pipe
+        anstream::ColorChoice::write_global(if color_enabled {
+            anstream::ColorChoice::Always
+        } else {
+            anstream::ColorChoice::Never
+        });
 
         Self {
             color_enabled,
             spinner_enabled,
+            full_paths: false,
+            output: OutputFormat::Text,
+            theme: Theme::default(),
+            pager: None,
+            assume_yes: false,
+            exact_names: false,
+            redactor: Redactor::default(),
+            interaction: None,
+        }
+    }
+
+    /// Answer every confirmation with yes
+    pub fn with_assume_yes(mut self, assume_yes: bool) -> Self {
+        self.assume_yes = assume_yes;
+        self
+    }
+
+    /// Accept profile names only exactly as typed, not in another case or shortened
+    pub fn with_exact_names(mut self, exact_names: bool) -> Self {
+        self.exact_names = exact_names;
+        self
+    }
+
+    /// Hide secret-looking values as `redactor` says
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Send prompts, messages and data lines to `interaction` instead of the
+    /// terminal, with colors, spinners and paging off
+    pub fn with_interaction(mut self, interaction: Rc<dyn Interaction>) -> Self {
+        self.interaction = Some(interaction);
+        self.color_enabled = false;
+        self.spinner_enabled = false;
+        self.pager = None;
+        self
+    }
+
+    /// Collect output for `command` instead of printing it, when stdout is a terminal
+    ///
+    /// The output is shown by [`Ui::finish_paging`]. Spinners and prompts are
+    /// not buffered, so only use this for read-only commands.
+    pub fn with_pager(mut self, command: Option<String>) -> Self {
+        self.pager = command
+            .filter(|_| std::io::stdout().is_terminal() && !self.is_ndjson())
+            .map(|command| Pager {
+                command,
+                include_stderr: std::io::stderr().is_terminal(),
+                buffer: Rc::default(),
+            });
+        self
+    }
+
+    /// Print the output collected for the pager, through the pager if it
+    /// doesn't fit on one screen
+    pub fn finish_paging(&self) {
+        let Some(pager) = &self.pager else {
+            return;
+        };
+        let output = pager.buffer.take();
+        let height = crossterm::terminal::size().map_or(usize::MAX, |(_, rows)| rows as usize);
+        if output.lines().count() < height || !run_pager(&pager.command, &output) {
+            print!("{}", output);
         }
     }
 
-    fn resolve_color(mode: ColorMode, force_no_color: bool) -> bool {
-        // --no-color flag takes highest priority
-        if force_no_color {
-            return false;
+    /// Print `line` to stdout or stderr, or hold it for the pager
+    fn emit(&self, stderr: bool, line: std::fmt::Arguments) {
+        match &self.pager {
+            Some(pager) if !stderr || pager.include_stderr => {
+                let mut buffer = pager.buffer.borrow_mut();
+                buffer.push_str(&line.to_string());
+                buffer.push('\n');
+            }
+            _ if stderr => eprintln!("{}", line),
+            _ => println!("{}", line),
         }
+    }
+
+    /// Use the icons, accent color and table style of `theme`
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
 
-        // NO_COLOR env var (any value disables color per spec)
-        if std::env::var("NO_COLOR").is_ok() {
-            return false;
+    /// Never shorten paths in tables
+    pub fn with_full_paths(mut self, full_paths: bool) -> Self {
+        self.full_paths = full_paths;
+        self
+    }
+
+    /// Select the output format; event streams are never colored
+    pub fn with_output(mut self, output: OutputFormat) -> Self {
+        self.output = output;
+        if output == OutputFormat::Ndjson {
+            self.color_enabled = false;
+            self.spinner_enabled = false;
+            anstream::ColorChoice::write_global(anstream::ColorChoice::Never);
         }
+        self
+    }
 
-        // TERM=dumb disables color
-        if std::env::var("TERM").map(|t| t == "dumb").unwrap_or(false) {
-            return false;
+    /// Whether output is an event stream
+    pub fn is_ndjson(&self) -> bool {
+        self.output == OutputFormat::Ndjson
+    }
+
+    /// Emit an event with `fields` (a JSON object) in ndjson mode; no-op otherwise
+    pub fn event(&self, event: &str, fields: Value) {
+        if !self.is_ndjson() {
+            return;
+        }
+        let mut object = serde_json::Map::new();
+        object.insert("event".to_string(), Value::from(event));
+        if let Value::Object(fields) = fields {
+            object.extend(fields);
         }
+        println!("{}", Value::Object(object));
+    }
 
-        match mode {
-            ColorMode::Always => true,
-            ColorMode::Never => false,
-            ColorMode::Auto => std::io::stdout().is_terminal(),
+    /// Hand a line to the interaction, or emit it as a `message` event in
+    /// ndjson mode; false if it should be printed
+    fn report(&self, level: Level, msg: &str) -> bool {
+        if let Some(interaction) = &self.interaction {
+            interaction.message(level, msg);
+            return true;
         }
+        let level = match level {
+            Level::Ok => "ok",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Note | Level::Output | Level::Progress => return self.is_ndjson(),
+        };
+        if self.is_ndjson() {
+            self.event("message", json!({"level": level, "message": msg}));
+        }
+        self.is_ndjson()
     }
 
     // -------------------------------------------------------------------------
@@ -112,28 +472,40 @@ impl Ui {
         }
     }
 
-    /// Print OK label (green) with message to stdout
+    /// Print OK label (green) with message to stderr
     pub fn ok(&self, msg: impl AsRef<str>) {
+        if self.report(Level::Ok, msg.as_ref()) {
+            return;
+        }
         let label = self.style_label(AnsiColor::Green);
-        println!("{label}OK{label:#} {}", msg.as_ref());
+        self.emit(true, format_args!("{label}OK{label:#} {}", msg.as_ref()));
     }
 
-    /// Print WARN label (yellow) with message to stdout
+    /// Print WARN label (yellow) with message to stderr
     pub fn warn(&self, msg: impl AsRef<str>) {
+        if self.report(Level::Warn, msg.as_ref()) {
+            return;
+        }
         let label = self.style_label(AnsiColor::Yellow);
-        println!("{label}WARN{label:#} {}", msg.as_ref());
+        self.emit(true, format_args!("{label}WARN{label:#} {}", msg.as_ref()));
     }
 
     /// Print ERROR label (red) with message to stderr
     pub fn err(&self, msg: impl AsRef<str>) {
+        if self.report(Level::Error, msg.as_ref()) {
+            return;
+        }
         let label = self.style_label(AnsiColor::Red);
-        eprintln!("{label}ERROR{label:#} {}", msg.as_ref());
+        self.emit(true, format_args!("{label}ERROR{label:#} {}", msg.as_ref()));
     }
 
-    /// Print INFO label (cyan) with message to stdout
+    /// Print INFO label (accent color) with message to stderr
     pub fn info(&self, msg: impl AsRef<str>) {
-        let label = self.style_label(AnsiColor::Cyan);
-        println!("{label}INFO{label:#} {}", msg.as_ref());
+        if self.report(Level::Info, msg.as_ref()) {
+            return;
+        }
+        let label = self.style_label(self.theme.accent);
+        self.emit(true, format_args!("{label}INFO{label:#} {}", msg.as_ref()));
     }
 
     /// Return a styled string (dimmed/gray) - for inline use
@@ -170,20 +542,28 @@ impl Ui {
     // Status icons (with fallback for no-color)
     // -------------------------------------------------------------------------
 
+    fn unicode_icons(&self) -> bool {
+        match self.theme.icons {
+            IconStyle::Auto => self.color_enabled,
+            IconStyle::Unicode => true,
+            IconStyle::Ascii => false,
+        }
+    }
+
     pub fn icon_ok(&self) -> &'static str {
-        if self.color_enabled { "✓" } else { "[OK]" }
+        if self.unicode_icons() { "✓" } else { "[OK]" }
     }
 
     pub fn icon_warn(&self) -> &'static str {
-        if self.color_enabled { "⚠" } else { "[!]" }
+        if self.unicode_icons() { "⚠" } else { "[!]" }
     }
 
     pub fn icon_err(&self) -> &'static str {
-        if self.color_enabled { "✗" } else { "[X]" }
+        if self.unicode_icons() { "✗" } else { "[X]" }
     }
 
     pub fn icon_info(&self) -> &'static str {
-        if self.color_enabled { "•" } else { "-" }
+        if self.unicode_icons() { "•" } else { "-" }
     }
 
     // -------------------------------------------------------------------------
@@ -195,11 +575,12 @@ impl Ui {
         let mut table = Table::new();
         table.set_content_arrangement(ContentArrangement::Dynamic);
 
-        if self.color_enabled {
-            table.load_preset(presets::UTF8_FULL_CONDENSED);
-        } else {
-            table.load_preset(presets::ASCII_MARKDOWN);
-        }
+        table.load_preset(match self.theme.table {
+            TableStyle::Auto if self.color_enabled => presets::UTF8_FULL_CONDENSED,
+            TableStyle::Auto | TableStyle::Markdown => presets::ASCII_MARKDOWN,
+            TableStyle::Unicode => presets::UTF8_FULL_CONDENSED,
+            TableStyle::Ascii => presets::ASCII_FULL_CONDENSED,
+        });
 
         table
     }
@@ -238,6 +619,33 @@ impl Ui {
         }
     }
 
+    /// Create a right-aligned cell for sizes and counts
+    pub fn number_cell(&self, content: impl Into<String>) -> Cell {
+        Cell::new(content.into()).set_alignment(CellAlignment::Right)
+    }
+
+    /// Display a path for a table column, shortened to fit the terminal
+    ///
+    /// `reserved` is the width taken by the other columns. Paths are only
+    /// shortened when stdout is a terminal and `--full-paths` wasn't given.
+    pub fn display_path(&self, path: &Path, reserved: usize) -> String {
+        let width = if self.full_paths {
+            None
+        } else {
+            Table::new().width()
+        };
+        match width {
+            Some(width) => {
+                let max = (width as usize)
+                    .saturating_sub(reserved)
+                    .max(MIN_PATH_WIDTH);
+                let home = directories::BaseDirs::new().map(|d| d.home_dir().to_path_buf());
+                shorten_path(path, home.as_deref(), max)
+            }
+            None => path.display().to_string(),
+        }
+    }
+
     /// Create a cell with an icon prefix (properly styled)
     pub fn status_cell(&self, icon: &str, content: impl Into<String>) -> Cell {
         Cell::new(format!("{} {}", icon, content.into()))
@@ -250,12 +658,16 @@ impl Ui {
     /// Create a spinner for longer operations.
     /// Returns a no-op spinner when disabled.
     pub fn spinner(&self, message: impl Into<std::borrow::Cow<'static, str>>) -> ProgressBar {
+        let message = message.into();
+        if let Some(interaction) = &self.interaction {
+            interaction.progress(&message);
+        }
         if self.spinner_enabled {
             let pb = ProgressBar::new_spinner();
             pb.set_style(
                 ProgressStyle::default_spinner()
                     .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
-                    .template("{spinner:.cyan} {msg}")
+                    .template(&format!("{{spinner:.{}}} {{msg}}", self.accent_name()))
                     .expect("valid template"),
             );
             pb.set_message(message);
@@ -281,7 +693,7 @@ impl Ui {
                     .template("{msg}")
                     .expect("valid template"),
             );
-            let icon = self.colored("✓", AnsiColor::Green);
+            let icon = self.colored(self.icon_ok(), AnsiColor::Green);
             pb.finish_with_message(format!("{} {}", icon, msg.into()));
         } else {
             pb.finish_and_clear();
@@ -301,7 +713,7 @@ impl Ui {
                     .template("{msg}")
                     .expect("valid template"),
             );
-            let icon = self.colored("✗", AnsiColor::Red);
+            let icon = self.colored(self.icon_err(), AnsiColor::Red);
             pb.finish_with_message(format!("{} {}", icon, msg.into()));
         } else {
             pb.finish_and_clear();
@@ -309,23 +721,203 @@ impl Ui {
         }
     }
 
+    /// Turn a spinner into a progress bar of `len` steps
+    pub fn spinner_progress(
+        &self,
+        pb: &ProgressBar,
+        len: u64,
+        message: impl Into<std::borrow::Cow<'static, str>>,
+    ) {
+        if self.spinner_enabled {
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template(&format!(
+                        "{{msg}} [{{bar:30.{}/blue}}] {{pos}}/{{len}}",
+                        self.accent_name()
+                    ))
+                    .expect("valid template")
+                    .progress_chars("=> "),
+            );
+            pb.set_position(0);
+            pb.set_length(len);
+        }
+        pb.set_message(message);
+    }
+
+    /// Progress bar for copying `files` files totalling `bytes`, advanced by bytes
+    ///
+    /// Without spinners, prints a single "copying N files…" line instead.
+    pub fn copy_progress(&self, message: &str, files: u64, bytes: u64) -> ProgressBar {
+        if self.is_ndjson() {
+            self.event(
+                "copy",
+                json!({"message": message, "files": files, "bytes": bytes}),
+            );
+            return ProgressBar::hidden();
+        }
+        if !self.spinner_enabled {
+            self.info(format!(
+                "{} ({} files, {})…",
+                message,
+                files,
+                indicatif::HumanBytes(bytes)
+            ));
+            return ProgressBar::hidden();
+        }
+        let pb = ProgressBar::new(bytes);
+        pb.set_style(
+            ProgressStyle::default_bar()
+                .template(&format!(
+                    "{{msg}} [{{bar:30.{}/blue}}] {{bytes}}/{{total_bytes}}",
+                    self.accent_name()
+                ))
+                .expect("valid template")
+                .progress_chars("=> "),
+        );
+        pb.set_message(format!("{} ({} files)", message, files));
+        pb
+    }
+
+    /// Turn a progress bar made with `spinner_progress` back into a spinner
+    pub fn spinner_restore(
+        &self,
+        pb: &ProgressBar,
+        message: impl Into<std::borrow::Cow<'static, str>>,
+    ) {
+        if self.spinner_enabled {
+            pb.set_style(
+                ProgressStyle::default_spinner()
+                    .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏")
+                    .template(&format!("{{spinner:.{}}} {{msg}}", self.accent_name()))
+                    .expect("valid template"),
+            );
+        }
+        pb.set_message(message);
+    }
+
+    /// Name of the accent color in progress bar templates
+    fn accent_name(&self) -> &'static str {
+        ACCENT_COLORS
+            .iter()
+            .find(|(_, color)| *color == self.theme.accent)
+            .map_or("cyan", |(name, _)| *name)
+    }
+
+    /// Print a line above a running spinner (plainly when spinners are disabled)
+    pub fn spinner_println(&self, pb: &ProgressBar, msg: impl AsRef<str>) {
+        if self.spinner_enabled {
+            pb.println(msg.as_ref());
+        } else {
+            self.note(msg);
+        }
+    }
+
     // -------------------------------------------------------------------------
     // Println helpers (using anstream for proper tty handling)
     // -------------------------------------------------------------------------
 
-    /// Print a line to stdout
+    /// Print a line of data to stdout (an `output` event in ndjson mode)
     pub fn println(&self, msg: impl AsRef<str>) {
-        println!("{}", msg.as_ref());
+        if let Some(interaction) = &self.interaction {
+            interaction.message(Level::Output, msg.as_ref());
+        } else if self.is_ndjson() {
+            self.event("output", json!({"text": msg.as_ref()}));
+        } else {
+            self.emit(false, format_args!("{}", msg.as_ref()));
+        }
     }
 
-    /// Print an empty line
+    /// Print a line of commentary (hints, progress) to stderr
+    pub fn note(&self, msg: impl AsRef<str>) {
+        if !self.report(Level::Note, msg.as_ref()) {
+            self.emit(true, format_args!("{}", msg.as_ref()));
+        }
+    }
+
+    /// Print an empty line to stderr
     pub fn newline(&self) {
-        println!();
+        if !self.is_ndjson() && self.interaction.is_none() {
+            self.emit(true, format_args!(""));
+        }
     }
 
-    /// Print a section header
+    /// Print a section header to stderr
     pub fn section(&self, title: impl AsRef<str>) {
-        println!("{}", self.bold(title));
+        if !self.report(Level::Note, title.as_ref()) {
+            self.emit(true, format_args!("{}", self.bold(title)));
+        }
+    }
+}
+
+/// The terminal: inquire prompts and printed messages
+impl Interaction for Ui {
+    /// `--yes` answers yes without asking. Without a terminal on stdin this
+    /// fails instead of hanging, and Esc or Ctrl-C give [`Aborted`].
+    fn confirm(&self, msg: &str, help: Option<&str>, default: bool) -> anyhow::Result<bool> {
+        if self.assume_yes {
+            return Ok(true);
+        }
+        if let Some(interaction) = &self.interaction {
+            return interaction.confirm(msg, help, default);
+        }
+        if !std::io::stdin().is_terminal() {
+            anyhow::bail!(
+                "Cannot ask \"{}\": stdin is not a terminal.\nHint: Pass --yes to confirm without a prompt.",
+                msg
+            );
+        }
+        let mut prompt = inquire::Confirm::new(msg).with_default(default);
+        if let Some(help) = help {
+            prompt = prompt.with_help_message(help);
+        }
+        prompt_result(prompt.prompt())
+    }
+
+    fn select_components(
+        &self,
+        msg: &str,
+        help: &str,
+        choices: &[ComponentChoice],
+    ) -> anyhow::Result<std::collections::HashSet<crate::components::Component>> {
+        if let Some(interaction) = &self.interaction {
+            return interaction.select_components(msg, help, choices);
+        }
+        let labels: Vec<&str> = choices.iter().map(|c| c.label.as_str()).collect();
+        let defaults: Vec<usize> = choices
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.selected)
+            .map(|(i, _)| i)
+            .collect();
+        let selected = prompt_result(
+            inquire::MultiSelect::new(msg, labels)
+                .with_default(&defaults)
+                .with_help_message(help)
+                .raw_prompt(),
+        )?;
+        Ok(selected
+            .into_iter()
+            .map(|option| choices[option.index].component)
+            .collect())
+    }
+
+    fn select(&self, msg: &str, options: &[String]) -> anyhow::Result<usize> {
+        if let Some(interaction) = &self.interaction {
+            return interaction.select(msg, options);
+        }
+        let option = prompt_result(inquire::Select::new(msg, options.to_vec()).raw_prompt())?;
+        Ok(option.index)
+    }
+
+    fn message(&self, level: Level, text: &str) {
+        match level {
+            Level::Ok => self.ok(text),
+            Level::Info => self.info(text),
+            Level::Warn => self.warn(text),
+            Level::Error => self.err(text),
+            Level::Note | Level::Progress => self.note(text),
+            Level::Output => self.println(text),
+        }
     }
 }
 
@@ -354,6 +946,133 @@ fn ansi_to_comfy_color(color: AnsiColor) -> comfy_table::Color {
     }
 }
 
+/// Whether to emit colors, given the environment lookup `env`
+fn resolve_color(
+    mode: ColorMode,
+    force_no_color: bool,
+    env: impl Fn(&str) -> Option<String>,
+    is_tty: bool,
+) -> bool {
+    // --no-color flag takes highest priority
+    if force_no_color {
+        return false;
+    }
+
+    // NO_COLOR env var (any value disables color per spec)
+    if env("NO_COLOR").is_some() {
+        return false;
+    }
+
+    // CLICOLOR_FORCE / FORCE_COLOR turn auto into always
+    let forced = env("CLICOLOR_FORCE").is_some_and(|v| v == "1")
+        || env("FORCE_COLOR").is_some_and(|v| !v.is_empty() && v != "0");
+    if mode == ColorMode::Auto && forced {
+        return true;
+    }
+
+    // TERM=dumb disables color
+    if env("TERM").is_some_and(|t| t == "dumb") {
+        return false;
+    }
+
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => is_tty,
+    }
+}
+
+/// Which pager to use, if any
+///
+/// `--no-pager` (`disabled`) wins, then config.toml's `pager`, then `PAGER`.
+/// An empty command or `cat` means no paging.
+pub fn resolve_pager(
+    disabled: bool,
+    configured: Option<&str>,
+    env_pager: Option<String>,
+) -> Option<String> {
+    if disabled {
+        return None;
+    }
+    let command = configured
+        .map(str::to_string)
+        .or(env_pager)
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    let command = command.trim();
+    if command.is_empty() || command == "cat" {
+        None
+    } else {
+        Some(command.to_string())
+    }
+}
+
+/// Show `output` in the pager; false if it couldn't be started
+fn run_pager(command: &str, output: &str) -> bool {
+    let mut parts = command.split_whitespace();
+    let Some(program) = parts.next() else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe; that's fine
+        let _ = stdin.write_all(output.as_bytes());
+    }
+    let _ = child.wait();
+    true
+}
+
+/// Shorten `text` to `max` characters, marking the cut with `…`
+///
+/// Counts characters, not bytes, so non-ASCII text is never cut mid-character.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(max.saturating_sub(1)).collect();
+    short.push('…');
+    short
+}
+
+/// Narrowest a shortened path gets, however little room the terminal has
+const MIN_PATH_WIDTH: usize = 20;
+
+/// Shorten `path` to at most `max` characters where possible
+///
+/// The home directory becomes `~`, then leading directories are elided while
+/// the last component is always kept, e.g. `~/…/work/agents`.
+fn shorten_path(path: &Path, home: Option<&Path>, max: usize) -> String {
+    let full = match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    };
+    if full.chars().count() <= max {
+        return full;
+    }
+
+    let parts: Vec<&str> = full.split('/').collect();
+    if parts.len() <= 2 {
+        return full;
+    }
+    let (head, last) = (parts[0], parts[parts.len() - 1]);
+    let mut tail = vec![last];
+    let mut len = head.chars().count() + "/…/".chars().count() + last.chars().count();
+    for part in parts[1..parts.len() - 1].iter().rev() {
+        len += part.chars().count() + 1;
+        if len > max {
+            break;
+        }
+        tail.insert(0, part);
+    }
+    format!("{}/…/{}", head, tail.join("/"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -378,6 +1097,51 @@ mod tests {
         assert!(!ui.color_enabled);
     }
 
+    #[test]
+    fn test_resolve_color_precedence() {
+        use ColorMode::*;
+        type Env = &'static [(&'static str, &'static str)];
+        #[rustfmt::skip]
+        let cases: &[(ColorMode, bool, Env, bool, bool)] = &[
+            // mode, --no-color, env, tty, expected
+            (Auto,   false, &[],                                        true,  true),
+            (Auto,   false, &[],                                        false, false),
+            (Always, false, &[],                                        false, true),
+            (Never,  false, &[],                                        true,  false),
+            (Always, true,  &[],                                        true,  false),
+            (Always, false, &[("NO_COLOR", "")],                        true,  false),
+            (Always, false, &[("TERM", "dumb")],                        true,  false),
+            (Auto,   false, &[("TERM", "dumb")],                        true,  false),
+            (Auto,   false, &[("CLICOLOR_FORCE", "1")],                 false, true),
+            (Auto,   false, &[("CLICOLOR_FORCE", "0")],                 false, false),
+            (Auto,   false, &[("FORCE_COLOR", "1")],                    false, true),
+            (Auto,   false, &[("FORCE_COLOR", "true")],                 false, true),
+            (Auto,   false, &[("FORCE_COLOR", "0")],                    false, false),
+            (Auto,   false, &[("FORCE_COLOR", "")],                     false, false),
+            (Auto,   false, &[("FORCE_COLOR", "1"), ("TERM", "dumb")],  false, true),
+            (Auto,   false, &[("FORCE_COLOR", "1"), ("NO_COLOR", "1")], false, false),
+            (Auto,   true,  &[("CLICOLOR_FORCE", "1")],                 false, false),
+            (Never,  false, &[("FORCE_COLOR", "1")],                    true,  false),
+        ];
+
+        for &(mode, no_color, env, tty, expected) in cases {
+            let lookup = |key: &str| {
+                env.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            };
+            assert_eq!(
+                resolve_color(mode, no_color, lookup, tty),
+                expected,
+                "{:?} --no-color={} env={:?} tty={}",
+                mode,
+                no_color,
+                env,
+                tty
+            );
+        }
+    }
+
     #[test]
     fn test_icons_no_color() {
         let ui = Ui::new(ColorMode::Never, false);
@@ -400,6 +1164,95 @@ mod tests {
         drop(table);
     }
 
+    #[test]
+    fn test_shorten_path() {
+        let home = Path::new("/home/me");
+        let agents = Path::new("/home/me/.claude-profiles/profiles/work/agents");
+        assert_eq!(
+            shorten_path(agents, Some(home), 80),
+            "~/.claude-profiles/profiles/work/agents"
+        );
+        assert_eq!(shorten_path(agents, Some(home), 20), "~/…/work/agents");
+        assert_eq!(shorten_path(agents, Some(home), 5), "~/…/agents");
+        assert_eq!(
+            shorten_path(
+                Path::new("/srv/claude/profiles/work/settings.json"),
+                None,
+                25
+            ),
+            "/…/work/settings.json"
+        );
+        assert_eq!(shorten_path(home, Some(home), 1), "~");
+    }
+
+    #[test]
+    fn test_ndjson_output_disables_color() {
+        let ui = Ui::new(ColorMode::Always, false).with_output(OutputFormat::Ndjson);
+        assert!(ui.is_ndjson());
+        assert!(!ui.color_enabled);
+        assert!(!ui.spinner_enabled);
+        assert_eq!(
+            "NDJSON".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Ndjson
+        );
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_cancelled_prompt_is_aborted() {
+        let ui = Ui::new(ColorMode::Never, false).with_assume_yes(true);
+        assert!(ui.confirm("Remove?", None, false).unwrap());
+
+        let err = prompt_result::<bool>(Err(inquire::InquireError::OperationCanceled)).unwrap_err();
+        assert!(err.is::<Aborted>());
+        let err =
+            prompt_result::<bool>(Err(inquire::InquireError::OperationInterrupted)).unwrap_err();
+        assert!(err.is::<Aborted>());
+        let err = prompt_result::<bool>(Err(inquire::InquireError::NotTTY)).unwrap_err();
+        assert!(!err.is::<Aborted>());
+    }
+
+    #[test]
+    fn test_ascii_theme_icons() {
+        let theme = Theme {
+            icons: IconStyle::Ascii,
+            ..Theme::default()
+        };
+        let ui = Ui::new(ColorMode::Always, false).with_theme(theme);
+        assert_eq!(ui.icon_ok(), "[OK]");
+        assert_eq!(ui.icon_info(), "-");
+        assert_eq!(parse_accent("Magenta"), Ok(AnsiColor::Magenta));
+        assert!(parse_accent("teal").is_err());
+    }
+
+    #[test]
+    fn test_truncate_counts_characters() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("abcdef", 4), "abc…");
+        // Multi-byte characters are never split
+        assert_eq!(truncate("日本語のテキスト", 5), "日本語の…");
+        assert_eq!(truncate("a—b—c—d", 4), "a—b…");
+    }
+
+    #[test]
+    fn test_resolve_pager() {
+        assert_eq!(
+            resolve_pager(false, None, None).as_deref(),
+            Some(DEFAULT_PAGER)
+        );
+        assert_eq!(
+            resolve_pager(false, None, Some("more".into())).as_deref(),
+            Some("more")
+        );
+        assert_eq!(
+            resolve_pager(false, Some("less -R"), Some("more".into())).as_deref(),
+            Some("less -R")
+        );
+        assert_eq!(resolve_pager(false, None, Some("cat".into())), None);
+        assert_eq!(resolve_pager(false, None, Some(String::new())), None);
+        assert_eq!(resolve_pager(true, Some("less"), None), None);
+    }
+
     #[test]
     fn test_spinner_disabled() {
         let ui = Ui::new(ColorMode::Never, false);
@@ -0,0 +1,366 @@
+//! Profiles cloned from git repositories.
+//!
+//! `ccprof clone <url>` shallow-clones a repository with the system `git` and
+//! copies the profile in it into a new profile: the components found in the
+//! repository root, or in `--path`, which must hold a settings.json or a
+//! metadata.json. The URL and path are recorded as the profile's [`Upstream`],
+//! so `ccprof pull` can fetch the repository again and update the profile.
+
+use anyhow::{Context, Result, bail};
+use chrono::Utc;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::components::{Component, ProfileMetadata, Upstream};
+use crate::error::Error;
+use crate::fs_utils::{CopyReport, copy_dir_recursive, copy_file, remove_path, replace_dir_atomic};
+use crate::paths::Paths;
+use crate::profiles::{remove_on_error, validate_json_file, validate_profile_name};
+
+/// A shallow clone in the staging directory, removed when dropped
+pub struct Checkout {
+    dir: PathBuf,
+    /// The directory holding the profile, standing in for ~/.claude
+    profile: Paths,
+    /// Components the profile provides
+    pub components: HashSet<Component>,
+    /// Commit that was checked out
+    pub commit: Option<String>,
+}
+
+impl Checkout {
+    /// Clone `url` and find the profile at `path` (default: the root)
+    pub fn fetch(paths: &Paths, url: &str, path: Option<&str>) -> Result<Self> {
+        let dir = paths.staging_dir.join(format!(
+            "clone-{}-{}",
+            Utc::now().format("%Y%m%d_%H%M%S%.f"),
+            std::process::id()
+        ));
+        fs::create_dir_all(&paths.staging_dir).with_context(|| {
+            format!(
+                "Failed to create staging directory {}",
+                paths.staging_dir.display()
+            )
+        })?;
+        let mut checkout = Self {
+            profile: Paths::with_dirs(&paths.base_dir, &dir),
+            dir,
+            components: HashSet::new(),
+            commit: None,
+        };
+        git(&[
+            OsStr::new("clone"),
+            OsStr::new("--depth"),
+            OsStr::new("1"),
+            OsStr::new("--quiet"),
+            OsStr::new("--"),
+            OsStr::new(url),
+            checkout.dir.as_os_str(),
+        ])?;
+        checkout.commit = git(&[
+            OsStr::new("-C"),
+            checkout.dir.as_os_str(),
+            OsStr::new("rev-parse"),
+            OsStr::new("HEAD"),
+        ])
+        .ok();
+
+        let profile_dir = match path {
+            Some(path) => {
+                let inside = Path::new(path)
+                    .components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_)));
+                let joined = checkout.dir.join(path);
+                if !inside || crate::paths::within(&joined, &checkout.dir).is_none() {
+                    bail!(
+                        "--path must be a directory inside the repository, not '{}'",
+                        path
+                    );
+                }
+                joined
+            }
+            None => checkout.dir.clone(),
+        };
+        let has_metadata = profile_dir.join("metadata.json").is_file();
+        if !profile_dir.join("settings.json").is_file() && !has_metadata {
+            bail!(
+                "{}{} doesn't look like a profile: it has no settings.json or metadata.json\nHint: Pass --path to name the directory holding the profile.",
+                url,
+                path.map(|p| format!(" at '{}'", p)).unwrap_or_default()
+            );
+        }
+        checkout.profile = Paths::with_dirs(&paths.base_dir, &profile_dir);
+
+        let listed = if has_metadata {
+            Some(ProfileMetadata::read(&profile_dir)?.managed_components)
+        } else {
+            None
+        };
+        checkout.components = Component::all()
+            .into_iter()
+            .filter(|c| listed.as_ref().is_none_or(|l| l.contains(c)))
+            .filter(|c| checkout.component_path(*c).exists())
+            .collect();
+        if checkout.components.contains(&Component::Settings) {
+            validate_json_file(&checkout.component_path(Component::Settings))?;
+        }
+        Ok(checkout)
+    }
+
+    /// Where a component is in the clone
+    pub fn component_path(&self, component: Component) -> PathBuf {
+        self.profile.claude_dir.join(component.file_name())
+    }
+
+    /// Components of the clone whose content differs from profile `name`'s
+    /// own copy; those the profile takes from a shared item are left out
+    pub fn changed_components(&self, paths: &Paths, name: &str) -> Result<Vec<Component>> {
+        let metadata = ProfileMetadata::read(&paths.profile_dir(name))?;
+        Ok(Component::all()
+            .into_iter()
+            .filter(|c| self.components.contains(c) && metadata.shared_source(*c).is_none())
+            .filter(|c| {
+                !crate::fs_utils::same_content(
+                    &c.profile_path(paths, name),
+                    &self.component_path(*c),
+                )
+            })
+            .collect())
+    }
+
+    /// Copy `components` from the clone over profile `name`'s copies, returning
+    /// what the copies reported
+    fn copy_into(&self, paths: &Paths, name: &str, components: &[Component]) -> Result<CopyReport> {
+        let mut report = CopyReport::default();
+        for component in components {
+            let from = self.component_path(*component);
+            let to = component.profile_path(paths, name);
+            if component.is_file() {
+                // Written beside the old file and renamed over it
+                let temp = to.with_file_name(format!(".{}.ccprof-pull", component.file_name()));
+                copy_file(&from, &temp)?;
+                fs::rename(&temp, &to)
+                    .with_context(|| format!("Failed to replace {}", to.display()))?;
+            } else if to.exists() {
+                report.extend(replace_dir_atomic(&from, &to)?);
+            } else {
+                report.extend(copy_dir_recursive(&from, &to)?);
+            }
+        }
+        Ok(report)
+    }
+}
+
+impl Drop for Checkout {
+    fn drop(&mut self) {
+        let _ = remove_path(&self.dir);
+    }
+}
+
+/// Profile name for a clone of `url` without `--name`: the last directory of
+/// `path`, or the repository name
+pub fn default_name(url: &str, path: Option<&str>) -> String {
+    let last = |s: &str| {
+        s.trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    };
+    match path {
+        Some(path) => last(path),
+        None => last(url).trim_end_matches(".git").to_string(),
+    }
+}
+
+/// Create profile `name` from the profile at `path` in the repository `url`,
+/// returning the components it manages with what copying them reported
+pub fn clone_profile(
+    paths: &Paths,
+    url: &str,
+    name: &str,
+    path: Option<&str>,
+) -> Result<(HashSet<Component>, CopyReport)> {
+    validate_profile_name(name)?;
+    let profile_dir = paths.profile_dir(name);
+    if profile_dir.exists() {
+        return Err(Error::ProfileExists {
+            name: name.to_string(),
+        }
+        .into());
+    }
+    let checkout = Checkout::fetch(paths, url, path)?;
+
+    remove_on_error(&profile_dir, || {
+        fs::create_dir_all(&profile_dir)
+            .with_context(|| format!("Failed to create directory {}", profile_dir.display()))?;
+        let components: Vec<Component> = checkout.components.iter().copied().collect();
+        let report = checkout.copy_into(paths, name, &components)?;
+
+        let mut metadata = ProfileMetadata::new(checkout.components.clone());
+        metadata.upstream = Some(Upstream {
+            url: url.to_string(),
+            path: path.map(str::to_string),
+            commit: checkout.commit.clone(),
+        });
+        metadata.write(&profile_dir)?;
+        Ok((checkout.components.clone(), report))
+    })
+}
+
+/// Copy the `changed` components of `checkout` into profile `name` and record
+/// the commit they came from, returning what the copies reported
+pub fn apply_pull(
+    paths: &Paths,
+    name: &str,
+    checkout: &Checkout,
+    changed: &[Component],
+) -> Result<CopyReport> {
+    let report = checkout.copy_into(paths, name, changed)?;
+    let profile_dir = paths.profile_dir(name);
+    let mut metadata = ProfileMetadata::read(&profile_dir)?;
+    metadata.managed_components.extend(changed.iter().copied());
+    if let Some(upstream) = &mut metadata.upstream {
+        upstream.commit = checkout.commit.clone();
+    }
+    metadata.updated_at = Utc::now();
+    metadata.write(&profile_dir)?;
+    Ok(report)
+}
+
+/// Run git, returning its trimmed stdout
+fn git(args: &[&OsStr]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .context("Failed to run 'git'\nHint: Clone and pull need git on PATH.")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args[0].to_string_lossy(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::setup_test_paths;
+    use tempfile::TempDir;
+
+    /// A repository with a profile under `team/`
+    fn make_repo(dir: &Path) {
+        fs::create_dir_all(dir.join("team/agents")).unwrap();
+        fs::write(dir.join("team/settings.json"), r#"{"model": "opus"}"#).unwrap();
+        fs::write(dir.join("team/agents/review.md"), "# Review").unwrap();
+        fs::write(dir.join("README.md"), "Starter profile").unwrap();
+        commit_all(dir, "init");
+    }
+
+    fn commit_all(dir: &Path, message: &str) {
+        for args in [
+            vec!["init", "--quiet"],
+            vec!["add", "-A"],
+            vec![
+                "-c",
+                "user.name=t",
+                "-c",
+                "user.email=t@example.com",
+                "-c",
+                "commit.gpgsign=false",
+                "commit",
+                "--quiet",
+                "-m",
+                message,
+            ],
+        ] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(dir)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn test_default_name() {
+        assert_eq!(
+            default_name("https://example.com/team/starter.git", None),
+            "starter"
+        );
+        assert_eq!(
+            default_name("git@example.com:team/starter", None),
+            "starter"
+        );
+        assert_eq!(
+            default_name("https://x/y.git", Some("profiles/work/")),
+            "work"
+        );
+    }
+
+    #[test]
+    fn test_clone_then_pull_changes() {
+        let temp_dir = TempDir::new().unwrap();
+        let paths = setup_test_paths(&temp_dir);
+        paths.ensure_dirs().unwrap();
+        let repo = temp_dir.path().join("repo");
+        make_repo(&repo);
+        let url = repo.to_string_lossy().to_string();
+
+        let err = clone_profile(&paths, &url, "team", None).unwrap_err();
+        assert!(err.to_string().contains("doesn't look like a profile"));
+        assert!(!paths.profile_dir("team").exists());
+        assert!(clone_profile(&paths, &url, "team", Some("../x")).is_err());
+
+        let (components, _) = clone_profile(&paths, &url, "team", Some("team")).unwrap();
+        assert_eq!(
+            components,
+            HashSet::from([Component::Settings, Component::Agents])
+        );
+        assert!(paths.profile_dir("team").join("agents/review.md").exists());
+        assert!(!paths.profile_dir("team").join("README.md").exists());
+        let metadata = ProfileMetadata::read(&paths.profile_dir("team")).unwrap();
+        let upstream = metadata.upstream.unwrap();
+        assert_eq!(upstream.url, url);
+        assert_eq!(upstream.path.as_deref(), Some("team"));
+        assert!(upstream.commit.is_some());
+
+        let checkout = Checkout::fetch(&paths, &url, Some("team")).unwrap();
+        assert!(
+            checkout
+                .changed_components(&paths, "team")
+                .unwrap()
+                .is_empty()
+        );
+        drop(checkout);
+
+        fs::write(repo.join("team/settings.json"), r#"{"model": "sonnet"}"#).unwrap();
+        fs::create_dir_all(repo.join("team/commands")).unwrap();
+        fs::write(repo.join("team/commands/ship.md"), "ship").unwrap();
+        commit_all(&repo, "update");
+
+        let checkout = Checkout::fetch(&paths, &url, Some("team")).unwrap();
+        let changed = checkout.changed_components(&paths, "team").unwrap();
+        assert_eq!(changed, vec![Component::Settings, Component::Commands]);
+        assert_eq!(
+            apply_pull(&paths, "team", &checkout, &changed).unwrap(),
+            CopyReport::default()
+        );
+        drop(checkout);
+        assert_eq!(
+            fs::read_to_string(paths.profile_settings("team")).unwrap(),
+            r#"{"model": "sonnet"}"#
+        );
+        let metadata = ProfileMetadata::read(&paths.profile_dir("team")).unwrap();
+        assert!(metadata.managed_components.contains(&Component::Commands));
+        assert_ne!(metadata.upstream.unwrap().commit, upstream.commit);
+        assert_eq!(fs::read_dir(&paths.staging_dir).unwrap().count(), 0);
+    }
+}
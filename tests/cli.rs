@@ -0,0 +1,1095 @@
+//! End-to-end tests running the ccprof binary against a temporary directory
+//!
+//! `CCPROF_HOME` and `CCPROF_CLAUDE_DIR` point every path into the temp dir,
+//! so the user's real `~/.claude` is never touched.
+
+use std::fs;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempfile::TempDir;
+
+fn ccprof(root: &Path, args: &[&str]) -> Output {
+    ccprof_with(root, args, &[])
+}
+
+fn ccprof_with(root: &Path, args: &[&str], envs: &[(&str, &Path)]) -> Output {
+    command(root, args)
+        .envs(envs.iter().copied())
+        .output()
+        .expect("failed to run ccprof")
+}
+
+fn command(root: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ccprof"));
+    command
+        .args(args)
+        .env("CCPROF_HOME", root.join("profiles-home"))
+        .env("CCPROF_CLAUDE_DIR", root.join("claude"))
+        .env_remove("CLAUDE_CONFIG_DIR")
+        .env_remove("VISUAL")
+        .env("NO_COLOR", "1");
+    command
+}
+
+fn ccprof_stdin(root: &Path, args: &[&str], input: &str) -> Output {
+    let mut child = command(root, args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run ccprof");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().expect("failed to run ccprof")
+}
+
+#[test]
+fn test_add_and_use_stay_inside_overridden_dirs() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "opus"}"#).unwrap();
+
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(
+        root.join("profiles-home/profiles/work/settings.json")
+            .exists()
+    );
+
+    let out = ccprof(root, &["use", "work"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read_link(root.join("claude/settings.json")).unwrap(),
+        root.join("profiles-home/profiles/work/settings.json")
+    );
+
+    let out = ccprof(root, &["list"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("work"));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_add_records_claude_version() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let claude = root.join("fake-claude");
+    fs::write(&claude, "#!/bin/sh\necho '1.0.3 (Claude Code)'\n").unwrap();
+    fs::set_permissions(&claude, fs::Permissions::from_mode(0o755)).unwrap();
+    let envs = [("CCPROF_CLAUDE_BIN", claude.as_path())];
+
+    let out = ccprof_with(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+        &envs,
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let metadata =
+        fs::read_to_string(root.join("profiles-home/profiles/work/metadata.json")).unwrap();
+    assert!(metadata.contains(r#""claude_version": "1.0.3""#));
+
+    let out = ccprof_with(root, &["inspect", "work"], &envs);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("1.0.3"));
+}
+
+#[test]
+fn test_diagnostics_stay_off_stdout() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("work"));
+    let out = ccprof(root, &["use", "work"]);
+    assert!(out.stdout.is_empty());
+
+    let out = ccprof(root, &["list"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("work"));
+    assert!(!stdout.contains("Profiles"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Profiles"));
+
+    let out = ccprof(root, &["current"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("work"));
+    assert!(!stdout.contains("Current Profile"));
+
+    let out = ccprof(root, &["components", "--json"]);
+    let json: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(json["profiles"][0], "work");
+}
+
+#[test]
+fn test_ndjson_output_is_one_event_per_line() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+
+    let out = ccprof(root, &["--output", "ndjson", "use", "work"]);
+    assert!(out.status.success());
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let backup = events.iter().find(|e| e["event"] == "backup").unwrap();
+    assert_eq!(backup["component"], "settings");
+    assert!(backup["path"].as_str().unwrap().contains("settings."));
+    assert_eq!(events.last().unwrap()["event"], "done");
+    assert_eq!(events.last().unwrap()["ok"], true);
+
+    let out = ccprof(root, &["--output", "ndjson", "use", "missing"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let done: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(done["ok"], false);
+}
+
+#[test]
+fn test_confirmation_needs_yes_without_a_terminal() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+
+    // stdin is not a terminal here, so the prompt fails instead of hanging
+    let out = ccprof(root, &["remove", "work"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--yes"));
+    assert!(root.join("profiles-home/profiles/work").exists());
+
+    let out = ccprof(root, &["--yes", "remove", "work"]);
+    assert!(out.status.success());
+    assert!(!root.join("profiles-home/profiles/work").exists());
+}
+
+#[test]
+fn test_config_file_yes_and_unknown_keys() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    fs::write(
+        root.join("profiles-home/config.toml"),
+        "yes = true\ncolour = \"red\"\n[backups]\nkeep_per_component = 1\n",
+    )
+    .unwrap();
+
+    let out = ccprof(root, &["config-file", "show"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("yes = true"));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Ignoring colour"));
+
+    let out = ccprof(root, &["remove", "work"]);
+    assert!(out.status.success());
+    assert!(!root.join("profiles-home/profiles/work").exists());
+
+    // `backup clean` without --keep keeps keep_per_component
+    let backups = root.join("profiles-home/backups");
+    fs::create_dir_all(&backups).unwrap();
+    for name in [
+        "settings.20250101_120000.bak",
+        "settings.20250102_120000.bak",
+    ] {
+        fs::write(backups.join(name), "{}").unwrap();
+    }
+    assert!(ccprof(root, &["backup", "clean"]).status.success());
+    assert!(!backups.join("settings.20250101_120000.bak").exists());
+    assert!(backups.join("settings.20250102_120000.bak").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_hook_modes_survive_profile_backup_and_restore() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let hooks = root.join("claude/hooks");
+    fs::create_dir_all(&hooks).unwrap();
+    fs::write(hooks.join("pre.sh"), "#!/bin/sh\necho hi\n").unwrap();
+    fs::set_permissions(hooks.join("pre.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+    fs::set_permissions(&hooks, fs::Permissions::from_mode(0o700)).unwrap();
+    let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "hooks"],
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        mode(&root.join("profiles-home/profiles/work/hooks/pre.sh")),
+        0o755
+    );
+
+    // Switching backs up the live directory, restoring copies it back
+    assert!(ccprof(root, &["use", "work"]).status.success());
+    let backup = fs::read_dir(root.join("profiles-home/backups"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .find(|name| name.to_string_lossy().ends_with(".bak"))
+        .unwrap();
+    let out = ccprof(
+        root,
+        &["--yes", "backup", "restore", backup.to_str().unwrap()],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(!fs::symlink_metadata(&hooks).unwrap().is_symlink());
+    assert_eq!(mode(&hooks.join("pre.sh")), 0o755);
+    assert_eq!(mode(&hooks), 0o700);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unreadable_files_are_skipped_unless_strict() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let commands = root.join("claude/commands");
+    fs::create_dir_all(&commands).unwrap();
+    fs::write(commands.join("deploy.md"), "deploy").unwrap();
+    // A socket can't be read, even as root
+    let _socket = std::os::unix::net::UnixListener::bind(commands.join("agent.sock")).unwrap();
+
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "commands"],
+    );
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Skipped unreadable"), "{stderr}");
+    assert!(stderr.contains("agent.sock"));
+    assert!(
+        root.join("profiles-home/profiles/work/commands/deploy.md")
+            .exists()
+    );
+
+    let out = ccprof(
+        root,
+        &[
+            "--strict",
+            "add",
+            "strict",
+            "--from-current",
+            "--components",
+            "commands",
+        ],
+    );
+    assert!(!out.status.success());
+    assert!(!root.join("profiles-home/profiles/strict").exists());
+}
+
+#[test]
+fn test_exit_status_tells_missing_from_invalid() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+
+    let out = ccprof(root, &["use", "missing"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("'missing' does not exist"));
+
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "bad.name",
+            "--from-current",
+            "--components",
+            "settings",
+        ],
+    );
+    assert_eq!(out.status.code(), Some(3));
+
+    fs::write(root.join("claude/settings.json"), "{ not json").unwrap();
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert_eq!(out.status.code(), Some(3));
+}
+
+#[test]
+fn test_diff_hides_secrets_unless_asked() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    let add = |name: &str, settings: &str| {
+        fs::write(root.join("claude/settings.json"), settings).unwrap();
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(out.status.success());
+    };
+    add("work", r#"{"env": {"ANTHROPIC_API_KEY": "sk-ant-work1"}}"#);
+    add("home", r#"{"env": {"ANTHROPIC_API_KEY": "sk-ant-home2"}}"#);
+
+    for args in [
+        &["diff", "work", "home"][..],
+        &["--output", "ndjson", "diff", "work", "home"],
+    ] {
+        let out = ccprof(root, args);
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.contains("env.ANTHROPIC_API_KEY"), "{stdout}");
+        assert!(stdout.contains("sk-…REDACTED"));
+        assert!(!stdout.contains("work1"));
+    }
+
+    let out = ccprof(root, &["--show-secrets", "diff", "work", "home"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("sk-ant-work1"));
+}
+
+#[test]
+fn test_diff_truncates_multibyte_values() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    for (name, prompt) in [
+        (
+            "work",
+            "レビューの前に必ずテストを実行してください — 例外はありません",
+        ),
+        (
+            "home",
+            "Always run the tests — no exceptions — before asking for review",
+        ),
+    ] {
+        let settings = serde_json::json!({ "systemPrompt": prompt });
+        fs::write(root.join("claude/settings.json"), settings.to_string()).unwrap();
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(out.status.success());
+    }
+
+    let out = ccprof(root, &["diff", "work", "home"]);
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(!stderr.contains("panicked"), "{stderr}");
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("systemPrompt"), "{stdout}");
+    assert!(stdout.contains("\"レビューの前に"));
+    assert!(stdout.contains('…'));
+}
+
+#[test]
+fn test_diff_walks_nested_directories() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    let commands = root.join("claude/commands");
+    fs::create_dir_all(commands.join("git")).unwrap();
+    fs::write(commands.join("review.md"), "Review the diff\n").unwrap();
+    let add = |name: &str, commit: &str| {
+        fs::write(commands.join("git/commit.md"), commit).unwrap();
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "commands"],
+        );
+        assert!(out.status.success());
+    };
+    add("work", "Write a commit message\nKeep it short\n");
+    add("home", "Write a commit message\nExplain why\n");
+    fs::create_dir_all(root.join("profiles-home/profiles/home/commands/git/release")).unwrap();
+    fs::write(
+        root.join("profiles-home/profiles/home/commands/git/release/tag.md"),
+        "Tag it\n",
+    )
+    .unwrap();
+
+    let out = ccprof(root, &["diff", "work", "home", "-c", "commands"]);
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("~ git/commit.md"), "{stdout}");
+    assert!(stdout.contains("+ git/release/tag.md"), "{stdout}");
+    assert!(!stdout.contains("review.md"));
+    assert!(!stdout.contains("-Keep it short"));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("0 only in work, 1 only in home, 1 different"),
+        "{stderr}"
+    );
+
+    let out = ccprof(
+        root,
+        &["diff", "work", "home", "-c", "commands", "--show-content"],
+    );
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("--- work/git/commit.md"), "{stdout}");
+    assert!(stdout.contains("-Keep it short"));
+    assert!(stdout.contains("+Explain why"));
+    assert!(stdout.contains(" Write a commit message"));
+}
+
+#[test]
+fn test_settings_with_comments_are_read_leniently() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    for (name, model) in [("work", "opus"), ("home", "sonnet")] {
+        fs::write(
+            root.join("claude/settings.json"),
+            format!("{{\n  // pinned\n  \"model\": \"{model}\",\n}}\n"),
+        )
+        .unwrap();
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+
+    let out = ccprof(root, &["diff", "work", "home"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\"sonnet\""));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("strict JSON is safer"));
+    // The profile keeps its comments
+    let saved = fs::read_to_string(root.join("profiles-home/profiles/work/settings.json")).unwrap();
+    assert!(saved.contains("// pinned"));
+}
+
+#[test]
+fn test_diff_exit_status_tells_whether_profiles_differ() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    for (name, model) in [("work", "opus"), ("copy", "opus"), ("home", "sonnet")] {
+        fs::write(
+            root.join("claude/settings.json"),
+            format!(r#"{{"model": "{model}"}}"#),
+        )
+        .unwrap();
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(out.status.success());
+    }
+
+    let out = ccprof(root, &["diff", "work", "copy"]);
+    assert_eq!(out.status.code(), Some(0));
+    let out = ccprof(root, &["diff", "work", "home", "--quiet"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+    assert!(out.stderr.is_empty());
+
+    // Errors never look like "different"
+    let out = ccprof(root, &["diff", "work", "nope", "-q"]);
+    assert_eq!(out.status.code(), Some(2));
+    let out = ccprof(root, &["diff", "work", "home", "-c", "bogus"]);
+    assert_eq!(out.status.code(), Some(2));
+}
+
+#[test]
+fn test_backup_list_filters_by_component_as_json() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude/agents")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    fs::write(root.join("claude/agents/review.md"), "# Review").unwrap();
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "work",
+            "--from-current",
+            "--components",
+            "settings,agents",
+        ],
+    );
+    assert!(out.status.success());
+    assert!(ccprof(root, &["--yes", "use", "work"]).status.success());
+
+    let out = ccprof(root, &["backup", "list", "--component", "agents", "--json"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let backups: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let backups = backups.as_array().unwrap();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(backups[0]["component"], "agents");
+    assert_eq!(backups[0]["size"], 8);
+    let id = backups[0]["id"].as_str().unwrap();
+    assert!(id.starts_with("agents.") && id.ends_with(".bak"));
+    assert!(backups[0]["timestamp"].as_str().unwrap().ends_with('Z'));
+
+    let out = ccprof(root, &["backup", "list", "--component", "hooks", "--json"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "[]");
+    let out = ccprof(root, &["backup", "list", "--component", "bogus"]);
+    assert!(!out.status.success());
+}
+
+#[test]
+fn test_backup_restore_to_another_path() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude/agents")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "old"}"#).unwrap();
+    fs::write(root.join("claude/agents/review.md"), "# Review").unwrap();
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "work",
+            "--from-current",
+            "--components",
+            "settings,agents",
+        ],
+    );
+    assert!(out.status.success());
+    assert!(ccprof(root, &["--yes", "use", "work"]).status.success());
+    let out = ccprof(root, &["backup", "list", "--json"]);
+    let backups: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+    let id_of = |component: &str| {
+        backups
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|b| b["component"] == component)
+            .unwrap()["id"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+
+    // Outside ~/.claude there is nothing to confirm
+    let out_file = root.join("out/old.json");
+    let out = ccprof(
+        root,
+        &[
+            "backup",
+            "restore",
+            &id_of("settings"),
+            "--to",
+            out_file.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(
+        fs::read_to_string(&out_file).unwrap(),
+        r#"{"model": "old"}"#
+    );
+    assert!(fs::read_link(root.join("claude/settings.json")).is_ok());
+
+    // An existing directory receives the backup inside it
+    let agents = id_of("agents");
+    let out_dir = root.join("out");
+    let out = ccprof(
+        root,
+        &[
+            "backup",
+            "restore",
+            &agents,
+            "--to",
+            out_dir.to_str().unwrap(),
+        ],
+    );
+    assert!(out.status.success());
+    assert!(out_dir.join(&agents).join("review.md").exists());
+
+    let inside = root.join("claude/old.json");
+    let out = ccprof(
+        root,
+        &[
+            "backup",
+            "restore",
+            &id_of("settings"),
+            "--to",
+            inside.to_str().unwrap(),
+        ],
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--yes"));
+    assert!(!inside.exists());
+}
+
+#[test]
+fn test_profile_prefixes_resolve_unless_exact() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+
+    let out = ccprof(root, &["inspect", "wo", "--json"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("\"work\""));
+
+    let out = ccprof(root, &["--exact", "inspect", "wo"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Did you mean 'work'?"));
+
+    let out = ccprof(root, &["use", "wrok", "--yes"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Did you mean 'work'?"));
+
+    let out = ccprof(
+        root,
+        &["add", "wiki", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+    let out = ccprof(root, &["hash", "W"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("ambiguous; it matches: wiki, work"));
+    let out = ccprof(root, &["hash", "WO"]);
+    assert!(out.status.success());
+}
+
+#[test]
+fn test_add_reads_settings_from_stdin() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude/agents")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "opus"}"#).unwrap();
+    fs::write(root.join("claude/agents/review.md"), "agent").unwrap();
+
+    let out = ccprof_stdin(
+        root,
+        &["add", "ci", "--from-stdin", "--components", "agents"],
+        r#"{"model": "sonnet"}"#,
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let profile = root.join("profiles-home/profiles/ci");
+    assert_eq!(
+        fs::read_to_string(profile.join("settings.json")).unwrap(),
+        r#"{"model": "sonnet"}"#
+    );
+    assert!(profile.join("agents/review.md").exists());
+
+    let out = ccprof_stdin(root, &["add", "empty", "--from-stdin"], " \n");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Nothing was piped in"));
+    let out = ccprof_stdin(root, &["add", "broken", "--from-stdin"], "{ nope");
+    assert_eq!(out.status.code(), Some(3));
+    assert!(!root.join("profiles-home/profiles/broken").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_edit_of_live_settings_keeps_them_valid() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "opus"}"#).unwrap();
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+    assert!(ccprof(root, &["use", "work", "--yes"]).status.success());
+
+    let settings = root.join("profiles-home/profiles/work/settings.json");
+    let truncate = Path::new("sh -c 'printf { > \"$1\"' sh");
+    let out = ccprof_with(root, &["edit", "work"], &[("EDITOR", truncate)]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not valid and was discarded"));
+    assert_eq!(
+        fs::read_to_string(&settings).unwrap(),
+        r#"{"model": "opus"}"#
+    );
+
+    let rewrite = Path::new("sh -c 'printf \"{\\\"model\\\": \\\"sonnet\\\"}\" > \"$1\"' sh");
+    let out = ccprof_with(root, &["edit", "work"], &[("EDITOR", rewrite)]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains(r#"+{"model": "sonnet"}"#));
+    assert_eq!(
+        fs::read_to_string(root.join("claude/settings.json")).unwrap(),
+        r#"{"model": "sonnet"}"#
+    );
+}
+
+#[test]
+fn test_use_of_active_profile_is_a_no_op() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+    assert!(ccprof(root, &["use", "work", "--yes"]).status.success());
+
+    let out = ccprof(root, &["use", "work"]);
+    assert!(out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Profile 'work' is already active"));
+    let out = ccprof(root, &["use", "work", "--quiet"]);
+    assert!(out.status.success());
+    assert!(out.stderr.is_empty());
+
+    let out = ccprof(root, &["use", "work", "--always"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Switched to profile 'work'"));
+
+    // A broken link takes the full switch, which repairs it
+    fs::remove_file(root.join("claude/settings.json")).unwrap();
+    let out = ccprof(root, &["use", "work", "--quiet"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Switched to profile 'work'"));
+    assert!(fs::read_link(root.join("claude/settings.json")).is_ok());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_profile_open_in_an_editor_is_not_removed() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let out = ccprof(
+        root,
+        &["add", "work", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+
+    // Another ccprof with an editor open, as far as the marker tells
+    let mut editor = Command::new("sleep").arg("30").spawn().unwrap();
+    let marker = root.join("profiles-home/profiles/work/.editing");
+    fs::write(
+        &marker,
+        format!(
+            r#"{{"pid": {}, "started_at": "2026-01-01T09:30:00Z"}}"#,
+            editor.id()
+        ),
+    )
+    .unwrap();
+
+    let out = ccprof(root, &["--yes", "remove", "work"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is open in an editor"));
+    let out = ccprof(root, &["rename", "work", "job"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(root.join("profiles-home/profiles/work").exists());
+
+    // Once the editing process is gone, doctor reports the marker
+    editor.kill().unwrap();
+    editor.wait().unwrap();
+    let out = ccprof(root, &["doctor"]);
+    assert!(String::from_utf8_lossy(&out.stdout).contains("no longer running"));
+    ccprof(root, &["doctor", "--fix"]);
+    assert!(!marker.exists());
+    assert!(ccprof(root, &["rename", "work", "job"]).status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_read_only_profiles_directory() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    for name in ["work", "personal"] {
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(out.status.success());
+    }
+    let home = root.join("profiles-home");
+    let set_mode = |mode| {
+        for dir in [home.join("profiles"), home.clone()] {
+            fs::set_permissions(dir, fs::Permissions::from_mode(mode)).unwrap();
+        }
+    };
+    set_mode(0o555);
+    // Permissions don't bind root
+    if fs::write(home.join("probe"), "").is_ok() {
+        set_mode(0o755);
+        return;
+    }
+
+    for args in [
+        &["list"][..],
+        &["current"],
+        &["inspect", "work"],
+        &["diff", "work", "personal"],
+    ] {
+        let out = ccprof(root, args);
+        assert!(
+            out.status.success(),
+            "{args:?}: {}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+
+    let out = ccprof(root, &["--yes", "remove", "personal"]);
+    set_mode(0o755);
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("is not writable (permission denied)"),
+        "{stderr}"
+    );
+    assert_eq!(stderr.lines().filter(|l| l.starts_with("Error")).count(), 1);
+    assert!(home.join("profiles/personal").exists());
+}
+
+#[test]
+fn test_dry_run_changes_nothing() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "opus"}"#).unwrap();
+    for name in ["work", "personal"] {
+        let out = ccprof(
+            root,
+            &["add", name, "--from-current", "--components", "settings"],
+        );
+        assert!(out.status.success());
+    }
+    assert!(ccprof(root, &["use", "work", "--yes"]).status.success());
+    let listing = |dir: &Path| {
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        entries.sort();
+        entries
+    };
+    let profiles = listing(&root.join("profiles-home/profiles"));
+    let backups = listing(&root.join("profiles-home/backups"));
+    let state = fs::read_to_string(root.join("profiles-home/state.json")).unwrap();
+
+    let out = ccprof(root, &["--dry-run", "use", "personal"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(
+        stdout.contains("profiles/personal/settings.json"),
+        "{stdout}"
+    );
+    assert!(stdout.contains("select profile 'personal'"));
+
+    let out = ccprof(root, &["remove", "personal", "--dry-run"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(String::from_utf8_lossy(&out.stdout).contains("remove "));
+    for args in [
+        &["rename", "personal", "home", "--dry-run"][..],
+        &["backup", "clean", "--keep", "0", "--dry-run"],
+        &["edit", "personal", "--track", "settings", "--dry-run"],
+    ] {
+        let out = ccprof(root, args);
+        assert!(out.status.success(), "{args:?}");
+    }
+
+    assert_eq!(listing(&root.join("profiles-home/profiles")), profiles);
+    assert_eq!(listing(&root.join("profiles-home/backups")), backups);
+    assert_eq!(
+        fs::read_to_string(root.join("profiles-home/state.json")).unwrap(),
+        state
+    );
+    assert!(
+        fs::read_link(root.join("claude/settings.json"))
+            .unwrap()
+            .ends_with("work/settings.json")
+    );
+
+    // Commands without a preview refuse rather than run
+    let out = ccprof(root, &["--dry-run", "sync"]);
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("doesn't support --dry-run"));
+}
+
+#[test]
+fn test_dry_run_leaves_resolved_settings_alone() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), r#"{"model": "opus"}"#).unwrap();
+    let out = ccprof(
+        root,
+        &["add", "base", "--from-current", "--components", "settings"],
+    );
+    assert!(out.status.success());
+    assert!(
+        ccprof(root, &["add", "child", "--extends", "base"])
+            .status
+            .success()
+    );
+    assert!(ccprof(root, &["use", "child", "--yes"]).status.success());
+    let resolved = root.join("profiles-home/profiles/child/settings.resolved.json");
+    let before = fs::read_to_string(&resolved).unwrap();
+    let modified = fs::metadata(&resolved).unwrap().modified().unwrap();
+
+    fs::write(
+        root.join("profiles-home/profiles/base/settings.json"),
+        r#"{"model": "sonnet"}"#,
+    )
+    .unwrap();
+    let out = ccprof(root, &["--dry-run", "use", "child"]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert_eq!(fs::read_to_string(&resolved).unwrap(), before);
+    assert_eq!(
+        fs::metadata(&resolved).unwrap().modified().unwrap(),
+        modified
+    );
+}
+
+#[test]
+fn test_porcelain_output_is_tab_separated() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude/agents")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "work",
+            "--from-current",
+            "--components",
+            "settings,agents",
+        ],
+    );
+    assert!(out.status.success());
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "personal",
+            "--from-current",
+            "--components",
+            "settings",
+        ],
+    );
+    assert!(out.status.success());
+
+    // Nothing selected yet: no line at all
+    let out = ccprof(root, &["current", "--porcelain"]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+
+    assert!(ccprof(root, &["use", "work", "--yes"]).status.success());
+    let out = ccprof(root, &["list", "--porcelain"]);
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        "personal\tinactive\tsettings\nwork\tactive\tagents,settings\n"
+    );
+    assert!(out.stderr.is_empty());
+    let out = ccprof(root, &["current", "--porcelain"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "work\tok\n");
+
+    fs::remove_file(root.join("claude/settings.json")).unwrap();
+    let out = ccprof(root, &["current", "--porcelain"]);
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "work\tdegraded\n");
+}
+
+#[test]
+fn test_env_component_is_linked_outside_claude_dir() {
+    let temp = TempDir::new().unwrap();
+    let root = temp.path();
+    fs::create_dir_all(root.join("claude")).unwrap();
+    fs::write(root.join("claude/settings.json"), "{}").unwrap();
+    let out = ccprof(
+        root,
+        &[
+            "add",
+            "work",
+            "--from-current",
+            "--components",
+            "settings,env",
+        ],
+    );
+    assert!(out.status.success());
+    let env_file = root.join("profiles-home/profiles/work/env.sh");
+    assert!(fs::read_to_string(&env_file).unwrap().starts_with('#'));
+    fs::write(&env_file, "export CCPROF_TEST_MODEL=opus\n").unwrap();
+
+    assert!(ccprof(root, &["use", "work", "--yes"]).status.success());
+    let active = root.join("profiles-home/active.env");
+    assert_eq!(fs::read_link(&active).unwrap(), env_file);
+    assert!(!root.join("claude/env.sh").exists());
+
+    let out = ccprof(root, &["env", "work", "--shell", "sh"]);
+    assert!(out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("export CCPROF_TEST_MODEL='opus'"));
+    assert!(!root.join("profiles-home/rendered/work/env.sh").exists());
+
+    let out = ccprof(
+        root,
+        &[
+            "exec",
+            "work",
+            "--",
+            "sh",
+            "-c",
+            "echo \"$CCPROF_TEST_MODEL\"",
+        ],
+    );
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "opus\n");
+}